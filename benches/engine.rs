@@ -0,0 +1,41 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use snake_game::bench::scenarios;
+use snake_game::lock_bench::{self, LockBenchConfig, Strategy};
+
+/// Registers every engine scenario with criterion
+fn engine_benchmarks(c: &mut Criterion) {
+    for scenario in scenarios() {
+        let template = scenario.setup();
+        c.bench_function(scenario.name, |b| {
+            b.iter_batched_ref(
+                || template.clone(),
+                |game| scenario.run(game),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+}
+
+/// Registers every locking strategy under every contention profile with criterion,
+/// so a regression of the concurrency design shows up next to the engine's
+fn lock_benchmarks(c: &mut Criterion) {
+    let config = LockBenchConfig {
+        threads: 4,
+        games: 16,
+        ops_per_thread: 1_000,
+    };
+    let mut group = c.benchmark_group("locks");
+    group.sample_size(10);
+    for profile in lock_bench::profiles() {
+        for strategy in Strategy::ALL {
+            let name = format!("{}/{}", profile.name, strategy.name());
+            group.bench_function(name, |b| {
+                b.iter(|| lock_bench::run(strategy, &profile, &config))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, engine_benchmarks, lock_benchmarks);
+criterion_main!(benches);
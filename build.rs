@@ -0,0 +1,10 @@
+fn main() {
+    // Generate the gRPC service from the protobuf definition using a vendored protoc
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/snake.proto");
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("vendored protoc unavailable");
+        std::env::set_var("PROTOC", protoc);
+        tonic_build::compile_protos("proto/snake.proto").expect("failed to compile protos");
+    }
+}
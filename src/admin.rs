@@ -0,0 +1,753 @@
+use crate::archive::{Archive, MAX_ARCHIVE_BYTES};
+use crate::audit::{AuditAction, AuditEntry, AuditQuery};
+use crate::game::{Direction, Game, Input, Point};
+#[cfg(feature = "ai")]
+use crate::imitation::{self, ExportConfig, ExportFormat};
+use crate::level::{self, Level};
+use crate::moderation::{HiddenScore, PendingScore, Suspicion};
+#[cfg(feature = "ai")]
+use crate::observation::ObservationFormat;
+use crate::replay::Replay;
+use crate::report::{Report, ReportTarget};
+use crate::request_id::RequestId;
+use crate::server::AppState;
+#[cfg(feature = "ai")]
+use crate::server::{BOARD_HEIGHT, BOARD_WIDTH};
+use crate::signer::{unix_now, Signer};
+use actix_web::{error, web, HttpMessage, HttpRequest, HttpResponse};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::mem::size_of;
+use std::time::{Duration, SystemTime};
+
+/// Health report of a long-running background task
+#[derive(Clone, Default, Serialize)]
+pub struct TaskHealth {
+    /// Number of completed runs
+    pub runs: u64,
+    /// Unix timestamp (seconds) of the last run
+    pub last_run: Option<u64>,
+    /// Error message of the last failed run, if any
+    pub last_error: Option<String>,
+    /// When the task runs, for tasks driven by the scheduler
+    pub schedule: Option<String>,
+    /// Unix timestamp (seconds) of the next scheduled run
+    pub next_run: Option<u64>,
+}
+
+/// Diagnostic view of a single game session
+#[derive(Serialize)]
+struct SessionDebug {
+    session_id: String,
+    snake_length: usize,
+    score: u32,
+    game_over: bool,
+    queued_inputs: usize,
+    connections: usize,
+    idle_secs: u64,
+    memory_bytes: usize,
+}
+
+/// Active games that have not advanced for this long are reported as stalled
+const STALL_AFTER: Duration = Duration::from_secs(10);
+
+/// Live view of an active game for the ops dashboard
+#[derive(Serialize)]
+struct ActiveGame {
+    session_id: String,
+    tick: u64,
+    /// Ticks per second over the last few seconds
+    tick_rate: f64,
+    connections: usize,
+    /// Seconds since the game last advanced
+    stalled_secs: u64,
+}
+
+/// Checks the `Authorization: Bearer <token>` header against the configured admin token
+/// The admin API is unavailable when no token is configured
+pub(crate) fn authorize(req: &HttpRequest, data: &AppState) -> actix_web::Result<()> {
+    let config = data.config();
+    let Some(expected) = config.admin_token.as_deref() else {
+        return Err(error::ErrorForbidden("admin API disabled"));
+    };
+    let provided = req
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    // Compared as HMACs under a throwaway key, so the time taken reveals nothing of the token
+    let signer = Signer::default();
+    if provided.is_some_and(|provided| {
+        signer.verifies(provided.as_bytes(), &signer.mac(expected.as_bytes()))
+    }) {
+        Ok(())
+    } else {
+        Err(error::ErrorUnauthorized("invalid admin token"))
+    }
+}
+
+/// Header naming the admin behind a request, recorded in the audit log
+/// The admin token is shared, so the name is informational only
+const ACTOR_HEADER: &str = "x-admin-actor";
+/// Longest admin name recorded in the audit log
+const MAX_ACTOR_LEN: usize = 64;
+
+/// Starts an audit entry for an action of the admin behind an authorized request
+fn audit_entry(req: &HttpRequest, action: AuditAction, target: &str) -> AuditEntry {
+    let name = req
+        .headers()
+        .get(ACTOR_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && name.len() <= MAX_ACTOR_LEN);
+    let actor = match name {
+        Some(name) => format!("admin:{}", name),
+        None => "admin".to_string(),
+    };
+    let request_id = req.extensions().get::<RequestId>().map(|id| id.0.clone());
+    AuditEntry::new(actor, action, target).request_id(request_id)
+}
+
+/// Estimates the heap and inline memory held by a session
+fn memory_estimate(session_id: &str, game: &Game) -> usize {
+    size_of::<Game>()
+        + session_id.len()
+        + game.snake.capacity() * size_of::<Point>()
+        + game.input_queue.capacity() * size_of::<Direction>()
+        + game.inputs.capacity() * size_of::<Input>()
+        + game
+            .history
+            .iter()
+            .map(|snapshot| size_of::<Game>() + snapshot.snake.capacity() * size_of::<Point>())
+            .sum::<usize>()
+}
+
+/// Reports per-session memory estimates, queued inputs and background task health
+/// Includes live heap statistics when built with the `dhat-heap` feature
+pub async fn debug(req: HttpRequest, data: web::Data<AppState>) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+
+    let mut sessions: Vec<SessionDebug> = {
+        let sessions = data.sessions.lock().unwrap();
+        sessions
+            .iter()
+            .map(|(session_id, session)| SessionDebug {
+                session_id: session_id.clone(),
+                snake_length: session.game.snake.len(),
+                score: session.game.score,
+                game_over: session.game.game_over,
+                queued_inputs: session.game.input_queue.len(),
+                connections: session.connections,
+                idle_secs: session.last_seen.elapsed().as_secs(),
+                memory_bytes: memory_estimate(session_id, &session.game),
+            })
+            .collect()
+    };
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.memory_bytes));
+    let tasks = data.tasks.lock().unwrap().clone();
+
+    #[cfg(feature = "dhat-heap")]
+    let heap = {
+        let stats = dhat::HeapStats::get();
+        Some(json!({
+            "current_bytes": stats.curr_bytes,
+            "current_blocks": stats.curr_blocks,
+            "peak_bytes": stats.max_bytes,
+            "total_bytes": stats.total_bytes,
+        }))
+    };
+    #[cfg(not(feature = "dhat-heap"))]
+    let heap: Option<serde_json::Value> = None;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "session_count": sessions.len(),
+        "total_memory_bytes": sessions.iter().map(|s| s.memory_bytes).sum::<usize>(),
+        "queued_inputs": sessions.iter().map(|s| s.queued_inputs).sum::<usize>(),
+        "sessions": sessions,
+        "tasks": tasks,
+        "heap": heap,
+    })))
+}
+
+/// Aggregates what a simple ops page needs: active games with their tick rates, games
+/// that stopped advancing, request error rates, games kept waiting for CPU, storage queue
+/// depths and task health
+pub async fn dashboard(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+
+    let (mut active, finished) = {
+        let sessions = data.sessions.lock().unwrap();
+        let active: Vec<ActiveGame> = sessions
+            .iter()
+            .filter(|(_, session)| !session.game.game_over)
+            .map(|(session_id, session)| ActiveGame {
+                session_id: session_id.clone(),
+                tick: session.game.tick,
+                tick_rate: session.current_tick_rate(),
+                connections: session.connections,
+                stalled_secs: session.last_tick_at.elapsed().as_secs(),
+            })
+            .collect();
+        let finished = sessions.len() - active.len();
+        (active, finished)
+    };
+    active.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+    let (stalled, active): (Vec<_>, Vec<_>) = active
+        .into_iter()
+        .partition(|game| game.stalled_secs >= STALL_AFTER.as_secs());
+    #[cfg(feature = "persistence")]
+    let (checkpoints_due, checkpoints_obsolete) = data.checkpoint_backlog();
+    #[cfg(not(feature = "persistence"))]
+    let (checkpoints_due, checkpoints_obsolete) = (0, 0);
+
+    #[cfg(feature = "oauth")]
+    let pending_logins = Some(data.oauth.pending_logins());
+    #[cfg(not(feature = "oauth"))]
+    let pending_logins: Option<usize> = None;
+
+    let tasks = data.tasks.lock().unwrap().clone();
+    let failing_tasks = tasks
+        .values()
+        .filter(|health| health.last_error.is_some())
+        .count();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "games": {
+            "active": active.len(),
+            "stalled": stalled.len(),
+            "finished": finished,
+            "ticks_per_sec": active.iter().map(|game| game.tick_rate).sum::<f64>(),
+        },
+        "active_games": active,
+        "stalled_games": stalled,
+        "requests": data.request_metrics.summary(),
+        "starvation": data.fairness.starvation(),
+        "queues": {
+            "checkpoints_due": checkpoints_due,
+            "checkpoints_obsolete": checkpoints_obsolete,
+            "review": data.review_queue.lock().unwrap().len(),
+            "link_codes": data.link_codes.lock().unwrap().len(),
+            "oauth_logins": pending_logins,
+        },
+        "failing_tasks": failing_tasks,
+        "tasks": tasks,
+    })))
+}
+
+/// Level chosen by an admin as the level of the week
+#[derive(Deserialize)]
+pub struct FeatureRequest {
+    level_id: String,
+}
+
+/// Features a published level for the rest of the current week, archiving the
+/// leaderboard of the level it replaces; the rotation resumes the next week
+pub async fn feature_level(
+    req: HttpRequest,
+    pick: web::Json<FeatureRequest>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    let published = data
+        .levels
+        .lock()
+        .unwrap()
+        .get(&pick.level_id)
+        .is_some_and(Level::is_public);
+    if !published {
+        return Err(error::ErrorNotFound("unknown level"));
+    }
+    let week = level::week_of(SystemTime::now());
+    let before = data.featured.lock().unwrap().clone();
+    level::feature(&data, Some(pick.level_id.clone()), week, true);
+    let after = data.featured.lock().unwrap().clone();
+    data.audit.record(
+        audit_entry(&req, AuditAction::LevelFeatured, &pick.level_id)
+            .before(before)
+            .after(after),
+    );
+    Ok(HttpResponse::Ok().json(json!({ "level_id": pick.level_id, "week": week })))
+}
+
+/// Reports the status of the scheduled background jobs, ordered by name
+pub async fn jobs(req: HttpRequest, data: web::Data<AppState>) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    let tasks = data.tasks.lock().unwrap();
+    let mut jobs: Vec<_> = tasks.iter().collect();
+    jobs.sort_by_key(|(name, _)| name.as_str());
+    let jobs: Vec<_> = jobs
+        .into_iter()
+        .map(|(name, health)| json!({ "name": name, "status": health }))
+        .collect();
+    Ok(HttpResponse::Ok().json(jobs))
+}
+
+/// Re-simulates every replay the server holds and lists those failing
+/// `Replay::check_properties`, ordered by ID; a safety net after the engine's rules changed
+pub async fn verify_replays(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    let replays: Vec<Replay> = data.replays.lock().unwrap().values().cloned().collect();
+    let checked = replays.len();
+    // Re-simulation is CPU-bound, keep it off the async workers
+    let mut failed = web::block(move || {
+        replays
+            .iter()
+            .filter_map(|replay| {
+                let error = replay.check_properties().err()?;
+                Some((replay.id.clone(), error))
+            })
+            .collect::<Vec<_>>()
+    })
+    .await?;
+    failed.sort();
+    for (replay_id, error) in &failed {
+        tracing::warn!(replay_id = %replay_id, "replay failed verification: {}", error);
+    }
+    let failed: Vec<_> = failed
+        .into_iter()
+        .map(|(replay_id, error)| json!({ "replay_id": replay_id, "error": error }))
+        .collect();
+    Ok(HttpResponse::Ok().json(json!({ "checked": checked, "failed": failed })))
+}
+
+/// Lists scores held for review, oldest first
+pub async fn review_queue(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    let queue = data.review_queue.lock().unwrap();
+    Ok(HttpResponse::Ok().json(&*queue))
+}
+
+/// Takes a score out of the review queue
+/// Responds with 404 Not Found for unknown review entries
+fn take_pending(data: &AppState, review_id: &str) -> actix_web::Result<PendingScore> {
+    let mut queue = data.review_queue.lock().unwrap();
+    let Some(index) = queue.iter().position(|pending| pending.id == review_id) else {
+        return Err(error::ErrorNotFound("unknown review entry"));
+    };
+    Ok(queue.remove(index))
+}
+
+/// Ranks a held score on its leaderboards
+/// review_id: Identifier of the review entry
+pub async fn approve_score(
+    req: HttpRequest,
+    review_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    let pending = take_pending(&data, &review_id)?;
+    let entry = audit_entry(&req, AuditAction::ScoreApproved, &review_id)
+        .before(&pending)
+        .after(&pending.score);
+    let rotation_hours = data.config().anonymous_id_rotation_hours;
+    data.anonymous_ids
+        .record_score(&pending.player, pending.score.score, rotation_hours);
+    let leaderboard = data.publish_score(pending.score, pending.speedrun);
+    data.audit.record(entry);
+    Ok(HttpResponse::Ok().json(leaderboard))
+}
+
+/// Reason given by a moderator for hiding or rejecting a score
+#[derive(Deserialize)]
+pub struct ModerationReason {
+    reason: String,
+}
+
+/// Keeps a held score off the leaderboards; it stays restorable among the hidden entries
+/// The reason defaults to the checks the score was held by
+/// review_id: Identifier of the review entry
+pub async fn reject_score(
+    req: HttpRequest,
+    review_id: web::Path<String>,
+    body: Option<web::Json<ModerationReason>>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    let pending = take_pending(&data, &review_id)?;
+    let entry = audit_entry(&req, AuditAction::ScoreRejected, &review_id);
+    let reason = match body {
+        Some(body) if !body.reason.trim().is_empty() => body.reason.trim().to_string(),
+        _ => {
+            let kinds: Vec<_> = pending.reasons.iter().map(Suspicion::kind).collect();
+            format!("rejected in review: {}", kinds.join(", "))
+        }
+    };
+    let hidden = HiddenScore {
+        score: pending.score.clone(),
+        reason,
+        hidden_by: entry.actor.clone(),
+        hidden_at: unix_now(),
+        placements: Vec::new(),
+        speedrun: pending.speedrun,
+        rejected: true,
+    };
+    data.audit.record(entry.before(&pending).after(&hidden));
+    data.keep_hidden(hidden);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Takes a ranked entry off every leaderboard it is on, keeping it restorable
+/// Responds with 400 Bad Request without a reason and 404 Not Found when no leaderboard
+/// holds the entry
+/// entry_id: Identifier of the leaderboard entry
+pub async fn hide_score(
+    req: HttpRequest,
+    entry_id: web::Path<String>,
+    body: web::Json<ModerationReason>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    let reason = body.reason.trim();
+    if reason.is_empty() {
+        return Err(error::ErrorBadRequest("a reason is required"));
+    }
+    let Some((score, placements, speedrun)) = data.unrank_score(&entry_id) else {
+        return Err(error::ErrorNotFound("unknown leaderboard entry"));
+    };
+    let entry = audit_entry(&req, AuditAction::ScoreHidden, &entry_id);
+    let hidden = HiddenScore {
+        score,
+        reason: reason.to_string(),
+        hidden_by: entry.actor.clone(),
+        hidden_at: unix_now(),
+        placements,
+        speedrun,
+        rejected: false,
+    };
+    data.audit
+        .record(entry.before(&hidden.score).after(&hidden));
+    data.keep_hidden(hidden.clone());
+    Ok(HttpResponse::Ok().json(hidden))
+}
+
+/// Ranks a hidden entry again: on the leaderboards it was taken off, or like an approved
+/// score when it was rejected in review
+/// Responds with 404 Not Found for entries that are not hidden
+/// entry_id: Identifier of the leaderboard entry
+pub async fn restore_score(
+    req: HttpRequest,
+    entry_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    let hidden = {
+        let mut hidden_scores = data.hidden_scores.lock().unwrap();
+        let Some(index) = hidden_scores.iter().position(|h| h.score.id == *entry_id) else {
+            return Err(error::ErrorNotFound("unknown hidden entry"));
+        };
+        hidden_scores.remove(index)
+    };
+    if hidden.rejected {
+        data.publish_score(hidden.score.clone(), hidden.speedrun);
+    } else {
+        data.rerank_score(&hidden.score, &hidden.placements, hidden.speedrun);
+    }
+    data.audit.record(
+        audit_entry(&req, AuditAction::ScoreRestored, &entry_id)
+            .before(&hidden)
+            .after(&hidden.score),
+    );
+    Ok(HttpResponse::Ok().json(hidden.score))
+}
+
+/// Lists hidden and rejected entries, most recently hidden first
+pub async fn hidden_scores(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    let hidden_scores = data.hidden_scores.lock().unwrap();
+    let newest_first: Vec<_> = hidden_scores.iter().rev().collect();
+    Ok(HttpResponse::Ok().json(newest_first))
+}
+
+/// Lists reports of offensive names and levels waiting for a moderator, oldest first
+pub async fn reports(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    let reports = data.reports.lock().unwrap();
+    Ok(HttpResponse::Ok().json(&*reports))
+}
+
+/// Finds an open report
+/// Responds with 404 Not Found for unknown reports
+fn open_report(data: &AppState, report_id: &str) -> actix_web::Result<Report> {
+    let reports = data.reports.lock().unwrap();
+    match reports.iter().find(|report| report.id == report_id) {
+        Some(report) => Ok(report.clone()),
+        None => Err(error::ErrorNotFound("unknown report")),
+    }
+}
+
+/// Takes every open report of a target out of the report queue
+fn close_reports(data: &AppState, target: &ReportTarget) -> Vec<Report> {
+    let mut reports = data.reports.lock().unwrap();
+    let (closed, open) = std::mem::take(&mut *reports)
+        .into_iter()
+        .partition(|report| report.target == *target);
+    *reports = open;
+    closed
+}
+
+/// Hides what a report flags and closes every open report of it: a reported name's entry
+/// is taken off the leaderboards like with `hide_score`, a reported level is hidden from
+/// everyone but its author and replaced when it is the level of the week
+/// The reason defaults to the one given by the reporter
+/// Responds with 404 Not Found for unknown reports and targets that are gone
+/// report_id: Identifier of the report
+pub async fn hide_reported(
+    req: HttpRequest,
+    report_id: web::Path<String>,
+    body: Option<web::Json<ModerationReason>>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    let report = open_report(&data, &report_id)?;
+    let hidden = match &report.target {
+        ReportTarget::Name { entry_id } => {
+            let Some((score, placements, speedrun)) = data.unrank_score(entry_id) else {
+                return Err(error::ErrorNotFound("unknown leaderboard entry"));
+            };
+            let reason = match body {
+                Some(body) if !body.reason.trim().is_empty() => body.reason.trim().to_string(),
+                _ if report.reason.is_empty() => "reported".to_string(),
+                _ => format!("reported: {}", report.reason),
+            };
+            let entry = audit_entry(&req, AuditAction::ScoreHidden, entry_id);
+            let hidden = HiddenScore {
+                score,
+                reason,
+                hidden_by: entry.actor.clone(),
+                hidden_at: unix_now(),
+                placements,
+                speedrun,
+                rejected: false,
+            };
+            data.audit
+                .record(entry.before(&hidden.score).after(&hidden));
+            data.keep_hidden(hidden.clone());
+            json!(hidden)
+        }
+        ReportTarget::Level { level_id } => {
+            let found = data.levels.lock().unwrap().get_mut(level_id).map(|level| {
+                level.hidden = true;
+                level.spec.name.clone()
+            });
+            let Some(name) = found else {
+                return Err(error::ErrorNotFound("unknown level"));
+            };
+            level::persist(&data, level_id).await?;
+            let featured = data.featured.lock().unwrap().clone();
+            if let Some(featured) = featured.filter(|featured| featured.level_id == *level_id) {
+                let pick = level::rotation_pick(&data.levels.lock().unwrap(), featured.week);
+                level::feature(&data, pick, featured.week, false);
+            }
+            data.audit.record(
+                audit_entry(&req, AuditAction::LevelHidden, level_id)
+                    .before(json!({ "name": name, "hidden": false }))
+                    .after(json!({ "name": name, "hidden": true })),
+            );
+            json!({ "level_id": level_id, "name": name })
+        }
+    };
+    let closed = close_reports(&data, &report.target);
+    Ok(HttpResponse::Ok().json(json!({ "hidden": hidden, "closed_reports": closed.len() })))
+}
+
+/// Closes a report without acting on what it flags
+/// Responds with 404 Not Found for unknown reports
+/// report_id: Identifier of the report
+pub async fn dismiss_report(
+    req: HttpRequest,
+    report_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    let report = {
+        let mut reports = data.reports.lock().unwrap();
+        let Some(index) = reports.iter().position(|report| report.id == *report_id) else {
+            return Err(error::ErrorNotFound("unknown report"));
+        };
+        reports.remove(index)
+    };
+    data.audit
+        .record(audit_entry(&req, AuditAction::ReportDismissed, &report_id).before(&report));
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Makes a hidden level public again
+/// Responds with 404 Not Found for levels that are not hidden
+/// level_id: Identifier of the level
+pub async fn restore_level(
+    req: HttpRequest,
+    level_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    let restored = data
+        .levels
+        .lock()
+        .unwrap()
+        .get_mut(level_id.as_str())
+        .filter(|level| level.hidden)
+        .map(|level| {
+            level.hidden = false;
+            level.spec.name.clone()
+        });
+    let Some(name) = restored else {
+        return Err(error::ErrorNotFound("unknown hidden level"));
+    };
+    level::persist(&data, &level_id).await?;
+    data.audit.record(
+        audit_entry(&req, AuditAction::LevelRestored, &level_id)
+            .before(json!({ "name": name, "hidden": true }))
+            .after(json!({ "name": name, "hidden": false })),
+    );
+    Ok(HttpResponse::Ok().json(json!({ "level_id": *level_id, "name": name })))
+}
+
+/// Exports the persistent state as a portable archive, for backups and moving to another
+/// server or storage: leaderboards with hidden entries, levels, replays, linked accounts
+/// and campaign progress
+pub async fn export_state(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    let archive = Archive::export(&data);
+    data.audit.record(
+        audit_entry(&req, AuditAction::StateExported, "state")
+            .after(json!({ "exported_at": archive.exported_at })),
+    );
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            "Content-Disposition",
+            format!(
+                "attachment; filename=\"snake-state-{}.json\"",
+                archive.exported_at
+            ),
+        ))
+        .json(archive))
+}
+
+/// Imports an archive written by `export_state`, see `Archive::restore`, and writes the
+/// imported levels to the level store
+/// Responds with 400 Bad Request for unreadable archives, those of a newer build and those
+/// with invalid levels, and 413 Payload Too Large beyond `MAX_ARCHIVE_BYTES`
+pub async fn import_state(
+    req: HttpRequest,
+    mut payload: web::Payload,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > MAX_ARCHIVE_BYTES {
+            return Err(error::ErrorPayloadTooLarge("archive is too large"));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    // Parsing a large archive is CPU-bound, keep it off the async workers
+    let archive: Archive = web::block(move || serde_json::from_slice(&body))
+        .await?
+        .map_err(error::ErrorBadRequest)?;
+    let exported_at = archive.exported_at;
+    let summary = archive.restore(&data).map_err(error::ErrorBadRequest)?;
+    for level_id in &summary.level_ids {
+        level::persist(&data, level_id).await?;
+    }
+    data.audit.record(
+        audit_entry(&req, AuditAction::StateImported, "state")
+            .before(json!({ "exported_at": exported_at }))
+            .after(&summary),
+    );
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+/// Lists recorded administrative and destructive actions, newest first
+/// Filters by `action`, `actor`, `target` and `since` (Unix seconds); `limit` defaults to 100
+pub async fn audit_log(
+    req: HttpRequest,
+    query: web::Query<AuditQuery>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    Ok(HttpResponse::Ok().json(data.audit.query(&query)))
+}
+
+/// Most games played for one training data export over HTTP; the CLI has no limit
+#[cfg(feature = "ai")]
+const MAX_TRAINING_GAMES: u32 = 20;
+
+/// Query parameters of the training data export
+#[cfg(feature = "ai")]
+#[derive(Deserialize)]
+pub struct TrainingDataQuery {
+    /// Games played, at most `MAX_TRAINING_GAMES`
+    #[serde(default = "default_training_games")]
+    games: u32,
+    /// Seed of the first game
+    #[serde(default)]
+    seed: u64,
+    #[serde(default)]
+    observation: ObservationFormat,
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[cfg(feature = "ai")]
+fn default_training_games() -> u32 {
+    5
+}
+
+/// Plays games with the strongest AI and exports its moves with the boards it saw, as
+/// JSON lines or an npz archive, for training policies by imitation
+#[cfg(feature = "ai")]
+pub async fn training_data(
+    req: HttpRequest,
+    query: web::Query<TrainingDataQuery>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    authorize(&req, &data)?;
+    let export = ExportConfig {
+        games: query.games.clamp(1, MAX_TRAINING_GAMES),
+        first_seed: query.seed,
+        width: BOARD_WIDTH,
+        height: BOARD_HEIGHT,
+        observation: query.observation,
+    };
+    let format = query.format;
+    let config = data.config().ai.clone();
+    // The games are CPU-bound, keep them off the async workers
+    let bytes = web::block(move || {
+        let mut bytes = Vec::new();
+        imitation::write(&export, &config, format, &mut bytes).map(|_| bytes)
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(match format {
+        ExportFormat::Jsonl => HttpResponse::Ok()
+            .content_type("application/x-ndjson")
+            .body(bytes),
+        ExportFormat::Npz => HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .insert_header((
+                "Content-Disposition",
+                "attachment; filename=\"training.npz\"",
+            ))
+            .body(bytes),
+    })
+}
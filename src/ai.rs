@@ -0,0 +1,483 @@
+#![deny(clippy::disallowed_types)]
+
+use crate::game::{Direction, Game, Point};
+use crate::observation::ObservationFormat;
+use crate::rng::SeededRng;
+use crate::topology::Topology;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Score of a line of play that ends with the snake dead
+const DEAD: i64 = i64::MIN;
+/// Full marks of each of the food and safety terms of a position's value
+const WEIGHT_SCALE: i64 = 1000;
+/// Most open cells counted when judging the room left to move
+const MAX_SAFETY_HORIZON: usize = 256;
+/// Deepest lookahead tried, in moves
+pub const MAX_LOOKAHEAD_DEPTH: u32 = 16;
+
+/// AI strategy steering a snake
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Moves to the free neighbor closest to the food
+    #[default]
+    Greedy,
+    /// Searches move sequences ever deeper until its budget runs out
+    Lookahead,
+    /// Plays the moves of the configured ONNX policy model
+    #[cfg(feature = "onnx")]
+    Neural,
+}
+
+impl Strategy {
+    /// Implementation of the strategy; only the lookahead has a personality
+    /// The neural strategy plays greedy when no model is loaded
+    #[cfg_attr(not(feature = "onnx"), allow(unused_variables))]
+    pub fn build(self, personality: Personality, config: &AiConfig) -> Box<dyn AiStrategy> {
+        match self {
+            Strategy::Greedy => Box::new(Greedy),
+            Strategy::Lookahead => Box::new(Lookahead { personality }),
+            #[cfg(feature = "onnx")]
+            Strategy::Neural => match &config.model.policy {
+                Some(policy) => Box::new(crate::neural::Neural {
+                    policy: policy.clone(),
+                }),
+                None => Box::new(Greedy),
+            },
+        }
+    }
+}
+
+/// Limits of a single AI decision; a decision stops at whichever limit it hits first
+/// A time limit makes decisions depend on the machine's speed, a node limit keeps them
+/// reproducible
+#[derive(Clone, Copy, Default, Debug, Deserialize)]
+#[serde(default)]
+pub struct AiBudget {
+    /// Wall-clock milliseconds a decision may take
+    pub max_millis: Option<u64>,
+    /// Positions a decision may evaluate
+    pub max_nodes: Option<u64>,
+}
+
+/// Budgets of the AI strategies, configured in the `[ai]` table
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct AiConfig {
+    pub greedy: AiBudget,
+    pub lookahead: AiBudget,
+    pub neural: AiBudget,
+    /// Policy model of the neural strategy
+    pub model: ModelConfig,
+}
+
+/// Policy model of the neural strategy, configured in the `[ai.model]` table
+/// (requires the `onnx` feature)
+#[derive(Clone, Default, Debug, Deserialize)]
+#[serde(default)]
+pub struct ModelConfig {
+    /// ONNX file of the model; the neural strategy is unavailable without one
+    pub path: Option<String>,
+    /// Observation format the model was trained on
+    pub observation: ObservationFormat,
+    /// The loaded model, see `ModelConfig::load`
+    #[cfg(feature = "onnx")]
+    #[serde(skip)]
+    pub policy: Option<std::sync::Arc<crate::neural::Policy>>,
+}
+
+impl ModelConfig {
+    /// Loads the configured model, failing with a message when it cannot be read
+    #[cfg(feature = "onnx")]
+    pub fn load(&mut self) -> Result<(), String> {
+        if let Some(path) = &self.path {
+            let policy = crate::neural::Policy::load(path, self.observation)?;
+            self.policy = Some(std::sync::Arc::new(policy));
+        }
+        Ok(())
+    }
+}
+
+impl Default for AiConfig {
+    fn default() -> Self {
+        AiConfig {
+            greedy: AiBudget::default(),
+            lookahead: AiBudget {
+                max_millis: Some(10),
+                max_nodes: Some(50_000),
+            },
+            neural: AiBudget::default(),
+            model: ModelConfig::default(),
+        }
+    }
+}
+
+impl AiConfig {
+    /// Budget of a strategy
+    pub fn budget(&self, strategy: Strategy) -> AiBudget {
+        match strategy {
+            Strategy::Greedy => self.greedy,
+            Strategy::Lookahead => self.lookahead,
+            #[cfg(feature = "onnx")]
+            Strategy::Neural => self.neural,
+        }
+    }
+}
+
+/// Spending of an `AiBudget` during one decision
+pub struct Budget {
+    deadline: Option<Instant>,
+    max_nodes: Option<u64>,
+    /// Positions evaluated so far
+    pub nodes: u64,
+    /// Whether a limit was hit
+    pub exhausted: bool,
+}
+
+impl Budget {
+    /// Starts spending a budget now
+    pub fn start(budget: AiBudget) -> Self {
+        Budget {
+            deadline: budget
+                .max_millis
+                .map(|millis| Instant::now() + Duration::from_millis(millis)),
+            max_nodes: budget.max_nodes,
+            nodes: 0,
+            exhausted: false,
+        }
+    }
+
+    /// Budget without limits
+    pub fn unlimited() -> Self {
+        Budget::start(AiBudget::default())
+    }
+
+    /// Pays for evaluating one position
+    /// Returns false once a limit is hit; strategies then settle for their best move so far
+    pub fn spend(&mut self) -> bool {
+        if self.exhausted {
+            return false;
+        }
+        let out_of_nodes = self.max_nodes.is_some_and(|max| self.nodes >= max);
+        let out_of_time = self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline);
+        if out_of_nodes || out_of_time {
+            self.exhausted = true;
+            return false;
+        }
+        self.nodes += 1;
+        true
+    }
+}
+
+/// Way of choosing the next move of a game's snake
+pub trait AiStrategy: Send + Sync {
+    /// Picks the direction to move in, or None when every move is fatal
+    /// Implementations must call `budget.spend()` for every position they evaluate and
+    /// return their best move so far once it fails
+    fn decide(&self, game: &Game, budget: &mut Budget) -> Option<Direction>;
+}
+
+/// Outcome of an AI decision
+pub struct Decision {
+    pub direction: Option<Direction>,
+    /// Positions evaluated
+    pub nodes: u64,
+    /// Whether the decision was cut short by its budget
+    pub exhausted: bool,
+    pub elapsed: Duration,
+}
+
+/// Runs a strategy with a personality on a game within the strategy's configured budget
+pub fn decide(
+    game: &Game,
+    strategy: Strategy,
+    personality: Personality,
+    config: &AiConfig,
+) -> Decision {
+    let start = Instant::now();
+    let mut budget = Budget::start(config.budget(strategy));
+    let direction = strategy
+        .build(personality, config)
+        .decide(game, &mut budget);
+    Decision {
+        direction,
+        nodes: budget.nodes,
+        exhausted: budget.exhausted,
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Moves to the free neighbor closest to the food, as the board measures distance
+pub struct Greedy;
+
+impl AiStrategy for Greedy {
+    fn decide(&self, game: &Game, budget: &mut Budget) -> Option<Direction> {
+        let head = *game.snake.front()?;
+        let mut best = None;
+        for direction in game.topology.directions() {
+            if !budget.spend() {
+                break;
+            }
+            let point = game.step(head, *direction);
+            if game.blocks(point, *direction) || game.on_snake(point) || game.hazard_ahead(point) {
+                continue;
+            }
+            let distance = game.distance(point, game.food);
+            if best.is_none_or(|(_, closest)| distance < closest) {
+                best = Some((*direction, distance));
+            }
+        }
+        best.map(|(direction, _)| direction)
+    }
+}
+
+/// Tunable behavior of the lookahead AI, chosen per game
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Personality {
+    /// Weight of reaching food against keeping room to move, from 0 (only safety) to
+    /// 100 (only food)
+    pub aggression: u32,
+    /// Randomness of the choice between moves, from 0 (always the best move) to 100
+    pub temperature: u32,
+    /// Moves searched ahead, from 1 to `MAX_LOOKAHEAD_DEPTH`
+    pub depth: u32,
+}
+
+impl Default for Personality {
+    fn default() -> Self {
+        Personality {
+            aggression: 100,
+            temperature: 0,
+            depth: MAX_LOOKAHEAD_DEPTH,
+        }
+    }
+}
+
+impl Personality {
+    /// Fails with a message for parameters out of their range
+    pub fn validate(&self) -> Result<(), String> {
+        if self.aggression > 100 || self.temperature > 100 {
+            return Err("aggression and temperature must be between 0 and 100".to_string());
+        }
+        if !(1..=MAX_LOOKAHEAD_DEPTH).contains(&self.depth) {
+            return Err(format!(
+                "lookahead depth must be between 1 and {}",
+                MAX_LOOKAHEAD_DEPTH
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Value of a line of play and whether it reaches the food
+#[derive(Clone, Copy)]
+struct Eval {
+    score: i64,
+    reached_food: bool,
+}
+
+/// Depth-first search over move sequences, deepened one move at a time while the budget
+/// lasts; the moves of the deepest completed search are played
+/// Positions are valued by closeness to the food and by the room left to move, weighted
+/// by the personality's aggression; its temperature makes worse moves more likely
+/// The search follows the snake's body but not food respawns, so reaching the food ends
+/// a line of play
+pub struct Lookahead {
+    pub personality: Personality,
+}
+
+impl Lookahead {
+    /// Value of a position between 0 and `WEIGHT_SCALE * 100`, or None when the budget ran
+    /// out
+    fn evaluate(
+        &self,
+        game: &Game,
+        snake: &VecDeque<Point>,
+        moves: u32,
+        budget: &mut Budget,
+    ) -> Option<Eval> {
+        let head = *snake.front().expect("snakes are never empty");
+        let reached_food = head == game.food;
+        let food = if reached_food {
+            WEIGHT_SCALE - moves as i64
+        } else {
+            let cells = (game.distance(head, game.food).to_bits() >> 16) as i64;
+            (WEIGHT_SCALE / 2 - cells).max(0)
+        };
+        let aggression = self.personality.aggression as i64;
+        let safety = if aggression < 100 {
+            let horizon = (2 * snake.len() + 8).min(MAX_SAFETY_HORIZON);
+            let room = reachable_cells(game, snake, horizon, budget)?;
+            room as i64 * WEIGHT_SCALE / horizon as i64
+        } else {
+            0
+        };
+        Some(Eval {
+            score: aggression * food + (100 - aggression) * safety,
+            reached_food,
+        })
+    }
+
+    /// Best line of play from a position within `depth` more moves, or None when the
+    /// budget ran out
+    fn search(
+        &self,
+        game: &Game,
+        snake: &mut VecDeque<Point>,
+        moves: u32,
+        depth: u32,
+        budget: &mut Budget,
+    ) -> Option<Eval> {
+        if !budget.spend() {
+            return None;
+        }
+        let head = *snake.front().expect("snakes are never empty");
+        if depth == 0 || head == game.food {
+            return self.evaluate(game, snake, moves, budget);
+        }
+        let mut best = Eval {
+            score: DEAD,
+            reached_food: false,
+        };
+        for direction in game.topology.directions() {
+            let next = game.step(head, *direction);
+            if game.blocks(next, *direction) || snake.contains(&next) || game.on_partner(next) {
+                continue;
+            }
+            snake.push_front(next);
+            let tail = snake.pop_back();
+            let eval = self.search(game, snake, moves + 1, depth - 1, budget);
+            snake.extend(tail);
+            snake.pop_front();
+            let eval = eval?;
+            if eval.score > best.score {
+                best = eval;
+            }
+        }
+        Some(best)
+    }
+
+    /// Picks one of the scored moves: the best one, or at a temperature above zero a
+    /// random one, each half as likely per `2 * temperature` points it is worse
+    /// The draw is seeded from the game's seed and tick, so it is reproducible
+    fn choose(&self, game: &Game, scored: &[(Direction, Eval)]) -> Option<Direction> {
+        let (best, top) = scored
+            .iter()
+            .max_by_key(|(_, eval)| eval.score)
+            .map(|(direction, eval)| (*direction, eval.score))?;
+        let temperature = self.personality.temperature as i64;
+        if temperature == 0 || top == DEAD {
+            return Some(best);
+        }
+        let weights: Vec<u64> = scored
+            .iter()
+            .map(|(_, eval)| match eval.score {
+                DEAD => 0,
+                score => halve((top - score) * 16 / (2 * temperature)),
+            })
+            .collect();
+        let mut rng = SeededRng::new(game.seed ^ game.tick.rotate_left(32));
+        let mut pick = rng.next_u64() % weights.iter().sum::<u64>();
+        for ((direction, _), weight) in scored.iter().zip(weights) {
+            if pick < weight {
+                return Some(*direction);
+            }
+            pick -= weight;
+        }
+        Some(best)
+    }
+}
+
+/// `2^32` halved `sixteenths / 16` times, to 16 significant bits and at least 1
+fn halve(sixteenths: i64) -> u64 {
+    // 2^(-i/16) in 16 fractional bits
+    const STEPS: [u64; 16] = [
+        65536, 62757, 60097, 57549, 55109, 52773, 50535, 48393, 46341, 44376, 42495, 40693, 38968,
+        37316, 35734, 34219,
+    ];
+    let whole = (sixteenths / 16).min(48) as u32;
+    ((STEPS[(sixteenths % 16) as usize] << 16) >> whole).max(1)
+}
+
+/// Number of open cells the head can reach, counting up to `horizon`, or None when the
+/// budget ran out
+/// Body cells count as blocked, so the estimate is cautious
+fn reachable_cells(
+    game: &Game,
+    snake: &VecDeque<Point>,
+    horizon: usize,
+    budget: &mut Budget,
+) -> Option<usize> {
+    let head = *snake.front().expect("snakes are never empty");
+    let mut seen = vec![head];
+    let mut frontier = VecDeque::from([head]);
+    while let Some(point) = frontier.pop_front() {
+        for direction in game.topology.directions() {
+            let next = game.step(point, *direction);
+            if game.blocks(next, *direction)
+                || snake.contains(&next)
+                || game.on_partner(next)
+                || seen.contains(&next)
+            {
+                continue;
+            }
+            if !budget.spend() {
+                return None;
+            }
+            if seen.len() > horizon {
+                return Some(horizon);
+            }
+            seen.push(next);
+            frontier.push_back(next);
+        }
+    }
+    Some((seen.len() - 1).min(horizon))
+}
+
+impl AiStrategy for Lookahead {
+    fn decide(&self, game: &Game, budget: &mut Budget) -> Option<Direction> {
+        let head = *game.snake.front()?;
+        let mut snake = game.snake.clone();
+        let mut chosen = None;
+        for depth in 0..self.personality.depth {
+            let mut scored = Vec::new();
+            for direction in game.topology.directions() {
+                let next = game.step(head, *direction);
+                // Hazards move on, so only the first move avoids them
+                if game.blocks(next, *direction)
+                    || snake.contains(&next)
+                    || game.on_partner(next)
+                    || game.hazard_ahead(next)
+                {
+                    continue;
+                }
+                snake.push_front(next);
+                let tail = snake.pop_back();
+                let eval = self.search(game, &mut snake, 1, depth, budget);
+                snake.extend(tail);
+                snake.pop_front();
+                match eval {
+                    Some(eval) => scored.push((*direction, eval)),
+                    // Out of budget: keep the moves of the last completed depth
+                    None => return chosen.or_else(|| self.choose(game, &scored)),
+                }
+            }
+            let Some(direction) = self.choose(game, &scored) else {
+                break;
+            };
+            chosen = Some(direction);
+            let best = scored.iter().max_by_key(|(_, eval)| eval.score);
+            if best.is_some_and(|(_, eval)| eval.reached_food) && self.personality.aggression == 100
+            {
+                // The shortest way to the food is found; deeper searches agree
+                break;
+            }
+        }
+        chosen
+    }
+}
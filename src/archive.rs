@@ -0,0 +1,248 @@
+use crate::campaign::Progress;
+use crate::leaderboard::{ClosedWindow, OpenWindow, Window};
+use crate::level::{self, Featured, Level};
+use crate::migration;
+use crate::moderation::HiddenScore;
+use crate::replay::Replay;
+use crate::server::{AppState, Score, SpeedRun};
+use crate::signer::unix_now;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+/// Version of the archive format written by this build
+pub const ARCHIVE_VERSION: u32 = 1;
+/// Largest archive accepted for import, in bytes
+pub const MAX_ARCHIVE_BYTES: usize = 256 * 1024 * 1024;
+
+/// Leaderboards with the moderation state of their entries
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Leaderboards {
+    all_time: Vec<Score>,
+    windows: HashMap<Window, OpenWindow>,
+    closed_windows: HashMap<Window, Vec<ClosedWindow>>,
+    // Map of level IDs to the leaderboards of their levels
+    levels: HashMap<String, Vec<Score>>,
+    speedruns: Vec<SpeedRun>,
+    featured: Option<Featured>,
+    featured_archive: Vec<Featured>,
+    hidden: Vec<HiddenScore>,
+    // Map of normalized leaderboard names to the client that first used them
+    name_owners: HashMap<String, String>,
+}
+
+/// Replay with the client of its player, which replays never serialize themselves
+#[derive(Serialize, Deserialize)]
+struct ArchivedReplay {
+    // Serialized replay, upgraded to the current schema on import
+    replay: Value,
+    owner: Option<String>,
+}
+
+/// Portable copy of the persistent server state: leaderboards, levels, replays, linked
+/// accounts and campaign progress
+/// Holds the client IDs players sign in with, so it must be kept as secret as the admin token
+#[derive(Serialize, Deserialize)]
+pub struct Archive {
+    /// Archive format version, see `ARCHIVE_VERSION`
+    pub version: u32,
+    /// Unix timestamp (seconds) of the export
+    pub exported_at: u64,
+    #[serde(default)]
+    leaderboards: Leaderboards,
+    #[serde(default)]
+    levels: Vec<Level>,
+    #[serde(default)]
+    replays: Vec<ArchivedReplay>,
+    /// Linked accounts by "provider:user ID"; empty when built without the `oauth` feature
+    #[serde(default)]
+    accounts: HashMap<String, Value>,
+    /// Campaign progress by client ID
+    #[serde(default)]
+    campaigns: HashMap<String, Progress>,
+}
+
+/// What an import took over
+#[derive(Default, Serialize)]
+pub struct ImportSummary {
+    pub levels: usize,
+    pub replays: usize,
+    pub accounts: usize,
+    pub campaigns: usize,
+    /// Replays and accounts left out, with the reasons
+    pub skipped: Vec<String>,
+    /// Identifiers of the imported levels, to be written to the level store
+    #[serde(skip)]
+    pub(crate) level_ids: Vec<String>,
+}
+
+impl Archive {
+    /// Copies the persistent state of a running server
+    pub fn export(data: &AppState) -> Self {
+        let leaderboards = Leaderboards {
+            all_time: data.leaderboard.lock().unwrap().clone(),
+            windows: data.windows.lock().unwrap().clone(),
+            closed_windows: data.closed_windows.lock().unwrap().clone(),
+            levels: data.level_leaderboards.lock().unwrap().clone(),
+            speedruns: data.speedruns.lock().unwrap().clone(),
+            featured: data.featured.lock().unwrap().clone(),
+            featured_archive: data.featured_archive.lock().unwrap().clone(),
+            hidden: data.hidden_scores.lock().unwrap().clone(),
+            name_owners: data.name_owners.lock().unwrap().clone(),
+        };
+        let mut levels: Vec<Level> = data.levels.lock().unwrap().values().cloned().collect();
+        levels.sort_by(|a, b| a.id.cmp(&b.id));
+        let mut replays: Vec<ArchivedReplay> = data
+            .replays
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|replay| {
+                Some(ArchivedReplay {
+                    replay: serde_json::to_value(replay).ok()?,
+                    owner: replay.owner.clone(),
+                })
+            })
+            .collect();
+        replays.sort_by(|a, b| a.replay["id"].as_str().cmp(&b.replay["id"].as_str()));
+        #[cfg(feature = "oauth")]
+        let accounts = data
+            .oauth
+            .accounts()
+            .into_iter()
+            .filter_map(|(key, account)| Some((key, serde_json::to_value(account).ok()?)))
+            .collect();
+        #[cfg(not(feature = "oauth"))]
+        let accounts = HashMap::new();
+        Archive {
+            version: ARCHIVE_VERSION,
+            exported_at: unix_now(),
+            leaderboards,
+            levels,
+            replays,
+            accounts,
+            campaigns: data.campaigns.all(),
+        }
+    }
+
+    /// Restores the archive into a running server
+    /// The leaderboards are replaced as a whole; levels, replays, accounts and campaign
+    /// progress are merged, replacing those with the same ID
+    /// Windows and rotations that ended since the export are closed right away
+    /// Fails for archives written by a newer build and archives with invalid levels, before
+    /// changing anything
+    pub(crate) fn restore(self, data: &AppState) -> Result<ImportSummary, String> {
+        if self.version > ARCHIVE_VERSION {
+            return Err(format!(
+                "archive version {} is newer than supported version {}",
+                self.version, ARCHIVE_VERSION
+            ));
+        }
+        self.validate_levels()?;
+        let mut summary = ImportSummary::default();
+
+        let mut replays = Vec::new();
+        for archived in self.replays {
+            let id = archived.replay["id"].as_str().unwrap_or("?").to_string();
+            let upgraded = migration::upgrade(archived.replay).and_then(|value| {
+                serde_json::from_value::<Replay>(value).map_err(|e| e.to_string())
+            });
+            match upgraded {
+                Ok(mut replay) => {
+                    replay.owner = archived.owner;
+                    replays.push(replay);
+                }
+                Err(e) => summary.skipped.push(format!("replay {}: {}", id, e)),
+            }
+        }
+        summary.replays = replays.len();
+        let mut stored = data.replays.lock().unwrap();
+        stored.extend(
+            replays
+                .into_iter()
+                .map(|replay| (replay.id.clone(), replay)),
+        );
+        drop(stored);
+
+        #[cfg(feature = "oauth")]
+        {
+            let mut accounts = HashMap::new();
+            for (key, account) in self.accounts {
+                match serde_json::from_value(account) {
+                    Ok(account) => {
+                        accounts.insert(key, account);
+                    }
+                    Err(e) => summary.skipped.push(format!("account {}: {}", key, e)),
+                }
+            }
+            summary.accounts = accounts.len();
+            if let Err(e) = data.oauth.import_accounts(accounts) {
+                tracing::error!("cannot save imported accounts: {}", e);
+            }
+        }
+        #[cfg(not(feature = "oauth"))]
+        if !self.accounts.is_empty() {
+            let skipped = format!(
+                "{} accounts: built without the oauth feature",
+                self.accounts.len()
+            );
+            summary.skipped.push(skipped);
+        }
+
+        summary.campaigns = self.campaigns.len();
+        data.campaigns.import(self.campaigns);
+
+        summary.levels = self.levels.len();
+        summary.level_ids = self.levels.iter().map(|level| level.id.clone()).collect();
+        let mut levels = data.levels.lock().unwrap();
+        levels.extend(
+            self.levels
+                .into_iter()
+                .map(|level| (level.id.clone(), level)),
+        );
+        drop(levels);
+
+        let Leaderboards {
+            all_time,
+            windows,
+            closed_windows,
+            levels,
+            speedruns,
+            featured,
+            featured_archive,
+            hidden,
+            name_owners,
+        } = self.leaderboards;
+        *data.leaderboard.lock().unwrap() = all_time;
+        *data.windows.lock().unwrap() = windows;
+        *data.closed_windows.lock().unwrap() = closed_windows;
+        *data.level_leaderboards.lock().unwrap() = levels;
+        *data.speedruns.lock().unwrap() = speedruns;
+        *data.featured.lock().unwrap() = featured;
+        *data.featured_archive.lock().unwrap() = featured_archive;
+        *data.hidden_scores.lock().unwrap() = hidden;
+        *data.name_owners.lock().unwrap() = name_owners;
+        let now = SystemTime::now();
+        data.close_windows(now);
+        level::rotate_featured(data, now);
+        Ok(summary)
+    }
+
+    /// Checks the levels as the level editor does, and that their IDs are UUIDs as the
+    /// server issues them, since the IDs name the files of the level store
+    fn validate_levels(&self) -> Result<(), String> {
+        for level in &self.levels {
+            if !Uuid::parse_str(&level.id).is_ok_and(|uuid| uuid.to_string() == level.id) {
+                return Err(format!("invalid level ID {:?}", level.id));
+            }
+            level
+                .spec
+                .validate()
+                .map_err(|e| format!("level {}: {}", level.id, e))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+use crate::signer::unix_now;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Number of most recent entries kept in memory and served by the admin API
+const MAX_ENTRIES: usize = 10_000;
+/// Entries returned by a query without a limit
+const DEFAULT_LIMIT: usize = 100;
+
+/// Administrative or destructive action recorded in the audit log
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// An abandoned game was removed with its checkpoint
+    GameDeleted,
+    /// A held score was ranked on its leaderboards
+    ScoreApproved,
+    /// A held score was discarded; it is kept hidden and can be restored
+    ScoreRejected,
+    /// A ranked entry was taken off the leaderboards
+    ScoreHidden,
+    /// A hidden or rejected entry was ranked again
+    ScoreRestored,
+    /// A level was made the level of the week
+    LevelFeatured,
+    /// A reported level was hidden from everyone but its author
+    LevelHidden,
+    /// A hidden level was made public again
+    LevelRestored,
+    /// A report was closed without action
+    ReportDismissed,
+    /// The persistent state was exported as an archive
+    StateExported,
+    /// An archive was imported into the persistent state
+    StateImported,
+}
+
+/// One recorded action with the state it changed
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: String,
+    /// Unix timestamp (seconds) of the action
+    pub at: u64,
+    /// Who acted: `admin`, `admin:<name>` or `system:<job>`
+    pub actor: String,
+    pub action: AuditAction,
+    /// Identifier of the game, review entry or level acted on
+    pub target: String,
+    /// Snapshot of the target before the action, if it existed
+    pub before: Option<Value>,
+    /// Snapshot of the target after the action, if it still exists
+    pub after: Option<Value>,
+    /// Request that triggered the action, for matching with the access log
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl AuditEntry {
+    /// Entry for an action taken now
+    pub fn new(actor: impl Into<String>, action: AuditAction, target: impl Into<String>) -> Self {
+        AuditEntry {
+            id: Uuid::new_v4().to_string(),
+            at: unix_now(),
+            actor: actor.into(),
+            action,
+            target: target.into(),
+            before: None,
+            after: None,
+            request_id: None,
+        }
+    }
+
+    /// Records the target's state before the action
+    pub fn before(mut self, snapshot: impl Serialize) -> Self {
+        self.before = serde_json::to_value(snapshot).ok();
+        self
+    }
+
+    /// Records the target's state after the action
+    pub fn after(mut self, snapshot: impl Serialize) -> Self {
+        self.after = serde_json::to_value(snapshot).ok();
+        self
+    }
+
+    /// Records the request that triggered the action
+    pub fn request_id(mut self, request_id: Option<String>) -> Self {
+        self.request_id = request_id;
+        self
+    }
+}
+
+/// Filters of an audit log query; all are optional
+#[derive(Default, Deserialize)]
+pub struct AuditQuery {
+    pub action: Option<AuditAction>,
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    /// Only entries at or after this Unix timestamp (seconds)
+    pub since: Option<u64>,
+    /// Maximum number of entries, newest first (default 100)
+    pub limit: Option<usize>,
+}
+
+/// Append-only log of administrative and destructive actions
+/// Entries are appended to the configured file as JSON lines, so the log outlives restarts
+pub struct AuditLog {
+    // Most recent entries, oldest first
+    entries: Mutex<VecDeque<AuditEntry>>,
+    // File the entries are appended to, if configured
+    file: Option<PathBuf>,
+}
+
+impl AuditLog {
+    /// Loads the most recent entries of the configured file
+    /// A missing file starts an empty log; unreadable lines are logged and skipped
+    pub fn open(file: Option<&str>) -> Self {
+        let file = file.map(PathBuf::from);
+        let mut entries = VecDeque::new();
+        match file.as_deref().map(fs::read_to_string) {
+            Some(Ok(contents)) => {
+                for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+                    match serde_json::from_str(line) {
+                        Ok(entry) => entries.push_back(entry),
+                        Err(e) => tracing::error!("skipping unreadable audit entry: {}", e),
+                    }
+                }
+                let excess = entries.len().saturating_sub(MAX_ENTRIES);
+                entries.drain(..excess);
+            }
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => {
+                tracing::error!("cannot read audit log: {}", e);
+            }
+            _ => {}
+        }
+        AuditLog {
+            entries: Mutex::new(entries),
+            file,
+        }
+    }
+
+    /// Appends an entry to the file, if configured
+    fn append(&self, entry: &AuditEntry) -> io::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(&line)
+    }
+
+    /// Records an action
+    /// Failing to write the file is logged; the entry is still kept in memory
+    pub fn record(&self, entry: AuditEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Err(e) = self.append(&entry) {
+            tracing::error!(action = ?entry.action, "cannot write audit log: {}", e);
+        }
+        tracing::info!(actor = %entry.actor, action = ?entry.action, target = %entry.target, "audit");
+        entries.push_back(entry);
+        if entries.len() > MAX_ENTRIES {
+            entries.pop_front();
+        }
+    }
+
+    /// Entries matching the query, newest first
+    pub fn query(&self, query: &AuditQuery) -> Vec<AuditEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .rev()
+            .filter(|entry| query.action.is_none_or(|action| entry.action == action))
+            .filter(|entry| {
+                query
+                    .actor
+                    .as_ref()
+                    .is_none_or(|actor| &entry.actor == actor)
+            })
+            .filter(|entry| {
+                query
+                    .target
+                    .as_ref()
+                    .is_none_or(|target| &entry.target == target)
+            })
+            .filter(|entry| query.since.is_none_or(|since| entry.at >= since))
+            .take(query.limit.unwrap_or(DEFAULT_LIMIT))
+            .cloned()
+            .collect()
+    }
+}
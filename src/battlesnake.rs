@@ -0,0 +1,320 @@
+use crate::ai::{self, Personality, Strategy};
+use crate::game::{Direction, Game, Point};
+use crate::room::{self, PassQuery};
+use crate::server::AppState;
+use crate::topology::{Board, ObstacleGrid, Wrap};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Version of the Battlesnake API spoken by the adapter
+const API_VERSION: &str = "1";
+/// Health reported for the engine's snakes, which never starve
+const FULL_HEALTH: u32 = 100;
+/// Time a Battlesnake engine gives a bot to answer a move, in milliseconds
+const DEFAULT_TIMEOUT_MS: u32 = 500;
+/// Largest width and height of a board the bot plays on
+pub const MAX_BOARD: i32 = 64;
+
+/// Cell of a Battlesnake board; `y` grows upwards, unlike the engine's rows
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Coord {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Snake as described by the Battlesnake API
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Battlesnake {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub health: u32,
+    /// Cells from head to tail
+    pub body: Vec<Coord>,
+    pub head: Coord,
+    #[serde(default)]
+    pub length: u32,
+    #[serde(default)]
+    pub latency: String,
+    #[serde(default)]
+    pub shout: String,
+    #[serde(default)]
+    pub squad: String,
+}
+
+/// Board as described by the Battlesnake API
+#[derive(Serialize, Deserialize)]
+pub struct BattlesnakeBoard {
+    pub width: i32,
+    pub height: i32,
+    #[serde(default)]
+    pub food: Vec<Coord>,
+    /// Cells that hurt snakes passing through
+    #[serde(default)]
+    pub hazards: Vec<Coord>,
+    #[serde(default)]
+    pub snakes: Vec<Battlesnake>,
+}
+
+/// Rules a Battlesnake game is played by, e.g. `solo`, `standard` or `wrapped`
+#[derive(Serialize, Deserialize)]
+pub struct Ruleset {
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+}
+
+/// Battlesnake game settings
+#[derive(Serialize, Deserialize)]
+pub struct GameInfo {
+    pub id: String,
+    pub ruleset: Ruleset,
+    #[serde(default)]
+    pub map: String,
+    /// Milliseconds a bot has to answer a move
+    #[serde(default = "default_timeout")]
+    pub timeout: u32,
+    #[serde(default)]
+    pub source: String,
+}
+
+fn default_timeout() -> u32 {
+    DEFAULT_TIMEOUT_MS
+}
+
+/// Body of the Battlesnake `/start`, `/move` and `/end` requests
+#[derive(Serialize, Deserialize)]
+pub struct GameState {
+    pub game: GameInfo,
+    pub turn: u64,
+    pub board: BattlesnakeBoard,
+    /// The snake the request is addressed to
+    pub you: Battlesnake,
+}
+
+/// Battlesnake move names of the four square directions
+fn move_name(direction: Direction) -> Option<&'static str> {
+    match direction {
+        Direction::Up => Some("up"),
+        Direction::Down => Some("down"),
+        Direction::Left => Some("left"),
+        Direction::Right => Some("right"),
+        Direction::UpLeft | Direction::UpRight | Direction::DownLeft | Direction::DownRight => None,
+    }
+}
+
+impl GameState {
+    /// Battlesnake view of a game, with walls inside the board shown as hazards
+    /// Returns None for hex and layered boards, which the API cannot describe
+    pub fn from_game(id: &str, game: &Game) -> Option<GameState> {
+        let ruleset = match &game.topology {
+            Board::Wrap(_) => "wrapped",
+            Board::Solid(_) | Board::Obstacles(_) => "solo",
+            Board::Hex(_) | Board::Layered(_) => return None,
+        };
+        let coord = |point: &Point| Coord {
+            x: point.x,
+            y: game.height - 1 - point.y,
+        };
+        let body: Vec<Coord> = game.snake.iter().map(coord).collect();
+        let you = Battlesnake {
+            id: id.to_string(),
+            name: "player".to_string(),
+            health: FULL_HEALTH,
+            head: *body.first()?,
+            length: body.len() as u32,
+            body,
+            latency: String::new(),
+            shout: String::new(),
+            squad: String::new(),
+        };
+        Some(GameState {
+            game: GameInfo {
+                id: id.to_string(),
+                ruleset: Ruleset {
+                    name: ruleset.to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                },
+                map: "standard".to_string(),
+                timeout: DEFAULT_TIMEOUT_MS,
+                source: "custom".to_string(),
+            },
+            turn: game.tick,
+            board: BattlesnakeBoard {
+                width: game.width,
+                height: game.height,
+                food: vec![coord(&game.food)],
+                hazards: game.topology.inner_walls().iter().map(coord).collect(),
+                snakes: vec![you.clone()],
+            },
+            you,
+        })
+    }
+
+    /// Checks that the board is at most `MAX_BOARD` cells wide and high and that every cell
+    /// given lies on it, so no request makes the engine build a huge or broken board
+    pub fn validate(&self) -> Result<(), String> {
+        let (width, height) = (self.board.width, self.board.height);
+        if !(1..=MAX_BOARD).contains(&width) || !(1..=MAX_BOARD).contains(&height) {
+            return Err(format!(
+                "board width and height must be between 1 and {}",
+                MAX_BOARD
+            ));
+        }
+        let on_board =
+            |coord: &Coord| (0..width).contains(&coord.x) && (0..height).contains(&coord.y);
+        let cells = self
+            .board
+            .snakes
+            .iter()
+            .chain([&self.you])
+            .flat_map(|snake| &snake.body)
+            .chain(&self.board.food)
+            .chain(&self.board.hazards);
+        for cell in cells {
+            if !on_board(cell) {
+                return Err(format!("cell ({}, {}) is off the board", cell.x, cell.y));
+            }
+        }
+        Ok(())
+    }
+
+    /// Engine game steering the `you` snake, or None when it has no body
+    /// The state must have passed `validate`
+    /// The engine has one snake and one food item: the other snakes and the hazards
+    /// become walls and the food closest to the head is the target; on wrapped boards,
+    /// which have no walls, other snakes and hazards are left out
+    pub fn to_game(&self) -> Option<Game> {
+        let (width, height) = (self.board.width, self.board.height);
+        let point = |coord: &Coord| Point {
+            x: coord.x,
+            y: height - 1 - coord.y,
+            z: 0,
+        };
+        let topology = if self.game.ruleset.name == "wrapped" {
+            Board::Wrap(Wrap { width, height })
+        } else {
+            let mut walls: Vec<Point> = self
+                .board
+                .snakes
+                .iter()
+                .filter(|snake| snake.id != self.you.id)
+                .flat_map(|snake| &snake.body)
+                .chain(&self.board.hazards)
+                .map(point)
+                .collect();
+            walls.sort_by_key(|wall| (wall.y, wall.x));
+            walls.dedup();
+            Board::Obstacles(ObstacleGrid {
+                width,
+                height,
+                walls,
+            })
+        };
+        let mut game = Game::with_topology(topology, self.turn);
+        game.snake = self.you.body.iter().map(point).collect();
+        let head = *game.snake.front()?;
+        if let Some(food) = self
+            .board
+            .food
+            .iter()
+            .map(point)
+            .min_by_key(|food| (food.x - head.x).abs() + (food.y - head.y).abs())
+        {
+            game.food = food;
+        }
+        game.direction = match game
+            .snake
+            .get(1)
+            .map(|neck| (head.x - neck.x, head.y - neck.y))
+        {
+            Some((0, 1)) => Direction::Down,
+            Some((-1, 0)) => Direction::Left,
+            Some((1, 0)) => Direction::Right,
+            _ => Direction::Up,
+        };
+        game.tick = self.turn;
+        Some(game)
+    }
+}
+
+/// Reports the state of a game in the Battlesnake API format
+/// Responds with 422 Unprocessable Entity for hex and layered boards; private rooms are
+/// checked as for the other live game endpoints, see `room::check_watcher`
+/// session_id: Unique identifier for the game instance
+pub async fn game_state(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    pass: web::Query<PassQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let session_id = session_id.into_inner();
+    let sessions = data.sessions.lock().unwrap();
+    let Some(session) = sessions.get(&session_id) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if let Some(rejection) = room::check_watcher(&req, session, &pass) {
+        return rejection;
+    }
+    match GameState::from_game(&session_id, &session.game) {
+        Some(state) => HttpResponse::Ok().json(state),
+        None => HttpResponse::UnprocessableEntity()
+            .json(json!({ "error": "the board cannot be described in the Battlesnake API" })),
+    }
+}
+
+/// Battlesnake bot metadata, served at the bot's root URL
+pub async fn info() -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "apiversion": API_VERSION,
+        "author": "rust-snake-game",
+        "color": "#4caf50",
+        "head": "default",
+        "tail": "default",
+        "version": env!("CARGO_PKG_VERSION"),
+    }))
+}
+
+/// Acknowledges the start or end of a Battlesnake game; the bot keeps no state
+pub async fn acknowledge(_state: web::Json<GameState>) -> HttpResponse {
+    HttpResponse::Ok().finish()
+}
+
+/// Answers a Battlesnake move request with the lookahead AI's move
+/// Responds with 400 Bad Request for boards larger than `MAX_BOARD`, cells off the board
+/// and snakes without body
+/// The board is built and the AI decides within its configured budget on the blocking
+/// thread pool once an AI slot is free; when every move is fatal it keeps going straight
+pub async fn battlesnake_move(
+    state: web::Json<GameState>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    if let Err(e) = state.validate() {
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": e })));
+    }
+    let state = state.into_inner();
+    let config = data.config().ai.clone();
+    let _slot = data.fairness.ai_slot().await;
+    let decision = web::block(move || {
+        let game = state.to_game()?;
+        let decision = ai::decide(&game, Strategy::Lookahead, Personality::default(), &config);
+        Some(decision.direction.unwrap_or(game.direction))
+    })
+    .await?;
+    let Some(decision) = decision else {
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": "the snake has no body" })));
+    };
+    Ok(HttpResponse::Ok().json(json!({ "move": move_name(decision).unwrap_or("up") })))
+}
+
+/// Registers the Battlesnake routes: the state of a game, and a Battlesnake bot played
+/// by the AI under `/battlesnake`
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/game/{session_id}/battlesnake", web::get().to(game_state))
+        .route("/battlesnake", web::get().to(info))
+        .route("/battlesnake/start", web::post().to(acknowledge))
+        .route("/battlesnake/move", web::post().to(battlesnake_move))
+        .route("/battlesnake/end", web::post().to(acknowledge));
+}
@@ -0,0 +1,199 @@
+use crate::game::{Direction, Game, Point};
+use crate::lock_bench;
+use std::collections::VecDeque;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Engine operation measured by a benchmark scenario
+#[derive(Clone, Copy)]
+pub enum Workload {
+    /// A single `Game::update` step
+    Tick,
+    /// A single `Game::spawn_food` call
+    SpawnFood,
+    /// A single `Game::ai_move` decision
+    AiMove,
+}
+
+/// A named engine micro-benchmark
+/// Shared by the criterion benches and the `bench` CLI subcommand
+pub struct Scenario {
+    pub name: &'static str,
+    pub workload: Workload,
+    pub width: i32,
+    pub height: i32,
+    pub snake_len: usize,
+}
+
+impl Scenario {
+    /// Builds the game the workload runs against
+    pub fn setup(&self) -> Game {
+        board_with_snake(self.width, self.height, self.snake_len)
+    }
+
+    /// Runs the workload once against a prepared game
+    pub fn run(&self, game: &mut Game) {
+        match self.workload {
+            Workload::Tick => {
+                game.update();
+            }
+            Workload::SpawnFood => game.spawn_food(),
+            Workload::AiMove => game.ai_move(),
+        }
+    }
+}
+
+/// Returns all engine scenarios
+pub fn scenarios() -> Vec<Scenario> {
+    vec![
+        Scenario {
+            name: "tick/short_snake",
+            workload: Workload::Tick,
+            width: 20,
+            height: 20,
+            snake_len: 3,
+        },
+        Scenario {
+            name: "tick/long_snake",
+            workload: Workload::Tick,
+            width: 20,
+            height: 20,
+            snake_len: 300,
+        },
+        Scenario {
+            name: "spawn_food/empty_board",
+            workload: Workload::SpawnFood,
+            width: 20,
+            height: 20,
+            snake_len: 1,
+        },
+        Scenario {
+            name: "spawn_food/crowded_board",
+            workload: Workload::SpawnFood,
+            width: 20,
+            height: 20,
+            snake_len: 390,
+        },
+        Scenario {
+            name: "ai_move/10x10",
+            workload: Workload::AiMove,
+            width: 10,
+            height: 10,
+            snake_len: 25,
+        },
+        Scenario {
+            name: "ai_move/20x20",
+            workload: Workload::AiMove,
+            width: 20,
+            height: 20,
+            snake_len: 100,
+        },
+        Scenario {
+            name: "ai_move/50x50",
+            workload: Workload::AiMove,
+            width: 50,
+            height: 50,
+            snake_len: 625,
+        },
+        Scenario {
+            name: "ai_move/100x100",
+            workload: Workload::AiMove,
+            width: 100,
+            height: 100,
+            snake_len: 2500,
+        },
+    ]
+}
+
+/// Creates a game whose snake is laid out along a serpentine path
+/// The head sits at the end of the path, facing the next free cell
+fn board_with_snake(width: i32, height: i32, snake_len: usize) -> Game {
+    let mut game = Game::new(width, height);
+    let path: Vec<Point> = (0..height)
+        .flat_map(|y| {
+            (0..width).map(move |i| Point {
+                x: if y % 2 == 0 { i } else { width - 1 - i },
+                y,
+                z: 0,
+            })
+        })
+        .collect();
+    let snake_len = snake_len.clamp(1, path.len() - 1);
+
+    game.snake = path[..snake_len]
+        .iter()
+        .rev()
+        .copied()
+        .collect::<VecDeque<_>>();
+    let head = path[snake_len - 1];
+    let next = path[snake_len];
+    game.direction = match (next.x - head.x, next.y - head.y) {
+        (1, _) => Direction::Right,
+        (-1, _) => Direction::Left,
+        (_, -1) => Direction::Up,
+        _ => Direction::Down,
+    };
+    game.spawn_food();
+    game
+}
+
+/// Timing summary of a single scenario
+struct Summary {
+    iterations: u32,
+    mean: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+/// Times a scenario, rebuilding the game before every iteration
+fn measure(scenario: &Scenario, iterations: u32) -> Summary {
+    let template = scenario.setup();
+    let mut total = Duration::ZERO;
+    let mut min = Duration::MAX;
+    let mut max = Duration::ZERO;
+
+    for _ in 0..iterations {
+        let mut game = template.clone();
+        let start = Instant::now();
+        scenario.run(black_box(&mut game));
+        let elapsed = start.elapsed();
+        total += elapsed;
+        min = min.min(elapsed);
+        max = max.max(elapsed);
+    }
+
+    Summary {
+        iterations,
+        mean: total / iterations,
+        min,
+        max,
+    }
+}
+
+/// Entry point of the `bench` subcommand
+/// Usage: bench [ITERATIONS] [FILTER], or bench locks ... for the locking strategies
+pub fn run_cli(args: &[String]) {
+    if args.first().map(String::as_str) == Some("locks") {
+        lock_bench::run_cli(&args[1..]);
+        return;
+    }
+    let iterations = args
+        .first()
+        .and_then(|arg| arg.parse::<u32>().ok())
+        .unwrap_or(10_000)
+        .max(1);
+    let filter = args.get(1).map(String::as_str).unwrap_or("");
+
+    println!(
+        "{:<28} {:>10} {:>12} {:>12} {:>12}",
+        "scenario", "iters", "mean", "min", "max"
+    );
+    for scenario in scenarios().iter().filter(|s| s.name.contains(filter)) {
+        let summary = measure(scenario, iterations);
+        println!(
+            "{:<28} {:>10} {:>12?} {:>12?} {:>12?}",
+            scenario.name, summary.iterations, summary.mean, summary.min, summary.max
+        );
+    }
+    println!("For statistically rigorous results run `cargo bench`");
+}
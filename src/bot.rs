@@ -0,0 +1,258 @@
+use crate::config::Config;
+use crate::game::Direction;
+use crate::observation::{self, Observation, ObservationFormat};
+use crate::server::AppState;
+use crate::topology::Topology;
+use actix_web::http::Uri;
+use actix_web::web;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::time::{Instant, MissedTickBehavior};
+
+/// Longest time a bot may be given to answer a tick
+pub const MAX_TIMEOUT_MS: u64 = 2000;
+
+/// External bot playing a game through HTTP callbacks, chosen when the game is created
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BotCallback {
+    /// URL the observation of every tick is POSTed to
+    pub url: String,
+    /// Milliseconds the bot has to answer, at most `MAX_TIMEOUT_MS`
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Format of the board in the observations sent
+    #[serde(default)]
+    pub observation: ObservationFormat,
+}
+
+fn default_timeout_ms() -> u64 {
+    250
+}
+
+impl BotCallback {
+    /// Fails with a message for URLs other than HTTP(S), hosts missing from the configured
+    /// `bot_hosts` and timeouts out of range
+    pub fn validate(&self, config: &Config) -> Result<(), String> {
+        if config.bot_hosts.is_empty() {
+            return Err("bots are not enabled on this server".to_string());
+        }
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err("bot URL must be an http or https URL".to_string());
+        }
+        let (host, _) = host_and_port(&self.url)?;
+        if !config
+            .bot_hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(&host))
+        {
+            return Err(format!("bot host {} is not allowed", host));
+        }
+        if !(1..=MAX_TIMEOUT_MS).contains(&self.timeout_ms) {
+            return Err(format!(
+                "bot timeout must be between 1 and {} ms",
+                MAX_TIMEOUT_MS
+            ));
+        }
+        Ok(())
+    }
+
+    /// Validates the callback and resolves the address of its host
+    /// Fails unless every address the host resolves to is public, so an allowed name cannot
+    /// point the server at itself or its private network
+    pub async fn resolve(&self, config: &Config) -> Result<SocketAddr, String> {
+        self.validate(config)?;
+        let (host, port) = host_and_port(&self.url)?;
+        let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), port))
+            .await
+            .map_err(|e| format!("cannot resolve bot host {}: {}", host, e))?
+            .collect();
+        if addresses.is_empty() {
+            return Err(format!("bot host {} has no address", host));
+        }
+        if let Some(address) = addresses.iter().find(|address| !is_public(address.ip())) {
+            return Err(format!(
+                "bot host {} resolves to non-public address {}",
+                host,
+                address.ip()
+            ));
+        }
+        Ok(addresses[0])
+    }
+}
+
+/// Lowercase host and port of a bot URL, the port defaulting to the scheme's
+fn host_and_port(url: &str) -> Result<(String, u16), String> {
+    let uri: Uri = url.parse().map_err(|_| "invalid bot URL".to_string())?;
+    let host = uri
+        .host()
+        .ok_or_else(|| "bot URL has no host".to_string())?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase();
+    let default_port = if uri.scheme_str() == Some("https") {
+        443
+    } else {
+        80
+    };
+    Ok((host, uri.port_u16().unwrap_or(default_port)))
+}
+
+/// Whether an address is reachable on the public internet, i.e. not loopback,
+/// unspecified, private, shared, link-local, multicast, broadcast or documentation
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_v4(mapped),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+                    // Documentation prefix 2001:db8::/32
+                    || ip.segments()[..2] == [0x2001, 0x0db8])
+            }
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [first, second, ..] = ip.octets();
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_multicast()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        // "This network" 0.0.0.0/8 and shared address space 100.64.0.0/10
+        || first == 0
+        || (first == 100 && (64..128).contains(&second)))
+}
+
+/// Answer of a bot to an observation
+#[derive(Deserialize)]
+struct BotMove {
+    direction: Direction,
+}
+
+/// Time between two ticks of a bot game
+fn tick_period(config: &Config) -> Duration {
+    let quota = match config.max_ticks_per_sec {
+        0 => Duration::ZERO,
+        per_sec => Duration::from_secs(1) / per_sec,
+    };
+    Duration::from_millis(config.bot_tick_millis.max(1)).max(quota)
+}
+
+/// Sends an observation to a bot and waits for its move within the bot's timeout
+/// The bot's host is checked and resolved again on every tick, so configuration changes
+/// and DNS changes cannot point the requests elsewhere
+async fn ask(
+    client: &awc::Client,
+    config: &Config,
+    bot: &BotCallback,
+    session_id: &str,
+    observation: Observation,
+) -> Result<Direction, String> {
+    let answer = async {
+        let address = bot.resolve(config).await?;
+        let mut response = client
+            .post(&bot.url)
+            .address(address)
+            .send_json(&json!({ "session_id": session_id, "observation": observation }))
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("bot answered {}", response.status()));
+        }
+        let answer: BotMove = response.json().await.map_err(|e| e.to_string())?;
+        Ok(answer.direction)
+    };
+    actix_web::rt::time::timeout(Duration::from_millis(bot.timeout_ms), answer)
+        .await
+        .map_err(|_| "bot timed out".to_string())?
+}
+
+/// Plays a bot game on the server until it ends or its session is removed
+/// Every tick the observation is POSTed to the bot, so bots hold no connections open;
+/// the move of a bot that fails, times out or sends no valid direction is made by the
+/// greedy AI instead
+/// Ticks follow each other at least `bot_tick_millis` apart, and no faster than the tick
+/// quota; a tick advanced by another request while the bot was thinking is asked again
+/// Ticks the server runs late, e.g. because it is busy with other games, are counted as
+/// starvation
+pub async fn play(data: web::Data<AppState>, session_id: String) {
+    // Redirects would lead past the host checks
+    let client = awc::Client::builder().disable_redirects().finish();
+    let period = tick_period(&data.config());
+    let mut interval = actix_web::rt::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        let waiting_since = Instant::now();
+        let due = interval.tick().await;
+        // A slow bot makes the next tick due at once; only time past both counts as late
+        data.fairness
+            .server_tick(due.max(waiting_since).elapsed(), period);
+        let (bot, tick, observation) = {
+            let mut sessions = data.sessions.lock().unwrap();
+            let Some(session) = sessions.get_mut(&session_id) else {
+                return;
+            };
+            let Some(bot) = session.bot.clone().filter(|_| !session.game.game_over) else {
+                return;
+            };
+            // Leave the game to the server taking over
+            if data.is_draining() {
+                return;
+            }
+            // Keep the session alive while the bot plays, even when nobody watches
+            session.touch();
+            let observation = observation::observe(&session.game, bot.observation);
+            (bot, session.game.tick, observation)
+        };
+        let answer = ask(&client, &data.config(), &bot, &session_id, observation).await;
+        let mut sessions = data.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&session_id) else {
+            return;
+        };
+        if session.game.tick != tick {
+            continue;
+        }
+        // Hex directions on square boards and the other way round are no valid move either
+        let answer = answer.and_then(|direction| {
+            if session.game.topology.directions().contains(&direction) {
+                Ok(direction)
+            } else {
+                Err("bot sent a direction this board does not have".to_string())
+            }
+        });
+        match answer {
+            Ok(direction) => session.game.steer(Some(direction)),
+            Err(e) => {
+                tracing::debug!(session_id = %session_id, error = %e, "bot missed a tick, the greedy AI moves");
+                session.game.ai_move();
+            }
+        }
+        session.tick();
+        data.record_if_finished(&session_id, session);
+    }
+}
+
+/// Resumes playing the unfinished bot games, e.g. those restored from checkpoints
+pub fn resume(data: &web::Data<AppState>) {
+    let session_ids: Vec<String> = data
+        .sessions
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, session)| session.bot.is_some() && !session.game.game_over)
+        .map(|(session_id, _)| session_id.clone())
+        .collect();
+    for session_id in session_ids {
+        actix_web::rt::spawn(play(data.clone(), session_id));
+    }
+}
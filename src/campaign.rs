@@ -0,0 +1,533 @@
+use crate::game::{Door, Hazard, Key, Objective, Point, Start, Treasure, MAX_TREASURE_POINTS};
+use crate::identity;
+use crate::level::LevelSpec;
+use crate::proof_of_work;
+use crate::rng::SeededRng;
+use crate::server::{AppState, NewGameOptions, BOARD_HEIGHT, BOARD_WIDTH};
+use crate::topology::{self, TopologySpec};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Players listed on the furthest stage leaderboard
+const LEADERBOARD_LEN: usize = 10;
+/// Layouts tried for a stage before falling back to a board with just the exit
+const MAX_LAYOUT_ATTEMPTS: u32 = 32;
+/// Random cells tried when placing an item of a layout
+const MAX_PLACEMENT_TRIES: u32 = 100;
+/// Cells around the start kept clear of walls, hazards and items, as a Chebyshev distance
+const START_CLEARANCE: i32 = 3;
+/// Most doors guarding the exit of a stage
+const MAX_LOCKS: u32 = 4;
+/// Most hazards patrolling a stage
+const MAX_STAGE_HAZARDS: u32 = 4;
+/// Most treasures of a stage
+const MAX_STAGE_TREASURES: u32 = 3;
+/// Most scattered walls of a stage
+const MAX_STAGE_WALLS: u32 = 60;
+
+/// Campaign a player is playing: the stage reached and the score carried from the
+/// completed ones
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Run {
+    /// Seed the levels of the campaign are generated from
+    pub seed: u64,
+    /// Stage being played, starting at 1
+    pub stage: u32,
+    /// Points scored on the completed stages
+    pub score: u32,
+    /// Session of the current stage's game
+    pub session_id: String,
+}
+
+/// Campaign progress of a player
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Progress {
+    /// Name shown on the leaderboard; players without one are not listed
+    pub name: Option<String>,
+    /// Highest stage any campaign of the player reached
+    pub furthest_stage: u32,
+    /// Score the player carried into that stage
+    pub score: u32,
+    /// Campaign being played, if any
+    pub run: Option<Run>,
+}
+
+impl Progress {
+    /// Records reaching a stage with a carried score, keeping the furthest one
+    fn reach(&mut self, stage: u32, score: u32) {
+        if (stage, score) > (self.furthest_stage, self.score) {
+            self.furthest_stage = stage;
+            self.score = score;
+        }
+    }
+}
+
+/// Campaign progress of all players
+/// Progress is saved to the configured file on every change, so it outlives restarts
+pub struct Campaigns {
+    // Map of client IDs to the progress of their player
+    progress: Mutex<HashMap<String, Progress>>,
+    // File where the progress is saved, if configured
+    file: Option<PathBuf>,
+}
+
+impl Campaigns {
+    /// Loads the progress saved in the configured file
+    /// A missing file starts with no progress; an unreadable one is logged and ignored
+    pub fn open(file: Option<&str>) -> Self {
+        let file = file.map(PathBuf::from);
+        let progress = match file.as_deref().map(fs::read) {
+            Some(Ok(contents)) => serde_json::from_slice(&contents).unwrap_or_else(|e| {
+                tracing::error!("ignoring unreadable campaign file: {}", e);
+                HashMap::new()
+            }),
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => {
+                tracing::error!("cannot read campaign file: {}", e);
+                HashMap::new()
+            }
+            _ => HashMap::new(),
+        };
+        Campaigns {
+            progress: Mutex::new(progress),
+            file,
+        }
+    }
+
+    /// Writes the progress of all players to the file, if configured
+    /// Failing to write it is logged; the progress is still kept in memory
+    fn save(&self, progress: &HashMap<String, Progress>) {
+        let Some(path) = &self.file else {
+            return;
+        };
+        let temp = path.with_extension("tmp");
+        let saved = serde_json::to_vec(progress)
+            .map_err(io::Error::from)
+            .and_then(|contents| fs::write(&temp, contents))
+            .and_then(|()| fs::rename(&temp, path));
+        if let Err(e) = saved {
+            tracing::error!("cannot save campaign progress: {}", e);
+        }
+    }
+
+    /// Progress of all players, for state archives
+    pub(crate) fn all(&self) -> HashMap<String, Progress> {
+        self.progress.lock().unwrap().clone()
+    }
+
+    /// Takes over the progress of a state archive, replacing that of the same players
+    pub(crate) fn import(&self, imported: HashMap<String, Progress>) {
+        let mut progress = self.progress.lock().unwrap();
+        progress.extend(imported);
+        self.save(&progress);
+    }
+}
+
+/// Cell within the board
+fn on_board(cell: Point) -> bool {
+    (0..BOARD_WIDTH).contains(&cell.x) && (0..BOARD_HEIGHT).contains(&cell.y)
+}
+
+/// Layout of a stage being generated: the cells taken so far and the level
+struct Layout {
+    rng: SeededRng,
+    taken: Vec<Point>,
+    walls: Vec<Point>,
+}
+
+impl Layout {
+    /// Whether a cell is on the board, clear of the start and not taken yet
+    fn is_free(&self, cell: Point) -> bool {
+        let start = topology::start_point(BOARD_WIDTH, BOARD_HEIGHT);
+        let near_start = (cell.x - start.x).abs().max((cell.y - start.y).abs()) <= START_CLEARANCE;
+        on_board(cell) && !near_start && !self.taken.contains(&cell)
+    }
+
+    /// Random cell of the board
+    fn random_cell(&mut self) -> Point {
+        Point {
+            x: self.rng.gen_range(0, BOARD_WIDTH),
+            y: self.rng.gen_range(0, BOARD_HEIGHT),
+            z: 0,
+        }
+    }
+
+    /// Random unit step along a row or a column
+    fn random_step(&mut self) -> (i32, i32) {
+        [(1, 0), (-1, 0), (0, 1), (0, -1)][self.rng.gen_range(0, 4) as usize]
+    }
+
+    /// Takes a random free cell; None when none was found in `MAX_PLACEMENT_TRIES` tries
+    fn place(&mut self) -> Option<Point> {
+        (0..MAX_PLACEMENT_TRIES).find_map(|_| {
+            let cell = self.random_cell();
+            self.is_free(cell).then(|| {
+                self.taken.push(cell);
+                cell
+            })
+        })
+    }
+
+    /// Takes a random straight line of free cells of the given length
+    fn place_line(&mut self, len: i32) -> Option<Vec<Point>> {
+        (0..MAX_PLACEMENT_TRIES).find_map(|_| {
+            let first = self.random_cell();
+            let (dx, dy) = self.random_step();
+            let line: Vec<Point> = (0..len)
+                .map(|i| Point {
+                    x: first.x + dx * i,
+                    y: first.y + dy * i,
+                    z: 0,
+                })
+                .collect();
+            line.iter().all(|cell| self.is_free(*cell)).then(|| {
+                self.taken.extend(&line);
+                line
+            })
+        })
+    }
+
+    /// Builds the vault of the exit: a dead-end corridor entered through `locks` doors in a
+    /// row, walled on both sides and behind the exit, with a free cell before its entrance
+    /// Returns the door cells, outermost first, and the exit
+    fn place_vault(&mut self, locks: u32) -> Option<(Vec<Point>, Point)> {
+        let len = locks as i32 + 1;
+        (0..MAX_PLACEMENT_TRIES).find_map(|_| {
+            let entrance = self.random_cell();
+            let (dx, dy) = self.random_step();
+            let cell = |i: i32| Point {
+                x: entrance.x + dx * i,
+                y: entrance.y + dy * i,
+                z: 0,
+            };
+            let corridor: Vec<Point> = (0..=len).map(cell).collect();
+            let sides = (1..=len).flat_map(|i| {
+                let Point { x, y, .. } = cell(i);
+                [(x + dy, y + dx), (x - dy, y - dx)]
+            });
+            let walls: Vec<Point> = sides
+                .map(|(x, y)| Point { x, y, z: 0 })
+                .chain([cell(len + 1)])
+                .filter(|wall| on_board(*wall))
+                .collect();
+            let fits = corridor
+                .iter()
+                .chain(&walls)
+                .all(|cell| self.is_free(*cell));
+            fits.then(|| {
+                self.taken.extend(&corridor);
+                self.taken.extend(&walls);
+                self.walls.extend(&walls);
+                (corridor[1..len as usize].to_vec(), corridor[len as usize])
+            })
+        })
+    }
+}
+
+/// Lays out a stage: the exit in a vault behind more doors, patrolled by more and faster
+/// hazards and among more walls the further the campaign goes
+/// None when some part of the layout found no room
+fn layout(rng: SeededRng, stage: u32) -> Option<LevelSpec> {
+    let depth = stage.saturating_sub(1);
+    let locks = depth.min(MAX_LOCKS);
+    let mut layout = Layout {
+        rng,
+        taken: Vec::new(),
+        walls: Vec::new(),
+    };
+    let (door_cells, exit) = layout.place_vault(locks)?;
+    let mut hazards = Vec::new();
+    for _ in 0..(depth / 2).min(MAX_STAGE_HAZARDS) {
+        let len = layout.rng.gen_range(3, 7);
+        hazards.push(Hazard {
+            path: layout.place_line(len)?,
+            period: 4u32.saturating_sub(depth / 4).max(1),
+        });
+    }
+    let mut keys = Vec::new();
+    for key in 0..locks {
+        keys.push(Key {
+            cell: layout.place()?,
+            key,
+        });
+    }
+    let mut treasures = Vec::new();
+    for _ in 0..stage.min(MAX_STAGE_TREASURES) {
+        treasures.push(Treasure {
+            cell: layout.place()?,
+            points: (5 * stage).min(MAX_TREASURE_POINTS),
+        });
+    }
+    for _ in 0..(3 * depth).min(MAX_STAGE_WALLS) {
+        let wall = layout.place()?;
+        layout.walls.push(wall);
+    }
+    let doors = door_cells
+        .into_iter()
+        .zip(0..)
+        .map(|(cell, key)| Door { cell, key })
+        .collect();
+    Some(LevelSpec {
+        name: format!("Stage {}", stage),
+        topology: TopologySpec::Obstacles {
+            walls: layout.walls,
+        },
+        start: Start::default(),
+        zones: Vec::new(),
+        portals: Vec::new(),
+        hazards,
+        tiles: Vec::new(),
+        gates: Vec::new(),
+        objective: Some(Objective {
+            keys,
+            doors,
+            treasures,
+            exit: Some(exit),
+        }),
+    })
+}
+
+/// Level of a stage of the campaign with the given seed
+/// The same seed and stage always give the same level; layouts are retried until one is
+/// playable, falling back to an open board with an exit in a corner
+pub fn stage_level(seed: u64, stage: u32) -> LevelSpec {
+    let mut rng = SeededRng::new(seed ^ (u64::from(stage) << 32));
+    for _ in 0..MAX_LAYOUT_ATTEMPTS {
+        let spec = layout(SeededRng::new(rng.next_u64()), stage);
+        if let Some(spec) = spec.filter(|spec| spec.validate().is_ok()) {
+            return spec;
+        }
+    }
+    LevelSpec {
+        name: format!("Stage {}", stage),
+        topology: TopologySpec::Solid,
+        start: Start::default(),
+        zones: Vec::new(),
+        portals: Vec::new(),
+        hazards: Vec::new(),
+        tiles: Vec::new(),
+        gates: Vec::new(),
+        objective: Some(Objective {
+            exit: Some(Point { x: 0, y: 0, z: 0 }),
+            ..Objective::default()
+        }),
+    }
+}
+
+/// Creates the game of a stage for a player
+/// Returns its session ID and token
+fn start_stage(
+    data: &AppState,
+    client_id: &str,
+    seed: u64,
+    stage: u32,
+) -> Result<(String, String), String> {
+    let spec = stage_level(seed, stage);
+    let options = NewGameOptions {
+        topology: spec.topology,
+        start: spec.start,
+        hazards: spec.hazards,
+        objective: spec.objective,
+        ..NewGameOptions::default()
+    };
+    let (session_id, token) = data.create_game(Some(client_id.to_string()), options)?;
+    if let Some(session) = data.sessions.lock().unwrap().get_mut(&session_id) {
+        session.campaign = true;
+    }
+    Ok((session_id, token))
+}
+
+/// Stage game a player was given, as returned when starting or advancing a campaign
+fn stage_json(run: &Run, token: &str) -> serde_json::Value {
+    json!({
+        "session_id": run.session_id,
+        "token": token,
+        "stage": run.stage,
+        "score": run.score,
+    })
+}
+
+/// Optional body of the start campaign endpoint
+#[derive(Default, Deserialize)]
+pub struct StartRequest {
+    // Name shown on the furthest stage leaderboard, following the rules of leaderboard names
+    #[serde(default)]
+    name: Option<String>,
+}
+
+/// Starts a campaign at stage 1, replacing the player's campaign in progress
+/// Names are cleaned and claimed as leaderboard names are: 400 Bad Request for invalid
+/// names and 409 Conflict for names claimed by another player
+/// Issues a client cookie on the first game of a browser, which the progress is kept under
+/// Asks for a solved challenge while proof of work is configured, see `proof_of_work::check`
+pub async fn start(
+    req: HttpRequest,
+    request: Option<web::Json<StartRequest>>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    if let Some(rejection) = proof_of_work::check(&req, &data) {
+        return rejection;
+    }
+    let request = request.map(web::Json::into_inner).unwrap_or_default();
+    let (client_id, is_new) = identity::client_id_or_new(&req);
+    let name = match request.name {
+        Some(name) => match data.config().name_filter.clean(&name) {
+            Ok(name) => Some(name),
+            Err(e) => return HttpResponse::BadRequest().json(e.to_json()),
+        },
+        None => None,
+    };
+    if let Some(name) = &name {
+        if let Err(e) = data.claim_name(name, Some(client_id.clone())) {
+            return HttpResponse::Conflict().json(e.to_json());
+        }
+    }
+    let seed = rand::random();
+    let (session_id, token) = match start_stage(&data, &client_id, seed, 1) {
+        Ok(created) => created,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({ "error": e })),
+    };
+    let run = Run {
+        seed,
+        stage: 1,
+        score: 0,
+        session_id,
+    };
+    let body = stage_json(&run, &token);
+    {
+        let mut progress = data.campaigns.progress.lock().unwrap();
+        let player = progress.entry(client_id.clone()).or_default();
+        if name.is_some() {
+            player.name = name;
+        }
+        player.reach(run.stage, run.score);
+        player.run = Some(run);
+        data.campaigns.save(&progress);
+    }
+    let mut response = HttpResponse::Ok();
+    if is_new {
+        response.cookie(identity::cookie(&data, &client_id));
+    }
+    response.json(body)
+}
+
+/// Moves the player's campaign to the next stage once the current stage's game completed
+/// its objective, carrying the score of that game over
+/// Responds with 404 Not Found without a campaign in progress and 409 Conflict while the
+/// stage is still being played; a stage whose game ended without reaching the exit, or
+/// was removed, ends the campaign with 409 Conflict
+pub async fn advance(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    let Some(client_id) = identity::client_id(&req) else {
+        return HttpResponse::NotFound().json(json!({ "error": "no campaign in progress" }));
+    };
+    let mut progress = data.campaigns.progress.lock().unwrap();
+    let Some(player) = progress.get_mut(&client_id) else {
+        return HttpResponse::NotFound().json(json!({ "error": "no campaign in progress" }));
+    };
+    let Some(run) = player.run.clone() else {
+        return HttpResponse::NotFound().json(json!({ "error": "no campaign in progress" }));
+    };
+    let stage_game = data
+        .sessions
+        .lock()
+        .unwrap()
+        .get(&run.session_id)
+        .map(|session| {
+            (
+                session.game.completion.is_some(),
+                session.game.game_over,
+                session.game.score,
+            )
+        });
+    let score = match stage_game {
+        Some((true, _, score)) => run.score + score,
+        Some((false, false, _)) => {
+            return HttpResponse::Conflict()
+                .json(json!({ "error": "the stage is not completed yet" }))
+        }
+        Some((false, true, _)) | None => {
+            player.run = None;
+            data.campaigns.save(&progress);
+            return HttpResponse::Conflict().json(json!({
+                "error": "the campaign is over",
+                "stage": run.stage,
+                "score": run.score,
+            }));
+        }
+    };
+    let stage = run.stage + 1;
+    let (session_id, token) = match start_stage(&data, &client_id, run.seed, stage) {
+        Ok(created) => created,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({ "error": e })),
+    };
+    let run = Run {
+        stage,
+        score,
+        session_id,
+        ..run
+    };
+    let body = stage_json(&run, &token);
+    player.reach(run.stage, run.score);
+    player.run = Some(run);
+    data.campaigns.save(&progress);
+    HttpResponse::Ok().json(body)
+}
+
+/// Returns the player's campaign progress: `furthest_stage` with the `score` carried into
+/// it and the campaign in progress, if any, with the score of its current game included
+/// Responds with 404 Not Found for players who never started a campaign
+pub async fn progress(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    let client_id = identity::client_id(&req);
+    let player = client_id.and_then(|client_id| {
+        let progress = data.campaigns.progress.lock().unwrap();
+        progress.get(&client_id).cloned()
+    });
+    let Some(player) = player else {
+        return HttpResponse::NotFound().json(json!({ "error": "no campaign started" }));
+    };
+    let run = player.run.as_ref().map(|run| {
+        let sessions = data.sessions.lock().unwrap();
+        let game = sessions.get(&run.session_id).map(|session| &session.game);
+        json!({
+            "stage": run.stage,
+            "session_id": run.session_id,
+            "score": run.score + game.map_or(0, |game| game.score),
+            "completed": game.is_some_and(|game| game.completion.is_some()),
+            "over": game.is_none_or(|game| game.game_over),
+        })
+    });
+    HttpResponse::Ok().json(json!({
+        "name": player.name,
+        "furthest_stage": player.furthest_stage,
+        "score": player.score,
+        "run": run,
+    }))
+}
+
+/// Lists the players who reached the furthest stages, ties broken by the score carried
+/// into the stage; players who gave no name are left out
+pub async fn leaderboard(data: web::Data<AppState>) -> HttpResponse {
+    let progress = data.campaigns.progress.lock().unwrap();
+    let mut players: Vec<_> = progress
+        .values()
+        .filter_map(|player| Some((player.name.as_deref()?, player)))
+        .collect();
+    players.sort_by_key(|(_, player)| std::cmp::Reverse((player.furthest_stage, player.score)));
+    let players: Vec<_> = players
+        .into_iter()
+        .take(LEADERBOARD_LEN)
+        .map(|(name, player)| {
+            json!({
+                "name": name,
+                "furthest_stage": player.furthest_stage,
+                "score": player.score,
+            })
+        })
+        .collect();
+    HttpResponse::Ok().json(players)
+}
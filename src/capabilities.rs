@@ -0,0 +1,152 @@
+use crate::ai::{Strategy, MAX_LOOKAHEAD_DEPTH};
+use crate::config::Config;
+use crate::game::{MAX_GROWTH_SEGMENTS, MAX_QUEUED_INPUTS};
+use crate::observation::ObservationFormat;
+use crate::server::{AppState, BOARD_HEIGHT, BOARD_WIDTH, MAX_PREDICT_TICKS, MAX_UPDATE_STEPS};
+use crate::session::{EVENT_BACKLOG, KEYFRAME_INTERVAL};
+use crate::topology::MAX_LAYERS;
+use crate::names;
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+
+/// Where a kind of data is kept: `file` when its file or directory is configured,
+/// `memory` otherwise
+fn storage(configured: bool) -> &'static str {
+    if configured {
+        "file"
+    } else {
+        "memory"
+    }
+}
+
+/// AI strategies clients may request, none without the `ai` feature; the neural one only
+/// with a loaded policy model
+#[cfg_attr(not(feature = "onnx"), allow(unused_mut, unused_variables))]
+fn strategies(config: &Config) -> Vec<Strategy> {
+    if !cfg!(feature = "ai") {
+        return Vec::new();
+    }
+    let mut strategies = vec![Strategy::Greedy, Strategy::Lookahead];
+    #[cfg(feature = "onnx")]
+    if config.ai.model.policy.is_some() {
+        strategies.push(Strategy::Neural);
+    }
+    strategies
+}
+
+/// Ways to follow a game live; the WebSockets need the `multiplayer` feature
+fn transports() -> Vec<&'static str> {
+    if cfg!(feature = "multiplayer") {
+        vec!["websocket", "websocket_multiplexed", "long_polling"]
+    } else {
+        vec!["long_polling"]
+    }
+}
+
+/// Most channels of a multiplexed WebSocket, 0 without the `multiplayer` feature
+fn max_channels() -> usize {
+    #[cfg(feature = "multiplayer")]
+    return crate::realtime::MAX_CHANNELS;
+    #[cfg(not(feature = "multiplayer"))]
+    0
+}
+
+/// Login providers offered, none without the `oauth` feature
+fn login_providers(config: &Config) -> Vec<&'static str> {
+    if !cfg!(feature = "oauth") {
+        return Vec::new();
+    }
+    let providers = [
+        ("github", config.oauth.github.is_some()),
+        ("google", config.oauth.google.is_some()),
+    ];
+    providers
+        .into_iter()
+        .filter(|(_, configured)| *configured)
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Describes the features this server was built and configured with and its limits, so
+/// clients and bots can adapt instead of assuming them
+pub async fn capabilities(data: web::Data<AppState>) -> HttpResponse {
+    let config = &data.config();
+    HttpResponse::Ok().json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "board": {
+            "width": BOARD_WIDTH,
+            "height": BOARD_HEIGHT,
+            "topologies": ["solid", "wrap", "obstacles", "hex", "layered"],
+            "max_layers": MAX_LAYERS,
+        },
+        "modes": {
+            "practice": true,
+            "levels": true,
+            "modifiers": ["mirror_controls", "random_control_scramble_every_n_apples"],
+            "food_behaviors": ["static", "fleeing"],
+            "food_expiry": true,
+            "combo": true,
+            "zones": true,
+            "portals": true,
+            "hazards": true,
+            "terrain": ["ice", "speed"],
+            "gates": true,
+            "objectives": true,
+            "campaign": true,
+            "hourly_challenge": true,
+            "max_growth_segments": MAX_GROWTH_SEGMENTS,
+            "multiplayer": cfg!(feature = "multiplayer"),
+        },
+        "ai": {
+            "strategies": strategies(config),
+            "personalities": true,
+            "max_lookahead_depth": MAX_LOOKAHEAD_DEPTH,
+            "bot_callbacks": cfg!(feature = "ai") && !config.bot_hosts.is_empty(),
+            "battlesnake": cfg!(feature = "ai"),
+            "training_data": cfg!(feature = "ai"),
+            "observations": [
+                ObservationFormat::Grid,
+                ObservationFormat::Channels,
+                ObservationFormat::Flat,
+            ],
+        },
+        "realtime": {
+            "transports": transports(),
+            "max_channels": max_channels(),
+            "max_spectators": config.max_spectators,
+            "reactions_per_minute": config.reactions_per_minute,
+            "keyframe_interval": KEYFRAME_INTERVAL,
+            "event_backlog": EVENT_BACKLOG,
+            "ping_secs": config.ws_ping_secs,
+            "idle_timeout_secs": config.ws_idle_timeout_secs,
+        },
+        "rendering": cfg!(feature = "rendering"),
+        "languages": data.catalogs.languages(),
+        "apis": {
+            "graphql": cfg!(feature = "graphql"),
+            "grpc": cfg!(feature = "grpc") && config.grpc_port.is_some(),
+            "admin": config.admin_token.is_some(),
+            "reports": true,
+            "leaderboard_exports": ["csv", "rss"],
+            "login_providers": login_providers(config),
+        },
+        "storage": {
+            "checkpoints": cfg!(feature = "persistence") && config.checkpoint_dir.is_some(),
+            "levels": storage(config.level_dir.is_some()),
+            "audit_log": storage(config.audit_log.is_some()),
+            "accounts": storage(config.oauth.account_file.is_some()),
+            "campaigns": storage(config.campaign_file.is_some()),
+        },
+        "limits": {
+            "max_name_len": names::MAX_NAME_LEN,
+            "max_queued_inputs": MAX_QUEUED_INPUTS,
+            "max_update_steps": MAX_UPDATE_STEPS,
+            "max_predict_ticks": MAX_PREDICT_TICKS,
+            "max_ticks_per_sec": config.max_ticks_per_sec,
+            "tick_burst": config.tick_burst,
+            "max_submissions_per_hour": config.anti_spam.max_submissions_per_hour,
+            "proof_of_work_difficulty": config.proof_of_work.difficulty(),
+            "session_grace_secs": config.session_grace_secs,
+        },
+    }))
+}
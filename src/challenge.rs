@@ -0,0 +1,68 @@
+use crate::identity;
+use crate::leaderboard::{Window, HOUR};
+use crate::proof_of_work;
+use crate::rng::SeededRng;
+use crate::server::{AppState, NewGameOptions};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde_json::json;
+use std::time::SystemTime;
+
+/// Seed shared by every game of the hourly challenge starting at `start` (Unix seconds)
+/// Every server derives the same seed for the same hour
+pub fn seed_of(start: u64) -> u64 {
+    SeededRng::new(start).next_u64()
+}
+
+/// Returns the current hourly challenge: its `seed`, the `start` and `end` of the hour
+/// (Unix seconds) and its leaderboard so far
+pub async fn current(data: web::Data<AppState>) -> HttpResponse {
+    let start = Window::Hourly.start_of(SystemTime::now());
+    let leaderboard = data
+        .windows
+        .lock()
+        .unwrap()
+        .get(&Window::Hourly)
+        .filter(|open| open.start == start)
+        .map(|open| open.scores.clone())
+        .unwrap_or_default();
+    HttpResponse::Ok().json(json!({
+        "seed": seed_of(start),
+        "start": start,
+        "end": start + HOUR.as_secs(),
+        "leaderboard": leaderboard,
+    }))
+}
+
+/// Starts a game of the current hourly challenge: the standard board and rules with the
+/// hour's seed, so every player of the hour gets the same food
+/// Its score enters the hourly leaderboard when submitted before the hour ends
+/// Issues a client cookie on the first game of a browser
+/// Asks for a solved challenge while proof of work is configured, see `proof_of_work::check`
+pub async fn start(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    if let Some(rejection) = proof_of_work::check(&req, &data) {
+        return rejection;
+    }
+    let start = Window::Hourly.start_of(SystemTime::now());
+    let (client_id, is_new) = identity::client_id_or_new(&req);
+    let options = NewGameOptions {
+        seed: Some(seed_of(start)),
+        ..NewGameOptions::default()
+    };
+    let (session_id, token) = match data.create_game(Some(client_id.clone()), options) {
+        Ok(created) => created,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({ "error": e })),
+    };
+    if let Some(session) = data.sessions.lock().unwrap().get_mut(&session_id) {
+        session.challenge = Some(start);
+    }
+    let mut response = HttpResponse::Ok();
+    if is_new {
+        response.cookie(identity::cookie(&data, &client_id));
+    }
+    response.json(json!({
+        "session_id": session_id,
+        "token": token,
+        "seed": seed_of(start),
+        "end": start + HOUR.as_secs(),
+    }))
+}
@@ -0,0 +1,204 @@
+use crate::ai::Personality;
+#[cfg(feature = "ai")]
+use crate::bot::BotCallback;
+use crate::game::Game;
+use crate::migration;
+use crate::replay::{Replay, Visibility};
+use crate::room::RoomAccess;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// File a server shutting down leaves in the store once its games are handed over
+const HANDOFF_MARKER: &str = "handoff";
+/// File holding the process ID of the server owning the store, which alone restores and
+/// writes checkpoints
+const OWNER_MARKER: &str = "owner";
+
+/// Saved progress of an active game, restored by re-simulating its replay
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Session the game belongs to
+    pub session_id: String,
+    /// Browser that created the session
+    pub client_id: Option<String>,
+    /// Wall-clock creation time of the session
+    pub created_at: SystemTime,
+    /// Seed, input log and tick count of the game so far
+    pub replay: Replay,
+    /// Personality of the lookahead AI steering the game, if any
+    #[serde(default)]
+    pub ai_personality: Option<Personality>,
+    /// External bot playing the game, if any
+    #[cfg(feature = "ai")]
+    #[serde(default)]
+    pub bot: Option<BotCallback>,
+    /// Who may spectate the game and watch its replay
+    #[serde(default)]
+    pub visibility: Visibility,
+    /// Password and invite key of the game's room, so invites stay valid across restarts
+    #[serde(default)]
+    pub access: RoomAccess,
+    /// Whether the game is a stage of a campaign
+    #[serde(default)]
+    pub campaign: bool,
+    /// Start of the hour whose challenge seed the game plays, if any
+    #[serde(default)]
+    pub challenge: Option<u64>,
+    /// Whether a server wrote it while shutting down, handing the game over to its successor
+    #[serde(default)]
+    pub handoff: bool,
+}
+
+impl Checkpoint {
+    /// Captures the progress of a game
+    pub fn new(
+        session_id: &str,
+        client_id: Option<String>,
+        created_at: SystemTime,
+        game: &Game,
+    ) -> Self {
+        Checkpoint {
+            session_id: session_id.to_string(),
+            client_id,
+            created_at,
+            replay: Replay::from_game(session_id, game),
+            ai_personality: None,
+            #[cfg(feature = "ai")]
+            bot: None,
+            visibility: Visibility::default(),
+            access: RoomAccess::default(),
+            campaign: false,
+            challenge: None,
+            handoff: false,
+        }
+    }
+
+    /// Rebuilds the game at the checkpointed tick, including its recorded splits
+    pub fn restore(&self) -> Game {
+        let mut game = self.replay.simulate();
+        game.splits = self.replay.splits.clone();
+        game.history_len = game.snapshot_len();
+        game
+    }
+}
+
+/// Directory of checkpoint files, one JSON file per session
+pub struct CheckpointStore {
+    dir: PathBuf,
+}
+
+impl CheckpointStore {
+    /// Opens the store, creating its directory if needed
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(CheckpointStore { dir })
+    }
+
+    /// File holding the checkpoint of a session
+    /// Session IDs are server-issued UUIDs, so they are safe as file names
+    fn path(&self, session_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", session_id))
+    }
+
+    /// Writes a checkpoint, replacing the previous one of the same session
+    /// The file is written next to its final name and renamed so a crash never leaves a torn file
+    pub fn save(&self, checkpoint: &Checkpoint) -> io::Result<()> {
+        let path = self.path(&checkpoint.session_id);
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_vec(checkpoint)?)?;
+        fs::rename(&temp, &path)
+    }
+
+    /// Deletes the checkpoint of a session, if any
+    pub fn remove(&self, session_id: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(session_id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Tells servers sharing the store that handed-over checkpoints are ready
+    pub fn mark_handoff(&self) -> io::Result<()> {
+        fs::write(self.dir.join(HANDOFF_MARKER), b"")
+    }
+
+    /// Removes the handoff marker; returns whether there was one
+    pub fn take_handoff(&self) -> io::Result<bool> {
+        match fs::remove_file(self.dir.join(HANDOFF_MARKER)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Makes this process the owner of the store, unless another running process owns it
+    /// Returns whether this process owns the store
+    pub fn claim(&self) -> io::Result<bool> {
+        let path = self.dir.join(OWNER_MARKER);
+        let me = std::process::id();
+        match fs::read_to_string(&path) {
+            Ok(owner) => {
+                if owner
+                    .trim()
+                    .parse()
+                    .is_ok_and(|owner: u32| owner != me && is_running(owner))
+                {
+                    return Ok(false);
+                }
+            }
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            Err(_) => {}
+        }
+        fs::write(path, me.to_string())?;
+        Ok(true)
+    }
+
+    /// Gives up the ownership of the store, for the server taking over
+    pub fn release(&self) -> io::Result<()> {
+        let path = self.dir.join(OWNER_MARKER);
+        match fs::read_to_string(&path) {
+            Ok(owner) if owner.trim() == std::process::id().to_string() => fs::remove_file(path),
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads every checkpoint in the store
+    /// Unreadable files are skipped with a warning rather than failing startup
+    pub fn load_all(&self) -> io::Result<Vec<Checkpoint>> {
+        let mut checkpoints = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            match read(&path) {
+                Ok(checkpoint) => checkpoints.push(checkpoint),
+                Err(e) => tracing::warn!("skipping checkpoint {}: {}", path.display(), e),
+            }
+        }
+        Ok(checkpoints)
+    }
+}
+
+/// Whether a process is running
+/// Processes are looked up in `/proc`; on systems without it every previous owner counts
+/// as stopped
+fn is_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Parses a checkpoint file, upgrading replays saved by older builds
+fn read(path: &Path) -> io::Result<Checkpoint> {
+    let contents = fs::read(path)?;
+    let mut checkpoint: Value = serde_json::from_slice(&contents)?;
+    if let Some(replay) = checkpoint.get_mut("replay") {
+        *replay = migration::upgrade(replay.take()).map_err(io::Error::other)?;
+    }
+    Ok(serde_json::from_value(checkpoint)?)
+}
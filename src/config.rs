@@ -0,0 +1,278 @@
+use crate::ai::AiConfig;
+use crate::moderation::SpamRules;
+use crate::names::NameFilter;
+use crate::proof_of_work::ProofOfWorkConfig;
+use crate::theme::ThemeManifest;
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Default location of the configuration file
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Server configuration loaded from a TOML file
+/// Every field is optional so an empty or missing file yields the defaults
+/// The file is reloaded when it changes; the settings read only at startup (the gRPC port,
+/// checkpoint, level and locale directories, score secret, account and audit log files, AI
+/// slots, port reuse, drain timeout) keep their values until a restart
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Bearer token required by the `/admin` endpoints
+    /// The admin API is disabled when no token is configured
+    pub admin_token: Option<String>,
+    /// Port of the gRPC server (requires the `grpc` feature)
+    /// The gRPC server is not started when no port is configured
+    pub grpc_port: Option<u16>,
+    /// Public base URL (e.g. `https://snake.example.com`) used in share links
+    /// Derived from the request's host when not configured
+    pub public_url: Option<String>,
+    /// Seconds a session without open connections survives after the owner's last activity
+    pub session_grace_secs: u64,
+    /// Directory for checkpoints of active games, restored on restart
+    /// Checkpointing is disabled when no directory is configured
+    pub checkpoint_dir: Option<String>,
+    /// Number of ticks between two checkpoints of the same game
+    pub checkpoint_every_ticks: u64,
+    /// Directory where custom levels are saved
+    /// Levels are kept in memory only when no directory is configured
+    pub level_dir: Option<String>,
+    /// Key signing the score tokens of finished games
+    /// A random key is used when not configured, invalidating tokens on restart
+    pub score_secret: Option<String>,
+    /// Key signing the client cookies, so only IDs the server issued identify a browser
+    /// A random key is used when not configured, giving every browser a new ID on restart
+    pub cookie_secret: Option<String>,
+    /// Rules for names on the leaderboards
+    pub name_filter: NameFilter,
+    /// Thresholds above which submitted scores are held for review
+    pub anti_spam: SpamRules,
+    /// Proof of work asked for before creating games and submitting scores
+    pub proof_of_work: ProofOfWorkConfig,
+    /// Hours after which players get new anonymous IDs, see `identity::AnonymousIds`
+    pub anonymous_id_rotation_hours: u64,
+    /// Login providers (requires the `oauth` feature)
+    pub oauth: OAuthConfig,
+    /// File where administrative and destructive actions are appended
+    /// The audit log is kept in memory only when no file is configured
+    pub audit_log: Option<String>,
+    /// File where finished team matches are appended
+    /// The match history is kept in memory only when no file is configured
+    pub match_log: Option<String>,
+    /// File where the campaign progress of the players is saved
+    /// Campaign progress is kept in memory only when no file is configured
+    pub campaign_file: Option<String>,
+    /// Time and node budgets of the AI strategies per decision
+    pub ai: AiConfig,
+    /// Ticks of the countdown before new games start moving, unless a game asks for another
+    /// Gives players of server-ticked games time to get ready; 0 starts games at once
+    pub countdown_ticks: u32,
+    /// Minimum milliseconds between two ticks of games played by external bots
+    pub bot_tick_millis: u64,
+    /// Hosts external bots may be called at, e.g. `bots.example.com`
+    /// Bots are disabled when no host is configured
+    pub bot_hosts: Vec<String>,
+    /// Most unfinished bot games a client may have at once
+    pub max_bot_games_per_client: usize,
+    /// Milliseconds between two ticks of demo games
+    pub demo_tick_millis: u64,
+    /// Seconds between two pings sent on realtime connections
+    pub ws_ping_secs: u64,
+    /// Seconds after which a realtime connection that sent nothing, not even a pong, is closed
+    pub ws_idle_timeout_secs: u64,
+    /// Seconds the owner may be without realtime connection before spectators are told
+    /// the player disconnected
+    pub disconnect_grace_secs: u64,
+    /// Most realtime connections without the session token a game accepts
+    pub max_spectators: usize,
+    /// Most reactions a spectator connection may send per minute; more are dropped, and 0
+    /// turns reactions off
+    pub reactions_per_minute: u32,
+    /// Most ticks per second a game advances on average, however it is driven; 0 for no limit
+    pub max_ticks_per_sec: u32,
+    /// Ticks a game may advance at once above that rate, e.g. in one multi-step update
+    pub tick_burst: u32,
+    /// Most AI decisions computed at once across all games, the others wait their turn
+    /// Defaults to the number of CPUs
+    pub ai_slots: Option<usize>,
+    /// Whether the HTTP port is bound with `SO_REUSEPORT`, so the server replacing this one
+    /// can listen on it before this one stops
+    pub reuse_port: bool,
+    /// Seconds requests in flight get to finish when the server shuts down
+    pub drain_timeout_secs: u64,
+    /// Log filter, e.g. `info` or `snake_game=debug,actix_web=warn`
+    /// `RUST_LOG` overrides it; `info` is used when neither is set
+    pub log_level: Option<String>,
+    /// Look of the bundled frontend, see `GET /api/theme`
+    pub theme: ThemeManifest,
+    /// Directory of tenant themes, `<host>.toml` files overriding `theme` for requests to
+    /// that host
+    pub theme_dir: Option<String>,
+    /// Directory of message catalogs, `<language>.toml` files translating error messages
+    /// for clients asking for that language, see `i18n`
+    /// Messages are only sent in English when no directory is configured
+    pub locale_dir: Option<String>,
+}
+
+/// OAuth application registered with a login provider
+#[derive(Clone, Deserialize)]
+pub struct OAuthApp {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+/// Login providers, configured in the `[oauth]` table
+/// A provider is offered when its application is configured
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct OAuthConfig {
+    pub github: Option<OAuthApp>,
+    pub google: Option<OAuthApp>,
+    /// File where linked accounts are saved
+    /// Accounts are kept in memory only when no file is configured
+    pub account_file: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            admin_token: None,
+            grpc_port: None,
+            public_url: None,
+            session_grace_secs: 600,
+            checkpoint_dir: None,
+            checkpoint_every_ticks: 50,
+            level_dir: None,
+            score_secret: None,
+            cookie_secret: None,
+            name_filter: NameFilter::default(),
+            anti_spam: SpamRules::default(),
+            proof_of_work: ProofOfWorkConfig::default(),
+            anonymous_id_rotation_hours: 24,
+            oauth: OAuthConfig::default(),
+            audit_log: None,
+            match_log: None,
+            campaign_file: None,
+            ai: AiConfig::default(),
+            countdown_ticks: 0,
+            bot_tick_millis: 100,
+            bot_hosts: Vec::new(),
+            max_bot_games_per_client: 2,
+            demo_tick_millis: 150,
+            ws_ping_secs: 10,
+            ws_idle_timeout_secs: 30,
+            disconnect_grace_secs: 10,
+            max_spectators: 100,
+            reactions_per_minute: 20,
+            max_ticks_per_sec: 60,
+            tick_burst: 100,
+            ai_slots: None,
+            reuse_port: false,
+            drain_timeout_secs: 30,
+            log_level: None,
+            theme: ThemeManifest::default(),
+            theme_dir: None,
+            locale_dir: None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from the file named by `SNAKE_CONFIG`
+    /// Falls back to `config.toml`, or the defaults when that file does not exist
+    /// `SNAKE_ADMIN_TOKEN`, `SNAKE_SCORE_SECRET` and `SNAKE_COOKIE_SECRET` override the admin
+    /// token, score secret and cookie secret from the file
+    pub fn load() -> Result<Config, String> {
+        let path = Config::path();
+        let mut config = if Path::new(&path).exists() {
+            Config::from_file(&path)?
+        } else {
+            Config::default()
+        };
+        if let Ok(token) = env::var("SNAKE_ADMIN_TOKEN") {
+            config.admin_token = Some(token);
+        }
+        if let Ok(secret) = env::var("SNAKE_SCORE_SECRET") {
+            config.score_secret = Some(secret);
+        }
+        if let Ok(secret) = env::var("SNAKE_COOKIE_SECRET") {
+            config.cookie_secret = Some(secret);
+        }
+        if let Some(level) = &config.log_level {
+            tracing_subscriber::EnvFilter::try_new(level)
+                .map_err(|e| format!("invalid log_level {}: {}", level, e))?;
+        }
+        config.theme.validate()?;
+        #[cfg(feature = "onnx")]
+        config.ai.model.load()?;
+        Ok(config)
+    }
+
+    /// Path of the configuration file, named by `SNAKE_CONFIG` or `config.toml`
+    pub fn path() -> String {
+        env::var("SNAKE_CONFIG").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string())
+    }
+
+    /// Carries the settings read only at startup over from the running configuration
+    /// Returns the names of those this configuration changes, which need a restart
+    pub fn keep_startup_settings(&mut self, running: &Config) -> Vec<&'static str> {
+        let kept = [
+            ("grpc_port", keep(&mut self.grpc_port, &running.grpc_port)),
+            (
+                "checkpoint_dir",
+                keep(&mut self.checkpoint_dir, &running.checkpoint_dir),
+            ),
+            ("level_dir", keep(&mut self.level_dir, &running.level_dir)),
+            (
+                "locale_dir",
+                keep(&mut self.locale_dir, &running.locale_dir),
+            ),
+            (
+                "score_secret",
+                keep(&mut self.score_secret, &running.score_secret),
+            ),
+            (
+                "cookie_secret",
+                keep(&mut self.cookie_secret, &running.cookie_secret),
+            ),
+            (
+                "oauth.account_file",
+                keep(&mut self.oauth.account_file, &running.oauth.account_file),
+            ),
+            ("audit_log", keep(&mut self.audit_log, &running.audit_log)),
+            ("match_log", keep(&mut self.match_log, &running.match_log)),
+            (
+                "campaign_file",
+                keep(&mut self.campaign_file, &running.campaign_file),
+            ),
+            ("ai_slots", keep(&mut self.ai_slots, &running.ai_slots)),
+            (
+                "reuse_port",
+                keep(&mut self.reuse_port, &running.reuse_port),
+            ),
+            (
+                "drain_timeout_secs",
+                keep(&mut self.drain_timeout_secs, &running.drain_timeout_secs),
+            ),
+        ];
+        kept.into_iter()
+            .filter(|(_, changed)| *changed)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Parses a configuration file
+    pub fn from_file(path: &str) -> Result<Config, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        toml::from_str(&contents).map_err(|e| format!("invalid config {}: {}", path, e))
+    }
+}
+
+/// Restores a setting to its running value; returns whether it differed
+fn keep<T: Clone + PartialEq>(setting: &mut T, running: &T) -> bool {
+    let changed = setting != running;
+    setting.clone_from(running);
+    changed
+}
@@ -0,0 +1,87 @@
+use crate::server::{AppState, NewGameOptions};
+use crate::session::Session;
+use actix_web::{web, HttpResponse};
+use rand::seq::IteratorRandom;
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+/// Most demo games running at once
+pub const MAX_DEMOS: usize = 4;
+
+/// Plays a demo game with the greedy AI until it ends or its session is removed
+/// Ticks follow each other `demo_tick_millis` apart; demos keep their session alive while
+/// they play and stop when the server starts shutting down
+async fn play(data: web::Data<AppState>, session_id: String) {
+    let period = Duration::from_millis(data.config().demo_tick_millis.max(1));
+    let mut interval = actix_web::rt::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        if data.is_draining() {
+            return;
+        }
+        let mut sessions = data.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&session_id) else {
+            return;
+        };
+        if session.game.game_over {
+            return;
+        }
+        session.touch();
+        session.game.ai_move();
+        session.tick();
+        data.record_if_finished(&session_id, session);
+    }
+}
+
+/// IDs of the demo games still playing
+fn live_demos(sessions: &HashMap<String, Session>) -> Vec<String> {
+    sessions
+        .iter()
+        .filter(|(_, session)| session.demo && !session.game.game_over)
+        .map(|(session_id, _)| session_id.clone())
+        .collect()
+}
+
+/// Starts a demo: a game the server plays with the AI, for landing pages to show
+/// Demos have no owner and no token; they are never ranked, counted in the heatmaps,
+/// checkpointed or handed over
+/// Responds with the session ID to spectate, or 429 Too Many Requests while `MAX_DEMOS`
+/// demos are playing
+pub async fn start(data: web::Data<AppState>) -> HttpResponse {
+    // Demos start at once, whatever countdown games get by default
+    let options = NewGameOptions {
+        countdown: Some(0),
+        ..NewGameOptions::default()
+    };
+    let (session_id, mut session) = match data.new_session(None, options) {
+        Ok(created) => created,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({ "error": e })),
+    };
+    session.demo = true;
+    {
+        // Counted and stored under one lock, so concurrent starts cannot exceed the limit
+        let mut sessions = data.sessions.lock().unwrap();
+        if live_demos(&sessions).len() >= MAX_DEMOS {
+            return HttpResponse::TooManyRequests()
+                .json(json!({ "error": "too many demos are playing" }));
+        }
+        sessions.insert(session_id.clone(), session);
+    }
+    actix_web::rt::spawn(play(data.clone(), session_id.clone()));
+    HttpResponse::Ok().json(json!({ "session_id": session_id }))
+}
+
+/// Picks a demo that is still playing at random, for landing pages to spectate
+/// Responds with its session ID, or 404 when no demo is playing
+pub async fn current(data: web::Data<AppState>) -> HttpResponse {
+    match live_demos(&data.sessions.lock().unwrap())
+        .into_iter()
+        .choose(&mut rand::thread_rng())
+    {
+        Some(session_id) => HttpResponse::Ok().json(json!({ "session_id": session_id })),
+        None => HttpResponse::NotFound().json(json!({ "error": "no demo is playing" })),
+    }
+}
@@ -0,0 +1,154 @@
+use crate::leaderboard::Window;
+use crate::server::{filter_verified, AppState, Score, WindowQuery};
+use crate::share::{self, html_escape};
+use actix_web::http::header::HttpDate;
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::time::{Duration, SystemTime};
+
+/// Most top-10 arrivals kept for the feed
+pub const MAX_TOP_ENTRIES: usize = 50;
+
+/// Entry that entered the all-time top 10
+#[derive(Clone)]
+pub struct TopEntry {
+    pub(crate) score: Score,
+    /// Unix timestamp (seconds) the entry was ranked at
+    pub ranked_at: u64,
+}
+
+/// Item of the leaderboard feed
+struct FeedItem {
+    guid: String,
+    title: String,
+    /// Unix timestamp (seconds) of the item
+    at: u64,
+}
+
+/// Quotes a CSV field when it holds separators, quotes or line breaks, and keeps
+/// spreadsheets from reading fields starting like a formula as one
+fn csv_field(text: &str) -> String {
+    let text = if text.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", text)
+    } else {
+        text.to_string()
+    };
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+/// RFC 822 date of a Unix timestamp, as RSS dates are written
+fn rss_date(secs: u64) -> String {
+    HttpDate::from(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)).to_string()
+}
+
+/// Calendar date (`YYYY-MM-DD`, UTC) of a Unix timestamp
+fn calendar_date(secs: u64) -> String {
+    // Days to civil date, after Howard Hinnant's `civil_from_days`
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Exports the leaderboard of the current hourly, daily or weekly window, or of all time,
+/// as CSV with the columns `rank`, `name`, `score`, `verified` and `id`
+/// With `verified=true` only entries whose replay was verified are listed
+pub async fn leaderboard_csv(
+    query: web::Query<WindowQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let scores = data.leaderboard_of(query.window);
+    let mut csv = String::from("rank,name,score,verified,id\r\n");
+    for (index, score) in filter_verified(&scores, query.verified).iter().enumerate() {
+        csv.push_str(&format!(
+            "{},{},{},{},{}\r\n",
+            index + 1,
+            csv_field(&score.name),
+            score.score,
+            score.verified,
+            csv_field(&score.id)
+        ));
+    }
+    HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .body(csv)
+}
+
+/// RSS feed of entries that entered the all-time top 10 and of the winners of past daily
+/// leaderboards, newest first
+pub async fn leaderboard_rss(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    let mut items: Vec<FeedItem> = data
+        .top_entries
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|entry| FeedItem {
+            guid: format!("top-{}", entry.score.id),
+            title: format!(
+                "{} entered the top 10 with {} points",
+                entry.score.name, entry.score.score
+            ),
+            at: entry.ranked_at,
+        })
+        .collect();
+    let closed = data.closed_windows.lock().unwrap();
+    for past in closed.get(&Window::Daily).into_iter().flatten() {
+        if let Some(winner) = past.scores.first() {
+            items.push(FeedItem {
+                guid: format!("daily-{}", past.start),
+                title: format!(
+                    "{} won the daily leaderboard of {} with {} points",
+                    winner.name,
+                    calendar_date(past.start),
+                    winner.score
+                ),
+                at: past.end,
+            });
+        }
+    }
+    drop(closed);
+    items.sort_by(|a, b| b.at.cmp(&a.at).then_with(|| a.guid.cmp(&b.guid)));
+
+    let base = html_escape(&share::base_url(&req, &data));
+    let mut rss = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0">
+<channel>
+    <title>Snake leaderboard</title>
+    <link>{base}/</link>
+    <description>New top 10 entries and daily winners</description>
+"#
+    );
+    for item in &items {
+        rss.push_str(&format!(
+            r#"    <item>
+        <title>{}</title>
+        <link>{base}/</link>
+        <guid isPermaLink="false">{}</guid>
+        <pubDate>{}</pubDate>
+    </item>
+"#,
+            html_escape(&item.title),
+            html_escape(&item.guid),
+            rss_date(item.at)
+        ));
+    }
+    rss.push_str("</channel>\n</rss>\n");
+    HttpResponse::Ok()
+        .content_type("application/rss+xml; charset=utf-8")
+        .body(rss)
+}
@@ -0,0 +1,110 @@
+use crate::game::{DeathCause, Game, GameEvent};
+use serde::Serialize;
+
+/// Ticks before food expires or a combo window closes from which clients are warned
+pub const EXPIRY_WARNING_TICKS: u32 = 5;
+
+/// Cue for a client feedback effect, such as a sound or a vibration
+/// Derived by the server from the engine's events and state, so every client reacts to
+/// the same moments without inferring them from state changes
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "hint", rename_all = "snake_case")]
+pub enum FeedbackHint {
+    /// The snake ate for `points`; `combo` is the combo multiplier of the pickup, 1 without
+    /// a running combo, and `zone` whether a score zone raised the points
+    AteFood { points: u32, combo: u32, zone: bool },
+    /// The snake turned away from a wall or its own body right in front of it
+    NearMiss,
+    /// The food expires in `ticks_left` ticks, sent every tick of the warning period
+    FoodExpiring { ticks_left: u32 },
+    /// The food expired before the snake got to it
+    FoodExpired,
+    /// Fleeing food stepped away
+    FoodMoved,
+    /// The combo window closes in `ticks_left` ticks, sent every tick of the warning period
+    ComboExpiring { ticks_left: u32 },
+    /// The combo window closed
+    ComboEnded,
+    /// The controls were scrambled
+    ControlsScrambled,
+    /// A speed-run split was reached
+    Split { apples: u32 },
+    /// The game ended
+    GameOver { cause: DeathCause },
+    /// A key was picked up, opening its doors
+    KeyCollected,
+    /// A treasure was eaten for `points`
+    TreasureCollected { points: u32 },
+    /// The level's exit was reached
+    LevelCompleted,
+}
+
+/// Feedback hints for a tick, from the events it produced and the state after it
+pub fn hints(game: &Game, events: &[GameEvent]) -> Vec<FeedbackHint> {
+    let mut hints = Vec::new();
+    let mut food_replaced = false;
+    for event in events {
+        let hint = match *event {
+            GameEvent::AteFood {
+                position, points, ..
+            } => {
+                food_replaced = true;
+                FeedbackHint::AteFood {
+                    points,
+                    combo: game.combo_multiplier,
+                    zone: game.zone_multiplier(position) > 1,
+                }
+            }
+            GameEvent::FoodExpired { .. } => {
+                food_replaced = true;
+                FeedbackHint::FoodExpired
+            }
+            GameEvent::FoodMoved { .. } => FeedbackHint::FoodMoved,
+            GameEvent::ComboEnded => FeedbackHint::ComboEnded,
+            GameEvent::ControlsScrambled => FeedbackHint::ControlsScrambled,
+            GameEvent::Split { apples, .. } => FeedbackHint::Split { apples },
+            GameEvent::GameOver { cause } => FeedbackHint::GameOver { cause },
+            GameEvent::KeyCollected { .. } => FeedbackHint::KeyCollected,
+            GameEvent::TreasureCollected { points, .. } => {
+                FeedbackHint::TreasureCollected { points }
+            }
+            GameEvent::LevelCompleted { .. } => FeedbackHint::LevelCompleted,
+            _ => continue,
+        };
+        hints.push(hint);
+    }
+    if game.game_over {
+        return hints;
+    }
+    if near_miss(game) {
+        hints.push(FeedbackHint::NearMiss);
+    }
+    if let Some(ticks_left) = game.food_ticks_left {
+        if ticks_left <= EXPIRY_WARNING_TICKS && !food_replaced {
+            hints.push(FeedbackHint::FoodExpiring { ticks_left });
+        }
+    }
+    if let Some(ticks_left) = game.combo_ticks_left {
+        if ticks_left <= EXPIRY_WARNING_TICKS {
+            hints.push(FeedbackHint::ComboExpiring { ticks_left });
+        }
+    }
+    hints
+}
+
+/// Whether the snake turned on the last tick where going straight would have killed it
+fn near_miss(game: &Game) -> bool {
+    let mut inputs = game.inputs.iter().rev().filter(|input| input.snake == 0);
+    // Inputs are logged with the tick they took effect on, before the tick counter advances
+    if inputs.next().is_none_or(|turn| turn.tick + 1 != game.tick) {
+        return false;
+    }
+    let straight = inputs
+        .next()
+        .map_or(game.start.direction, |input| input.direction);
+    let Some(previous_head) = game.snake.get(1) else {
+        return false;
+    };
+    let ahead = game.step(*previous_head, straight);
+    game.blocks(ahead, straight) || game.snake.iter().skip(1).any(|segment| *segment == ahead)
+}
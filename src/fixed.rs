@@ -0,0 +1,51 @@
+#![deny(clippy::disallowed_types)]
+
+use std::ops::Add;
+
+/// Number of fractional bits of a `Fixed`
+const FRACTION_BITS: u32 = 16;
+
+/// Non-negative fixed-point number with 16 fractional bits
+/// The simulation measures distances with it instead of floats: integer arithmetic
+/// gives bit-identical results on every platform and compiler, so replays of a seed
+/// and input log reproduce the same states everywhere
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub struct Fixed(u64);
+
+impl Fixed {
+    pub const ZERO: Fixed = Fixed(0);
+    /// Largest representable value, standing in for "unreachable"
+    pub const MAX: Fixed = Fixed(u64::MAX);
+
+    /// Whole number
+    pub fn from_int(value: u32) -> Self {
+        Fixed((value as u64) << FRACTION_BITS)
+    }
+
+    /// Raw representation: the value times 2^16
+    pub fn to_bits(self) -> u64 {
+        self.0
+    }
+
+    /// Square root of a whole number, rounded down to the nearest representable value
+    pub fn sqrt(value: u64) -> Self {
+        let scaled = (value as u128) << (2 * FRACTION_BITS);
+        Fixed(scaled.isqrt().min(u64::MAX as u128) as u64)
+    }
+
+    /// Euclidean length of a vector with integer components
+    pub fn hypot(dx: i32, dy: i32) -> Self {
+        let dx = dx.unsigned_abs() as u64;
+        let dy = dy.unsigned_abs() as u64;
+        Fixed::sqrt(dx * dx + dy * dy)
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+
+    /// Saturates at `Fixed::MAX`, so "unreachable" stays unreachable
+    fn add(self, other: Fixed) -> Fixed {
+        Fixed(self.0.saturating_add(other.0))
+    }
+}
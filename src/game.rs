@@ -1,27 +1,540 @@
+#![deny(clippy::disallowed_types)]
+
+use crate::ai::{self, AiStrategy};
+use crate::fixed::Fixed;
+use crate::migration::SCHEMA_VERSION;
+use crate::rng::SeededRng;
+use crate::state_hash::StateHasher;
+use crate::topology::{self, Board, Solid, Topology};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 
-/// Represents a point in 2D space
+/// Random food positions tried before falling back to a scan of the free cells
+const FOOD_SPAWN_ATTEMPTS: u32 = 64;
+
+/// Maximum number of direction changes buffered between ticks
+pub const MAX_QUEUED_INPUTS: usize = 3;
+
+/// How many ticks late a timestamped input may arrive and still be applied at its tick
+pub const LATENCY_WINDOW_TICKS: u64 = 3;
+
+/// How many ticks a practice game can be rewound
+pub const PRACTICE_REWIND_TICKS: u64 = 50;
+
+/// Number of food items eaten between two speed-run splits
+pub const SPLIT_EVERY_APPLES: u32 = 10;
+
+/// Longest countdown before a game starts moving, in ticks
+pub const MAX_COUNTDOWN_TICKS: u32 = 300;
+
+/// Most moving hazards a game may have
+pub const MAX_HAZARDS: usize = 16;
+
+/// Most cells on the path of a moving hazard
+pub const MAX_HAZARD_PATH: usize = 64;
+
+/// Most terrain tiles a game may have
+pub const MAX_TILES: usize = 1024;
+
+/// Most one-way gates a game may have
+pub const MAX_GATES: usize = 256;
+
+/// Most keys, doors or treasures an objective may have, each
+pub const MAX_OBJECTIVE_ITEMS: usize = 64;
+
+/// Most points a single treasure may be worth
+pub const MAX_TREASURE_POINTS: u32 = 100;
+
+/// Represents a point on the board
 /// Used for both snake body segments and food position
 #[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Point {
     pub x: i32,
     pub y: i32,
+    /// Layer of layered boards; zero, and left out of the JSON, on flat boards
+    #[serde(default, skip_serializing_if = "is_ground_layer")]
+    pub z: i32,
+}
+
+fn is_ground_layer(z: &i32) -> bool {
+    *z == 0
 }
 
 /// Represents the possible directions the snake can move
+/// The diagonal directions only exist on hex boards, see `Topology::directions`
 #[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Down,
     Left,
     Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
+
+impl Direction {
+    /// The direction pointing the other way
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+            Direction::UpLeft => Direction::DownRight,
+            Direction::UpRight => Direction::DownLeft,
+            Direction::DownLeft => Direction::UpRight,
+            Direction::DownRight => Direction::UpLeft,
+        }
+    }
+
+    /// The direction after a turn: a quarter turn on square boards, a sixth on hex boards
+    pub fn turned(self, turn: Turn, hex: bool) -> Direction {
+        let ring: &[Direction] = if hex {
+            &HEX_CLOCKWISE
+        } else {
+            &SQUARE_CLOCKWISE
+        };
+        let Some(index) = ring.iter().position(|direction| *direction == self) else {
+            return self;
+        };
+        match turn {
+            Turn::Left => ring[(index + ring.len() - 1) % ring.len()],
+            Turn::Right => ring[(index + 1) % ring.len()],
+            Turn::Straight => self,
+        }
+    }
+}
+
+/// Directions of square boards in clockwise order
+const SQUARE_CLOCKWISE: [Direction; 4] = [
+    Direction::Up,
+    Direction::Right,
+    Direction::Down,
+    Direction::Left,
+];
+
+/// Directions of hex boards in clockwise order
+const HEX_CLOCKWISE: [Direction; 6] = [
+    Direction::UpRight,
+    Direction::Right,
+    Direction::DownRight,
+    Direction::DownLeft,
+    Direction::Left,
+    Direction::UpLeft,
+];
+
+/// Turn relative to the snake's heading, for voice commands, single-switch devices and bots
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Turn {
+    Left,
+    Right,
+    Straight,
+}
+
+/// Challenge modifiers chosen when a game is created
+/// They change how player input maps to movement; the AI steers unaffected
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Modifiers {
+    /// Every direction input moves the snake the opposite way
+    pub mirror_controls: bool,
+    /// Shuffle which input moves in which direction every time this many more food items are eaten
+    pub random_control_scramble_every_n_apples: Option<u32>,
+}
+
+impl Modifiers {
+    /// Whether no modifier is active
+    pub fn is_empty(&self) -> bool {
+        *self == Modifiers::default()
+    }
+}
+
+/// How the food behaves between being eaten
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FoodBehavior {
+    /// The food stays where it spawned
+    #[default]
+    Static,
+    /// Every `every_ticks` ticks the food steps one cell away from the snake head,
+    /// never onto the snake, walls or ladders; it stays put when cornered
+    Fleeing { every_ticks: u32 },
+}
+
+/// Limited food lifetime: uneaten food disappears and respawns elsewhere
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FoodExpiry {
+    /// Ticks a food item stays on the board
+    pub lifetime_ticks: u32,
+    /// Extra points for eating food quickly, scaled by its remaining lifetime
+    #[serde(default)]
+    pub quick_bonus: u32,
+}
+
+impl FoodExpiry {
+    /// Points for eating food with `ticks_left` of its lifetime remaining
+    pub fn points(&self, ticks_left: u32) -> u32 {
+        let bonus = self.quick_bonus as u64 * ticks_left as u64 / self.lifetime_ticks.max(1) as u64;
+        1 + bonus as u32
+    }
+}
+
+/// Combo scoring: food eaten in quick succession is worth more
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ComboScoring {
+    /// Ticks after a pickup within which the next one raises the multiplier
+    pub window_ticks: u32,
+    /// Highest multiplier a combo can reach; unlimited when unset
+    #[serde(default)]
+    pub max_multiplier: Option<u32>,
+}
+
+/// How much the snake grows from eating
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Growth {
+    /// Segments added per growing pickup, between 1 and `MAX_GROWTH_SEGMENTS`
+    pub segments: u32,
+    /// Only every n-th food item eaten makes the snake grow
+    pub every_n_apples: u32,
+}
+
+/// Largest number of segments a single pickup may add
+pub const MAX_GROWTH_SEGMENTS: u32 = 5;
+
+impl Default for Growth {
+    fn default() -> Self {
+        Growth {
+            segments: 1,
+            every_n_apples: 1,
+        }
+    }
+}
+
+impl Growth {
+    /// Segments gained by eating the `food_eaten`-th food item
+    /// Out-of-range settings are clamped rather than rejected
+    pub fn segments_for(&self, food_eaten: u32) -> u32 {
+        if food_eaten.is_multiple_of(self.every_n_apples.max(1)) {
+            self.segments.clamp(1, MAX_GROWTH_SEGMENTS)
+        } else {
+            0
+        }
+    }
+}
+
+/// Snake a game starts with
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Start {
+    /// Number of segments, laid out in a line behind the head
+    pub length: u32,
+    /// Cell of the head; the center of the board when unset
+    pub position: Option<Point>,
+    /// Direction the snake starts moving in
+    pub direction: Direction,
+}
+
+impl Default for Start {
+    fn default() -> Self {
+        Start {
+            length: 1,
+            position: None,
+            direction: INITIAL_DIRECTION,
+        }
+    }
+}
+
+/// Rectangle of cells where eaten food is worth more points
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Zone {
+    /// Top-left cell of the zone
+    pub x: i32,
+    pub y: i32,
+    /// Layer of the zone on layered boards
+    #[serde(default)]
+    pub z: i32,
+    pub width: i32,
+    pub height: i32,
+    /// Factor applied to the points of food eaten inside the zone
+    pub multiplier: u32,
+}
+
+impl Zone {
+    /// Whether a cell lies inside the zone
+    pub fn contains(&self, point: Point) -> bool {
+        point.z == self.z
+            && (self.x..self.x + self.width).contains(&point.x)
+            && (self.y..self.y + self.height).contains(&point.y)
+    }
+}
+
+/// Pair of linked cells: entering either one emits the snake from the other, moving on
+/// in the same direction
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Portal {
+    pub a: Point,
+    pub b: Point,
+}
+
+/// Hazard patrolling a path of cells, back and forth between its ends one cell every
+/// `period` ticks; a snake whose head meets it, or whose body it moves onto, dies
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hazard {
+    /// Cells of the patrol in order; consecutive cells need not be adjacent
+    pub path: Vec<Point>,
+    /// Ticks between two steps
+    #[serde(default = "default_hazard_period")]
+    pub period: u32,
+}
+
+fn default_hazard_period() -> u32 {
+    1
+}
+
+impl Hazard {
+    /// Cell of the hazard at a tick
+    /// phase: Steps of the round trip the hazard made before the first tick
+    pub fn position(&self, tick: u64, phase: u64) -> Option<Point> {
+        let last = self.path.len().checked_sub(1)? as u64;
+        if last == 0 {
+            return self.path.first().copied();
+        }
+        let step = (tick / u64::from(self.period.max(1)) + phase) % (2 * last);
+        let index = if step <= last { step } else { 2 * last - step };
+        self.path.get(index as usize).copied()
+    }
+}
+
+/// Ground of a cell that changes how snakes move over it
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Terrain {
+    /// A snake whose head moves onto it slides one cell further on the same tick
+    Ice,
+    /// A snake whose head starts a tick on it moves two cells that tick
+    Speed,
+}
+
+/// Cell with a terrain
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Tile {
+    pub cell: Point,
+    pub terrain: Terrain,
+}
+
+/// One-way gate: a cell snakes may only enter moving in its direction
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Gate {
+    pub cell: Point,
+    pub direction: Direction,
+}
+
+/// Key lying on the board; picking it up opens every door of the same `key`
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Key {
+    pub cell: Point,
+    pub key: u32,
+}
+
+/// Door that stops snakes like a wall until one of them picks up its key
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Door {
+    pub cell: Point,
+    pub key: u32,
+}
+
+/// Bonus food a level places, worth `points` and eaten once
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Treasure {
+    pub cell: Point,
+    pub points: u32,
+}
+
+/// Keys and doors goal of a level: snakes collect keys to open the doors guarding
+/// treasures or the exit, and entering the exit completes the level
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Objective {
+    #[serde(default)]
+    pub keys: Vec<Key>,
+    #[serde(default)]
+    pub doors: Vec<Door>,
+    #[serde(default)]
+    pub treasures: Vec<Treasure>,
+    /// Cell that completes the level once a snake's head enters it
+    #[serde(default)]
+    pub exit: Option<Point>,
 }
 
+impl Objective {
+    /// Cells of the keys, doors, treasures and exit
+    fn cells(&self) -> impl Iterator<Item = Point> + '_ {
+        let keys = self.keys.iter().map(|key| key.cell);
+        let doors = self.doors.iter().map(|door| door.cell);
+        let treasures = self.treasures.iter().map(|treasure| treasure.cell);
+        keys.chain(doors).chain(treasures).chain(self.exit)
+    }
+}
+
+/// How fast a level's objective was completed
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Completion {
+    /// Ticks from the end of the countdown to entering the exit
+    pub ticks: u64,
+    /// Direction changes made on the way
+    pub turns: u32,
+}
+
+/// Speed-run split: when a game reached a number of food items eaten
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Split {
+    /// Food items eaten at the split
+    pub apples: u32,
+    /// Tick at which the split was reached
+    pub tick: u64,
+    /// Wall-clock milliseconds from the creation of the game's session
+    pub elapsed_ms: u64,
+}
+
+/// A direction change recorded in the input log
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Input {
+    /// Tick at which the direction took effect
+    pub tick: u64,
+    /// Direction used from that tick on
+    pub direction: Direction,
+    /// Snake that changed direction: 0 for the first, 1 for the partner of a co-op game
+    /// Left out of the JSON for the first snake, so single-player logs keep their checksums
+    #[serde(default, skip_serializing_if = "is_first_snake")]
+    pub snake: u8,
+}
+
+fn is_first_snake(snake: &u8) -> bool {
+    *snake == 0
+}
+
+/// Second snake of a co-op game, steered by the second player
+/// Both snakes share the food and the score, and the game ends when either crashes
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Partner {
+    /// Body of the snake, head first
+    pub snake: VecDeque<Point>,
+    /// Current direction of the snake
+    pub direction: Direction,
+    /// Segments still to be added to this snake
+    #[serde(default)]
+    pub pending_growth: u32,
+    /// Direction changes waiting to be applied, one per tick
+    #[serde(skip)]
+    pub input_queue: VecDeque<Direction>,
+}
+
+/// How a timestamped direction change was applied
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputTiming {
+    /// The input was on time and buffered for the next tick
+    Queued,
+    /// The input was late and applied retroactively, re-simulating the given number of ticks
+    Compensated { ticks: u64 },
+    /// The input was too late to compensate and was buffered for the next tick
+    Late,
+    /// The board has no such direction; the input was ignored
+    Rejected,
+    /// The input repeated the last buffered direction, or the current one with none
+    /// buffered, and was merged with it
+    Coalesced,
+    /// The buffer was full with `MAX_QUEUED_INPUTS` inputs for the upcoming ticks; the input
+    /// was dropped
+    QueueFull,
+}
+
+/// Stage of a game's life
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// The countdown is running; the snake holds still while inputs are buffered
+    Starting,
+    /// The snake is moving
+    Running,
+    /// The game has ended
+    Over,
+}
+
+/// Reason a game ended
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeathCause {
+    Wall,
+    SelfCollision,
+    /// A snake of a co-op game ran into the other one, or both moved onto the same cell
+    PartnerCollision,
+    /// A moving hazard hit the snake
+    Hazard,
+    /// A snake entered a one-way gate against its direction
+    Gate,
+    /// A snake ran into a door before picking up its key
+    Door,
+}
+
+/// Something notable that happened during a tick
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GameEvent {
+    /// The snake ate the food at `position` for `points`, raising the score to `score`
+    AteFood {
+        position: Point,
+        points: u32,
+        score: u32,
+    },
+    /// The combo window ran out and the multiplier went back to 1
+    ComboEnded,
+    /// New food appeared at `position`
+    FoodSpawned { position: Point },
+    /// The snake crashed and the game ended
+    GameOver { cause: DeathCause },
+    /// The control scramble modifier reassigned the direction inputs
+    ControlsScrambled,
+    /// A speed-run split was reached
+    Split { apples: u32, elapsed_ms: u64 },
+    /// A practice game was rewound by `ticks`
+    Rewound { ticks: u64 },
+    /// Fleeing food stepped to `position`
+    FoodMoved { position: Point },
+    /// The food at `position` was not eaten in time and disappeared
+    FoodExpired { position: Point },
+    /// The owner's realtime connections have all been gone for the disconnect grace period
+    PlayerDisconnected,
+    /// The owner connected again after being reported disconnected
+    PlayerReconnected,
+    /// The countdown advanced; the snake starts moving in `ticks_left` ticks
+    Countdown { ticks_left: u32 },
+    /// The countdown ended; the snake moves from the next tick on
+    Started,
+    /// A snake picked up the key at `position`, opening the doors of `key`
+    KeyCollected { position: Point, key: u32 },
+    /// A snake ate the treasure at `position` for `points`, raising the score to `score`
+    TreasureCollected {
+        position: Point,
+        points: u32,
+        score: u32,
+    },
+    /// A snake entered the exit, completing the level and ending the game
+    LevelCompleted { completion: Completion },
+}
+
+/// Direction every new snake starts moving in
+pub const INITIAL_DIRECTION: Direction = Direction::Right;
+
 /// Main game state structure
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Game {
+    /// Schema version of the serialized game, see `migration`
+    #[serde(default)]
+    pub schema_version: u32,
     /// Snake body represented as a double-ended queue
     /// First element is the head, last is the tail
     pub snake: VecDeque<Point>,
@@ -29,154 +542,1631 @@ pub struct Game {
     pub food: Point,
     /// Current direction of snake movement
     pub direction: Direction,
-    /// Number of food items eaten (score)
+    /// Points scored; one per food item unless bonuses apply
     pub score: u32,
+    /// Number of food items eaten
+    #[serde(default)]
+    pub food_eaten: u32,
     /// Whether the game has ended
     pub game_over: bool,
     /// Game board width
     pub width: i32,
     /// Game board height
     pub height: i32,
+    /// Shape of the board: walls, wrapping edges and obstacles
+    pub topology: Board,
+    /// Challenge modifiers active in this game
+    pub modifiers: Modifiers,
+    /// How the food moves between being eaten
+    #[serde(default)]
+    pub food_behavior: FoodBehavior,
+    /// Lifetime of uneaten food; food never expires when unset
+    /// Set with `set_food_expiry` so the current food's timer starts too
+    #[serde(default)]
+    pub food_expiry: Option<FoodExpiry>,
+    /// Ticks until the current food expires, for client countdowns
+    #[serde(default)]
+    pub food_ticks_left: Option<u32>,
+    /// Combo scoring rules; pickups score without multiplier when unset
+    #[serde(default)]
+    pub combo: Option<ComboScoring>,
+    /// Multiplier of the running combo, applied to the last pickup's points; 1 without combo
+    #[serde(default = "default_combo_multiplier")]
+    pub combo_multiplier: u32,
+    /// Ticks left in the combo window; unset when no combo is running
+    #[serde(default)]
+    pub combo_ticks_left: Option<u32>,
+    /// Growth per food item eaten
+    #[serde(default)]
+    pub growth: Growth,
+    /// Segments still to be added; the tail stays in place while this is above zero
+    #[serde(default)]
+    pub pending_growth: u32,
+    /// Snake the game started with
+    #[serde(default)]
+    pub start: Start,
+    /// Ticks of the countdown before the snake starts moving, at most `MAX_COUNTDOWN_TICKS`
+    #[serde(default)]
+    pub countdown: u32,
+    /// Second snake of a co-op game
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partner: Option<Partner>,
+    /// Practice game: can be rewound and is not ranked on the leaderboard
+    #[serde(default)]
+    pub practice: bool,
+    /// Speed-run splits taken every `SPLIT_EVERY_APPLES` food items
+    /// They are wall-clock times taken by the session, so re-simulation does not produce them
+    #[serde(default)]
+    pub splits: Vec<Split>,
+    /// Score multiplier zones of the level
+    #[serde(default)]
+    pub zones: Vec<Zone>,
+    /// Teleport portals of the level
+    /// Set with `set_portals` so they are validated against the board
+    #[serde(default)]
+    pub portals: Vec<Portal>,
+    /// Moving hazards of the level
+    /// Set with `set_hazards` so they are validated against the board
+    #[serde(default)]
+    pub hazards: Vec<Hazard>,
+    /// Ice and speed tiles of the level
+    /// Set with `set_tiles` so they are validated against the board
+    #[serde(default)]
+    pub tiles: Vec<Tile>,
+    /// One-way gates of the level
+    /// Set with `set_gates` so they are validated against the board
+    #[serde(default)]
+    pub gates: Vec<Gate>,
+    /// Keys and doors goal of the level, if it has one
+    /// Set with `set_objective` so it is validated against the board
+    #[serde(default)]
+    pub objective: Option<Objective>,
+    /// Keys picked up, whose doors are open
+    #[serde(default)]
+    pub keys_held: Vec<u32>,
+    /// Cells of the keys and treasures picked up
+    #[serde(default)]
+    pub collected: Vec<Point>,
+    /// How fast the objective was completed, once a snake entered the exit
+    #[serde(default)]
+    pub completion: Option<Completion>,
+    /// Custom level the game is played on, if any
+    #[serde(default)]
+    pub level: Option<String>,
+    /// Signed token certifying the final score, issued by the server once the game is over
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score_token: Option<String>,
+    /// Scrambled controls: the direction moved for each of the board's directions, in
+    /// `Topology::directions` order; empty while the controls are not scrambled
+    #[serde(skip)]
+    pub controls: Vec<Direction>,
+    /// Direction changes waiting to be applied, one per tick
+    #[serde(skip)]
+    pub input_queue: VecDeque<Direction>,
+    /// Number of steps simulated so far
+    pub tick: u64,
+    /// Hash chained over the state after every tick so far, see `state_hash`
+    /// Two simulations of a game agree up to a tick exactly when their rolling hashes
+    /// at that tick are equal
+    #[serde(skip)]
+    pub rolling_hash: u64,
+    /// Seed of the random generator used for food placement
+    #[serde(skip)]
+    pub seed: u64,
+    /// Random generator used for food placement
+    #[serde(skip, default = "default_rng")]
+    rng: SeededRng,
+    /// Log of direction changes, enough to replay the game from its seed
+    #[serde(skip)]
+    pub inputs: Vec<Input>,
+    /// Snapshots taken before each of the most recent ticks, oldest first
+    #[serde(skip)]
+    pub history: VecDeque<Game>,
+    /// Number of snapshots kept in `history`; zero disables snapshots
+    #[serde(skip)]
+    pub history_len: usize,
+}
+
+fn default_rng() -> SeededRng {
+    SeededRng::new(0)
+}
+
+fn default_combo_multiplier() -> u32 {
+    1
+}
+
+/// Everything that defines a game before its first input: board, rules and seed
+/// A game is its config plus its input log; any of its states is derived by folding
+/// the log over the initial state, which is how replays, checkpoints and score
+/// verification rebuild games
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameConfig {
+    /// Shape of the board
+    pub topology: Board,
+    /// Challenge modifiers
+    #[serde(default)]
+    pub modifiers: Modifiers,
+    /// How the food moves between being eaten
+    #[serde(default)]
+    pub food_behavior: FoodBehavior,
+    /// Lifetime of uneaten food
+    #[serde(default)]
+    pub food_expiry: Option<FoodExpiry>,
+    /// Combo scoring rules
+    #[serde(default)]
+    pub combo: Option<ComboScoring>,
+    /// Growth per food item eaten
+    #[serde(default)]
+    pub growth: Growth,
+    /// Snake the game starts with
+    #[serde(default)]
+    pub start: Start,
+    /// Ticks of the countdown before the snake starts moving
+    #[serde(default)]
+    pub countdown: u32,
+    /// Whether a second player steers a second snake, see `Partner`
+    #[serde(default)]
+    pub coop: bool,
+    /// Whether the game is a practice game
+    #[serde(default)]
+    pub practice: bool,
+    /// Score multiplier zones
+    #[serde(default)]
+    pub zones: Vec<Zone>,
+    /// Teleport portals
+    #[serde(default)]
+    pub portals: Vec<Portal>,
+    /// Moving hazards
+    #[serde(default)]
+    pub hazards: Vec<Hazard>,
+    /// Ice and speed tiles
+    #[serde(default)]
+    pub tiles: Vec<Tile>,
+    /// One-way gates
+    #[serde(default)]
+    pub gates: Vec<Gate>,
+    /// Keys and doors goal
+    #[serde(default)]
+    pub objective: Option<Objective>,
+    /// Custom level the game is played on
+    #[serde(default)]
+    pub level: Option<String>,
+    /// Seed of the game's random generator
+    pub seed: u64,
+}
+
+impl GameConfig {
+    /// Builds the state of the game before its first tick
+    /// Fails when the portals, hazards, tiles, gates, objective or the start snake do not fit
+    /// on the board
+    pub fn start(&self) -> Result<Game, String> {
+        let mut game = Game::with_topology(self.topology.clone(), self.seed);
+        game.zones = self.zones.clone();
+        game.set_portals(self.portals.clone())?;
+        game.set_hazards(self.hazards.clone())?;
+        game.set_tiles(self.tiles.clone())?;
+        game.set_gates(self.gates.clone())?;
+        game.set_objective(self.objective.clone())?;
+        game.set_start(self.start)?;
+        if self.coop {
+            game.add_partner()?;
+        }
+        game.modifiers = self.modifiers;
+        game.food_behavior = self.food_behavior;
+        game.set_food_expiry(self.food_expiry);
+        game.combo = self.combo;
+        game.growth = self.growth;
+        game.countdown = self.countdown.min(MAX_COUNTDOWN_TICKS);
+        game.practice = self.practice;
+        game.level = self.level.clone();
+        Ok(game)
+    }
+
+    /// Folds an input log over the initial state, yielding the initial state followed by
+    /// the state after each of `ticks` ticks; every input takes effect on its tick
+    /// Fails like `start`
+    pub fn fold<'a>(
+        &self,
+        inputs: &'a [Input],
+        ticks: u64,
+    ) -> Result<impl Iterator<Item = Game> + 'a, String> {
+        let mut game = self.start()?;
+        let mut inputs = inputs.iter().peekable();
+        let mut tick = 0;
+        Ok(std::iter::once(game.clone()).chain(std::iter::from_fn(move || {
+            if tick >= ticks {
+                return None;
+            }
+            while let Some(input) = inputs.next_if(|input| input.tick <= tick) {
+                game.apply_input(input);
+            }
+            game.update();
+            tick += 1;
+            Some(game.clone())
+        })))
+    }
 }
 
 impl Game {
-    /// Creates a new game instance with specified dimensions
+    /// Creates a new game instance with specified dimensions and a random seed
     /// Initializes snake at the center of the board
     pub fn new(width: i32, height: i32) -> Self {
+        Game::with_seed(width, height, rand::thread_rng().gen())
+    }
+
+    /// Creates a new game instance whose food placement is derived from `seed`
+    /// Two games with the same seed and input log evolve identically
+    pub fn with_seed(width: i32, height: i32, seed: u64) -> Self {
+        Game::with_topology(Board::Solid(Solid { width, height }), seed)
+    }
+
+    /// Creates a new game instance on a board of the given topology
+    /// The board dimensions are the topology's bounding box
+    pub fn with_topology(topology: Board, seed: u64) -> Self {
+        let (width, height) = topology.size();
         let mut game = Game {
+            schema_version: SCHEMA_VERSION,
             snake: VecDeque::new(),
-            food: Point { x: 0, y: 0 },
-            direction: Direction::Right,
+            food: Point { x: 0, y: 0, z: 0 },
+            direction: INITIAL_DIRECTION,
             score: 0,
+            food_eaten: 0,
             game_over: false,
             width,
             height,
+            topology,
+            modifiers: Modifiers::default(),
+            food_behavior: FoodBehavior::default(),
+            food_expiry: None,
+            food_ticks_left: None,
+            combo: None,
+            combo_multiplier: 1,
+            combo_ticks_left: None,
+            growth: Growth::default(),
+            pending_growth: 0,
+            start: Start::default(),
+            countdown: 0,
+            partner: None,
+            practice: false,
+            splits: Vec::new(),
+            zones: Vec::new(),
+            portals: Vec::new(),
+            hazards: Vec::new(),
+            tiles: Vec::new(),
+            gates: Vec::new(),
+            objective: None,
+            keys_held: Vec::new(),
+            collected: Vec::new(),
+            completion: None,
+            level: None,
+            score_token: None,
+            controls: Vec::new(),
+            input_queue: VecDeque::new(),
+            tick: 0,
+            rolling_hash: 0,
+            seed,
+            rng: SeededRng::new(seed),
+            inputs: Vec::new(),
+            history: VecDeque::new(),
+            history_len: 0,
         };
 
         // Initialize snake at the center
-        game.snake.push_back(Point {
-            x: width / 2,
-            y: height / 2,
-        });
+        game.snake.push_back(topology::start_point(width, height));
         game.spawn_food();
         game
     }
 
+    /// Config the game was started with; with `inputs` it defines every state of the game
+    pub fn config(&self) -> GameConfig {
+        GameConfig {
+            topology: self.topology.clone(),
+            modifiers: self.modifiers,
+            food_behavior: self.food_behavior,
+            food_expiry: self.food_expiry,
+            combo: self.combo,
+            growth: self.growth,
+            start: self.start,
+            countdown: self.countdown,
+            coop: self.partner.is_some(),
+            practice: self.practice,
+            zones: self.zones.clone(),
+            portals: self.portals.clone(),
+            hazards: self.hazards.clone(),
+            tiles: self.tiles.clone(),
+            gates: self.gates.clone(),
+            objective: self.objective.clone(),
+            level: self.level.clone(),
+            seed: self.seed,
+        }
+    }
+
+    /// Hash of everything that determines how the game continues: the snake, food, score,
+    /// timers, scrambled controls, tick and the position of the random generator
+    /// Equal configs and input logs give equal hashes on every platform, which replays
+    /// record so re-simulations can be checked for divergence
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::default();
+        hasher.write_u64(self.tick);
+        hasher.write_u32(self.score);
+        hasher.write_u32(self.food_eaten);
+        hasher.write_bytes(&[self.game_over as u8, self.direction as u8]);
+        hasher.write_point(self.food);
+        hasher.write_u64(self.snake.len() as u64);
+        for segment in &self.snake {
+            hasher.write_point(*segment);
+        }
+        hasher.write_bytes(&self.controls.iter().map(|d| *d as u8).collect::<Vec<_>>());
+        hasher.write_u32(self.pending_growth);
+        hasher.write_u32(self.combo_multiplier);
+        hasher.write_option_u32(self.combo_ticks_left);
+        hasher.write_option_u32(self.food_ticks_left);
+        hasher.write_u64(self.rng.state());
+        // Only co-op games hash a partner, so the hashes of other games stay as they were
+        if let Some(partner) = &self.partner {
+            hasher.write_bytes(&[partner.direction as u8]);
+            hasher.write_u64(partner.snake.len() as u64);
+            for segment in &partner.snake {
+                hasher.write_point(*segment);
+            }
+            hasher.write_u32(partner.pending_growth);
+        }
+        // Likewise only games with an objective hash its progress
+        if self.objective.is_some() {
+            hasher.write_u64(self.collected.len() as u64);
+            for cell in &self.collected {
+                hasher.write_point(*cell);
+            }
+            hasher.write_bytes(&[self.completion.is_some() as u8]);
+        }
+        hasher.finish()
+    }
+
     /// Updates the game state for one time step
     /// Handles movement, collisions, and food consumption
-    pub fn update(&mut self) {
+    /// Returns the events that happened during the step
+    pub fn update(&mut self) -> Vec<GameEvent> {
         if self.game_over {
-            return;
+            return Vec::new();
         }
+        let events = self.advance();
+        let mut hasher = StateHasher::resume(self.rolling_hash);
+        hasher.write_u64(self.state_hash());
+        self.rolling_hash = hasher.finish();
+        events
+    }
 
-        let head = self.snake.front().unwrap();
-        // Calculate new head position based on current direction
-        let new_head = match self.direction {
-            Direction::Up => Point {
-                x: head.x,
-                y: head.y - 1,
-            },
-            Direction::Down => Point {
-                x: head.x,
-                y: head.y + 1,
-            },
-            Direction::Left => Point {
-                x: head.x - 1,
-                y: head.y,
+    /// Simulates one tick of a running game
+    fn advance(&mut self) -> Vec<GameEvent> {
+        // Remember the state before this tick so late inputs can be applied retroactively
+        if self.history_len > 0 {
+            let snapshot = self.snapshot();
+            self.history.push_back(snapshot);
+            while self.history.len() > self.history_len {
+                self.history.pop_front();
+            }
+        }
+
+        // The snake holds still during the countdown; inputs stay buffered for its first move
+        if self.tick < u64::from(self.countdown) {
+            self.tick += 1;
+            return vec![match self.countdown_ticks_left() {
+                0 => GameEvent::Started,
+                ticks_left => GameEvent::Countdown { ticks_left },
+            }];
+        }
+
+        // Apply the oldest buffered direction change of each snake, if any
+        if let Some(direction) = self.input_queue.pop_front() {
+            self.direction = direction;
+        }
+        if let Some(partner) = &mut self.partner {
+            if let Some(direction) = partner.input_queue.pop_front() {
+                partner.direction = direction;
+            }
+        }
+
+        // Log the directions whenever they differ from the ones used on the previous tick
+        self.log_direction(0, self.direction);
+        if let Some(direction) = self.partner.as_ref().map(|partner| partner.direction) {
+            self.log_direction(1, direction);
+        }
+        self.tick += 1;
+
+        // Speed strips move the snakes starting the tick on them twice
+        let speeding = (
+            self.terrain(self.snake[0]) == Some(Terrain::Speed),
+            self.partner
+                .as_ref()
+                .is_some_and(|partner| self.terrain(partner.snake[0]) == Some(Terrain::Speed)),
+        );
+        let mut events = match self.move_snakes(true, true) {
+            Ok(events) => events,
+            Err(cause) => {
+                self.game_over = true;
+                return vec![GameEvent::GameOver { cause }];
+            }
+        };
+        let mut ate = !events.is_empty();
+        events.extend(self.reach_objective());
+        if self.game_over {
+            return events;
+        }
+        // Ice slides the snakes that moved onto it one cell further, in the same direction
+        let extra = (
+            speeding.0 || self.terrain(self.snake[0]) == Some(Terrain::Ice),
+            speeding.1
+                || self
+                    .partner
+                    .as_ref()
+                    .is_some_and(|partner| self.terrain(partner.snake[0]) == Some(Terrain::Ice)),
+        );
+        if extra.0 || extra.1 {
+            match self.move_snakes(extra.0, extra.1) {
+                Ok(more) => {
+                    ate |= !more.is_empty();
+                    events.extend(more);
+                    events.extend(self.reach_objective());
+                    if self.game_over {
+                        return events;
+                    }
+                }
+                Err(cause) => {
+                    self.game_over = true;
+                    events.push(GameEvent::GameOver { cause });
+                    return events;
+                }
+            }
+        }
+
+        // Timers run on ticks without a pickup
+        if !ate {
+            events.extend(self.move_food());
+            events.extend(self.expire_food());
+            events.extend(self.tick_combo());
+        }
+        events
+    }
+
+    /// Moves the chosen snakes one cell on in their direction, eating and growing
+    /// Returns the events of the food eaten, none when no food was eaten, or the death
+    /// cause when a snake crashes; neither snake moves then
+    fn move_snakes(
+        &mut self,
+        move_own: bool,
+        move_partner: bool,
+    ) -> Result<Vec<GameEvent>, DeathCause> {
+        // Calculate new head positions based on the current directions
+        let new_head = move_own.then(|| self.step(self.snake[0], self.direction));
+        let partner_head = self
+            .partner
+            .as_ref()
+            .filter(|_| move_partner)
+            .map(|partner| self.step(partner.snake[0], partner.direction));
+
+        // Check collisions with walls, gates, the snakes themselves, each other and hazards;
+        // a snake that does not move is checked against where its head is
+        let partner = self.partner.as_ref();
+        let other =
+            partner.map(|partner| (&partner.snake, partner_head.unwrap_or(partner.snake[0])));
+        let crash = new_head
+            .and_then(|next| self.crash(next, self.direction, &self.snake, other))
+            .or_else(|| {
+                let partner = partner?;
+                let other = (&self.snake, new_head.unwrap_or(self.snake[0]));
+                let next = partner_head?;
+                self.crash(next, partner.direction, &partner.snake, Some(other))
+            });
+        if let Some(cause) = crash {
+            return Err(cause);
+        }
+
+        // Add new heads to the snakes
+        if let Some(new_head) = new_head {
+            self.snake.push_front(new_head);
+        }
+        if let (Some(partner), Some(partner_head)) = (&mut self.partner, partner_head) {
+            partner.snake.push_front(partner_head);
+        }
+
+        // Check if food is eaten; the snakes cannot both reach it, as that is a crash
+        let ate = new_head == Some(self.food);
+        let partner_ate = partner_head == Some(self.food);
+        if ate || partner_ate {
+            self.food_eaten += 1;
+            let segments = self.growth.segments_for(self.food_eaten);
+            match &mut self.partner {
+                Some(partner) if partner_ate => partner.pending_growth += segments,
+                _ => self.pending_growth += segments,
+            }
+        }
+
+        // Grow while growth is pending, otherwise the tail follows the head
+        if new_head.is_some() {
+            if self.pending_growth > 0 {
+                self.pending_growth -= 1;
+            } else {
+                self.snake.pop_back();
+            }
+        }
+        if let (Some(partner), Some(_)) = (&mut self.partner, partner_head) {
+            if partner.pending_growth > 0 {
+                partner.pending_growth -= 1;
+            } else {
+                partner.snake.pop_back();
+            }
+        }
+
+        if !ate && !partner_ate {
+            return Ok(Vec::new());
+        }
+        let position = self.food;
+        let base = match (self.food_expiry, self.food_ticks_left) {
+            (Some(expiry), Some(ticks_left)) => expiry.points(ticks_left),
+            _ => 1,
+        };
+        let points = base * self.combo_pickup() * self.zone_multiplier(position);
+        self.score += points;
+        self.spawn_food();
+        let mut events = vec![
+            GameEvent::AteFood {
+                position,
+                points,
+                score: self.score,
             },
-            Direction::Right => Point {
-                x: head.x + 1,
-                y: head.y,
+            GameEvent::FoodSpawned {
+                position: self.food,
             },
+        ];
+        if let Some(every) = self.modifiers.random_control_scramble_every_n_apples {
+            if every > 0 && self.food_eaten.is_multiple_of(every) {
+                self.scramble_controls();
+                events.push(GameEvent::ControlsScrambled);
+            }
+        }
+        Ok(events)
+    }
+
+    /// Logs the direction of a snake when it differs from the one it used on the previous tick
+    fn log_direction(&mut self, snake: u8, direction: Direction) {
+        let previous = self
+            .inputs
+            .iter()
+            .rev()
+            .find(|input| input.snake == snake)
+            .map_or_else(|| self.start_direction(snake), |input| input.direction);
+        if direction != previous {
+            self.inputs.push(Input {
+                tick: self.tick,
+                direction,
+                snake,
+            });
+        }
+    }
+
+    /// Direction a snake starts moving in; the partner heads opposite the first snake
+    fn start_direction(&self, snake: u8) -> Direction {
+        match snake {
+            0 => self.start.direction,
+            _ => self.start.direction.opposite(),
+        }
+    }
+
+    /// Points the snake of a logged input in its direction
+    pub fn apply_input(&mut self, input: &Input) {
+        match (input.snake, &mut self.partner) {
+            (0, _) => self.direction = input.direction,
+            (_, Some(partner)) => partner.direction = input.direction,
+            (_, None) => {}
+        }
+    }
+
+    /// Why a head moving to `next` in `direction` crashes: into a wall, a one-way gate
+    /// against its direction, a locked door, its own body, the other snake of a co-op game, given with the
+    /// cell its head moves to, or a moving hazard; None when it does not crash
+    /// Bodies are checked before their tails move, as for a single snake
+    fn crash(
+        &self,
+        next: Point,
+        direction: Direction,
+        own: &VecDeque<Point>,
+        other: Option<(&VecDeque<Point>, Point)>,
+    ) -> Option<DeathCause> {
+        if self.topology.is_wall(next) {
+            Some(DeathCause::Wall)
+        } else if self.gate_blocks(next, direction) {
+            Some(DeathCause::Gate)
+        } else if self.door_locked(next) {
+            Some(DeathCause::Door)
+        } else if own.contains(&next) {
+            Some(DeathCause::SelfCollision)
+        } else if other.is_some_and(|(body, other_next)| other_next == next || body.contains(&next))
+        {
+            Some(DeathCause::PartnerCollision)
+        } else if self.hits_hazard(next, own) {
+            Some(DeathCause::Hazard)
+        } else {
+            None
+        }
+    }
+
+    /// Whether a cell is taken by the partner of a co-op game
+    pub fn on_partner(&self, point: Point) -> bool {
+        self.partner
+            .as_ref()
+            .is_some_and(|partner| partner.snake.contains(&point))
+    }
+
+    /// Whether a cell is taken by either snake
+    pub fn on_snake(&self, point: Point) -> bool {
+        self.snake.contains(&point) || self.on_partner(point)
+    }
+
+    /// Adds the partner of a co-op game opposite the first snake: its head mirrored through
+    /// the center of the board, heading the other way, with as many segments behind it
+    /// Fails when the partner does not fit on free cells off the first snake; the game is
+    /// left unchanged then
+    fn add_partner(&mut self) -> Result<(), String> {
+        let head = self.snake[0];
+        let head = Point {
+            x: self.width - 1 - head.x,
+            y: self.height - 1 - head.y,
+            z: head.z,
+        };
+        let behind = self.start.direction;
+        let fits = |point: Point, body: &VecDeque<Point>| {
+            self.is_open(point) && !self.snake.contains(&point) && !body.contains(&point)
+        };
+        let mut body = VecDeque::new();
+        let mut next = head;
+        while body.len() < self.snake.len() {
+            if !fits(next, &body) {
+                return Err("the partner snake does not fit opposite the first one".to_string());
+            }
+            body.push_back(next);
+            next = self.topology.neighbor(next, behind);
+        }
+        self.partner = Some(Partner {
+            snake: body,
+            direction: behind.opposite(),
+            pending_growth: 0,
+            input_queue: VecDeque::new(),
+        });
+        if self.on_partner(self.food) {
+            self.spawn_food();
+        }
+        Ok(())
+    }
+
+    /// Buffers a direction change of the partner of a co-op game for an upcoming tick, like
+    /// `queue_direction` after the modifiers' control mapping
+    /// Partner inputs are not compensated for latency; without a partner they are rejected
+    pub fn queue_partner_direction(&mut self, direction: Direction) -> InputTiming {
+        let direction = self.control(direction);
+        let valid = self.topology.directions().contains(&direction);
+        let Some(partner) = self.partner.as_mut().filter(|_| valid) else {
+            return InputTiming::Rejected;
+        };
+        let heading = partner.input_queue.back().copied();
+        if heading.unwrap_or(partner.direction) == direction {
+            return InputTiming::Coalesced;
+        }
+        if partner.input_queue.len() >= MAX_QUEUED_INPUTS {
+            return InputTiming::QueueFull;
+        }
+        partner.input_queue.push_back(direction);
+        InputTiming::Queued
+    }
+
+    /// Ticks until the snake starts moving; zero once the countdown is over
+    /// The countdown takes the first ticks of the game, so it follows from the tick
+    pub fn countdown_ticks_left(&self) -> u32 {
+        u64::from(self.countdown).saturating_sub(self.tick) as u32
+    }
+
+    /// Stage of the game: starting during the countdown, then running until it is over
+    pub fn phase(&self) -> Phase {
+        if self.game_over {
+            Phase::Over
+        } else if self.countdown_ticks_left() > 0 {
+            Phase::Starting
+        } else {
+            Phase::Running
+        }
+    }
+
+    /// Points multiplier of a cell: the highest of the zones containing it, 1 outside zones
+    pub fn zone_multiplier(&self, point: Point) -> u32 {
+        self.zones
+            .iter()
+            .filter(|zone| zone.contains(point))
+            .map(|zone| zone.multiplier)
+            .max()
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    /// Multiplier earned by a pickup now; a pickup within the combo window escalates the
+    /// multiplier, and every pickup reopens the window
+    fn combo_pickup(&mut self) -> u32 {
+        let Some(combo) = self.combo else {
+            return 1;
         };
+        if self.combo_ticks_left.is_some() {
+            let next = self.combo_multiplier.saturating_add(1);
+            self.combo_multiplier = combo.max_multiplier.map_or(next, |max| next.min(max.max(1)));
+        }
+        self.combo_ticks_left = Some(combo.window_ticks.max(1));
+        self.combo_multiplier
+    }
+
+    /// Counts down the combo window, resetting the multiplier once it closes
+    fn tick_combo(&mut self) -> Option<GameEvent> {
+        let ticks_left = self.combo_ticks_left?;
+        if ticks_left > 1 {
+            self.combo_ticks_left = Some(ticks_left - 1);
+            return None;
+        }
+        self.combo_ticks_left = None;
+        self.combo_multiplier = 1;
+        Some(GameEvent::ComboEnded)
+    }
 
-        // Check collision with walls
-        if new_head.x < 0
-            || new_head.x >= self.width
-            || new_head.y < 0
-            || new_head.y >= self.height
+    /// Replaces the initial snake, respawning the food if the snake now covers it
+    /// Fails when the direction does not exist on the board or the segments do not fit
+    /// on free cells behind the head; the game is left unchanged then
+    /// Must be called before the first tick
+    pub fn set_start(&mut self, start: Start) -> Result<(), String> {
+        if !self.topology.directions().contains(&start.direction) {
+            return Err("start direction does not exist on this board".to_string());
+        }
+        let cells = self.width as i64 * self.height as i64 * self.topology.layers() as i64;
+        if start.length == 0 || start.length as i64 > cells {
+            return Err(format!("start length must be between 1 and {}", cells));
+        }
+        let head = start
+            .position
+            .unwrap_or_else(|| topology::start_point(self.width, self.height));
+        if !self.is_open(head) {
+            return Err("start position is not a free cell of the board".to_string());
+        }
+
+        let mut snake = VecDeque::from([head]);
+        let behind = start.direction.opposite();
+        while snake.len() < start.length as usize {
+            let next = self.topology.neighbor(*snake.back().unwrap(), behind);
+            if !self.is_open(next) || snake.contains(&next) {
+                return Err("the snake does not fit behind its start position".to_string());
+            }
+            snake.push_back(next);
+        }
+
+        self.snake = snake;
+        self.direction = start.direction;
+        self.start = start;
+        if self.snake.contains(&self.food) {
+            self.spawn_food();
+        }
+        Ok(())
+    }
+
+    /// Replaces the portals, respawning the food if it is on one of their cells
+    /// Fails when a portal cell is not a free cell of the board or is used twice; the game
+    /// is left unchanged then
+    /// Must be called before `set_start` and the first tick
+    pub fn set_portals(&mut self, portals: Vec<Portal>) -> Result<(), String> {
+        let cells: Vec<Point> = portals.iter().flat_map(|portal| [portal.a, portal.b]).collect();
+        let previous = std::mem::take(&mut self.portals);
+        if let Some(cell) = cells.iter().find(|cell| !self.is_open(**cell)) {
+            let message = format!(
+                "portal cell ({}, {}) is not a free cell of the board",
+                cell.x, cell.y
+            );
+            self.portals = previous;
+            return Err(message);
+        }
+        if (1..cells.len()).any(|i| cells[..i].contains(&cells[i])) {
+            self.portals = previous;
+            return Err("portal cells must all be different".to_string());
+        }
+        self.portals = portals;
+        if cells.contains(&self.food) {
+            self.spawn_food();
+        }
+        Ok(())
+    }
+
+    /// Replaces the moving hazards
+    /// Fails when there are more than `MAX_HAZARDS`, a path is empty or longer than
+    /// `MAX_HAZARD_PATH`, or a path cell is not a free cell of the board; the game is left
+    /// unchanged then
+    /// Must be called after `set_portals`
+    pub fn set_hazards(&mut self, hazards: Vec<Hazard>) -> Result<(), String> {
+        if hazards.len() > MAX_HAZARDS {
+            return Err(format!("a game has at most {} hazards", MAX_HAZARDS));
+        }
+        if hazards
+            .iter()
+            .any(|hazard| hazard.path.is_empty() || hazard.path.len() > MAX_HAZARD_PATH)
         {
-            self.game_over = true;
-            return;
+            return Err(format!("hazard paths have 1 to {} cells", MAX_HAZARD_PATH));
+        }
+        let mut cells = hazards.iter().flat_map(|hazard| &hazard.path);
+        if let Some(cell) = cells.find(|cell| !self.is_open(**cell)) {
+            return Err(format!(
+                "hazard cell ({}, {}) is not a free cell of the board",
+                cell.x, cell.y
+            ));
+        }
+        self.hazards = hazards;
+        Ok(())
+    }
+
+    /// Replaces the ice and speed tiles
+    /// Fails when there are more than `MAX_TILES`, a tile is not a free cell of the board or
+    /// two tiles share a cell; the game is left unchanged then
+    /// Must be called after `set_portals`
+    pub fn set_tiles(&mut self, tiles: Vec<Tile>) -> Result<(), String> {
+        if tiles.len() > MAX_TILES {
+            return Err(format!("a game has at most {} tiles", MAX_TILES));
+        }
+        if let Some(tile) = tiles.iter().find(|tile| !self.is_open(tile.cell)) {
+            return Err(format!(
+                "tile ({}, {}) is not a free cell of the board",
+                tile.cell.x, tile.cell.y
+            ));
+        }
+        if (1..tiles.len()).any(|i| tiles[..i].iter().any(|tile| tile.cell == tiles[i].cell)) {
+            return Err("tiles must all be on different cells".to_string());
         }
+        self.tiles = tiles;
+        Ok(())
+    }
 
-        // Check collision with self
-        if self.snake.contains(&new_head) {
-            self.game_over = true;
-            return;
+    /// Replaces the one-way gates
+    /// Fails when there are more than `MAX_GATES`, a gate is not a free cell of the board,
+    /// points in a direction the board does not have, or two gates share a cell; the game
+    /// is left unchanged then
+    /// Must be called after `set_portals`
+    pub fn set_gates(&mut self, gates: Vec<Gate>) -> Result<(), String> {
+        if gates.len() > MAX_GATES {
+            return Err(format!("a game has at most {} gates", MAX_GATES));
+        }
+        if let Some(gate) = gates.iter().find(|gate| !self.is_open(gate.cell)) {
+            return Err(format!(
+                "gate ({}, {}) is not a free cell of the board",
+                gate.cell.x, gate.cell.y
+            ));
         }
+        let directions = self.topology.directions();
+        if gates
+            .iter()
+            .any(|gate| !directions.contains(&gate.direction))
+        {
+            return Err("gates must point in a direction of the board".to_string());
+        }
+        if (1..gates.len()).any(|i| gates[..i].iter().any(|gate| gate.cell == gates[i].cell)) {
+            return Err("gates must all be on different cells".to_string());
+        }
+        self.gates = gates;
+        Ok(())
+    }
 
-        // Add new head to snake
-        self.snake.push_front(new_head);
+    /// Whether a one-way gate keeps a head moving in `direction` from entering `next`
+    pub fn gate_blocks(&self, next: Point, direction: Direction) -> bool {
+        self.gates
+            .iter()
+            .any(|gate| gate.cell == next && gate.direction != direction)
+    }
 
-        // Check if food is eaten
-        if new_head.x == self.food.x && new_head.y == self.food.y {
-            self.score += 1;
+    /// Replaces the keys and doors objective
+    /// Fails when it has more than `MAX_OBJECTIVE_ITEMS` keys, doors or treasures, one of
+    /// its cells is not a free cell of the board or shared with another, a door has no key
+    /// or a treasure is worth 0 or more than `MAX_TREASURE_POINTS`; the game is left
+    /// unchanged then
+    /// Must be called after `set_portals`
+    pub fn set_objective(&mut self, objective: Option<Objective>) -> Result<(), String> {
+        let Some(objective) = objective else {
+            self.objective = None;
+            return Ok(());
+        };
+        let counts = [
+            objective.keys.len(),
+            objective.doors.len(),
+            objective.treasures.len(),
+        ];
+        if counts.iter().any(|count| *count > MAX_OBJECTIVE_ITEMS) {
+            return Err(format!(
+                "an objective has at most {} keys, doors and treasures each",
+                MAX_OBJECTIVE_ITEMS
+            ));
+        }
+        let cells: Vec<Point> = objective.cells().collect();
+        if let Some(cell) = cells.iter().find(|cell| !self.is_open(**cell)) {
+            return Err(format!(
+                "objective cell ({}, {}) is not a free cell of the board",
+                cell.x, cell.y
+            ));
+        }
+        if (1..cells.len()).any(|i| cells[..i].contains(&cells[i])) {
+            return Err(
+                "keys, doors, treasures and the exit must all be on different cells".to_string(),
+            );
+        }
+        let opens = |door: &Door| objective.keys.iter().any(|key| key.key == door.key);
+        if !objective.doors.iter().all(opens) {
+            return Err("every door needs a key".to_string());
+        }
+        let points = 1..=MAX_TREASURE_POINTS;
+        if !objective
+            .treasures
+            .iter()
+            .all(|treasure| points.contains(&treasure.points))
+        {
+            return Err(format!(
+                "treasures are worth 1 to {} points",
+                MAX_TREASURE_POINTS
+            ));
+        }
+        self.objective = Some(objective);
+        if cells.contains(&self.food) {
             self.spawn_food();
+        }
+        Ok(())
+    }
+
+    /// Whether a cell holds a door whose key no snake picked up yet
+    pub fn door_locked(&self, point: Point) -> bool {
+        self.objective.as_ref().is_some_and(|objective| {
+            objective
+                .doors
+                .iter()
+                .any(|door| door.cell == point && !self.keys_held.contains(&door.key))
+        })
+    }
+
+    /// Whether a head moving to `next` in `direction` runs into a wall, a one-way gate
+    /// against its direction or a locked door
+    pub fn blocks(&self, next: Point, direction: Direction) -> bool {
+        self.topology.is_wall(next) || self.gate_blocks(next, direction) || self.door_locked(next)
+    }
+
+    /// Picks up the keys and treasures under the snakes' heads and completes the level
+    /// when a head entered the exit
+    fn reach_objective(&mut self) -> Vec<GameEvent> {
+        let Some(objective) = self.objective.clone() else {
+            return Vec::new();
+        };
+        let partner = self.partner.as_ref().map(|partner| partner.snake[0]);
+        let mut events = Vec::new();
+        for head in std::iter::once(self.snake[0]).chain(partner) {
+            if self.collected.contains(&head) {
+                continue;
+            }
+            if let Some(key) = objective.keys.iter().find(|key| key.cell == head) {
+                self.collected.push(head);
+                if !self.keys_held.contains(&key.key) {
+                    self.keys_held.push(key.key);
+                }
+                events.push(GameEvent::KeyCollected {
+                    position: head,
+                    key: key.key,
+                });
+            } else if let Some(treasure) = objective.treasures.iter().find(|t| t.cell == head) {
+                self.collected.push(head);
+                self.score += treasure.points;
+                events.push(GameEvent::TreasureCollected {
+                    position: head,
+                    points: treasure.points,
+                    score: self.score,
+                });
+            } else if objective.exit == Some(head) {
+                let completion = Completion {
+                    ticks: self.tick - u64::from(self.countdown),
+                    turns: self.inputs.len() as u32,
+                };
+                self.completion = Some(completion);
+                self.game_over = true;
+                events.push(GameEvent::LevelCompleted { completion });
+                break;
+            }
+        }
+        events
+    }
+
+    /// Terrain of a cell, None for plain ground
+    pub fn terrain(&self, point: Point) -> Option<Terrain> {
+        self.tiles
+            .iter()
+            .find(|tile| tile.cell == point)
+            .map(|tile| tile.terrain)
+    }
+
+    /// Steps of its round trip a hazard made before the first tick, drawn from the game's
+    /// seed without advancing its random generator, so every game of a level patrols
+    /// differently while replays stay exact
+    fn hazard_phase(&self, index: usize) -> u64 {
+        let mut rng = SeededRng::new(self.seed ^ (index as u64 + 1).rotate_left(48));
+        rng.next_u64()
+    }
+
+    /// Cells of the moving hazards at a tick
+    pub fn hazard_cells(&self, tick: u64) -> impl Iterator<Item = Point> + '_ {
+        self.hazards
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, hazard)| hazard.position(tick, self.hazard_phase(i)))
+    }
+
+    /// Whether a snake moving its head to `next` on the current tick meets a hazard: the
+    /// head moves onto a hazard's cell before or after the hazard's move, or the hazard
+    /// moves onto the body
+    /// Bodies are checked before their tails move, as for collisions
+    pub fn hits_hazard(&self, next: Point, body: &VecDeque<Point>) -> bool {
+        let before = self.hazard_cells(self.tick.saturating_sub(1));
+        let after: Vec<Point> = self.hazard_cells(self.tick).collect();
+        before.chain(after.iter().copied()).any(|cell| cell == next)
+            || after.iter().any(|cell| body.contains(cell))
+    }
+
+    /// Whether a head moving to `next` on the coming tick meets a hazard, for the AI
+    pub fn hazard_ahead(&self, next: Point) -> bool {
+        self.hazard_cells(self.tick)
+            .chain(self.hazard_cells(self.tick + 1))
+            .any(|cell| cell == next)
+    }
+
+    /// Cell the snake emerges from when it enters `point`, if that is a portal cell
+    fn portal_exit(&self, point: Point) -> Option<Point> {
+        self.portals.iter().find_map(|portal| {
+            if portal.a == point {
+                Some(portal.b)
+            } else if portal.b == point {
+                Some(portal.a)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Cell reached by moving from `point` in `direction`, passing through portals
+    pub fn step(&self, point: Point, direction: Direction) -> Point {
+        let next = self.topology.neighbor(point, direction);
+        self.portal_exit(next).unwrap_or(next)
+    }
+
+    /// Distance estimate between two cells that also considers a shortcut through each portal
+    pub fn distance(&self, from: Point, to: Point) -> Fixed {
+        self.portals
+            .iter()
+            .flat_map(|portal| [(portal.a, portal.b), (portal.b, portal.a)])
+            .map(|(entrance, exit)| {
+                self.topology.distance(from, entrance) + self.topology.distance(exit, to)
+            })
+            .fold(self.topology.distance(from, to), Fixed::min)
+    }
+
+    /// Whether every cell food can appear on is reachable from the snake's head
+    /// The body is treated as passable since it moves out of the way
+    pub fn food_reachable(&self) -> bool {
+        let (width, height, layers) = (self.width, self.height, self.topology.layers());
+        let index = |point: Point| -> Option<usize> {
+            let in_bounds = (0..width).contains(&point.x)
+                && (0..height).contains(&point.y)
+                && (0..layers).contains(&point.z);
+            in_bounds.then(|| ((point.z * height + point.y) * width + point.x) as usize)
+        };
+        let mut reached = vec![false; (width * height * layers).max(0) as usize];
+        let mut queue = VecDeque::new();
+        if let Some(head) = self.snake.front().copied() {
+            if let Some(i) = index(head) {
+                reached[i] = true;
+                queue.push_back(head);
+            }
+        }
+        while let Some(point) = queue.pop_front() {
+            for direction in self.topology.directions() {
+                let next = self.step(point, *direction);
+                let Some(i) = index(next) else { continue };
+                if !reached[i]
+                    && !self.topology.is_wall(next)
+                    && !self.gate_blocks(next, *direction)
+                {
+                    reached[i] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        self.open_cells()
+            .all(|point| index(point).is_some_and(|i| reached[i]))
+    }
+
+    /// Whether the snake can reach the exit of the objective from its start, picking up the
+    /// keys of the doors in its way; doors open once their key is within reach
+    /// Games without an exit have nothing to reach
+    pub fn exit_reachable(&self) -> bool {
+        let Some(objective) = &self.objective else {
+            return true;
+        };
+        let (Some(exit), Some(head)) = (objective.exit, self.snake.front().copied()) else {
+            return objective.exit.is_none();
+        };
+        let layers = self.topology.layers();
+        let mut keys: Vec<u32> = Vec::new();
+        loop {
+            let locked = |point: Point| {
+                objective
+                    .doors
+                    .iter()
+                    .any(|door| door.cell == point && !keys.contains(&door.key))
+            };
+            let mut reached = vec![head];
+            let mut queue = VecDeque::from([head]);
+            while let Some(point) = queue.pop_front() {
+                for direction in self.topology.directions() {
+                    let next = self.step(point, *direction);
+                    let on_board = (0..self.width).contains(&next.x)
+                        && (0..self.height).contains(&next.y)
+                        && (0..layers).contains(&next.z);
+                    if on_board
+                        && !self.topology.is_wall(next)
+                        && !self.gate_blocks(next, *direction)
+                        && !locked(next)
+                        && !reached.contains(&next)
+                    {
+                        reached.push(next);
+                        queue.push_back(next);
+                    }
+                }
+            }
+            if reached.contains(&exit) {
+                return true;
+            }
+            let found: Vec<u32> = objective
+                .keys
+                .iter()
+                .filter(|key| reached.contains(&key.cell) && !keys.contains(&key.key))
+                .map(|key| key.key)
+                .collect();
+            if found.is_empty() {
+                return false;
+            }
+            keys.extend(found);
+        }
+    }
+
+    /// Cells food may appear on: on the board and not walls, ladders or portals
+    pub(crate) fn open_cells(&self) -> impl Iterator<Item = Point> + '_ {
+        let (width, height, layers) = (self.width, self.height, self.topology.layers());
+        (0..layers)
+            .flat_map(move |z| {
+                (0..height).flat_map(move |y| (0..width).map(move |x| Point { x, y, z }))
+            })
+            .filter(|point| self.is_open(*point))
+    }
+
+    /// Highest score the game's board and scoring rules allow, or None when combos
+    /// are uncapped
+    /// Bounds the food eaten by the snake filling every open cell, at the most
+    /// points any single food item can give
+    pub fn max_score(&self) -> Option<u32> {
+        let combo = match self.combo {
+            Some(combo) => combo.max_multiplier?,
+            None => 1,
+        };
+        let zone = self.zones.iter().map(|zone| zone.multiplier).max().unwrap_or(1).max(1);
+        let food = 1 + self.food_expiry.map_or(0, |expiry| expiry.quick_bonus);
+        let apples = self.open_cells().count() as u32 * self.growth.every_n_apples.max(1);
+        let food = apples
+            .saturating_mul(food)
+            .saturating_mul(combo)
+            .saturating_mul(zone);
+        let treasures: u32 = self
+            .objective
+            .iter()
+            .flat_map(|objective| &objective.treasures)
+            .map(|treasure| treasure.points)
+            .sum();
+        Some(food.saturating_add(treasures))
+    }
+
+    /// Wall-clock milliseconds the game took to eat `apples` food items, if it got there
+    pub fn split_time(&self, apples: u32) -> Option<u64> {
+        self.splits
+            .iter()
+            .find(|split| split.apples == apples)
+            .map(|split| split.elapsed_ms)
+    }
+
+    /// Sets the food lifetime and restarts the current food's timer
+    pub fn set_food_expiry(&mut self, expiry: Option<FoodExpiry>) {
+        self.food_expiry = expiry;
+        self.reset_food_timer();
+    }
+
+    /// Starts the lifetime of freshly placed food
+    fn reset_food_timer(&mut self) {
+        self.food_ticks_left = self.food_expiry.map(|expiry| expiry.lifetime_ticks.max(1));
+    }
+
+    /// Counts down the food's lifetime, respawning it elsewhere once it runs out
+    fn expire_food(&mut self) -> Vec<GameEvent> {
+        let Some(ticks_left) = self.food_ticks_left else {
+            return Vec::new();
+        };
+        if ticks_left > 1 {
+            self.food_ticks_left = Some(ticks_left - 1);
+            return Vec::new();
+        }
+        let expired = self.food;
+        self.spawn_food();
+        vec![
+            GameEvent::FoodExpired { position: expired },
+            GameEvent::FoodSpawned {
+                position: self.food,
+            },
+        ]
+    }
+
+    /// Lets fleeing food take its step when one is due this tick
+    /// The food moves to the free neighboring cell farthest from the snake head, and only
+    /// if that increases the distance; ties go to the board's first direction, so the
+    /// movement needs no random draws and replays stay deterministic
+    fn move_food(&mut self) -> Option<GameEvent> {
+        let FoodBehavior::Fleeing { every_ticks } = self.food_behavior else {
+            return None;
+        };
+        if every_ticks == 0 || !self.tick.is_multiple_of(every_ticks as u64) {
+            return None;
+        }
+        let head = *self.snake.front().unwrap();
+        let mut best = (self.food, self.distance(head, self.food));
+        for direction in self.topology.directions() {
+            let point = self.topology.neighbor(self.food, *direction);
+            let distance = self.distance(head, point);
+            if distance > best.1 && self.is_free(point) {
+                best = (point, distance);
+            }
+        }
+        if best.0 == self.food {
+            return None;
+        }
+        self.food = best.0;
+        Some(GameEvent::FoodMoved {
+            position: self.food,
+        })
+    }
+
+    /// Simulates the next `ticks` steps on a copy of the game without committing them
+    /// Buffered inputs are applied as they would be; stops early once the copy is over
+    pub fn predict(&self, ticks: u32) -> Vec<Game> {
+        let mut copy = self.clone();
+        let mut states = Vec::with_capacity(ticks as usize);
+        for _ in 0..ticks {
+            if copy.game_over {
+                break;
+            }
+            copy.update();
+            states.push(copy.clone());
+        }
+        states
+    }
+
+    /// Number of snapshots a live game keeps: enough to compensate late inputs, or to
+    /// rewind practice games
+    pub fn snapshot_len(&self) -> usize {
+        if self.practice {
+            PRACTICE_REWIND_TICKS.max(LATENCY_WINDOW_TICKS) as usize
+        } else {
+            LATENCY_WINDOW_TICKS as usize
+        }
+    }
+
+    /// Restores the state of `ticks` ticks ago, or the oldest snapshot if the history does
+    /// not reach back that far; buffered inputs are dropped
+    /// Returns the number of ticks actually rewound
+    pub fn rewind(&mut self, ticks: u64) -> u64 {
+        let target = self.tick.saturating_sub(ticks);
+        let Some(position) = self.history.iter().position(|snapshot| snapshot.tick >= target)
+        else {
+            return 0;
+        };
+        let mut history = std::mem::take(&mut self.history);
+        let mut game = history.remove(position).expect("snapshot position is valid");
+        history.truncate(position);
+
+        game.history = history;
+        game.history_len = self.history_len;
+        game.input_queue.clear();
+        let rewound = self.tick - game.tick;
+        *self = game;
+        rewound
+    }
+
+    /// Copies the game without its snapshot history
+    pub fn snapshot(&mut self) -> Game {
+        let history = std::mem::take(&mut self.history);
+        let snapshot = self.clone();
+        self.history = history;
+        snapshot
+    }
+
+    /// Direction the snake moves in for a player's direction input under the active modifiers
+    pub fn control(&self, input: Direction) -> Direction {
+        let directions = self.topology.directions();
+        let direction = directions
+            .iter()
+            .position(|direction| *direction == input)
+            .and_then(|index| self.controls.get(index).copied())
+            .unwrap_or(input);
+        if self.modifiers.mirror_controls {
+            direction.opposite()
         } else {
-            // Remove tail if food wasn't eaten
-            self.snake.pop_back();
+            direction
+        }
+    }
+
+    /// Reassigns the direction inputs with a shuffle drawn from the game's seeded generator
+    fn scramble_controls(&mut self) {
+        let mut controls = self.topology.directions().to_vec();
+        for i in (1..controls.len()).rev() {
+            let j = self.rng.gen_range(0, i as i32 + 1) as usize;
+            controls.swap(i, j);
+        }
+        self.controls = controls;
+    }
+
+    /// Applies a direction change that the client made while looking at the state of `tick`
+    /// Inputs arriving up to `LATENCY_WINDOW_TICKS` late are applied at their tick by
+    /// rewinding to the snapshot taken then and re-simulating up to the current tick,
+    /// replaying the inputs logged in between; anything else is buffered as usual
+    /// The input is a player's and goes through the modifiers' control mapping first
+    pub fn queue_direction_at(&mut self, direction: Direction, tick: u64) -> InputTiming {
+        let direction = self.control(direction);
+        if !self.topology.directions().contains(&direction) {
+            return InputTiming::Rejected;
+        }
+        if tick >= self.tick {
+            return self.queue_direction(direction);
+        }
+        let position = self.history.iter().position(|snapshot| snapshot.tick == tick);
+        let Some(position) = position.filter(|_| self.tick - tick <= LATENCY_WINDOW_TICKS) else {
+            return match self.queue_direction(direction) {
+                InputTiming::Queued => InputTiming::Late,
+                timing => timing,
+            };
+        };
+
+        let target = self.tick;
+        let mut later_inputs: VecDeque<Input> = self
+            .inputs
+            .iter()
+            // The partner keeps the turn it made at the rewound tick itself
+            .filter(|input| input.tick > tick || (input.tick == tick && input.snake != 0))
+            .copied()
+            .collect();
+        let pending = std::mem::take(&mut self.input_queue);
+        let partner_pending = self
+            .partner
+            .as_mut()
+            .map(|partner| std::mem::take(&mut partner.input_queue));
+        let mut history = std::mem::take(&mut self.history);
+        let mut game = history.remove(position).expect("snapshot position is valid");
+        history.truncate(position);
+
+        game.history = history;
+        game.history_len = self.history_len;
+        game.input_queue.clear();
+        if let Some(partner) = &mut game.partner {
+            partner.input_queue.clear();
+        }
+        game.direction = direction;
+        while game.tick < target && !game.game_over {
+            while later_inputs.front().is_some_and(|input| input.tick == game.tick) {
+                game.apply_input(&later_inputs.pop_front().unwrap());
+            }
+            game.update();
+        }
+        game.input_queue = pending;
+        if let (Some(partner), Some(pending)) = (&mut game.partner, partner_pending) {
+            partner.input_queue = pending;
         }
+        *self = game;
+        InputTiming::Compensated {
+            ticks: target - tick,
+        }
+    }
+
+    /// Direction the snake heads in as seen by a client looking at the state of `tick`:
+    /// the direction of that state when a late input can still be applied at it, otherwise
+    /// the last buffered direction change or the current direction
+    fn heading_at(&self, tick: u64) -> Direction {
+        let compensated = tick < self.tick && self.tick - tick <= LATENCY_WINDOW_TICKS;
+        self.history
+            .iter()
+            .find(|snapshot| compensated && snapshot.tick == tick)
+            .map_or_else(
+                || self.input_queue.back().copied().unwrap_or(self.direction),
+                |snapshot| snapshot.direction,
+            )
     }
 
-    /// Spawns new food at a random position
-    /// Ensures food doesn't spawn on snake body
+    /// Applies a turn relative to the heading the client saw at `tick`, as
+    /// `queue_direction_at` applies a direction; consecutive turns build on each other
+    /// Turns follow the snake rather than the keys: mirrored controls swap left and right,
+    /// and scrambled controls leave turns unchanged
+    pub fn queue_turn_at(&mut self, turn: Turn, tick: u64) -> InputTiming {
+        let turn = match (turn, self.modifiers.mirror_controls) {
+            (Turn::Left, true) => Turn::Right,
+            (Turn::Right, true) => Turn::Left,
+            (turn, _) => turn,
+        };
+        let hex = matches!(self.topology, Board::Hex(_));
+        let direction = self.heading_at(tick).turned(turn, hex);
+        // Queue the input the controls map to the direction of the turn
+        let input = self
+            .topology
+            .directions()
+            .iter()
+            .copied()
+            .find(|input| self.control(*input) == direction)
+            .unwrap_or(direction);
+        self.queue_direction_at(input, tick)
+    }
+
+    /// Buffers a direction change to be applied on an upcoming tick
+    /// Only one input takes effect per tick, so inputs arriving faster than the ticks are
+    /// limited to the queue: repeats of the last direction are coalesced and inputs beyond
+    /// `MAX_QUEUED_INPUTS` are dropped rather than pushing out earlier ones; directions the
+    /// board does not have are ignored
+    pub fn queue_direction(&mut self, direction: Direction) -> InputTiming {
+        if !self.topology.directions().contains(&direction) {
+            return InputTiming::Rejected;
+        }
+        if self.input_queue.back().copied().unwrap_or(self.direction) == direction {
+            return InputTiming::Coalesced;
+        }
+        if self.input_queue.len() >= MAX_QUEUED_INPUTS {
+            return InputTiming::QueueFull;
+        }
+        self.input_queue.push_back(direction);
+        InputTiming::Queued
+    }
+
+    /// Whether a cell is on the board and neither a wall, a ladder nor a portal
+    pub(crate) fn is_open(&self, point: Point) -> bool {
+        (0..self.width).contains(&point.x)
+            && (0..self.height).contains(&point.y)
+            && (0..self.topology.layers()).contains(&point.z)
+            && !self.topology.is_wall(point)
+            && !self.topology.is_passage(point)
+            && self.portal_exit(point).is_none()
+    }
+
+    /// Whether food may be placed on a cell
+    fn is_free(&self, point: Point) -> bool {
+        let mut objective = self.objective.iter().flat_map(Objective::cells);
+        self.is_open(point) && !self.on_snake(point) && !objective.any(|cell| cell == point)
+    }
+
+    /// Spawns new food at a random position drawn from the game's seeded generator
+    /// Ensures food doesn't spawn on snake body or walls; on crowded boards a free cell
+    /// is picked from a scan, and the food stays put when there is none
     pub fn spawn_food(&mut self) {
-        let mut rng = rand::thread_rng();
-        loop {
-            let food = Point {
-                x: rng.gen_range(0..self.width),
-                y: rng.gen_range(0..self.height),
+        for _ in 0..FOOD_SPAWN_ATTEMPTS {
+            let x = self.rng.gen_range(0, self.width);
+            let y = self.rng.gen_range(0, self.height);
+            // Flat boards draw no layer, keeping their food sequence unchanged
+            let z = match self.topology.layers() {
+                1 => 0,
+                layers => self.rng.gen_range(0, layers),
             };
-            if !self.snake.contains(&food) {
+            let food = Point { x, y, z };
+            if self.is_free(food) {
                 self.food = food;
-                break;
+                self.reset_food_timer();
+                return;
             }
         }
+        let (width, height) = (self.width, self.height);
+        let free: Vec<Point> = (0..self.topology.layers())
+            .flat_map(|z| (0..height).flat_map(move |y| (0..width).map(move |x| Point { x, y, z })))
+            .filter(|point| self.is_free(*point))
+            .collect();
+        if !free.is_empty() {
+            self.food = free[self.rng.gen_range(0, free.len() as i32) as usize];
+        }
+        self.reset_food_timer();
     }
 
     /// AI control function that chooses the next move
-    /// Uses a simple algorithm to move towards food while avoiding obstacles
+    /// Moves towards food while avoiding obstacles, see `ai::Greedy`
     pub fn ai_move(&mut self) {
+        let direction = ai::Greedy.decide(self, &mut ai::Budget::unlimited());
+        self.steer(direction);
+    }
+
+    /// Applies the move chosen by an AI strategy
+    /// AI decisions override any buffered player input; without a move the snake keeps
+    /// its direction
+    pub fn steer(&mut self, direction: Option<Direction>) {
         if self.game_over {
             return;
         }
+        self.input_queue.clear();
+        if let Some(direction) = direction {
+            self.direction = direction;
+        }
+    }
+}
 
-        let head = self.snake.front().unwrap();
-        // Define possible moves and their resulting positions
-        let possible_moves = [
-            (Direction::Up, Point { x: head.x, y: head.y - 1 }),
-            (Direction::Down, Point { x: head.x, y: head.y + 1 }),
-            (Direction::Left, Point { x: head.x - 1, y: head.y }),
-            (Direction::Right, Point { x: head.x + 1, y: head.y }),
-        ];
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // Simple AI: Choose the direction that gets closer to the food
-        let mut best_move = None;
-        let mut min_distance = f64::MAX;
-
-        // Evaluate each possible move
-        for (dir, point) in possible_moves.iter() {
-            // Check if move is valid (within bounds and doesn't hit snake)
-            if point.x >= 0
-                && point.x < self.width
-                && point.y >= 0
-                && point.y < self.height
-                && !self.snake.contains(point)
-            {
-                // Calculate Euclidean distance to food
-                let distance = (((point.x - self.food.x).pow(2) + (point.y - self.food.y).pow(2)) as f64).sqrt();
-                // Update best move if this is the closest to food so far
-                if distance < min_distance {
-                    min_distance = distance;
-                    best_move = Some(*dir);
-                }
+    /// Plays a game from `seed`, turning clockwise every few ticks, and returns it with the
+    /// state hash before the first tick and after every tick
+    fn play(seed: u64, ticks: u64) -> (Game, Vec<u64>) {
+        let turns = [
+            Direction::Down,
+            Direction::Left,
+            Direction::Up,
+            Direction::Right,
+        ];
+        let mut game = Game::with_seed(20, 20, seed);
+        let mut hashes = vec![game.state_hash()];
+        for tick in 0..ticks {
+            if tick % 4 == 3 {
+                game.queue_direction(turns[(tick / 4) as usize % turns.len()]);
             }
+            game.update();
+            hashes.push(game.state_hash());
         }
+        (game, hashes)
+    }
+
+    #[test]
+    fn same_seed_and_inputs_give_same_hash_every_tick() {
+        let (first, first_hashes) = play(42, 200);
+        let (second, second_hashes) = play(42, 200);
+        assert!(!first.inputs.is_empty());
+        assert!(first.inputs == second.inputs);
+        assert_eq!(first_hashes, second_hashes);
+        assert_eq!(first.rolling_hash, second.rolling_hash);
 
-        // Update direction if a valid move was found
-        if let Some(dir) = best_move {
-            self.direction = dir;
+        let folded: Vec<u64> = first
+            .config()
+            .fold(&first.inputs, 200)
+            .unwrap()
+            .map(|game| game.state_hash())
+            .collect();
+        assert_eq!(first_hashes, folded);
+    }
+
+    #[test]
+    fn late_input_equals_input_applied_live() {
+        let mut late = Game::with_seed(20, 20, 7);
+        late.history_len = late.snapshot_len();
+        for _ in 0..10 {
+            late.update();
+        }
+        assert!(matches!(
+            late.queue_direction_at(Direction::Up, 7),
+            InputTiming::Compensated { ticks: 3 }
+        ));
+
+        let mut live = Game::with_seed(20, 20, 7);
+        for _ in 0..7 {
+            live.update();
+        }
+        live.queue_direction(Direction::Up);
+        for _ in 0..3 {
+            live.update();
         }
+        assert_eq!(late.tick, live.tick);
+        assert!(late.snake == live.snake);
+        assert!(late.inputs == live.inputs);
+        assert_eq!(late.state_hash(), live.state_hash());
+        assert_eq!(late.rolling_hash, live.rolling_hash);
     }
-} 
\ No newline at end of file
+}
@@ -0,0 +1,393 @@
+use crate::game::{self, Game};
+use crate::proof_of_work;
+use crate::replay::{Replay, Visibility};
+use crate::room::RoomPass;
+use crate::server::{self, AppState, NewGameOptions};
+use crate::session::Session;
+use crate::state_hash;
+use actix_web::{guard, web, HttpRequest, HttpResponse};
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{Context, Enum, Object, Result, Schema, SimpleObject, Subscription};
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse, GraphQLSubscription};
+use futures_util::stream::{Stream, StreamExt};
+
+/// Complete GraphQL schema of the game API
+pub type SnakeSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
+
+/// Snake movement direction
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+pub enum DirectionValue {
+    Up,
+    Down,
+    Left,
+    Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
+
+impl From<game::Direction> for DirectionValue {
+    fn from(direction: game::Direction) -> Self {
+        match direction {
+            game::Direction::Up => DirectionValue::Up,
+            game::Direction::Down => DirectionValue::Down,
+            game::Direction::Left => DirectionValue::Left,
+            game::Direction::Right => DirectionValue::Right,
+            game::Direction::UpLeft => DirectionValue::UpLeft,
+            game::Direction::UpRight => DirectionValue::UpRight,
+            game::Direction::DownLeft => DirectionValue::DownLeft,
+            game::Direction::DownRight => DirectionValue::DownRight,
+        }
+    }
+}
+
+impl From<DirectionValue> for game::Direction {
+    fn from(direction: DirectionValue) -> Self {
+        match direction {
+            DirectionValue::Up => game::Direction::Up,
+            DirectionValue::Down => game::Direction::Down,
+            DirectionValue::Left => game::Direction::Left,
+            DirectionValue::Right => game::Direction::Right,
+            DirectionValue::UpLeft => game::Direction::UpLeft,
+            DirectionValue::UpRight => game::Direction::UpRight,
+            DirectionValue::DownLeft => game::Direction::DownLeft,
+            DirectionValue::DownRight => game::Direction::DownRight,
+        }
+    }
+}
+
+/// A board cell
+#[derive(SimpleObject)]
+pub struct PointObject {
+    pub x: i32,
+    pub y: i32,
+    /// Layer on layered boards, zero otherwise
+    pub z: i32,
+}
+
+impl From<&game::Point> for PointObject {
+    fn from(point: &game::Point) -> Self {
+        PointObject {
+            x: point.x,
+            y: point.y,
+            z: point.z,
+        }
+    }
+}
+
+/// Snapshot of a game session
+#[derive(SimpleObject)]
+pub struct GameObject {
+    pub session_id: String,
+    pub snake: Vec<PointObject>,
+    pub food: PointObject,
+    pub direction: DirectionValue,
+    pub score: u32,
+    pub game_over: bool,
+    pub width: i32,
+    pub height: i32,
+    pub tick: u64,
+    /// Rolling hash of the states up to `tick`, for detecting diverging simulations
+    pub state_hash: String,
+    /// Session token steering the game, only returned by `newGame`
+    pub token: Option<String>,
+}
+
+impl GameObject {
+    fn new(session_id: &str, game: &Game) -> Self {
+        GameObject {
+            session_id: session_id.to_string(),
+            snake: game.snake.iter().map(PointObject::from).collect(),
+            food: PointObject::from(&game.food),
+            direction: game.direction.into(),
+            score: game.score,
+            game_over: game.game_over,
+            width: game.width,
+            height: game.height,
+            tick: game.tick,
+            state_hash: state_hash::to_hex(game.rolling_hash),
+            token: None,
+        }
+    }
+}
+
+/// Leaderboard entry
+#[derive(SimpleObject)]
+pub struct ScoreObject {
+    pub name: String,
+    pub score: u32,
+}
+
+/// Recording of a finished game
+#[derive(SimpleObject)]
+pub struct ReplayObject {
+    pub id: String,
+    pub width: i32,
+    pub height: i32,
+    pub ticks: u64,
+    pub score: u32,
+    /// Link to the animated GIF rendering of the replay
+    pub animation_url: String,
+}
+
+impl From<&Replay> for ReplayObject {
+    fn from(replay: &Replay) -> Self {
+        ReplayObject {
+            id: replay.id.clone(),
+            width: replay.width,
+            height: replay.height,
+            ticks: replay.ticks,
+            score: replay.score,
+            animation_url: format!("/replays/{}/animation.gif", replay.id),
+        }
+    }
+}
+
+/// Aggregate server statistics
+#[derive(SimpleObject)]
+pub struct StatsObject {
+    pub active_games: usize,
+    pub finished_games: usize,
+    pub leaderboard_entries: usize,
+    pub top_score: Option<u32>,
+}
+
+/// Looks up a game and converts it to its GraphQL representation
+fn find_game(state: &AppState, session_id: &str) -> Option<GameObject> {
+    let sessions = state.sessions.lock().unwrap();
+    sessions
+        .get(session_id)
+        .map(|session| GameObject::new(session_id, &session.game))
+}
+
+/// Whether credentials admit watching a live game, see `Session::watchable_by`
+/// Fails for rooms with a password; private games are not revealed and only answer false
+fn admits(session: &Session, token: Option<&str>, pass: &RoomPass) -> Result<bool> {
+    if session.watchable_by(token, None, pass) {
+        Ok(true)
+    } else if session.visibility == Visibility::Private {
+        Ok(false)
+    } else {
+        Err("a password or invite is required".into())
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Current state of a game session
+    /// Private rooms need the session `token`, or the room's `password` or an `invite`
+    async fn game(
+        &self,
+        ctx: &Context<'_>,
+        session_id: String,
+        token: Option<String>,
+        password: Option<String>,
+        invite: Option<String>,
+    ) -> Result<Option<GameObject>> {
+        let state = ctx.data_unchecked::<web::Data<AppState>>();
+        let pass = RoomPass {
+            password: password.as_deref(),
+            invite: invite.as_deref(),
+        };
+        let sessions = state.sessions.lock().unwrap();
+        let Some(session) = sessions.get(&session_id) else {
+            return Ok(None);
+        };
+        Ok(admits(session, token.as_deref(), &pass)?
+            .then(|| GameObject::new(&session_id, &session.game)))
+    }
+
+    /// Top scores in descending order
+    async fn leaderboard(&self, ctx: &Context<'_>) -> Vec<ScoreObject> {
+        let state = ctx.data_unchecked::<web::Data<AppState>>();
+        let leaderboard = state.leaderboard.lock().unwrap();
+        leaderboard
+            .iter()
+            .map(|entry| ScoreObject {
+                name: entry.name.clone(),
+                score: entry.score,
+            })
+            .collect()
+    }
+
+    /// Recording of a finished game, unless it is private
+    async fn replay(&self, ctx: &Context<'_>, id: String) -> Option<ReplayObject> {
+        let state = ctx.data_unchecked::<web::Data<AppState>>();
+        state
+            .viewable_replay(&id, None)
+            .as_ref()
+            .map(ReplayObject::from)
+    }
+
+    /// Public recordings of finished games, highest score first
+    async fn replays(&self, ctx: &Context<'_>, limit: Option<usize>) -> Vec<ReplayObject> {
+        let state = ctx.data_unchecked::<web::Data<AppState>>();
+        let replays = state.replays.lock().unwrap();
+        let mut replays: Vec<_> = replays
+            .values()
+            .filter(|replay| replay.visibility == Visibility::Public)
+            .collect();
+        replays.sort_by_key(|replay| std::cmp::Reverse(replay.score));
+        replays
+            .into_iter()
+            .take(limit.unwrap_or(10))
+            .map(ReplayObject::from)
+            .collect()
+    }
+
+    /// Aggregate statistics over all sessions and the leaderboard
+    async fn stats(&self, ctx: &Context<'_>) -> StatsObject {
+        let state = ctx.data_unchecked::<web::Data<AppState>>();
+        let (active_games, finished_games) = {
+            let sessions = state.sessions.lock().unwrap();
+            let finished = sessions
+                .values()
+                .filter(|session| session.game.game_over)
+                .count();
+            (sessions.len() - finished, finished)
+        };
+        let leaderboard = state.leaderboard.lock().unwrap();
+        StatsObject {
+            active_games,
+            finished_games,
+            leaderboard_entries: leaderboard.len(),
+            top_score: leaderboard.first().map(|entry| entry.score),
+        }
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    /// Creates a new game session
+    async fn new_game(
+        &self,
+        ctx: &Context<'_>,
+        proof_of_work: Option<String>,
+    ) -> Result<Option<GameObject>> {
+        let state = ctx.data_unchecked::<web::Data<AppState>>();
+        if let Err(difficulty) = proof_of_work::verify(proof_of_work.as_deref(), state) {
+            return Err(format!(
+                "missing or invalid proof of work, difficulty {}",
+                difficulty
+            )
+            .into());
+        }
+        let Ok((session_id, token)) = state.create_game(None, NewGameOptions::default()) else {
+            return Ok(None);
+        };
+        Ok(find_game(state, &session_id).map(|game| GameObject {
+            token: Some(token),
+            ..game
+        }))
+    }
+
+    /// Changes the direction of the snake in a game session
+    /// Needs the session `token` returned by `newGame`
+    async fn set_direction(
+        &self,
+        ctx: &Context<'_>,
+        session_id: String,
+        direction: DirectionValue,
+        token: String,
+    ) -> Result<bool> {
+        let state = ctx.data_unchecked::<web::Data<AppState>>();
+        let mut sessions = state.sessions.lock().unwrap();
+        let session = sessions.get_mut(&session_id).ok_or("game not found")?;
+        if !session.authorizes(Some(&token)) {
+            return Err("only the player may change the game".into());
+        }
+        session.touch();
+        let tick = session.game.tick;
+        session.game.queue_direction_at(direction.into(), tick);
+        Ok(true)
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Emits the game state whenever it changes, ending after the game is over
+    /// Private rooms need the session `token`, or the room's `password` or an `invite`
+    async fn game_updates(
+        &self,
+        ctx: &Context<'_>,
+        session_id: String,
+        token: Option<String>,
+        password: Option<String>,
+        invite: Option<String>,
+    ) -> Result<impl Stream<Item = GameObject>> {
+        let state = ctx.data_unchecked::<web::Data<AppState>>().clone();
+        let pass = RoomPass {
+            password: password.as_deref(),
+            invite: invite.as_deref(),
+        };
+        if let Some(session) = state.sessions.lock().unwrap().get(&session_id) {
+            if !admits(session, token.as_deref(), &pass)? {
+                return Err("game not found".into());
+            }
+        }
+        Ok(server::watch_game(state, session_id.clone())
+            .map(move |game| GameObject::new(&session_id, &game)))
+    }
+}
+
+/// Builds the GraphQL schema
+/// Application state is attached per request so the schema itself stays stateless
+pub fn schema() -> SnakeSchema {
+    Schema::build(QueryRoot, MutationRoot, SubscriptionRoot).finish()
+}
+
+/// Executes GraphQL queries and mutations
+async fn execute(
+    schema: web::Data<SnakeSchema>,
+    data: web::Data<AppState>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    schema.execute(request.into_inner().data(data)).await.into()
+}
+
+/// Serves subscriptions over the GraphQL WebSocket protocols
+async fn subscribe(
+    schema: web::Data<SnakeSchema>,
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    payload: web::Payload,
+) -> actix_web::Result<HttpResponse> {
+    let mut context = async_graphql::Data::default();
+    context.insert(data);
+    GraphQLSubscription::new(SnakeSchema::clone(&schema))
+        .with_data(context)
+        .start(&req, payload)
+}
+
+/// Serves the GraphiQL explorer
+async fn graphiql() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(
+            GraphiQLSource::build()
+                .endpoint("/graphql")
+                .subscription_endpoint("/graphql")
+                .finish(),
+        )
+}
+
+/// Registers the `/graphql` endpoint
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(web::Data::new(schema())).service(
+        web::resource("/graphql")
+            .route(web::post().to(execute))
+            .route(
+                web::get()
+                    .guard(guard::Header("upgrade", "websocket"))
+                    .to(subscribe),
+            )
+            .route(web::get().to(graphiql)),
+    );
+}
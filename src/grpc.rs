@@ -0,0 +1,211 @@
+use crate::game::{self, Game};
+use crate::proof_of_work;
+use crate::server::{self, AppState, NewGameOptions};
+use crate::state_hash;
+use actix_web::web;
+use futures_util::stream::{Stream, StreamExt};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use tonic::{Request, Response, Status};
+
+/// Types generated from `proto/snake.proto`
+pub mod proto {
+    tonic::include_proto!("snake");
+}
+
+use proto::snake_server::{Snake, SnakeServer};
+
+impl From<game::Direction> for proto::Direction {
+    fn from(direction: game::Direction) -> Self {
+        match direction {
+            game::Direction::Up => proto::Direction::Up,
+            game::Direction::Down => proto::Direction::Down,
+            game::Direction::Left => proto::Direction::Left,
+            game::Direction::Right => proto::Direction::Right,
+            game::Direction::UpLeft => proto::Direction::UpLeft,
+            game::Direction::UpRight => proto::Direction::UpRight,
+            game::Direction::DownLeft => proto::Direction::DownLeft,
+            game::Direction::DownRight => proto::Direction::DownRight,
+        }
+    }
+}
+
+impl From<proto::Direction> for game::Direction {
+    fn from(direction: proto::Direction) -> Self {
+        match direction {
+            proto::Direction::Up => game::Direction::Up,
+            proto::Direction::Down => game::Direction::Down,
+            proto::Direction::Left => game::Direction::Left,
+            proto::Direction::Right => game::Direction::Right,
+            proto::Direction::UpLeft => game::Direction::UpLeft,
+            proto::Direction::UpRight => game::Direction::UpRight,
+            proto::Direction::DownLeft => game::Direction::DownLeft,
+            proto::Direction::DownRight => game::Direction::DownRight,
+        }
+    }
+}
+
+impl From<&game::Point> for proto::Point {
+    fn from(point: &game::Point) -> Self {
+        proto::Point {
+            x: point.x,
+            y: point.y,
+            z: point.z,
+        }
+    }
+}
+
+/// Metadata key carrying the session token: returned by `NewGame`, and required by
+/// `SetDirection` and `Tick`
+const TOKEN_METADATA: &str = "x-session-token";
+/// Metadata carrying a solved proof-of-work challenge, like the `X-Proof-Of-Work` header
+const PROOF_OF_WORK_METADATA: &str = "x-proof-of-work";
+
+/// Reads the session token from the metadata of a request
+fn session_token<T>(request: &Request<T>) -> Option<String> {
+    request
+        .metadata()
+        .get(TOKEN_METADATA)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Converts an engine game into its protobuf representation
+fn game_state(session_id: &str, game: &Game) -> proto::GameState {
+    proto::GameState {
+        session_id: session_id.to_string(),
+        snake: game.snake.iter().map(proto::Point::from).collect(),
+        food: Some(proto::Point::from(&game.food)),
+        direction: proto::Direction::from(game.direction).into(),
+        score: game.score,
+        game_over: game.game_over,
+        width: game.width,
+        height: game.height,
+        tick: game.tick,
+        state_hash: state_hash::to_hex(game.rolling_hash),
+    }
+}
+
+/// gRPC implementation of the game API, sharing state with the HTTP server
+pub struct SnakeService {
+    data: web::Data<AppState>,
+}
+
+#[tonic::async_trait]
+impl Snake for SnakeService {
+    async fn new_game(
+        &self,
+        request: Request<proto::NewGameRequest>,
+    ) -> Result<Response<proto::GameState>, Status> {
+        let proof = request
+            .metadata()
+            .get(PROOF_OF_WORK_METADATA)
+            .and_then(|value| value.to_str().ok());
+        if let Err(difficulty) = proof_of_work::verify(proof, &self.data) {
+            return Err(Status::permission_denied(format!(
+                "missing or invalid proof of work, difficulty {}",
+                difficulty
+            )));
+        }
+        let (session_id, token) = self
+            .data
+            .create_game(None, NewGameOptions::default())
+            .map_err(Status::invalid_argument)?;
+        let sessions = self.data.sessions.lock().unwrap();
+        let session = sessions
+            .get(&session_id)
+            .ok_or_else(|| Status::internal("game vanished"))?;
+        let mut response = Response::new(game_state(&session_id, &session.game));
+        // Session tokens are UUIDs, always valid metadata
+        response
+            .metadata_mut()
+            .insert(TOKEN_METADATA, token.parse().unwrap());
+        Ok(response)
+    }
+
+    async fn set_direction(
+        &self,
+        request: Request<proto::SetDirectionRequest>,
+    ) -> Result<Response<proto::SetDirectionResponse>, Status> {
+        let token = session_token(&request);
+        let request = request.into_inner();
+        let direction = proto::Direction::try_from(request.direction)
+            .map_err(|_| Status::invalid_argument("unknown direction"))?;
+        let mut sessions = self.data.sessions.lock().unwrap();
+        let session = sessions
+            .get_mut(&request.session_id)
+            .ok_or_else(|| Status::not_found("game not found"))?;
+        if !session.authorizes(token.as_deref()) {
+            return Err(Status::permission_denied(
+                "only the player may change the game",
+            ));
+        }
+        session.touch();
+        let tick = session.game.tick;
+        session.game.queue_direction_at(direction.into(), tick);
+        Ok(Response::new(proto::SetDirectionResponse {}))
+    }
+
+    async fn tick(
+        &self,
+        request: Request<proto::TickRequest>,
+    ) -> Result<Response<proto::GameState>, Status> {
+        let token = session_token(&request);
+        let session_id = request.into_inner().session_id;
+        let mut sessions = self.data.sessions.lock().unwrap();
+        let session = sessions
+            .get_mut(&session_id)
+            .ok_or_else(|| Status::not_found("game not found"))?;
+        if !session.authorizes(token.as_deref()) {
+            return Err(Status::permission_denied(
+                "only the player may change the game",
+            ));
+        }
+        session.touch();
+        if let Err(retry_after) = self.data.take_ticks(session, 1) {
+            return Err(Status::resource_exhausted(format!(
+                "the game is advancing faster than its tick quota, retry in {} ms",
+                retry_after.as_millis()
+            )));
+        }
+        session.tick();
+        self.data.record_if_finished(&session_id, session);
+        Ok(Response::new(game_state(&session_id, &session.game)))
+    }
+
+    type StreamStateStream = Pin<Box<dyn Stream<Item = Result<proto::GameState, Status>> + Send>>;
+
+    // tonic dictates `Status` as the stream error type
+    #[allow(clippy::result_large_err)]
+    async fn stream_state(
+        &self,
+        request: Request<proto::StreamStateRequest>,
+    ) -> Result<Response<Self::StreamStateStream>, Status> {
+        let session_id = request.into_inner().session_id;
+        // Requests carry no credentials, so private rooms are not streamed
+        let watchable = self
+            .data
+            .sessions
+            .lock()
+            .unwrap()
+            .get(&session_id)
+            .is_some_and(|session| !session.is_private());
+        if !watchable {
+            return Err(Status::not_found("game not found"));
+        }
+        let stream = server::watch_game(self.data.clone(), session_id.clone())
+            .map(move |game| Ok(game_state(&session_id, &game)));
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// Serves the gRPC API on its own port until the process exits
+pub async fn serve(
+    data: web::Data<AppState>,
+    addr: SocketAddr,
+) -> Result<(), tonic::transport::Error> {
+    tonic::transport::Server::builder()
+        .add_service(SnakeServer::new(SnakeService { data }))
+        .serve(addr)
+        .await
+}
@@ -0,0 +1,123 @@
+use crate::server::AppState;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServerHandle, ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use serde_json::json;
+use std::env;
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+
+/// First file descriptor passed by systemd socket activation
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Socket passed by systemd socket activation, if the server was started that way
+/// `LISTEN_PID` must name this process, so a socket meant for a parent is not taken
+#[cfg(unix)]
+fn activated_socket() -> Option<TcpListener> {
+    use std::os::unix::io::FromRawFd;
+
+    let pid = env::var("LISTEN_PID").ok()?.parse::<u32>().ok()?;
+    let fds = env::var("LISTEN_FDS").ok()?.parse::<i32>().ok()?;
+    if pid != std::process::id() || fds < 1 {
+        return None;
+    }
+    // SAFETY: systemd passes the listening socket as the first descriptor after stderr,
+    // and nothing else in the process owns it
+    Some(unsafe { TcpListener::from_raw_fd(LISTEN_FDS_START) })
+}
+
+#[cfg(not(unix))]
+fn activated_socket() -> Option<TcpListener> {
+    None
+}
+
+/// Listening socket of the HTTP server
+/// Taken from systemd when socket activated, so the socket outlives server processes;
+/// otherwise bound to the port on localhost, with `SO_REUSEPORT` when `reuse_port` is set
+/// so the server taking over can listen before this one stops
+pub fn listener(port: u16, reuse_port: bool) -> io::Result<TcpListener> {
+    if let Some(listener) = activated_socket() {
+        tracing::info!("listening on the socket passed by systemd");
+        return Ok(listener);
+    }
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    if !reuse_port {
+        return TcpListener::bind(addr);
+    }
+    let socket = socket2::Socket::new(
+        socket2::Domain::IPV4,
+        socket2::Type::STREAM,
+        Some(socket2::Protocol::TCP),
+    )?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(socket.into())
+}
+
+/// Middleware turning requests away while the server drains
+/// Responds with 503 Service Unavailable and closes the connection, so the client's retry
+/// opens a new one, which reaches the server taking over
+pub async fn middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let draining = req
+        .app_data::<web::Data<AppState>>()
+        .is_some_and(|data| data.is_draining());
+    if draining {
+        let response = HttpResponse::ServiceUnavailable()
+            .insert_header(("Retry-After", "1"))
+            .force_close()
+            .json(json!({ "error": "server restarting" }));
+        return Ok(req.into_response(response));
+    }
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
+
+/// Resolves on SIGTERM or Ctrl-C
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+            }
+            Err(e) => {
+                tracing::warn!("cannot listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Waits for a shutdown signal, then hands the server's games over and stops it
+/// New requests are turned away at once, realtime clients are told to reconnect and every
+/// active game is checkpointed for the server taking over; requests in flight get the
+/// server's shutdown timeout to finish
+pub async fn drain_on_signal(data: web::Data<AppState>, server: ServerHandle) {
+    shutdown_signal().await;
+    tracing::info!("shutting down, handing games over");
+    data.start_draining();
+    #[cfg(feature = "persistence")]
+    {
+        let handoff_data = data.clone();
+        match web::block(move || handoff_data.hand_over()).await {
+            Ok(Ok(games)) => tracing::info!(games, "games handed over"),
+            Ok(Err(e)) => tracing::error!("cannot hand games over: {}", e),
+            Err(e) => tracing::error!("cannot hand games over: {}", e),
+        }
+    }
+    server.stop(true).await;
+}
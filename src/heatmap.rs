@@ -0,0 +1,129 @@
+use crate::game::{Game, Point};
+use crate::server::AppState;
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// Where snakes died and ate in all counted games on boards of one size
+/// Cells are counted by row and column; the layers of layered boards are added up
+#[derive(Clone, Serialize)]
+pub struct Heatmap {
+    pub width: i32,
+    pub height: i32,
+    /// Number of games counted
+    pub games: u64,
+    /// Deaths per cell, at the cell of the head when the snake crashed, by row then column
+    pub deaths: Vec<Vec<u64>>,
+    /// Food items eaten per cell, by row then column
+    pub food: Vec<Vec<u64>>,
+}
+
+impl Heatmap {
+    fn new(width: i32, height: i32) -> Self {
+        let grid = vec![vec![0; width.max(0) as usize]; height.max(0) as usize];
+        Heatmap {
+            width,
+            height,
+            games: 0,
+            deaths: grid.clone(),
+            food: grid,
+        }
+    }
+
+    /// Counts cells in a grid, ignoring those off the board
+    fn count(grid: &mut [Vec<u64>], point: Point) {
+        let row = usize::try_from(point.y).ok().and_then(|y| grid.get_mut(y));
+        if let Some(cell) =
+            row.and_then(|row| usize::try_from(point.x).ok().and_then(|x| row.get_mut(x)))
+        {
+            *cell += 1;
+        }
+    }
+
+    /// Writes the heatmap as CSV, one line per cell with a death or pickup
+    fn csv(&self) -> String {
+        let mut csv = String::from("x,y,deaths,food\n");
+        for (y, (deaths, food)) in self.deaths.iter().zip(&self.food).enumerate() {
+            for (x, (deaths, food)) in deaths.iter().zip(food).enumerate() {
+                if *deaths > 0 || *food > 0 {
+                    let _ = writeln!(csv, "{},{},{},{}", x, y, deaths, food);
+                }
+            }
+        }
+        csv
+    }
+}
+
+/// Heatmaps of finished games by board size, kept in memory only
+#[derive(Default)]
+pub struct Heatmaps {
+    boards: Mutex<HashMap<(i32, i32), Heatmap>>,
+}
+
+impl Heatmaps {
+    /// Counts a finished game: the cell it ended on and the cells where it ate
+    pub fn record(&self, game: &Game, eaten: &[Point]) {
+        let mut boards = self.boards.lock().unwrap();
+        let heatmap = boards
+            .entry((game.width, game.height))
+            .or_insert_with(|| Heatmap::new(game.width, game.height));
+        heatmap.games += 1;
+        if let Some(head) = game.snake.front() {
+            Heatmap::count(&mut heatmap.deaths, *head);
+        }
+        for point in eaten {
+            Heatmap::count(&mut heatmap.food, *point);
+        }
+    }
+
+    /// Heatmap of the games on boards of a size, if any were counted
+    pub fn get(&self, width: i32, height: i32) -> Option<Heatmap> {
+        self.boards.lock().unwrap().get(&(width, height)).cloned()
+    }
+}
+
+/// Format of a downloaded heatmap
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeatmapFormat {
+    /// Grids of counts by row then column
+    #[default]
+    Json,
+    /// One `x,y,deaths,food` line per cell with a death or pickup
+    Csv,
+}
+
+/// Query parameters of the heatmap endpoint
+#[derive(Deserialize)]
+pub struct HeatmapQuery {
+    width: i32,
+    height: i32,
+    #[serde(default)]
+    format: HeatmapFormat,
+}
+
+/// Serves where snakes died and ate across all finished ranked games on boards of a size,
+/// as JSON grids or a CSV download
+/// Responds with 404 when no game on such a board finished since the server started
+pub async fn heatmap(query: web::Query<HeatmapQuery>, data: web::Data<AppState>) -> HttpResponse {
+    let Some(heatmap) = data.heatmaps.get(query.width, query.height) else {
+        return HttpResponse::NotFound()
+            .json(json!({ "error": "no games counted on boards of this size" }));
+    };
+    match query.format {
+        HeatmapFormat::Json => HttpResponse::Ok().json(heatmap),
+        HeatmapFormat::Csv => HttpResponse::Ok()
+            .content_type("text/csv")
+            .insert_header((
+                "Content-Disposition",
+                format!(
+                    "attachment; filename=\"heatmap-{}x{}.csv\"",
+                    heatmap.width, heatmap.height
+                ),
+            ))
+            .body(heatmap.csv()),
+    }
+}
@@ -0,0 +1,302 @@
+use crate::server::AppState;
+use crate::share;
+use crate::signer::unix_now;
+use crate::team::{RoundResult, TeamMatch, TeamReplay, TEAMS};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Number of most recent matches kept in memory and served by the API
+const MAX_MATCHES: usize = 10_000;
+/// Matches returned by a query without a limit
+const DEFAULT_LIMIT: usize = 20;
+
+/// Player of a snake in a recorded match
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Participant {
+    pub snake: usize,
+    pub team: usize,
+    /// Name the player gave, None for anonymous players
+    pub name: Option<String>,
+    /// Whether the AI steered the snake at some point while its player was gone
+    pub taken_over: bool,
+    /// Whether the snake was eliminated because its player was gone
+    pub forfeited: bool,
+}
+
+/// Finished team match as kept in the match history
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MatchRecord {
+    pub id: String,
+    /// Unix timestamp (seconds) the match ended at
+    pub finished_at: u64,
+    pub participants: Vec<Participant>,
+    /// Results of the rounds, in order
+    pub rounds: Vec<RoundResult>,
+    /// Rounds won by each team
+    pub round_wins: [u32; TEAMS],
+    /// Team scores of the last round
+    pub scores: [u32; TEAMS],
+    /// Team that won the match; None for a draw
+    pub winner: Option<usize>,
+    /// Settings, seed and input log of the match
+    pub replay: TeamReplay,
+}
+
+impl MatchRecord {
+    /// Record of a finished match
+    pub fn new(match_id: &str, game: &TeamMatch) -> Self {
+        MatchRecord {
+            id: match_id.to_string(),
+            finished_at: unix_now(),
+            participants: game
+                .snakes
+                .iter()
+                .enumerate()
+                .map(|(snake, player)| Participant {
+                    snake,
+                    team: player.team,
+                    name: player.player.clone(),
+                    taken_over: player.taken_over,
+                    forfeited: player.forfeited,
+                })
+                .collect(),
+            rounds: game.history.clone(),
+            round_wins: game.round_wins,
+            scores: game.scores,
+            winner: game.winner,
+            replay: game.replay(),
+        }
+    }
+
+    /// Team a player played for, by normalized name
+    fn team_of(&self, player: &str, normalize: impl Fn(&str) -> String) -> Option<usize> {
+        self.participants
+            .iter()
+            .find(|participant| {
+                participant
+                    .name
+                    .as_deref()
+                    .is_some_and(|name| normalize(name) == player)
+            })
+            .map(|participant| participant.team)
+    }
+
+    /// Record without its input log, which is served as the replay, with a link to it
+    fn summary(&self, base: &str) -> serde_json::Value {
+        json!({
+            "id": self.id,
+            "finished_at": self.finished_at,
+            "participants": self.participants,
+            "rounds": self.rounds,
+            "round_wins": self.round_wins,
+            "scores": self.scores,
+            "winner": self.winner,
+            "replay_url": format!("{}/matches/{}/replay", base, self.id),
+        })
+    }
+}
+
+/// Finished team matches, oldest first
+/// Matches are appended to the configured file as JSON lines, so the history outlives
+/// restarts
+pub struct MatchHistory {
+    // Most recent matches, oldest first
+    matches: Mutex<VecDeque<MatchRecord>>,
+    // File the matches are appended to, if configured
+    file: Option<PathBuf>,
+}
+
+impl MatchHistory {
+    /// Loads the most recent matches of the configured file
+    /// A missing file starts an empty history; unreadable lines are logged and skipped
+    pub fn open(file: Option<&str>) -> Self {
+        let file = file.map(PathBuf::from);
+        let mut matches = VecDeque::new();
+        match file.as_deref().map(fs::read_to_string) {
+            Some(Ok(contents)) => {
+                for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+                    match serde_json::from_str(line) {
+                        Ok(record) => matches.push_back(record),
+                        Err(e) => tracing::error!("skipping unreadable match: {}", e),
+                    }
+                }
+                let excess = matches.len().saturating_sub(MAX_MATCHES);
+                matches.drain(..excess);
+            }
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => {
+                tracing::error!("cannot read match history: {}", e);
+            }
+            _ => {}
+        }
+        MatchHistory {
+            matches: Mutex::new(matches),
+            file,
+        }
+    }
+
+    /// Appends a match to the file, if configured
+    fn append(&self, record: &MatchRecord) -> io::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(&line)
+    }
+
+    /// Records a finished match
+    /// Failing to write the file is logged; the match is still kept in memory
+    pub fn record(&self, record: MatchRecord) {
+        let mut matches = self.matches.lock().unwrap();
+        if let Err(e) = self.append(&record) {
+            tracing::error!(match_id = %record.id, "cannot write match history: {}", e);
+        }
+        matches.push_back(record);
+        if matches.len() > MAX_MATCHES {
+            matches.pop_front();
+        }
+    }
+
+    /// Recorded match by ID
+    pub fn get(&self, match_id: &str) -> Option<MatchRecord> {
+        let matches = self.matches.lock().unwrap();
+        matches.iter().find(|record| record.id == match_id).cloned()
+    }
+
+    /// Matches matching a filter, newest first
+    fn query(&self, filter: impl Fn(&MatchRecord) -> bool) -> Vec<MatchRecord> {
+        let matches = self.matches.lock().unwrap();
+        matches
+            .iter()
+            .rev()
+            .filter(|record| filter(record))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Query parameters of the match history endpoint
+#[derive(Deserialize)]
+pub struct MatchesQuery {
+    // Name of a player whose matches are listed; all matches by default
+    player: Option<String>,
+    // Maximum number of matches, newest first (default 20)
+    limit: Option<usize>,
+}
+
+/// Lists finished team matches newest first, those of a player when given
+/// Players are matched by name the way leaderboard names are told apart, so "B0b" finds
+/// the matches of "bob"
+pub async fn matches(
+    req: HttpRequest,
+    query: web::Query<MatchesQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let config = data.config();
+    let normalize = |name: &str| config.name_filter.normalize(name);
+    let player = query.player.as_deref().map(normalize);
+    let records = data.match_history.query(|record| {
+        player
+            .as_deref()
+            .is_none_or(|player| record.team_of(player, normalize).is_some())
+    });
+    let base = share::base_url(&req, &data);
+    let matches: Vec<_> = records
+        .iter()
+        .take(query.limit.unwrap_or(DEFAULT_LIMIT))
+        .map(|record| record.summary(&base))
+        .collect();
+    HttpResponse::Ok().json(matches)
+}
+
+/// Returns a finished team match with a link to its replay
+/// match_id: Unique identifier for the team match
+pub async fn get(
+    req: HttpRequest,
+    match_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    match data.match_history.get(&match_id) {
+        Some(record) => HttpResponse::Ok().json(record.summary(&share::base_url(&req, &data))),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Returns the recording of a finished team match: its settings, seed and input log, from
+/// which clients re-simulate it
+/// match_id: Unique identifier for the team match
+pub async fn replay(match_id: web::Path<String>, data: web::Data<AppState>) -> HttpResponse {
+    match data.match_history.get(&match_id) {
+        Some(record) => HttpResponse::Ok().json(record.replay),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Query parameters of the head-to-head endpoint
+#[derive(Deserialize)]
+pub struct HeadToHeadQuery {
+    player: String,
+    opponent: String,
+}
+
+/// Sums up the matches two players played on opposing teams: matches and rounds won by
+/// each, draws, and the most recent matches
+/// Players are matched by name as in the match history
+pub async fn head_to_head(
+    req: HttpRequest,
+    query: web::Query<HeadToHeadQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let config = data.config();
+    let normalize = |name: &str| config.name_filter.normalize(name);
+    let (player, opponent) = (normalize(&query.player), normalize(&query.opponent));
+    if player.is_empty() || player == opponent {
+        return HttpResponse::BadRequest().json(json!({ "error": "name two different players" }));
+    }
+    let teams = |record: &MatchRecord| {
+        let teams = record
+            .team_of(&player, normalize)
+            .zip(record.team_of(&opponent, normalize));
+        teams.filter(|(team, opposing)| team != opposing)
+    };
+    let records = data.match_history.query(|record| teams(record).is_some());
+    let (mut wins, mut losses, mut draws, mut rounds_won, mut rounds_lost) = (0, 0, 0, 0, 0);
+    for record in &records {
+        let Some((team, opposing)) = teams(record) else {
+            continue;
+        };
+        match record.winner {
+            Some(winner) if winner == team => wins += 1,
+            Some(_) => losses += 1,
+            None => draws += 1,
+        }
+        rounds_won += record.round_wins[team];
+        rounds_lost += record.round_wins[opposing];
+    }
+    let base = share::base_url(&req, &data);
+    HttpResponse::Ok().json(json!({
+        "player": query.player,
+        "opponent": query.opponent,
+        "matches": records.len(),
+        "wins": wins,
+        "losses": losses,
+        "draws": draws,
+        "rounds_won": rounds_won,
+        "rounds_lost": rounds_lost,
+        "recent": records
+            .iter()
+            .take(DEFAULT_LIMIT)
+            .map(|record| record.summary(&base))
+            .collect::<Vec<_>>(),
+    }))
+}
@@ -0,0 +1,239 @@
+use crate::server::AppState;
+use actix_web::body::{self, BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use std::collections::HashMap;
+use std::fs;
+
+/// Language the server's messages are written in
+pub const SOURCE_LANGUAGE: &str = "en";
+
+/// Largest error body translated; larger ones are passed on unchanged
+const MAX_TRANSLATED_BODY: u64 = 64 * 1024;
+
+/// Stands for a variable part of a message, e.g. a number
+const PLACEHOLDER: &str = "{}";
+
+/// Translations of the server's messages into one language, keyed by the English message
+/// A `{}` in a message matches any text, which its translation repeats in the same order
+#[derive(Default)]
+pub struct Catalog {
+    messages: HashMap<String, String>,
+}
+
+/// Texts matched by the placeholders of a pattern, or None when the message does not match
+fn capture<'a>(pattern: &str, message: &'a str) -> Option<Vec<&'a str>> {
+    let mut literals = pattern.split(PLACEHOLDER);
+    let mut rest = message.strip_prefix(literals.next()?)?;
+    let literals: Vec<&str> = literals.collect();
+    let mut args = Vec::with_capacity(literals.len());
+    for (i, literal) in literals.iter().enumerate() {
+        if i + 1 == literals.len() {
+            args.push(rest.strip_suffix(literal)?);
+            rest = "";
+        } else {
+            let end = rest.find(literal)?;
+            args.push(&rest[..end]);
+            rest = &rest[end + literal.len()..];
+        }
+    }
+    rest.is_empty().then_some(args)
+}
+
+/// Replaces the placeholders of a translation with the captured texts, in order
+fn fill(translation: &str, args: &[&str]) -> String {
+    let mut args = args.iter();
+    let mut filled = String::with_capacity(translation.len());
+    for (i, part) in translation.split(PLACEHOLDER).enumerate() {
+        if i > 0 {
+            filled.push_str(args.next().copied().unwrap_or(PLACEHOLDER));
+        }
+        filled.push_str(part);
+    }
+    filled
+}
+
+impl Catalog {
+    /// Translation of a message, or None when the catalog has none
+    /// Exact entries win over patterns; of several matching patterns the longest wins
+    pub fn translate(&self, message: &str) -> Option<String> {
+        if let Some(translation) = self.messages.get(message) {
+            return Some(translation.clone());
+        }
+        self.messages
+            .iter()
+            .filter(|(pattern, _)| pattern.contains(PLACEHOLDER))
+            .filter_map(|(pattern, translation)| {
+                capture(pattern, message).map(|args| (pattern.len(), fill(translation, &args)))
+            })
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, translation)| translation)
+    }
+}
+
+/// Whether a file name is a language tag such as `de` or `pt-br`
+fn is_language_tag(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 35
+        && name
+            .split('-')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// Message catalogs by lowercase language tag
+#[derive(Default)]
+pub struct Catalogs {
+    catalogs: HashMap<String, Catalog>,
+}
+
+impl Catalogs {
+    /// Loads every `<language>.toml` file of a directory, e.g. `de.toml` or `pt-br.toml`,
+    /// each a table of English messages and their translations
+    /// Fails with a message for unreadable directories, invalid files and translations
+    /// with another number of placeholders than their message
+    pub fn load(dir: &str) -> Result<Catalogs, String> {
+        let entries = fs::read_dir(dir).map_err(|e| format!("cannot read {}: {}", dir, e))?;
+        let mut catalogs = HashMap::new();
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.extension().is_none_or(|extension| extension != "toml") {
+                continue;
+            }
+            let language = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .map(str::to_ascii_lowercase)
+                .filter(|language| is_language_tag(language))
+                .ok_or_else(|| format!("{} is not named after a language", path.display()))?;
+            let contents = fs::read_to_string(&path)
+                .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+            let messages: HashMap<String, String> = toml::from_str(&contents)
+                .map_err(|e| format!("invalid catalog {}: {}", path.display(), e))?;
+            let placeholders = |text: &str| text.matches(PLACEHOLDER).count();
+            if let Some((message, _)) = messages
+                .iter()
+                .find(|(message, translation)| placeholders(message) != placeholders(translation))
+            {
+                return Err(format!(
+                    "the translation of {:?} in {} has another number of placeholders",
+                    message,
+                    path.display()
+                ));
+            }
+            catalogs.insert(language, Catalog { messages });
+        }
+        Ok(Catalogs { catalogs })
+    }
+
+    /// Languages that have a catalog, sorted
+    pub fn languages(&self) -> Vec<&str> {
+        let mut languages: Vec<&str> = self.catalogs.keys().map(String::as_str).collect();
+        languages.sort_unstable();
+        languages
+    }
+
+    /// Catalog of the language a client prefers, from its `Accept-Language` header
+    /// Languages are tried by decreasing quality, a tag such as `de-at` falling back to
+    /// `de`; None when English comes first or no preferred language has a catalog
+    pub fn negotiate(&self, accept_language: &str) -> Option<(&str, &Catalog)> {
+        let mut ranges: Vec<(f64, String)> = accept_language
+            .split(',')
+            .filter_map(|range| {
+                let mut params = range.split(';');
+                let tag = params.next()?.trim().to_ascii_lowercase();
+                let quality = match params.find_map(|param| param.trim().strip_prefix("q=")) {
+                    Some(quality) => quality.trim().parse().ok()?,
+                    None => 1.0,
+                };
+                (!tag.is_empty() && quality > 0.0).then_some((quality, tag))
+            })
+            .collect();
+        // Stable, so ranges of equal quality keep the client's order
+        ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+        for (_, tag) in ranges {
+            let primary = tag.split('-').next().unwrap_or_default();
+            if primary == SOURCE_LANGUAGE {
+                return None;
+            }
+            let catalog = self
+                .catalogs
+                .get_key_value(&tag)
+                .or_else(|| self.catalogs.get_key_value(primary));
+            if let Some((language, catalog)) = catalog {
+                return Some((language, catalog));
+            }
+        }
+        None
+    }
+}
+
+/// Translates the `error` of a JSON error body; None when there is nothing to translate
+fn translate_json(catalog: &Catalog, bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut body: serde_json::Value = serde_json::from_slice(bytes).ok()?;
+    let error = body.get_mut("error")?;
+    *error = catalog.translate(error.as_str()?)?.into();
+    serde_json::to_vec(&body).ok()
+}
+
+/// Middleware translating error messages into the language of the `Accept-Language` header
+/// Covers the `error` of JSON error responses and plain-text error responses, whose
+/// messages are looked up in the catalogs of `locale_dir`; translated responses carry
+/// `Content-Language`, and messages without a translation stay in English
+pub async fn middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let data = req.app_data::<web::Data<AppState>>().cloned();
+    let accept_language = req
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let res = next.call(req).await?.map_into_boxed_body();
+    let (Some(data), Some(accept_language)) = (data, accept_language) else {
+        return Ok(res);
+    };
+    let status = res.status();
+    let small =
+        matches!(res.response().body().size(), BodySize::Sized(len) if len <= MAX_TRANSLATED_BODY);
+    if !(status.is_client_error() || status.is_server_error()) || !small {
+        return Ok(res);
+    }
+    let Some((language, catalog)) = data.catalogs.negotiate(&accept_language) else {
+        return Ok(res);
+    };
+    let content_type = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    let (req, res) = res.into_parts();
+    let (mut res, body) = res.into_parts();
+    let bytes = body::to_bytes(body)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let translated = if content_type.starts_with("application/json") {
+        translate_json(catalog, &bytes)
+    } else if content_type.starts_with("text/plain") {
+        std::str::from_utf8(&bytes)
+            .ok()
+            .and_then(|text| catalog.translate(text))
+            .map(String::into_bytes)
+    } else {
+        None
+    };
+    let res = match translated {
+        Some(translated) => {
+            if let Ok(value) = HeaderValue::from_str(language) {
+                res.headers_mut().insert(header::CONTENT_LANGUAGE, value);
+            }
+            res.set_body(BoxBody::new(translated))
+        }
+        None => res.set_body(BoxBody::new(bytes)),
+    };
+    Ok(ServiceResponse::new(req, res))
+}
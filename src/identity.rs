@@ -0,0 +1,196 @@
+use crate::server::AppState;
+use crate::signer::{unix_now, Signer};
+use actix_web::cookie::time::Duration;
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::{error, web, HttpRequest, HttpResponse};
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{self, SystemTime};
+use uuid::Uuid;
+
+/// Cookie identifying a browser across games
+pub const CLIENT_COOKIE: &str = "snake_client";
+/// Lifetime of the client cookie
+const CLIENT_COOKIE_DAYS: i64 = 365;
+/// Lifetime of a device link code
+pub const LINK_CODE_TTL: time::Duration = time::Duration::from_secs(10 * 60);
+/// Characters of a link code, without look-alikes such as 0 and O
+const LINK_CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+/// Length of a link code
+const LINK_CODE_LEN: usize = 8;
+/// Length of an anonymous ID, in bytes of the HMAC kept
+const ANONYMOUS_ID_LEN: usize = 16;
+
+/// Client ID sent with the request, if the server issued it
+/// The cookie carries the ID signed with the server's cookie key, so made-up IDs, e.g.
+/// fresh ones dodging a ban, are ignored
+pub fn client_id(req: &HttpRequest) -> Option<String> {
+    let cookie = req.cookie(CLIENT_COOKIE)?;
+    let data = req.app_data::<web::Data<AppState>>()?;
+    let id = data.cookie_signer.verify(cookie.value())?;
+    Uuid::parse_str(id).ok().map(|id| id.to_string())
+}
+
+/// Client ID of the request, issuing a new one when the request has none
+/// Returns the ID and whether the cookie has to be set on the response
+pub fn client_id_or_new(req: &HttpRequest) -> (String, bool) {
+    match client_id(req) {
+        Some(id) => (id, false),
+        None => (Uuid::new_v4().to_string(), true),
+    }
+}
+
+/// Builds the cookie carrying a client ID, signed with the server's cookie key
+pub fn cookie(data: &AppState, client_id: &str) -> Cookie<'static> {
+    Cookie::build(CLIENT_COOKIE, data.cookie_signer.sign(client_id))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(Duration::days(CLIENT_COOKIE_DAYS))
+        .finish()
+}
+
+/// Client a link code signs in, and when the code expires
+pub struct LinkCode {
+    client_id: String,
+    expires_at: SystemTime,
+}
+
+/// Issues a one-time code that signs another device in as the requesting client
+/// Responds with 401 without client cookie
+pub async fn create_link_code(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    let Some(client_id) = client_id(&req) else {
+        return Err(error::ErrorUnauthorized("missing client cookie"));
+    };
+    let mut rng = rand::thread_rng();
+    let code: String = (0..LINK_CODE_LEN)
+        .map(|_| LINK_CODE_CHARS[rng.gen_range(0..LINK_CODE_CHARS.len())] as char)
+        .collect();
+    let now = SystemTime::now();
+    let mut codes = data.link_codes.lock().unwrap();
+    codes.retain(|_, link| link.expires_at > now);
+    codes.insert(
+        code.clone(),
+        LinkCode {
+            client_id,
+            expires_at: now + LINK_CODE_TTL,
+        },
+    );
+    Ok(HttpResponse::Ok().json(json!({
+        "code": code,
+        "expires_in_secs": LINK_CODE_TTL.as_secs(),
+    })))
+}
+
+/// Body of a device link request
+#[derive(Deserialize)]
+pub struct LinkRequest {
+    code: String,
+}
+
+/// Signs this device in as the client that issued a link code, replacing its client cookie
+/// The device then lists and resumes that client's games; codes work once
+/// Responds with 404 for unknown or expired codes
+pub async fn link_device(link: web::Json<LinkRequest>, data: web::Data<AppState>) -> HttpResponse {
+    let code = link.code.trim().to_uppercase();
+    let linked = data.link_codes.lock().unwrap().remove(&code);
+    match linked {
+        Some(link) if link.expires_at > SystemTime::now() => HttpResponse::Ok()
+            .cookie(cookie(&data, &link.client_id))
+            .json(json!({ "linked": true })),
+        _ => HttpResponse::NotFound().json(json!({ "error": "unknown or expired code" })),
+    }
+}
+
+/// Anonymous identities of players, for deployments without accounts
+/// A player's ID is a keyed hash of the client IP and client cookie, so per-player limits
+/// and records work without storing IP addresses; the key is a random secret of this
+/// server mixed with the current rotation period, after which every player gets a new ID
+/// and the records kept under the old ones are dropped
+pub struct AnonymousIds {
+    // Random secret keying the hashes, never leaving the server
+    secret: Signer,
+    // Rotation period of the best scores and the best score of every player in it
+    best_scores: Mutex<(u64, HashMap<String, u32>)>,
+}
+
+impl Default for AnonymousIds {
+    fn default() -> Self {
+        AnonymousIds {
+            secret: Signer::default(),
+            best_scores: Mutex::new((0, HashMap::new())),
+        }
+    }
+}
+
+/// Number of the current rotation period of anonymous IDs
+/// rotation_hours: Length of a period, at least an hour
+fn rotation_period(rotation_hours: u64) -> u64 {
+    unix_now() / (rotation_hours.max(1) * 60 * 60)
+}
+
+impl AnonymousIds {
+    /// Anonymous ID of the player sending a request, in the current rotation period
+    /// Requests without client cookie are identified by their IP address alone
+    pub fn of(&self, req: &HttpRequest, rotation_hours: u64) -> String {
+        let ip = req.peer_addr().map(|addr| addr.ip().to_string());
+        let period = rotation_period(rotation_hours);
+        let mut message = period.to_be_bytes().to_vec();
+        for part in [ip, client_id(req)] {
+            // Length-prefixed, so no two pairs of parts hash the same input
+            let part = part.unwrap_or_default();
+            message.extend_from_slice(&(part.len() as u64).to_be_bytes());
+            message.extend_from_slice(part.as_bytes());
+        }
+        hex::encode(&self.secret.mac(&message)[..ANONYMOUS_ID_LEN])
+    }
+
+    /// Anonymous ID of the IP address sending a request, in the current rotation period
+    /// Unlike `of` it ignores the client cookie, so a client dropping or renewing its
+    /// cookie keeps the same ID
+    pub fn network_of(&self, req: &HttpRequest, rotation_hours: u64) -> String {
+        let ip = req
+            .peer_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_default();
+        let period = rotation_period(rotation_hours);
+        // Salted with a purpose, so no IP hashes to the ID `of` gives a client
+        let message = format!("network.{}.{}", period, ip);
+        hex::encode(&self.secret.mac(message.as_bytes())[..ANONYMOUS_ID_LEN])
+    }
+
+    /// Records a ranked score of a player, keeping the best one of the rotation period
+    pub fn record_score(&self, player: &str, score: u32, rotation_hours: u64) {
+        let period = rotation_period(rotation_hours);
+        let mut best_scores = self.best_scores.lock().unwrap();
+        if best_scores.0 != period {
+            *best_scores = (period, HashMap::new());
+        }
+        let best = best_scores.1.entry(player.to_string()).or_default();
+        *best = (*best).max(score);
+    }
+
+    /// Best ranked score of a player in the current rotation period
+    pub fn best_score(&self, player: &str, rotation_hours: u64) -> Option<u32> {
+        let period = rotation_period(rotation_hours);
+        let best_scores = self.best_scores.lock().unwrap();
+        (best_scores.0 == period)
+            .then(|| best_scores.1.get(player).copied())
+            .flatten()
+    }
+}
+
+/// Reports the best ranked score of the requesting player since their anonymous ID last
+/// rotated, for "your best score" displays
+pub async fn my_best(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    let rotation_hours = data.config().anonymous_id_rotation_hours;
+    let player = data.anonymous_ids.of(&req, rotation_hours);
+    let best = data.anonymous_ids.best_score(&player, rotation_hours);
+    HttpResponse::Ok().json(json!({ "player": player, "best_score": best }))
+}
@@ -0,0 +1,310 @@
+use crate::ai::{AiBudget, AiConfig, Budget, Personality, Strategy};
+use crate::game::{Direction, Game};
+use crate::observation::{self, Observation, ObservationFormat};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Ticks after which an exported game is cut off, so a snake circling forever still ends
+pub const MAX_GAME_TICKS: u64 = 10_000;
+
+/// Directions in the order of their action codes in npz exports
+pub const ACTIONS: [Direction; 8] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+    Direction::UpLeft,
+    Direction::UpRight,
+    Direction::DownLeft,
+    Direction::DownRight,
+];
+
+/// Layout of an exported data set
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// One JSON sample per line
+    #[default]
+    Jsonl,
+    /// Uncompressed NumPy archive of stacked arrays, as written by `numpy.savez`
+    Npz,
+}
+
+/// Games played for an export
+#[derive(Clone, Copy)]
+pub struct ExportConfig {
+    /// Number of games; game `i` is played with seed `first_seed + i`
+    pub games: u32,
+    pub first_seed: u64,
+    pub width: i32,
+    pub height: i32,
+    pub observation: ObservationFormat,
+}
+
+/// One move of an AI game: the board the AI saw and the direction it moved in
+#[derive(Serialize)]
+pub struct Sample {
+    /// Seed of the game the sample was taken from
+    pub seed: u64,
+    #[serde(flatten)]
+    pub observation: Observation,
+    pub action: Direction,
+}
+
+/// Budget of the exporting AI: the lookahead's node limit without its time limit, so an
+/// export is the same on every machine
+fn budget(config: &AiConfig) -> AiBudget {
+    AiBudget {
+        max_millis: None,
+        max_nodes: config.lookahead.max_nodes,
+    }
+}
+
+/// Plays one game with the strongest AI, the lookahead at full depth, and records every
+/// move it makes
+/// A move is recorded as the direction the snake took, which is its old direction when
+/// every move is fatal
+pub fn play(seed: u64, export: &ExportConfig, config: &AiConfig) -> Vec<Sample> {
+    let strategy = Strategy::Lookahead.build(Personality::default(), config);
+    let mut game = Game::with_seed(export.width, export.height, seed);
+    let mut samples = Vec::new();
+    while !game.game_over && game.tick < MAX_GAME_TICKS {
+        let observation = observation::observe(&game, export.observation);
+        let direction = strategy.decide(&game, &mut Budget::start(budget(config)));
+        game.steer(direction);
+        samples.push(Sample {
+            seed,
+            observation,
+            action: game.direction,
+        });
+        game.update();
+    }
+    samples
+}
+
+/// Plays the games of an export in seed order and writes their samples
+/// Returns the number of samples written
+pub fn write(
+    export: &ExportConfig,
+    config: &AiConfig,
+    format: ExportFormat,
+    out: &mut impl Write,
+) -> io::Result<usize> {
+    let seeds = (0..u64::from(export.games)).map(|game| export.first_seed.wrapping_add(game));
+    match format {
+        ExportFormat::Jsonl => {
+            let mut count = 0;
+            for seed in seeds {
+                for sample in play(seed, export, config) {
+                    serde_json::to_writer(&mut *out, &sample)?;
+                    out.write_all(b"\n")?;
+                    count += 1;
+                }
+            }
+            Ok(count)
+        }
+        ExportFormat::Npz => {
+            let shape = observation::observe(
+                &Game::with_seed(export.width, export.height, export.first_seed),
+                export.observation,
+            )
+            .shape;
+            let mut observations: Vec<u8> = Vec::new();
+            let mut actions: Vec<u8> = Vec::new();
+            let mut seed_column: Vec<u8> = Vec::new();
+            let mut ticks: Vec<u8> = Vec::new();
+            for seed in seeds {
+                for sample in play(seed, export, config) {
+                    observations.extend(sample.observation.data.into_flat());
+                    actions.push(action_code(sample.action));
+                    seed_column.extend(sample.seed.to_le_bytes());
+                    ticks.extend(sample.observation.tick.to_le_bytes());
+                }
+            }
+            let count = actions.len();
+            let arrays = [
+                Array {
+                    name: "observations",
+                    descr: "|u1",
+                    shape: std::iter::once(count)
+                        .chain(shape.iter().copied())
+                        .collect(),
+                    data: observations,
+                },
+                Array {
+                    name: "actions",
+                    descr: "|u1",
+                    shape: vec![count],
+                    data: actions,
+                },
+                Array {
+                    name: "seeds",
+                    descr: "<u8",
+                    shape: vec![count],
+                    data: seed_column,
+                },
+                Array {
+                    name: "ticks",
+                    descr: "<u8",
+                    shape: vec![count],
+                    data: ticks,
+                },
+            ];
+            out.write_all(&npz(&arrays)?)?;
+            Ok(count)
+        }
+    }
+}
+
+/// Code of a direction in npz exports, its index in `ACTIONS`
+pub fn action_code(direction: Direction) -> u8 {
+    ACTIONS
+        .iter()
+        .position(|action| *action == direction)
+        .expect("every direction has a code") as u8
+}
+
+/// Array of an npz archive with its NumPy type, shape and little-endian values
+struct Array {
+    name: &'static str,
+    descr: &'static str,
+    shape: Vec<usize>,
+    data: Vec<u8>,
+}
+
+impl Array {
+    /// The array in the `.npy` format, version 1.0
+    fn npy(&self) -> Vec<u8> {
+        let shape = match self.shape.as_slice() {
+            [length] => format!("({},)", length),
+            dimensions => format!(
+                "({})",
+                dimensions
+                    .iter()
+                    .map(usize::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        let mut header = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+            self.descr, shape
+        );
+        // Magic, version and header length take 10 bytes; spaces and a newline align the
+        // values to 64 bytes
+        let padding = (64 - (10 + header.len() + 1) % 64) % 64;
+        header.push_str(&" ".repeat(padding));
+        header.push('\n');
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend((header.len() as u16).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        bytes.extend(&self.data);
+        bytes
+    }
+}
+
+/// Stores arrays uncompressed in a zip archive, one `.npy` file per array
+/// Fails for archives beyond the 4 GiB a zip file without extensions can hold
+fn npz(arrays: &[Array]) -> io::Result<Vec<u8>> {
+    let too_large = |_: std::num::TryFromIntError| {
+        io::Error::new(io::ErrorKind::InvalidData, "export exceeds 4 GiB")
+    };
+    let mut archive: Vec<u8> = Vec::new();
+    let mut directory: Vec<u8> = Vec::new();
+    for array in arrays {
+        let name = format!("{}.npy", array.name);
+        let contents = array.npy();
+        let size = u32::try_from(contents.len()).map_err(too_large)?;
+        let offset = u32::try_from(archive.len()).map_err(too_large)?;
+        // Version 2.0, no flags, stored, dated 1980-01-01 00:00, no extra field
+        let mut fields: Vec<u8> = Vec::new();
+        for field in [20, 0, 0, 0, 0x21u16] {
+            fields.extend(field.to_le_bytes());
+        }
+        fields.extend(crc32(&contents).to_le_bytes());
+        fields.extend(size.to_le_bytes());
+        fields.extend(size.to_le_bytes());
+        fields.extend((name.len() as u16).to_le_bytes());
+        fields.extend(0u16.to_le_bytes());
+        archive.extend(0x0403_4b50u32.to_le_bytes());
+        archive.extend(&fields);
+        archive.extend(name.as_bytes());
+        archive.extend(&contents);
+        directory.extend(0x0201_4b50u32.to_le_bytes());
+        directory.extend(20u16.to_le_bytes());
+        directory.extend(&fields);
+        // No comment, disk 0, no attributes
+        directory.extend([0u8; 10]);
+        directory.extend(offset.to_le_bytes());
+        directory.extend(name.as_bytes());
+    }
+    let directory_offset = u32::try_from(archive.len()).map_err(too_large)?;
+    let entries = (arrays.len() as u16).to_le_bytes();
+    archive.extend(&directory);
+    archive.extend(0x0605_4b50u32.to_le_bytes());
+    archive.extend([0u8; 4]);
+    archive.extend(entries);
+    archive.extend(entries);
+    archive.extend((directory.len() as u32).to_le_bytes());
+    archive.extend(directory_offset.to_le_bytes());
+    archive.extend([0u8; 2]);
+    Ok(archive)
+}
+
+/// CRC-32 of the zip format
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Entry point of the `export-training` subcommand
+/// Usage: export-training GAMES PATH [grid|channels|flat] [FIRST_SEED]
+/// Plays GAMES games on the standard board and writes the samples to PATH, as an npz
+/// archive when PATH ends in `.npz` and as JSON lines otherwise
+pub fn run_cli(args: &[String], config: &AiConfig) -> io::Result<()> {
+    let usage = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "usage: export-training GAMES PATH [grid|channels|flat] [FIRST_SEED]",
+        )
+    };
+    let games = args
+        .first()
+        .and_then(|arg| arg.parse::<u32>().ok())
+        .ok_or_else(usage)?;
+    let path = args.get(1).ok_or_else(usage)?;
+    let observation = match args.get(2).map(String::as_str) {
+        None | Some("grid") => ObservationFormat::Grid,
+        Some("channels") => ObservationFormat::Channels,
+        Some("flat") => ObservationFormat::Flat,
+        Some(_) => return Err(usage()),
+    };
+    let first_seed = match args.get(3) {
+        Some(arg) => arg.parse::<u64>().map_err(|_| usage())?,
+        None => 0,
+    };
+    let format = if path.ends_with(".npz") {
+        ExportFormat::Npz
+    } else {
+        ExportFormat::Jsonl
+    };
+    let export = ExportConfig {
+        games,
+        first_seed,
+        width: crate::server::BOARD_WIDTH,
+        height: crate::server::BOARD_HEIGHT,
+        observation,
+    };
+    let mut out = BufWriter::new(File::create(path)?);
+    let count = write(&export, config, format, &mut out)?;
+    out.flush()?;
+    println!("{} samples from {} games written to {}", count, games, path);
+    Ok(())
+}
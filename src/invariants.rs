@@ -0,0 +1,352 @@
+#![deny(clippy::disallowed_types)]
+
+use crate::game::{
+    ComboScoring, Direction, FoodBehavior, FoodExpiry, Game, GameConfig, Growth, Modifiers, Point,
+    Portal, Start, Zone,
+};
+use crate::rng::SeededRng;
+use crate::topology::{Topology, TopologySpec};
+use std::collections::VecDeque;
+
+/// Every direction, including those the board may not have, so rejected inputs are fuzzed too
+const ALL_DIRECTIONS: [Direction; 8] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+    Direction::UpLeft,
+    Direction::UpRight,
+    Direction::DownLeft,
+    Direction::DownRight,
+];
+
+impl Game {
+    /// Checks that the state is one the rules can reach, failing with the first rule broken:
+    /// - the snake has segments, all on cells of the board and none of them twice
+    /// - every segment is one step from the one behind it, so the head leads an unbroken
+    ///   body to the tail
+    /// - the partner of a co-op game follows the same rules and shares no cell with the snake
+    /// - the food is on an open cell off the snakes, unless they leave no cell free
+    /// - the snakes' lengths plus their pending growth are their start lengths plus the
+    ///   growth of every food item eaten
+    /// - the score is the number of food items eaten, more only when bonuses apply, and at
+    ///   most `max_score`
+    pub fn check_invariants(&self) -> Result<(), String> {
+        self.check_body(&self.snake)?;
+        if let Some(partner) = &self.partner {
+            self.check_body(&partner.snake)
+                .map_err(|e| format!("partner {}", e))?;
+            if let Some(segment) = partner
+                .snake
+                .iter()
+                .find(|point| self.snake.contains(point))
+            {
+                return Err(format!("the snakes overlap at {}", cell(*segment)));
+            }
+        }
+        let board_full = self.open_cells().all(|point| self.on_snake(point));
+        if !board_full && (!self.is_open(self.food) || self.on_snake(self.food)) {
+            return Err(format!(
+                "the food at {} is not on a free cell",
+                cell(self.food)
+            ));
+        }
+
+        let grown: u64 = (1..=self.food_eaten)
+            .map(|eaten| u64::from(self.growth.segments_for(eaten)))
+            .sum();
+        let (partner_len, partner_pending, snakes) = match &self.partner {
+            Some(partner) => (partner.snake.len() as u64, partner.pending_growth, 2),
+            None => (0, 0, 1),
+        };
+        let length = self.snake.len() as u64
+            + u64::from(self.pending_growth)
+            + partner_len
+            + u64::from(partner_pending);
+        if length != snakes * u64::from(self.start.length) + grown {
+            return Err(format!(
+                "the snakes have {} segments and {} pending after starting with {} each and eating {}",
+                self.snake.len() as u64 + partner_len,
+                self.pending_growth + partner_pending,
+                self.start.length,
+                self.food_eaten
+            ));
+        }
+
+        let bonuses = self.combo.is_some() || self.food_expiry.is_some() || !self.zones.is_empty();
+        if self.score < self.food_eaten || (!bonuses && self.score != self.food_eaten) {
+            return Err(format!(
+                "the score is {} after eating {} food items",
+                self.score, self.food_eaten
+            ));
+        }
+        if let Some(max) = self.max_score().filter(|max| self.score > *max) {
+            return Err(format!(
+                "the score {} exceeds the maximum {}",
+                self.score, max
+            ));
+        }
+        if self.combo_multiplier == 0
+            || (self.combo_ticks_left.is_none() && self.combo_multiplier != 1)
+        {
+            return Err(format!(
+                "the combo multiplier is {} with {:?} ticks left",
+                self.combo_multiplier, self.combo_ticks_left
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that a snake has segments, all on cells of the board, none of them twice and
+    /// each one step from the one behind it
+    fn check_body(&self, body: &VecDeque<Point>) -> Result<(), String> {
+        if body.is_empty() {
+            return Err("snake has no segments".to_string());
+        }
+        for (i, segment) in body.iter().enumerate() {
+            let on_board = (0..self.width).contains(&segment.x)
+                && (0..self.height).contains(&segment.y)
+                && (0..self.topology.layers()).contains(&segment.z);
+            if !on_board || self.topology.is_wall(*segment) {
+                return Err(format!(
+                    "segment {} at {} is off the board",
+                    i,
+                    cell(*segment)
+                ));
+            }
+            if body.iter().skip(i + 1).any(|other| other == segment) {
+                return Err(format!("segment {} at {} appears twice", i, cell(*segment)));
+            }
+        }
+        for (i, (ahead, behind)) in body.iter().zip(body.iter().skip(1)).enumerate() {
+            let adjacent = self
+                .topology
+                .directions()
+                .iter()
+                .any(|direction| self.step(*behind, *direction) == *ahead);
+            if !adjacent {
+                return Err(format!(
+                    "segments {} at {} and {} at {} are not adjacent",
+                    i,
+                    cell(*ahead),
+                    i + 1,
+                    cell(*behind)
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats a cell for messages
+fn cell(point: Point) -> String {
+    format!("({}, {}, {})", point.x, point.y, point.z)
+}
+
+/// Random cell of a board of the given size
+fn random_cell(rng: &mut SeededRng, width: i32, height: i32) -> Point {
+    Point {
+        x: rng.gen_range(0, width),
+        y: rng.gen_range(0, height),
+        z: 0,
+    }
+}
+
+/// Whether a one-in-`n` draw hits
+fn one_in(rng: &mut SeededRng, n: i32) -> bool {
+    rng.gen_range(0, n) == 0
+}
+
+/// Draws the config of a game: any board and rules a client may ask for
+/// Starts and portals that do not fit the board are dropped
+fn random_config(rng: &mut SeededRng) -> GameConfig {
+    let width = rng.gen_range(5, 21);
+    let height = rng.gen_range(5, 21);
+    let spec = match rng.gen_range(0, 5) {
+        0 => TopologySpec::Solid,
+        1 => TopologySpec::Wrap,
+        2 => TopologySpec::Obstacles {
+            walls: (0..width * height / 10)
+                .map(|_| random_cell(rng, width, height))
+                .collect(),
+        },
+        3 => TopologySpec::Hex,
+        _ => TopologySpec::Layered {
+            layers: rng.gen_range(2, 4),
+            ladders: (0..rng.gen_range(1, 5))
+                .map(|_| Point {
+                    z: rng.gen_range(0, 2),
+                    ..random_cell(rng, width, height)
+                })
+                .collect(),
+        },
+    };
+    let topology = spec.build(width, height);
+    let (width, height) = topology.size();
+    let directions = topology.directions();
+    let mut config = GameConfig {
+        modifiers: Modifiers::default(),
+        food_behavior: FoodBehavior::Static,
+        food_expiry: None,
+        combo: None,
+        growth: Growth::default(),
+        start: Start::default(),
+        countdown: 0,
+        coop: false,
+        practice: one_in(rng, 4),
+        zones: Vec::new(),
+        portals: Vec::new(),
+        hazards: Vec::new(),
+        tiles: Vec::new(),
+        gates: Vec::new(),
+        objective: None,
+        level: None,
+        seed: rng.next_u64(),
+        topology,
+    };
+    config.modifiers.mirror_controls = one_in(rng, 4);
+    if one_in(rng, 4) {
+        config.modifiers.random_control_scramble_every_n_apples = Some(rng.gen_range(1, 4) as u32);
+    }
+    if one_in(rng, 4) {
+        config.food_behavior = FoodBehavior::Fleeing {
+            every_ticks: rng.gen_range(1, 4) as u32,
+        };
+    }
+    if one_in(rng, 4) {
+        config.food_expiry = Some(FoodExpiry {
+            lifetime_ticks: rng.gen_range(1, 40) as u32,
+            quick_bonus: rng.gen_range(0, 5) as u32,
+        });
+    }
+    if one_in(rng, 4) {
+        config.combo = Some(ComboScoring {
+            window_ticks: rng.gen_range(1, 20) as u32,
+            max_multiplier: (!one_in(rng, 3)).then(|| rng.gen_range(1, 6) as u32),
+        });
+    }
+    if one_in(rng, 3) {
+        config.growth = Growth {
+            segments: rng.gen_range(1, 6) as u32,
+            every_n_apples: rng.gen_range(1, 4) as u32,
+        };
+    }
+    if one_in(rng, 4) {
+        let corner = random_cell(rng, width, height);
+        config.zones.push(Zone {
+            x: corner.x,
+            y: corner.y,
+            z: 0,
+            width: rng.gen_range(1, 6),
+            height: rng.gen_range(1, 6),
+            multiplier: rng.gen_range(0, 4) as u32,
+        });
+    }
+    if one_in(rng, 4) {
+        config.portals.push(Portal {
+            a: random_cell(rng, width, height),
+            b: random_cell(rng, width, height),
+        });
+    }
+    if one_in(rng, 4) {
+        config.countdown = rng.gen_range(1, 10) as u32;
+    }
+    config.coop = one_in(rng, 4);
+    if one_in(rng, 3) {
+        config.start = Start {
+            length: rng.gen_range(1, 6) as u32,
+            position: None,
+            direction: directions[rng.gen_range(0, directions.len() as i32) as usize],
+        };
+    }
+    if config.start().is_err() {
+        config.start = Start::default();
+        config.portals.clear();
+    }
+    // Boards too crowded for a second snake are played alone
+    if config.start().is_err() {
+        config.coop = false;
+    }
+    config
+}
+
+/// Plays a game with random inputs, checking the invariants after every change
+/// Inputs are player directions (on time, late and for directions the board lacks), partner
+/// directions, AI moves and, in practice games, rewinds; fails with the tick and the rule
+/// broken
+fn fuzz_game(rng: &mut SeededRng, config: &GameConfig, ticks: u64) -> Result<(), String> {
+    let mut game = config.start()?;
+    game.history_len = game.snapshot_len();
+    let check = |game: &Game, what: &str| {
+        game.check_invariants()
+            .map_err(|e| format!("after {} at tick {}: {}", what, game.tick, e))
+    };
+    check(&game, "the start")?;
+    for _ in 0..ticks {
+        let direction = ALL_DIRECTIONS[rng.gen_range(0, ALL_DIRECTIONS.len() as i32) as usize];
+        match rng.gen_range(0, 10) {
+            0..=3 => {
+                game.queue_direction_at(direction, game.tick);
+                check(&game, "an input")?;
+            }
+            4 => {
+                let late = rng.gen_range(1, 6) as u64;
+                game.queue_direction_at(direction, game.tick.saturating_sub(late));
+                check(&game, "a late input")?;
+            }
+            5 => {
+                game.ai_move();
+                check(&game, "an AI move")?;
+            }
+            6 if game.partner.is_some() => {
+                game.queue_partner_direction(direction);
+                check(&game, "a partner input")?;
+            }
+            7 if game.practice => {
+                game.rewind(rng.gen_range(1, 10) as u64);
+                check(&game, "a rewind")?;
+            }
+            _ => {}
+        }
+        game.update();
+        check(&game, "a tick")?;
+        if game.game_over {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Entry point of the `fuzz` subcommand
+/// Usage: fuzz [GAMES] [TICKS] [SEED]
+/// Plays games on random boards and rules with random inputs, checking the engine's
+/// invariants after every change; the same seed plays the same games, so failures can be
+/// reproduced; returns whether no invariant broke
+pub fn fuzz_cli(args: &[String]) -> bool {
+    let arg = |index: usize, default: u64| {
+        args.get(index)
+            .and_then(|arg| arg.parse::<u64>().ok())
+            .unwrap_or(default)
+    };
+    let games = arg(0, 1_000);
+    let ticks = arg(1, 500);
+    let seed = arg(2, rand::random());
+    println!(
+        "Fuzzing {} games of up to {} ticks with seed {}",
+        games, ticks, seed
+    );
+
+    let mut rng = SeededRng::new(seed);
+    let mut failures = 0;
+    for index in 0..games {
+        let config = random_config(&mut rng);
+        if let Err(e) = fuzz_game(&mut rng, &config, ticks) {
+            failures += 1;
+            let config = serde_json::to_string(&config).unwrap_or_default();
+            println!("BROKEN  game {}: {}", index, e);
+            println!("        config {}", config);
+        }
+    }
+    println!("{} games played, {} broke an invariant", games, failures);
+    failures == 0
+}
@@ -0,0 +1,84 @@
+use crate::level::FEATURE_PERIOD;
+use crate::server::Score;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Length of an hour, the period of the hourly challenge leaderboard
+pub const HOUR: Duration = Duration::from_secs(60 * 60);
+
+/// Length of a day, the period of the daily leaderboard
+pub const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Most closed windows kept per window kind
+pub const MAX_CLOSED_WINDOWS: usize = 60;
+
+/// Time span a leaderboard collects scores over
+/// Hourly windows start on the hour and only rank games of the hour's challenge seed;
+/// daily windows start at midnight UTC; weekly windows start on Thursdays at midnight UTC,
+/// together with the level of the week rotation
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Window {
+    Hourly,
+    Daily,
+    Weekly,
+    #[default]
+    AllTime,
+}
+
+impl Window {
+    /// Windows that close and start over
+    pub const CLOSING: [Window; 3] = [Window::Hourly, Window::Daily, Window::Weekly];
+
+    /// Length of the window; None for the all-time leaderboard
+    pub fn period(self) -> Option<Duration> {
+        match self {
+            Window::Hourly => Some(HOUR),
+            Window::Daily => Some(DAY),
+            Window::Weekly => Some(FEATURE_PERIOD),
+            Window::AllTime => None,
+        }
+    }
+
+    /// Unix timestamp (seconds) of the start of the window containing `time`
+    pub fn start_of(self, time: SystemTime) -> u64 {
+        let elapsed = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        match self.period() {
+            Some(period) => elapsed - elapsed % period.as_secs(),
+            None => 0,
+        }
+    }
+}
+
+/// Leaderboard of the current hourly, daily or weekly window
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OpenWindow {
+    /// Unix timestamp (seconds) the window started at
+    pub start: u64,
+    /// Top scores submitted in the window
+    pub(crate) scores: Vec<Score>,
+}
+
+impl OpenWindow {
+    /// Empty leaderboard of the window containing `now`
+    pub fn starting(window: Window, now: SystemTime) -> Self {
+        OpenWindow {
+            start: window.start_of(now),
+            scores: Vec::new(),
+        }
+    }
+}
+
+/// Final leaderboard of a window that has closed
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ClosedWindow {
+    /// Unix timestamp (seconds) the window started at
+    pub start: u64,
+    /// Unix timestamp (seconds) the window ended at
+    pub end: u64,
+    /// Top scores submitted in the window; the first one is the winner
+    pub(crate) scores: Vec<Score>,
+}
@@ -0,0 +1,562 @@
+use crate::game::{
+    FoodBehavior, Game, GameConfig, Gate, Growth, Hazard, Modifiers, Objective, Portal, Start,
+    Tile, Zone,
+};
+use crate::identity;
+use crate::server::{AppState, Score, BOARD_HEIGHT, BOARD_WIDTH};
+use crate::topology::TopologySpec;
+use actix_web::{error, web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Longest allowed level name, in characters
+pub const MAX_LEVEL_NAME_LEN: usize = 64;
+
+/// Most levels returned by one listing
+pub const MAX_LISTED_LEVELS: usize = 100;
+
+/// Highest rating a level can be given, in stars
+pub const MAX_RATING: u8 = 5;
+
+/// Length of one level of the week rotation
+pub const FEATURE_PERIOD: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Most past rotations kept in the archive
+pub const MAX_ARCHIVED_FEATURES: usize = 52;
+
+/// Content of a custom level as written in the level editor
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LevelSpec {
+    /// Display name of the level
+    pub name: String,
+    /// Board variant
+    #[serde(default)]
+    pub topology: TopologySpec,
+    /// Snake the level starts with
+    #[serde(default)]
+    pub start: Start,
+    /// Score multiplier zones
+    #[serde(default)]
+    pub zones: Vec<Zone>,
+    /// Teleport portals
+    #[serde(default)]
+    pub portals: Vec<Portal>,
+    /// Moving hazards
+    #[serde(default)]
+    pub hazards: Vec<Hazard>,
+    /// Ice and speed tiles
+    #[serde(default)]
+    pub tiles: Vec<Tile>,
+    /// One-way gates
+    #[serde(default)]
+    pub gates: Vec<Gate>,
+    /// Keys and doors goal; levels with an exit rank games by how fast they complete it
+    #[serde(default)]
+    pub objective: Option<Objective>,
+}
+
+impl LevelSpec {
+    /// Config of a game of the level on a board of the given size, with default rules
+    pub fn config(&self, width: i32, height: i32, seed: u64) -> GameConfig {
+        GameConfig {
+            topology: self.topology.clone().build(width, height),
+            modifiers: Modifiers::default(),
+            food_behavior: FoodBehavior::default(),
+            food_expiry: None,
+            combo: None,
+            growth: Growth::default(),
+            start: self.start,
+            countdown: 0,
+            coop: false,
+            practice: false,
+            zones: self.zones.clone(),
+            portals: self.portals.clone(),
+            hazards: self.hazards.clone(),
+            tiles: self.tiles.clone(),
+            gates: self.gates.clone(),
+            objective: self.objective.clone(),
+            level: None,
+            seed,
+        }
+    }
+
+    /// Builds the initial game of the level on a board of the given size
+    /// Fails when the portals, hazards, tiles, gates, objective or the start snake do not fit
+    /// on the board
+    pub fn build(&self, width: i32, height: i32, seed: u64) -> Result<Game, String> {
+        self.config(width, height, seed).start()
+    }
+
+    /// Checks that the level is playable: it has a name, the start snake lies on free
+    /// cells, the snake can reach every cell food may appear on and, if the level has an
+    /// exit, the exit
+    pub fn validate(&self) -> Result<(), String> {
+        let name_len = self.name.trim().chars().count();
+        if name_len == 0 || name_len > MAX_LEVEL_NAME_LEN {
+            return Err(format!(
+                "level name must have between 1 and {} characters",
+                MAX_LEVEL_NAME_LEN
+            ));
+        }
+        let game = self.build(BOARD_WIDTH, BOARD_HEIGHT, 0)?;
+        if !game.food_reachable() {
+            return Err("food could appear on cells the snake cannot reach".to_string());
+        }
+        if !game.exit_reachable() {
+            return Err("the snake cannot reach the exit with the keys it can pick up".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Custom level with its authorship and publication state
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Level {
+    /// Level identifier
+    pub id: String,
+    /// Client ID of the author; never sent to clients
+    pub author: String,
+    /// Whether everyone can play the level; published levels can no longer be changed
+    pub published: bool,
+    /// Wall-clock creation time
+    pub created_at: SystemTime,
+    /// Board and features of the level
+    pub spec: LevelSpec,
+    /// Number of games started on the level
+    #[serde(default)]
+    pub plays: u64,
+    /// Star ratings keyed by client ID; never sent to clients
+    #[serde(default)]
+    pub ratings: HashMap<String, u8>,
+    /// Whether a moderator hid the level after it was reported
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+impl Level {
+    /// Whether the level is open to everyone: published and not hidden by a moderator
+    pub fn is_public(&self) -> bool {
+        self.published && !self.hidden
+    }
+
+    /// Whether a client may start games on the level: anyone once it is published and
+    /// unless it was hidden, always its author
+    pub fn playable_by(&self, client_id: Option<&str>) -> bool {
+        self.is_public() || client_id == Some(self.author.as_str())
+    }
+
+    /// Average star rating, or None before the first rating
+    pub fn rating(&self) -> Option<f64> {
+        if self.ratings.is_empty() {
+            return None;
+        }
+        let total: u32 = self.ratings.values().map(|&stars| u32::from(stars)).sum();
+        Some(f64::from(total) / self.ratings.len() as f64)
+    }
+
+    /// Level as shown to a client
+    fn view(&self, client_id: Option<&str>) -> serde_json::Value {
+        json!({
+            "id": self.id,
+            "published": self.published,
+            "own": client_id == Some(self.author.as_str()),
+            "plays": self.plays,
+            "rating": self.rating(),
+            "ratings": self.ratings.len(),
+            "level": self.spec,
+        })
+    }
+}
+
+/// Level of the week with the scores set on it during its rotation
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Featured {
+    /// Identifier of the featured level
+    pub level_id: String,
+    /// Rotation week, counted in `FEATURE_PERIOD`s since the Unix epoch
+    pub week: u64,
+    /// Whether an admin picked the level instead of the rotation
+    pub picked: bool,
+    /// Top scores of games on the level while it was featured
+    pub(crate) leaderboard: Vec<Score>,
+}
+
+/// Rotation week a point in time falls in
+pub fn week_of(time: SystemTime) -> u64 {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    elapsed.as_secs() / FEATURE_PERIOD.as_secs()
+}
+
+/// Level the rotation features in a week: public levels take turns in creation order
+pub fn rotation_pick(levels: &HashMap<String, Level>, week: u64) -> Option<String> {
+    let mut published: Vec<&Level> = levels.values().filter(|level| level.is_public()).collect();
+    if published.is_empty() {
+        return None;
+    }
+    published.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+    let index = (week % published.len() as u64) as usize;
+    Some(published[index].id.clone())
+}
+
+/// Makes a level the level of the week, archiving the leaderboard of the previous one
+/// Featuring the current level again only updates how it was chosen
+pub(crate) fn feature(data: &AppState, level_id: Option<String>, week: u64, picked: bool) {
+    let mut featured = data.featured.lock().unwrap();
+    if let Some(current) = featured.as_mut() {
+        if Some(&current.level_id) == level_id.as_ref() && current.week == week {
+            current.picked = picked;
+            return;
+        }
+    }
+    if let Some(previous) = featured.take() {
+        tracing::info!(level_id = %previous.level_id, week = previous.week, "archived featured level");
+        let mut archive = data.featured_archive.lock().unwrap();
+        archive.insert(0, previous);
+        archive.truncate(MAX_ARCHIVED_FEATURES);
+    }
+    *featured = level_id.map(|level_id| Featured {
+        level_id,
+        week,
+        picked,
+        leaderboard: Vec::new(),
+    });
+}
+
+/// Starts the rotation of the week `now` falls in, unless it already started
+pub(crate) fn rotate_featured(data: &AppState, now: SystemTime) {
+    let week = week_of(now);
+    let current_week = data.featured.lock().unwrap().as_ref().map(|f| f.week);
+    if current_week == Some(week) {
+        return;
+    }
+    let pick = rotation_pick(&data.levels.lock().unwrap(), week);
+    if pick.is_some() || current_week.is_some() {
+        feature(data, pick, week, false);
+    }
+}
+
+/// Directory of saved levels, one JSON file per level
+pub struct LevelStore {
+    dir: PathBuf,
+}
+
+impl LevelStore {
+    /// Opens the store, creating its directory if needed
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(LevelStore { dir })
+    }
+
+    /// Writes a level, replacing its previous version
+    /// Level IDs are server-issued UUIDs, so they are safe as file names
+    pub fn save(&self, level: &Level) -> io::Result<()> {
+        let path = self.dir.join(format!("{}.json", level.id));
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_vec(level)?)?;
+        fs::rename(&temp, &path)
+    }
+
+    /// Reads every level in the store, skipping unreadable files with a warning
+    pub fn load_all(&self) -> io::Result<Vec<Level>> {
+        let mut levels = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            match read(&path) {
+                Ok(level) => levels.push(level),
+                Err(e) => tracing::warn!("skipping level {}: {}", path.display(), e),
+            }
+        }
+        Ok(levels)
+    }
+}
+
+/// Parses a level file
+fn read(path: &Path) -> io::Result<Level> {
+    let contents = fs::read(path)?;
+    Ok(serde_json::from_slice(&contents)?)
+}
+
+/// Stores a level in memory and, when configured, on disk
+async fn save(data: &web::Data<AppState>, level: Level) -> actix_web::Result<()> {
+    data.levels
+        .lock()
+        .unwrap()
+        .insert(level.id.clone(), level.clone());
+    write(data, level).await
+}
+
+/// Writes the in-memory version of a level to disk, when a level store is configured
+pub async fn persist(data: &web::Data<AppState>, level_id: &str) -> actix_web::Result<()> {
+    let level = data.levels.lock().unwrap().get(level_id).cloned();
+    match level {
+        Some(level) => write(data, level).await,
+        None => Ok(()),
+    }
+}
+
+/// Writes a level to the level store, if one is configured
+async fn write(data: &web::Data<AppState>, level: Level) -> actix_web::Result<()> {
+    if data.level_store.is_some() {
+        let data = data.clone();
+        web::block(move || {
+            let store = data.level_store.as_ref().expect("checked above");
+            store.save(&level)
+        })
+        .await?
+        .map_err(error::ErrorInternalServerError)?;
+    }
+    Ok(())
+}
+
+/// Looks up a level for a change by its author
+/// Responds with 401 without client cookie, 404 for unknown levels, 403 for other
+/// clients' levels and 409 for published levels
+fn editable(req: &HttpRequest, data: &AppState, level_id: &str) -> actix_web::Result<Level> {
+    let Some(client_id) = identity::client_id(req) else {
+        return Err(error::ErrorUnauthorized("missing client cookie"));
+    };
+    let levels = data.levels.lock().unwrap();
+    let Some(level) = levels.get(level_id) else {
+        return Err(error::ErrorNotFound("unknown level"));
+    };
+    if level.author != client_id {
+        return Err(error::ErrorForbidden("not the level's author"));
+    }
+    if level.published {
+        return Err(error::ErrorConflict("level is published"));
+    }
+    Ok(level.clone())
+}
+
+/// Checks a level without saving it
+pub async fn validate(spec: web::Json<LevelSpec>) -> HttpResponse {
+    match spec.validate() {
+        Ok(()) => HttpResponse::Ok().json(json!({ "valid": true })),
+        Err(e) => HttpResponse::BadRequest().json(json!({ "error": e })),
+    }
+}
+
+/// Creates an unpublished level authored by the requesting client
+/// Issues a client cookie to clients without one
+pub async fn create(
+    req: HttpRequest,
+    spec: web::Json<LevelSpec>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    let spec = spec.into_inner();
+    if let Err(e) = spec.validate() {
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": e })));
+    }
+    let (client_id, is_new) = identity::client_id_or_new(&req);
+    let level = Level {
+        id: Uuid::new_v4().to_string(),
+        author: client_id.clone(),
+        published: false,
+        created_at: SystemTime::now(),
+        spec,
+        plays: 0,
+        ratings: HashMap::new(),
+        hidden: false,
+    };
+    let view = level.view(Some(&client_id));
+    save(&data, level).await?;
+    let mut response = HttpResponse::Ok();
+    if is_new {
+        response.cookie(identity::cookie(&data, &client_id));
+    }
+    Ok(response.json(view))
+}
+
+/// Replaces the content of an unpublished level
+/// level_id: Identifier of the level
+pub async fn update(
+    req: HttpRequest,
+    level_id: web::Path<String>,
+    spec: web::Json<LevelSpec>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    let mut level = editable(&req, &data, &level_id)?;
+    let spec = spec.into_inner();
+    if let Err(e) = spec.validate() {
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": e })));
+    }
+    level.spec = spec;
+    let view = level.view(Some(&level.author));
+    save(&data, level).await?;
+    Ok(HttpResponse::Ok().json(view))
+}
+
+/// Publishes a level so everyone can play it; it can no longer be changed afterwards
+/// level_id: Identifier of the level
+pub async fn publish(
+    req: HttpRequest,
+    level_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    let mut level = editable(&req, &data, &level_id)?;
+    if let Err(e) = level.spec.validate() {
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": e })));
+    }
+    level.published = true;
+    let view = level.view(Some(&level.author));
+    save(&data, level).await?;
+    Ok(HttpResponse::Ok().json(view))
+}
+
+/// Gets a level; unpublished and hidden levels are only visible to their author
+/// level_id: Identifier of the level
+pub async fn get(
+    req: HttpRequest,
+    level_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let client_id = identity::client_id(&req);
+    let levels = data.levels.lock().unwrap();
+    match levels.get(level_id.as_str()) {
+        Some(level) if level.playable_by(client_id.as_deref()) => {
+            HttpResponse::Ok().json(level.view(client_id.as_deref()))
+        }
+        _ => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Order of a level listing
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LevelSort {
+    /// Most played first
+    #[default]
+    Popular,
+    /// Best rated first; unrated levels last
+    Rating,
+    /// Most recently created first
+    Newest,
+}
+
+/// Query parameters of a level listing
+#[derive(Deserialize)]
+pub struct ListQuery {
+    /// Order of the listed levels
+    #[serde(default)]
+    sort: LevelSort,
+}
+
+/// Lists published levels that were not hidden, at most `MAX_LISTED_LEVELS` of them
+pub async fn list(
+    req: HttpRequest,
+    query: web::Query<ListQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let client_id = identity::client_id(&req);
+    let levels = data.levels.lock().unwrap();
+    let mut published: Vec<&Level> = levels.values().filter(|level| level.is_public()).collect();
+    match query.sort {
+        LevelSort::Popular => published.sort_by_key(|level| std::cmp::Reverse(level.plays)),
+        LevelSort::Rating => published.sort_by(|a, b| {
+            let rating = |level: &Level| level.rating().unwrap_or(-1.0);
+            rating(b).total_cmp(&rating(a))
+        }),
+        LevelSort::Newest => published.sort_by_key(|level| std::cmp::Reverse(level.created_at)),
+    }
+    let views: Vec<_> = published
+        .into_iter()
+        .take(MAX_LISTED_LEVELS)
+        .map(|level| level.view(client_id.as_deref()))
+        .collect();
+    HttpResponse::Ok().json(views)
+}
+
+/// Rating of a level
+#[derive(Deserialize)]
+pub struct RateRequest {
+    /// Stars given, between 1 and `MAX_RATING`
+    stars: u8,
+}
+
+/// Rates a published level; a later rating by the same client replaces the earlier one
+/// level_id: Identifier of the level
+pub async fn rate(
+    req: HttpRequest,
+    level_id: web::Path<String>,
+    rating: web::Json<RateRequest>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    let Some(client_id) = identity::client_id(&req) else {
+        return Err(error::ErrorUnauthorized("missing client cookie"));
+    };
+    if rating.stars == 0 || rating.stars > MAX_RATING {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("stars must be between 1 and {}", MAX_RATING)
+        })));
+    }
+    let view = {
+        let mut levels = data.levels.lock().unwrap();
+        match levels.get_mut(level_id.as_str()) {
+            Some(level) if level.is_public() => {
+                level.ratings.insert(client_id.clone(), rating.stars);
+                level.view(Some(&client_id))
+            }
+            _ => return Ok(HttpResponse::NotFound().finish()),
+        }
+    };
+    persist(&data, &level_id).await?;
+    Ok(HttpResponse::Ok().json(view))
+}
+
+/// Gets the top scores of games on a level
+/// level_id: Identifier of the level
+pub async fn leaderboard(
+    req: HttpRequest,
+    level_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let client_id = identity::client_id(&req);
+    let playable = data
+        .levels
+        .lock()
+        .unwrap()
+        .get(level_id.as_str())
+        .is_some_and(|level| level.playable_by(client_id.as_deref()));
+    if !playable {
+        return HttpResponse::NotFound().finish();
+    }
+    let leaderboards = data.level_leaderboards.lock().unwrap();
+    match leaderboards.get(level_id.as_str()) {
+        Some(scores) => HttpResponse::Ok().json(scores),
+        None => HttpResponse::Ok().json(json!([])),
+    }
+}
+
+/// Gets the level of the week with the leaderboard of its rotation
+pub async fn featured(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    let client_id = identity::client_id(&req);
+    let Some(featured) = data.featured.lock().unwrap().clone() else {
+        return HttpResponse::NotFound().finish();
+    };
+    let levels = data.levels.lock().unwrap();
+    let Some(level) = levels.get(&featured.level_id) else {
+        return HttpResponse::NotFound().finish();
+    };
+    HttpResponse::Ok().json(json!({
+        "week": featured.week,
+        "picked": featured.picked,
+        "level": level.view(client_id.as_deref()),
+        "leaderboard": featured.leaderboard,
+    }))
+}
+
+/// Lists past levels of the week with their final leaderboards, newest first
+pub async fn featured_archive(data: web::Data<AppState>) -> HttpResponse {
+    let archive = data.featured_archive.lock().unwrap();
+    HttpResponse::Ok().json(&*archive)
+}
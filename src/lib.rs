@@ -0,0 +1,71 @@
+#![allow(clippy::disallowed_types)]
+pub mod admin;
+pub mod ai;
+pub mod archive;
+pub mod audit;
+#[cfg(feature = "ai")]
+pub mod battlesnake;
+pub mod bench;
+#[cfg(feature = "ai")]
+pub mod bot;
+pub mod campaign;
+pub mod capabilities;
+pub mod challenge;
+#[cfg(feature = "persistence")]
+pub mod checkpoint;
+pub mod config;
+pub mod demo;
+pub mod feed;
+pub mod feedback;
+pub mod fixed;
+pub mod game;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod handoff;
+pub mod heatmap;
+#[cfg(feature = "multiplayer")]
+pub mod history;
+pub mod i18n;
+pub mod identity;
+pub mod invariants;
+#[cfg(feature = "ai")]
+pub mod imitation;
+pub mod leaderboard;
+pub mod level;
+pub mod loadtest;
+pub mod lock_bench;
+pub mod metrics;
+pub mod migration;
+pub mod moderation;
+pub mod names;
+#[cfg(feature = "onnx")]
+pub mod neural;
+#[cfg(feature = "oauth")]
+pub mod oauth;
+pub mod observation;
+pub mod proof_of_work;
+pub mod quota;
+#[cfg(feature = "multiplayer")]
+pub mod realtime;
+pub mod reload;
+pub mod render;
+pub mod replay;
+pub mod report;
+pub mod request_id;
+pub mod rng;
+pub mod room;
+pub mod score_token;
+pub mod scheduler;
+pub mod server;
+pub mod session;
+pub mod share;
+pub mod signer;
+pub mod state_hash;
+pub mod summary;
+#[cfg(feature = "multiplayer")]
+pub mod team;
+pub mod theme;
+pub mod topology;
+pub mod view;
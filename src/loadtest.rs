@@ -0,0 +1,199 @@
+use crate::config::Config;
+use crate::server::SESSION_TOKEN_HEADER;
+use crate::{request_id, server};
+use actix_web::middleware::from_fn;
+use actix_web::{App, HttpServer};
+use rand::seq::SliceRandom;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Parameters of a load-generation run
+pub struct LoadTestConfig {
+    /// Number of concurrent simulated clients
+    pub clients: usize,
+    /// How long every client keeps playing
+    pub duration: Duration,
+    /// Polling rate of every client in requests per second
+    pub rate_hz: u32,
+}
+
+/// Latency samples grouped by request kind
+#[derive(Default)]
+struct Samples {
+    latencies: HashMap<&'static str, Vec<Duration>>,
+    errors: usize,
+}
+
+impl Samples {
+    fn record(&mut self, kind: &'static str, latency: Duration, ok: bool) {
+        self.latencies.entry(kind).or_default().push(latency);
+        if !ok {
+            self.errors += 1;
+        }
+    }
+
+    fn merge(&mut self, other: Samples) {
+        for (kind, mut latencies) in other.latencies {
+            self.latencies
+                .entry(kind)
+                .or_default()
+                .append(&mut latencies);
+        }
+        self.errors += other.errors;
+    }
+}
+
+/// Returns the given percentile of a sorted latency list
+pub(crate) fn percentile(sorted: &[Duration], pct: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((pct / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    sorted[rank.min(sorted.len() - 1)]
+}
+
+/// Simulates a single player: creates a game, then steers and polls it
+async fn run_client(base_url: String, config: &LoadTestConfig) -> Samples {
+    let client = awc::Client::default();
+    let mut samples = Samples::default();
+    let directions = ["Up", "Down", "Left", "Right"];
+    let interval = Duration::from_secs(1) / config.rate_hz.max(1);
+    let deadline = Instant::now() + config.duration;
+
+    let start = Instant::now();
+    let created = match client.post(format!("{}/new-game", base_url)).send().await {
+        Ok(mut response) => {
+            let body: serde_json::Value = response.json().await.unwrap_or_default();
+            samples.record("new-game", start.elapsed(), response.status().is_success());
+            body["session_id"]
+                .as_str()
+                .map(str::to_string)
+                .zip(body["token"].as_str().map(str::to_string))
+        }
+        Err(_) => {
+            samples.record("new-game", start.elapsed(), false);
+            None
+        }
+    };
+    let Some((session_id, token)) = created else {
+        return samples;
+    };
+
+    while Instant::now() < deadline {
+        let direction = *directions.choose(&mut rand::thread_rng()).unwrap();
+        let start = Instant::now();
+        let result = client
+            .post(format!("{}/direction/{}", base_url, session_id))
+            .insert_header((SESSION_TOKEN_HEADER, token.as_str()))
+            .send_json(&direction)
+            .await;
+        samples.record(
+            "direction",
+            start.elapsed(),
+            result.is_ok_and(|r| r.status().is_success()),
+        );
+
+        let start = Instant::now();
+        let result = client
+            .post(format!("{}/update/{}", base_url, session_id))
+            .insert_header((SESSION_TOKEN_HEADER, token.as_str()))
+            .send()
+            .await;
+        samples.record(
+            "update",
+            start.elapsed(),
+            result.is_ok_and(|r| r.status().is_success()),
+        );
+
+        let start = Instant::now();
+        let result = client
+            .get(format!("{}/game/{}", base_url, session_id))
+            .send()
+            .await;
+        samples.record(
+            "state",
+            start.elapsed(),
+            result.is_ok_and(|r| r.status().is_success()),
+        );
+
+        actix_web::rt::time::sleep(interval).await;
+    }
+    samples
+}
+
+/// Starts the server in-process on an ephemeral port and drives simulated clients against it
+pub async fn run(config: LoadTestConfig) -> std::io::Result<()> {
+    // Simulated clients may tick faster than players; the tick quota would cap the load
+    let app_state = server::new_state(Config {
+        max_ticks_per_sec: 0,
+        ..Config::default()
+    });
+    let http_server = HttpServer::new(move || {
+        App::new()
+            .app_data(app_state.clone())
+            .wrap(from_fn(request_id::middleware))
+            .configure(server::configure)
+    })
+    .bind("127.0.0.1:0")?;
+    let addr = http_server.addrs()[0];
+    let http_server = http_server.run();
+    let handle = http_server.handle();
+    actix_web::rt::spawn(http_server);
+
+    println!(
+        "Load testing http://{} with {} clients for {:?} at {} Hz",
+        addr, config.clients, config.duration, config.rate_hz
+    );
+    let base_url = format!("http://{}", addr);
+    let started = Instant::now();
+    let clients = (0..config.clients).map(|_| run_client(base_url.clone(), &config));
+    let mut samples = Samples::default();
+    for client_samples in futures_util::future::join_all(clients).await {
+        samples.merge(client_samples);
+    }
+    let elapsed = started.elapsed();
+    handle.stop(true).await;
+
+    let mut total = 0;
+    println!(
+        "{:<12} {:>10} {:>12} {:>12}",
+        "request", "count", "p50", "p99"
+    );
+    let mut kinds: Vec<_> = samples.latencies.into_iter().collect();
+    kinds.sort_by_key(|(kind, _)| *kind);
+    for (kind, mut latencies) in kinds {
+        latencies.sort();
+        total += latencies.len();
+        println!(
+            "{:<12} {:>10} {:>12?} {:>12?}",
+            kind,
+            latencies.len(),
+            percentile(&latencies, 50.0),
+            percentile(&latencies, 99.0)
+        );
+    }
+    println!(
+        "{} requests in {:?} ({:.0} req/s), {} errors",
+        total,
+        elapsed,
+        total as f64 / elapsed.as_secs_f64(),
+        samples.errors
+    );
+    Ok(())
+}
+
+/// Entry point of the `loadtest` subcommand
+/// Usage: loadtest [CLIENTS] [SECONDS] [RATE_HZ]
+pub async fn run_cli(args: &[String]) -> std::io::Result<()> {
+    let arg = |index: usize, default: u64| {
+        args.get(index)
+            .and_then(|arg| arg.parse::<u64>().ok())
+            .unwrap_or(default)
+    };
+    run(LoadTestConfig {
+        clients: arg(0, 50) as usize,
+        duration: Duration::from_secs(arg(1, 10)),
+        rate_hz: arg(2, 10) as u32,
+    })
+    .await
+}
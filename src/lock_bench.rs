@@ -0,0 +1,312 @@
+use crate::game::Game;
+use crate::loadtest::percentile;
+use crate::view::GameView;
+use rand::Rng;
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Width and height of the games operated on
+const BOARD_SIZE: i32 = 20;
+
+/// How the games of a server are locked against concurrent requests
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// One mutex around the map of all games, as `AppState::sessions`
+    GlobalMutex,
+    /// A read-write lock around the map and a mutex around every game
+    PerGameLock,
+    /// A thread per game owning it, sent operations over a channel
+    Actor,
+}
+
+impl Strategy {
+    pub const ALL: [Strategy; 3] = [
+        Strategy::GlobalMutex,
+        Strategy::PerGameLock,
+        Strategy::Actor,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::GlobalMutex => "global_mutex",
+            Strategy::PerGameLock => "per_game_lock",
+            Strategy::Actor => "actor",
+        }
+    }
+}
+
+/// Which games the workers operate on, and how often they only read them
+pub struct Profile {
+    pub name: &'static str,
+    /// Share of operations reading a game's state rather than ticking it
+    pub read_share: f64,
+    /// Share of operations on the first game, e.g. a game many spectators watch
+    pub hot_share: f64,
+    /// Whether every worker keeps to its own games, as players polling their own game do
+    pub owned: bool,
+}
+
+/// Returns all contention profiles
+pub fn profiles() -> Vec<Profile> {
+    vec![
+        Profile {
+            name: "owned",
+            read_share: 0.5,
+            hot_share: 0.0,
+            owned: true,
+        },
+        Profile {
+            name: "uniform",
+            read_share: 0.5,
+            hot_share: 0.0,
+            owned: false,
+        },
+        Profile {
+            name: "hot",
+            read_share: 0.9,
+            hot_share: 0.9,
+            owned: false,
+        },
+    ]
+}
+
+/// Size of a lock benchmark run
+#[derive(Clone, Copy)]
+pub struct LockBenchConfig {
+    /// Number of worker threads sending operations
+    pub threads: usize,
+    /// Number of games the operations are spread over
+    pub games: usize,
+    /// Operations every worker sends
+    pub ops_per_thread: usize,
+}
+
+/// Operation a request makes on a game
+#[derive(Clone, Copy)]
+enum Op {
+    /// Builds the game's view, as polling its state does
+    Read,
+    /// Advances the game by a tick
+    Tick,
+}
+
+/// Applies an operation to a game, starting a new game in place of one that ended
+fn apply(game: &mut Game, op: Op) {
+    match op {
+        Op::Read => {
+            black_box(GameView::from(&*game));
+        }
+        Op::Tick => {
+            if game.game_over {
+                *game = Game::new(BOARD_SIZE, BOARD_SIZE);
+            }
+            black_box(game.update());
+        }
+    }
+}
+
+/// Games behind one locking strategy
+trait Store: Sync {
+    /// Applies an operation to a game, waiting until it is done
+    fn apply(&self, game: usize, op: Op);
+}
+
+struct GlobalMutex(Mutex<HashMap<usize, Game>>);
+
+impl Store for GlobalMutex {
+    fn apply(&self, game: usize, op: Op) {
+        let mut games = self.0.lock().unwrap();
+        if let Some(game) = games.get_mut(&game) {
+            apply(game, op);
+        }
+    }
+}
+
+struct PerGameLock(RwLock<HashMap<usize, Arc<Mutex<Game>>>>);
+
+impl Store for PerGameLock {
+    fn apply(&self, game: usize, op: Op) {
+        // The map is only locked to find the game, not while the operation runs
+        let game = self.0.read().unwrap().get(&game).cloned();
+        if let Some(game) = game {
+            apply(&mut game.lock().unwrap(), op);
+        }
+    }
+}
+
+/// Mailboxes of the game actors; every operation carries the channel its completion is sent on
+struct Actors(Vec<mpsc::Sender<(Op, mpsc::SyncSender<()>)>>);
+
+impl Store for Actors {
+    fn apply(&self, game: usize, op: Op) {
+        let (done, completion) = mpsc::sync_channel(1);
+        if self.0[game].send((op, done)).is_ok() {
+            let _ = completion.recv();
+        }
+    }
+}
+
+/// Sends operations from every worker to the store for a profile
+/// Returns the latency of every operation and the wall time of the run
+fn drive(
+    store: &dyn Store,
+    profile: &Profile,
+    config: &LockBenchConfig,
+) -> (Vec<Duration>, Duration) {
+    let started = Instant::now();
+    let latencies = thread::scope(|scope| {
+        let workers: Vec<_> = (0..config.threads)
+            .map(|worker| {
+                scope.spawn(move || {
+                    let mut rng = rand::thread_rng();
+                    let mut latencies = Vec::with_capacity(config.ops_per_thread);
+                    for _ in 0..config.ops_per_thread {
+                        let game = pick_game(&mut rng, profile, worker, config);
+                        let op = if rng.gen_bool(profile.read_share) {
+                            Op::Read
+                        } else {
+                            Op::Tick
+                        };
+                        let start = Instant::now();
+                        store.apply(game, op);
+                        latencies.push(start.elapsed());
+                    }
+                    latencies
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    (latencies, started.elapsed())
+}
+
+/// Chooses the game of a worker's next operation
+fn pick_game(
+    rng: &mut impl Rng,
+    profile: &Profile,
+    worker: usize,
+    config: &LockBenchConfig,
+) -> usize {
+    if rng.gen_bool(profile.hot_share) {
+        return 0;
+    }
+    if profile.owned {
+        // Worker w owns games w, w + threads, w + 2 * threads...
+        let owned = (config.games / config.threads).max(1);
+        return (worker + config.threads * rng.gen_range(0..owned)) % config.games;
+    }
+    rng.gen_range(0..config.games)
+}
+
+/// Throughput and latency of one strategy under one profile
+pub struct Report {
+    pub strategy: Strategy,
+    pub profile: &'static str,
+    pub ops: usize,
+    pub elapsed: Duration,
+    pub p50: Duration,
+    pub p99: Duration,
+}
+
+impl Report {
+    pub fn ops_per_sec(&self) -> f64 {
+        self.ops as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Runs a strategy under a profile against freshly created games
+pub fn run(strategy: Strategy, profile: &Profile, config: &LockBenchConfig) -> Report {
+    let config = LockBenchConfig {
+        threads: config.threads.max(1),
+        games: config.games.max(1),
+        ..*config
+    };
+    let games = (0..config.games).map(|id| (id, Game::new(BOARD_SIZE, BOARD_SIZE)));
+    let (mut latencies, elapsed) = match strategy {
+        Strategy::GlobalMutex => drive(&GlobalMutex(Mutex::new(games.collect())), profile, &config),
+        Strategy::PerGameLock => drive(
+            &PerGameLock(RwLock::new(
+                games
+                    .map(|(id, game)| (id, Arc::new(Mutex::new(game))))
+                    .collect(),
+            )),
+            profile,
+            &config,
+        ),
+        Strategy::Actor => thread::scope(|scope| {
+            let mailboxes = games
+                .map(|(_, mut game)| {
+                    let (mailbox, requests) = mpsc::channel::<(Op, mpsc::SyncSender<()>)>();
+                    scope.spawn(move || {
+                        for (op, done) in requests {
+                            apply(&mut game, op);
+                            let _ = done.send(());
+                        }
+                    });
+                    mailbox
+                })
+                .collect();
+            // The actors stop once their mailboxes are dropped with the store
+            drive(&Actors(mailboxes), profile, &config)
+        }),
+    };
+    latencies.sort();
+    Report {
+        strategy,
+        profile: profile.name,
+        ops: latencies.len(),
+        elapsed,
+        p50: percentile(&latencies, 50.0),
+        p99: percentile(&latencies, 99.0),
+    }
+}
+
+/// Entry point of the `bench locks` subcommand
+/// Usage: bench locks [THREADS] [GAMES] [OPS_PER_THREAD] [PROFILE]
+pub fn run_cli(args: &[String]) {
+    let arg = |index: usize, default: usize| {
+        args.get(index)
+            .and_then(|arg| arg.parse::<usize>().ok())
+            .unwrap_or(default)
+    };
+    let threads = thread::available_parallelism().map_or(4, |n| n.get());
+    let config = LockBenchConfig {
+        threads: arg(0, threads),
+        games: arg(1, 64),
+        ops_per_thread: arg(2, 20_000),
+    };
+    let filter = args.get(3).map(String::as_str).unwrap_or("");
+
+    println!(
+        "{} threads, {} games, {} operations per thread",
+        config.threads, config.games, config.ops_per_thread
+    );
+    println!(
+        "{:<10} {:<14} {:>12} {:>12} {:>12} {:>10}",
+        "profile", "strategy", "ops/s", "p50", "p99", "vs global"
+    );
+    for profile in profiles().iter().filter(|p| p.name.contains(filter)) {
+        let mut global = None;
+        for strategy in Strategy::ALL {
+            let report = run(strategy, profile, &config);
+            // Strategies are compared with the global mutex, which runs first
+            let baseline = *global.get_or_insert(report.ops_per_sec());
+            println!(
+                "{:<10} {:<14} {:>12.0} {:>12?} {:>12?} {:>9.2}x",
+                report.profile,
+                strategy.name(),
+                report.ops_per_sec(),
+                report.p50,
+                report.p99,
+                report.ops_per_sec() / baseline
+            );
+        }
+    }
+}
@@ -1,165 +1,109 @@
-use actix_files as fs;
-use actix_web::{web, App, HttpResponse, HttpServer, Result};
-use serde::{Deserialize, Serialize};
-use serde_json::json;
-use std::sync::Mutex;
+use actix_web::middleware::from_fn;
+use actix_web::{App, HttpServer};
+use snake_game::config::Config;
+#[cfg(feature = "ai")]
+use snake_game::{bot, imitation};
+use snake_game::{
+    bench, handoff, i18n, invariants, loadtest, reload, replay, request_id, scheduler, server,
+};
 use std::env;
-use std::collections::HashMap;
-use uuid::Uuid;
-mod game;
 
-/// Represents a player's score entry for the leaderboard
-#[derive(Serialize, Deserialize)]
-struct Score {
-    name: String,
-    score: u32,
-}
-
-/// Application state that is shared between all routes
-/// Uses Mutex for thread-safe access to games and leaderboard
-struct AppState {
-    // Map of session IDs to game instances
-    games: Mutex<HashMap<String, game::Game>>,
-    // Vector of top scores
-    leaderboard: Mutex<Vec<Score>>,
-}
-
-/// Serves the main HTML page
-async fn index() -> Result<fs::NamedFile> {
-    Ok(fs::NamedFile::open("static/index.html")?)
-}
-
-/// Returns the current state of a specific game
-/// session_id: Unique identifier for the game instance
-async fn get_game_state(
-    session_id: web::Path<String>,
-    data: web::Data<AppState>,
-) -> HttpResponse {
-    let games = data.games.lock().unwrap();
-    if let Some(game) = games.get(&session_id.into_inner()) {
-        HttpResponse::Ok().json(game)
-    } else {
-        HttpResponse::NotFound().finish()
-    }
-}
-
-/// Updates the direction of the snake for a specific game
-/// session_id: Unique identifier for the game instance
-/// direction: New direction for the snake
-async fn update_direction(
-    session_id: web::Path<String>,
-    direction: web::Json<game::Direction>,
-    data: web::Data<AppState>,
-) -> HttpResponse {
-    let mut games = data.games.lock().unwrap();
-    if let Some(game) = games.get_mut(&session_id.into_inner()) {
-        game.direction = direction.into_inner();
-        HttpResponse::Ok().finish()
-    } else {
-        HttpResponse::NotFound().finish()
-    }
-}
-
-/// Updates the game state (moves snake, checks collisions, etc.)
-/// session_id: Unique identifier for the game instance
-async fn update_game(
-    session_id: web::Path<String>,
-    data: web::Data<AppState>,
-) -> HttpResponse {
-    let mut games = data.games.lock().unwrap();
-    if let Some(game) = games.get_mut(&session_id.into_inner()) {
-        game.update();
-        HttpResponse::Ok().json(game)
-    } else {
-        HttpResponse::NotFound().finish()
-    }
-}
-
-/// Makes an AI move for a specific game
-/// session_id: Unique identifier for the game instance
-async fn ai_move(
-    session_id: web::Path<String>,
-    data: web::Data<AppState>,
-) -> HttpResponse {
-    let mut games = data.games.lock().unwrap();
-    if let Some(game) = games.get_mut(&session_id.into_inner()) {
-        game.ai_move();
-        game.update();
-        HttpResponse::Ok().json(game)
-    } else {
-        HttpResponse::NotFound().finish()
-    }
-}
-
-/// Creates a new game instance and returns its session ID
-async fn new_game(data: web::Data<AppState>) -> HttpResponse {
-    // Generate a unique session ID
-    let session_id = Uuid::new_v4().to_string();
-    let mut games = data.games.lock().unwrap();
-    // Create new game and store it in the HashMap
-    games.insert(session_id.clone(), game::Game::new(20, 20));
-    HttpResponse::Ok().json(json!({ "session_id": session_id }))
-}
-
-/// Submits a new score to the leaderboard
-/// Keeps only top 10 scores
-async fn submit_score(
-    score: web::Json<Score>,
-    data: web::Data<AppState>,
-) -> HttpResponse {
-    let mut leaderboard = data.leaderboard.lock().unwrap();
-    leaderboard.push(score.into_inner());
-    // Sort leaderboard by score in descending order
-    leaderboard.sort_by(|a, b| b.score.cmp(&a.score));
-    // Keep only top 10 scores
-    if leaderboard.len() > 10 {
-        leaderboard.truncate(10);
-    }
-    HttpResponse::Ok().json(&*leaderboard)
-}
-
-/// Returns the current leaderboard
-async fn get_leaderboard(data: web::Data<AppState>) -> HttpResponse {
-    let leaderboard = data.leaderboard.lock().unwrap();
-    HttpResponse::Ok().json(&*leaderboard)
-}
+#[cfg(feature = "dhat-heap")]
+#[global_allocator]
+static ALLOC: dhat::Alloc = dhat::Alloc;
 
 /// Main function that sets up and runs the web server
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Parse command line arguments for port number
+    // Parse command line arguments for subcommand or port number
     let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("bench") => {
+            bench::run_cli(&args[2..]);
+            return Ok(());
+        }
+        Some("loadtest") => return loadtest::run_cli(&args[2..]).await,
+        Some("fuzz") => {
+            if !invariants::fuzz_cli(&args[2..]) {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some("check-replays") => {
+            if !replay::check_cli(&args[2..]) {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some("verify-replays") => {
+            let config = Config::load()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            if !replay::verify_cli(&args[2..], config.checkpoint_dir.as_deref()) {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        #[cfg(feature = "ai")]
+        Some("export-training") => {
+            let config = Config::load()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            return imitation::run_cli(&args[2..], &config.ai);
+        }
+        _ => {}
+    }
     let port = if args.len() > 1 {
         args[1].parse::<u16>().unwrap_or(8080)
     } else {
         8080
     };
 
+    // Heap profiling is written to dhat-heap.json when the server stops
+    #[cfg(feature = "dhat-heap")]
+    let _profiler = dhat::Profiler::new_heap();
+
     // Initialize shared application state
-    let app_state = web::Data::new(AppState {
-        games: Mutex::new(HashMap::new()),
-        leaderboard: Mutex::new(Vec::new()),
-    });
+    let config = Config::load().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    request_id::init_tracing(config.log_level.as_deref());
+    let app_state = server::new_state(config);
+    // Tunables are reloaded when the configuration file changes, for as long as this lives
+    let _config_watcher = reload::watch(app_state.clone())
+        .map_err(|e| tracing::warn!("configuration changes need a restart, cannot watch: {}", e))
+        .ok();
+    scheduler::Scheduler::standard(&app_state.config()).spawn(app_state.clone());
+    #[cfg(feature = "ai")]
+    bot::resume(&app_state);
 
-    println!("Starting server on port {}", port);
-    
-    // Configure and start the HTTP server
-    HttpServer::new(move || {
+    // Start the gRPC server alongside the HTTP server when a port is configured
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_port) = app_state.config().grpc_port {
+        let grpc_state = app_state.clone();
+        let addr = std::net::SocketAddr::from(([127, 0, 0, 1], grpc_port));
+        tracing::info!("Starting gRPC server on port {}", grpc_port);
+        actix_web::rt::spawn(async move {
+            if let Err(e) = snake_game::grpc::serve(grpc_state, addr).await {
+                tracing::error!("gRPC server failed: {}", e);
+            }
+        });
+    }
+
+    tracing::info!("Starting server on port {}", port);
+    let listener = handoff::listener(port, app_state.config().reuse_port)?;
+    let drain_timeout = app_state.config().drain_timeout_secs;
+    let drain_state = app_state.clone();
+
+    // Configure and start the HTTP server; shutdown signals hand the games over first
+    let http_server = HttpServer::new(move || {
         App::new()
             .app_data(app_state.clone())
-            // Serve static files from the 'static' directory
-            .service(fs::Files::new("/static", "static").show_files_listing())
-            // Define routes
-            .route("/", web::get().to(index))
-            .route("/game/{session_id}", web::get().to(get_game_state))
-            .route("/direction/{session_id}", web::post().to(update_direction))
-            .route("/update/{session_id}", web::post().to(update_game))
-            .route("/ai-move/{session_id}", web::post().to(ai_move))
-            .route("/new-game", web::post().to(new_game))
-            .route("/submit-score", web::post().to(submit_score))
-            .route("/leaderboard", web::get().to(get_leaderboard))
+            .wrap(from_fn(handoff::middleware))
+            .wrap(from_fn(i18n::middleware))
+            .wrap(from_fn(request_id::middleware))
+            .configure(server::configure)
     })
-    .bind(format!("127.0.0.1:{}", port))?
-    .run()
-    .await
-} 
\ No newline at end of file
+    .disable_signals()
+    .shutdown_timeout(drain_timeout)
+    .listen(listener)?
+    .run();
+    actix_web::rt::spawn(handoff::drain_on_signal(drain_state, http_server.handle()));
+    http_server.await
+}
@@ -0,0 +1,93 @@
+use crate::signer::unix_now;
+use actix_web::http::StatusCode;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Length of one bucket of request counts, in seconds
+const BUCKET_SECS: u64 = 60;
+/// Number of buckets kept: the error rate covers the last five minutes
+const BUCKETS: usize = 5;
+
+/// Requests and failures counted in one minute
+#[derive(Clone, Copy, Default)]
+struct Bucket {
+    // Unix minute the bucket counts
+    minute: u64,
+    requests: u64,
+    client_errors: u64,
+    server_errors: u64,
+}
+
+/// Request counts of the last few minutes, fed by the request middleware
+#[derive(Default)]
+pub struct RequestMetrics {
+    buckets: Mutex<VecDeque<Bucket>>,
+}
+
+/// Totals over the retained minutes
+#[derive(serde::Serialize)]
+pub struct RequestSummary {
+    pub window_secs: u64,
+    pub requests: u64,
+    /// Responses with a 4xx status
+    pub client_errors: u64,
+    /// Responses with a 5xx status
+    pub server_errors: u64,
+    /// Share of requests that failed with a 5xx status
+    pub error_rate: f64,
+}
+
+/// Current Unix minute
+fn current_minute() -> u64 {
+    unix_now() / BUCKET_SECS
+}
+
+impl RequestMetrics {
+    /// Counts a finished request
+    pub fn record(&self, status: StatusCode) {
+        let minute = current_minute();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.back().is_none_or(|bucket| bucket.minute != minute) {
+            buckets.push_back(Bucket {
+                minute,
+                ..Bucket::default()
+            });
+            while buckets.len() > BUCKETS {
+                buckets.pop_front();
+            }
+        }
+        let bucket = buckets.back_mut().expect("pushed above");
+        bucket.requests += 1;
+        if status.is_client_error() {
+            bucket.client_errors += 1;
+        } else if status.is_server_error() {
+            bucket.server_errors += 1;
+        }
+    }
+
+    /// Totals of the last `BUCKETS` minutes, the current one included
+    pub fn summary(&self) -> RequestSummary {
+        let oldest = current_minute().saturating_sub(BUCKETS as u64 - 1);
+        let buckets = self.buckets.lock().unwrap();
+        let recent = buckets.iter().filter(|bucket| bucket.minute >= oldest);
+        let (requests, client_errors, server_errors) =
+            recent.fold((0, 0, 0), |(requests, client, server), bucket| {
+                (
+                    requests + bucket.requests,
+                    client + bucket.client_errors,
+                    server + bucket.server_errors,
+                )
+            });
+        RequestSummary {
+            window_secs: BUCKETS as u64 * BUCKET_SECS,
+            requests,
+            client_errors,
+            server_errors,
+            error_rate: if requests == 0 {
+                0.0
+            } else {
+                server_errors as f64 / requests as f64
+            },
+        }
+    }
+}
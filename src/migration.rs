@@ -0,0 +1,52 @@
+use serde_json::{json, Map, Value};
+
+/// Schema version of serialized games and replays written by this build
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Upgrade of a serialized game or replay from one schema version to the next
+type Migration = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+/// Migrations in order: the one at index `n` upgrades version `n` to `n + 1`
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [add_topology];
+
+/// Upgrades a serialized game or replay to the current schema, one version at a time
+/// Documents without `schema_version` predate versioning and are version 0
+/// Fails for documents that are not objects or were written by a newer build
+pub fn upgrade(mut value: Value) -> Result<Value, String> {
+    let doc = value
+        .as_object_mut()
+        .ok_or_else(|| "serialized game is not an object".to_string())?;
+    let version = doc
+        .get("schema_version")
+        .map(|version| {
+            version
+                .as_u64()
+                .ok_or_else(|| "schema_version is not a number".to_string())
+        })
+        .transpose()?
+        .unwrap_or(0);
+    if version > SCHEMA_VERSION as u64 {
+        return Err(format!(
+            "schema version {} is newer than supported version {}",
+            version, SCHEMA_VERSION
+        ));
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(doc)?;
+    }
+    doc.insert("schema_version".to_string(), json!(SCHEMA_VERSION));
+    Ok(value)
+}
+
+/// Version 1: boards gained a topology; earlier games were played on walled rectangles
+fn add_topology(doc: &mut Map<String, Value>) -> Result<(), String> {
+    if doc.contains_key("topology") {
+        return Ok(());
+    }
+    let (Some(width), Some(height)) = (doc.get("width"), doc.get("height")) else {
+        return Err("board without topology or size".to_string());
+    };
+    let topology = json!({ "kind": "solid", "width": width, "height": height });
+    doc.insert("topology".to_string(), topology);
+    Ok(())
+}
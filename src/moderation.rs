@@ -0,0 +1,156 @@
+use crate::leaderboard::Window;
+use crate::server::Score;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+
+/// Number of hidden entries kept for restoring; the oldest are dropped beyond it
+pub const MAX_HIDDEN_SCORES: usize = 1000;
+/// Most scores waiting for review; further held scores are refused until admins catch up
+pub const MAX_PENDING_SCORES: usize = 1000;
+
+/// Span over which submissions are counted for the rate limit
+pub const SUBMISSION_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Thresholds of the anti-spam checks, configured in the `[anti_spam]` table
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct SpamRules {
+    /// Scores a player or a client may submit per hour before further ones are reviewed
+    pub max_submissions_per_hour: usize,
+    /// Points per second of wall-clock game time above which a score is reviewed
+    pub max_points_per_sec: f64,
+}
+
+impl Default for SpamRules {
+    fn default() -> Self {
+        SpamRules {
+            max_submissions_per_hour: 20,
+            max_points_per_sec: 5.0,
+        }
+    }
+}
+
+/// Reason a score was held for review
+#[derive(Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Suspicion {
+    /// The player (anonymous ID) or client submitted more scores in the last hour than allowed
+    TooFrequent { source: String, submissions: usize },
+    /// The score was earned faster than the allowed points per second
+    TooFast { points_per_sec: f64 },
+    /// The score is higher than the board and scoring rules of its game allow
+    Impossible { max_score: u32 },
+}
+
+impl Suspicion {
+    /// Name of the check that raised the suspicion, as serialized in `kind`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Suspicion::TooFrequent { .. } => "too_frequent",
+            Suspicion::TooFast { .. } => "too_fast",
+            Suspicion::Impossible { .. } => "impossible",
+        }
+    }
+}
+
+/// Game facts the plausibility checks look at
+pub struct Played {
+    /// Wall-clock time from the creation of the game to the submission
+    pub elapsed: Duration,
+    /// Highest score the game allows, if bounded
+    pub max_score: Option<u32>,
+}
+
+impl SpamRules {
+    /// Plausibility problems of a score; empty when it looks legitimate
+    pub fn check(&self, score: u32, played: &Played) -> Vec<Suspicion> {
+        let mut reasons = Vec::new();
+        if let Some(max_score) = played.max_score.filter(|&max| score > max) {
+            reasons.push(Suspicion::Impossible { max_score });
+        }
+        // Short games are judged as if they lasted a second
+        let points_per_sec = f64::from(score) / played.elapsed.as_secs_f64().max(1.0);
+        if points_per_sec > self.max_points_per_sec {
+            reasons.push(Suspicion::TooFast { points_per_sec });
+        }
+        reasons
+    }
+}
+
+/// Recent submission times per source (anonymous player ID or client)
+#[derive(Default)]
+pub struct SubmissionLog {
+    times: HashMap<String, VecDeque<SystemTime>>,
+}
+
+impl SubmissionLog {
+    /// Records a submission and returns the number of submissions from the source
+    /// within the last `SUBMISSION_WINDOW`, including this one
+    /// Sources without recent submissions are forgotten
+    pub fn record(&mut self, source: &str, now: SystemTime) -> usize {
+        let since = now - SUBMISSION_WINDOW;
+        self.times.retain(|_, times| {
+            while times.front().is_some_and(|&time| time < since) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+        let times = self.times.entry(source.to_string()).or_default();
+        times.push_back(now);
+        times.len()
+    }
+}
+
+/// Score held back from the leaderboards until an admin reviews it
+#[derive(Clone, Serialize)]
+pub struct PendingScore {
+    /// Identifier of the review entry
+    pub id: String,
+    /// Entry to rank once approved
+    pub(crate) score: Score,
+    /// Speed-run time to rank once approved, if the game reached the split
+    pub(crate) speedrun: Option<u64>,
+    /// Why the score was held
+    pub reasons: Vec<Suspicion>,
+    /// Unix timestamp (seconds) of the submission
+    pub submitted_at: u64,
+    /// Anonymous ID of the player who submitted the score, see `identity::AnonymousIds`
+    pub player: String,
+    /// Client that played the game, if known
+    pub client_id: Option<String>,
+}
+
+/// Leaderboard where a hidden entry was ranked, so restoring puts it back
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "board", rename_all = "snake_case")]
+pub enum Placement {
+    /// All-time, hourly, daily or weekly leaderboard; `start` tells their windows apart
+    /// and is 0 for the all-time leaderboard
+    Window { window: Window, start: u64 },
+    /// Leaderboard of a custom level
+    Level { level_id: String },
+    /// Leaderboard of a level of the week rotation
+    Featured { level_id: String, week: u64 },
+}
+
+/// Leaderboard entry taken off the leaderboards by a moderator, or a held score rejected
+/// in review, kept so the decision can be reversed
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HiddenScore {
+    /// The hidden entry; its `id` identifies it for restoring
+    pub(crate) score: Score,
+    /// Why the entry was hidden
+    pub reason: String,
+    /// Who hid it, as recorded in the audit log
+    pub hidden_by: String,
+    /// Unix timestamp (seconds) the entry was hidden at
+    pub hidden_at: u64,
+    /// Leaderboards the entry was taken off; empty for rejected scores
+    pub placements: Vec<Placement>,
+    /// Speed-run time taken off the speed-run leaderboard, or never ranked when rejected
+    pub speedrun: Option<u64>,
+    /// Whether the score was rejected in review rather than hidden after ranking;
+    /// restoring a rejected score ranks it like an approved one
+    pub rejected: bool,
+}
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Fewest characters of a leaderboard name
+pub const MIN_NAME_LEN: usize = 2;
+
+/// Most characters of a leaderboard name
+pub const MAX_NAME_LEN: usize = 20;
+
+/// Punctuation allowed in names besides letters, digits and single spaces
+const NAME_PUNCTUATION: &[char] = &['-', '_', '.', '\''];
+
+/// Words rejected in names on top of the configured blocklist
+const DEFAULT_BLOCKLIST: &[&str] = &[
+    "admin",
+    "moderator",
+    "fuck",
+    "shit",
+    "cunt",
+    "bitch",
+    "dick",
+    "cock",
+    "pussy",
+    "nazi",
+    "hitler",
+    "whore",
+    "slut",
+    "fag",
+    "retard",
+];
+
+/// Look-alike characters replaced before matching the blocklist
+const DEFAULT_SUBSTITUTIONS: &[(char, char)] = &[
+    ('0', 'o'),
+    ('1', 'i'),
+    ('!', 'i'),
+    ('3', 'e'),
+    ('4', 'a'),
+    ('@', 'a'),
+    ('5', 's'),
+    ('$', 's'),
+    ('7', 't'),
+    ('8', 'b'),
+];
+
+/// Rules applied to leaderboard names, configured in the `[name_filter]` table
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct NameFilter {
+    /// Words rejected anywhere in a normalized name, in addition to the built-in list
+    pub blocklist: Vec<String>,
+    /// Look-alike characters and their replacements, used instead of the built-in ones
+    pub substitutions: HashMap<char, char>,
+}
+
+impl Default for NameFilter {
+    fn default() -> Self {
+        NameFilter {
+            blocklist: Vec::new(),
+            substitutions: DEFAULT_SUBSTITUTIONS.iter().copied().collect(),
+        }
+    }
+}
+
+/// Reason a leaderboard name was rejected
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NameError {
+    TooShort,
+    TooLong,
+    InvalidCharacter,
+    Blocked,
+    Taken,
+}
+
+impl NameError {
+    /// Message shown to players
+    pub fn message(&self) -> String {
+        match self {
+            NameError::TooShort => format!("name must have at least {} characters", MIN_NAME_LEN),
+            NameError::TooLong => format!("name must have at most {} characters", MAX_NAME_LEN),
+            NameError::InvalidCharacter => format!(
+                "name may only contain letters, digits, spaces and {}",
+                NAME_PUNCTUATION.iter().collect::<String>()
+            ),
+            NameError::Blocked => "name is not allowed".to_string(),
+            NameError::Taken => "name is used by another player".to_string(),
+        }
+    }
+
+    /// Body of the 400 Bad Request or 409 Conflict response
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({ "error": self.message(), "field": "name", "code": self })
+    }
+}
+
+impl NameFilter {
+    /// Cleans up a name and checks it against the length, charset and blocklist rules
+    /// Returns the name with surrounding whitespace removed and inner runs of
+    /// whitespace collapsed to single spaces
+    pub fn clean(&self, name: &str) -> Result<String, NameError> {
+        let cleaned = name.split_whitespace().collect::<Vec<_>>().join(" ");
+        let len = cleaned.chars().count();
+        if len < MIN_NAME_LEN {
+            return Err(NameError::TooShort);
+        }
+        if len > MAX_NAME_LEN {
+            return Err(NameError::TooLong);
+        }
+        if !cleaned
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == ' ' || NAME_PUNCTUATION.contains(&c))
+        {
+            return Err(NameError::InvalidCharacter);
+        }
+        let normalized = self.normalize(&cleaned);
+        let blocked = DEFAULT_BLOCKLIST
+            .iter()
+            .copied()
+            .chain(self.blocklist.iter().map(String::as_str))
+            .map(|word| self.normalize(word))
+            .any(|word| !word.is_empty() && normalized.contains(&word));
+        if blocked {
+            return Err(NameError::Blocked);
+        }
+        Ok(cleaned)
+    }
+
+    /// Form of a name used for the blocklist and for telling names apart: lowercase,
+    /// look-alikes substituted and everything but letters and digits removed,
+    /// so "B0b", "bob" and "b.o.b" are the same name
+    pub fn normalize(&self, name: &str) -> String {
+        name.chars()
+            .flat_map(char::to_lowercase)
+            .map(|c| self.substitutions.get(&c).copied().unwrap_or(c))
+            .filter(|c| c.is_alphanumeric())
+            .collect()
+    }
+}
@@ -0,0 +1,92 @@
+use crate::ai::{AiStrategy, Budget, Greedy};
+use crate::game::{Direction, Game};
+use crate::imitation;
+use crate::observation::{self, ObservationFormat};
+use crate::topology::Topology;
+use std::fmt;
+use std::sync::Arc;
+use tract_onnx::prelude::*;
+
+/// ONNX policy model played by the neural strategy
+/// The model takes an observation as `f32` values, shaped like the observation with a
+/// leading batch dimension of 1, and returns a score per direction in the order of the
+/// action codes of the training data export, see `imitation::ACTIONS`
+pub struct Policy {
+    model: TypedRunnableModel<TypedModel>,
+    observation: ObservationFormat,
+}
+
+impl fmt::Debug for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Policy")
+            .field("observation", &self.observation)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Policy {
+    /// Loads and optimizes a model file
+    pub fn load(path: &str, observation: ObservationFormat) -> Result<Policy, String> {
+        let model = tract_onnx::onnx()
+            .model_for_path(path)
+            .and_then(|model| model.into_optimized())
+            .and_then(|model| model.into_runnable())
+            .map_err(|e| format!("cannot load model {}: {}", path, e))?;
+        Ok(Policy { model, observation })
+    }
+
+    /// Scores of the directions on the board of a game
+    fn scores(&self, game: &Game) -> TractResult<Vec<f32>> {
+        let observation = observation::observe(game, self.observation);
+        let shape: Vec<usize> = std::iter::once(1).chain(observation.shape).collect();
+        let values: Vec<f32> = observation
+            .data
+            .into_flat()
+            .into_iter()
+            .map(f32::from)
+            .collect();
+        let input = Tensor::from_shape(&shape, &values)?;
+        let outputs = self.model.run(tvec!(input.into()))?;
+        Ok(outputs[0].to_array_view::<f32>()?.iter().copied().collect())
+    }
+}
+
+/// Plays the move its policy scores highest among those that do not hit a wall or the
+/// snake; one inference counts as one position of the budget
+/// Falls back to the greedy strategy when the model fails on a board, e.g. one of a size
+/// it was not trained for
+/// Inference uses floats, so unlike the other strategies its moves may differ across
+/// platforms
+pub struct Neural {
+    pub policy: Arc<Policy>,
+}
+
+impl AiStrategy for Neural {
+    fn decide(&self, game: &Game, budget: &mut Budget) -> Option<Direction> {
+        let head = *game.snake.front()?;
+        if !budget.spend() {
+            return None;
+        }
+        let scores = match self.policy.scores(game) {
+            Ok(scores) => scores,
+            Err(e) => {
+                tracing::warn!("policy model failed, playing greedy: {}", e);
+                return Greedy.decide(game, budget);
+            }
+        };
+        game.topology
+            .directions()
+            .iter()
+            .copied()
+            .filter(|direction| {
+                let point = game.step(head, *direction);
+                !game.blocks(point, *direction) && !game.snake.contains(&point)
+            })
+            .filter_map(|direction| {
+                let score = scores.get(imitation::action_code(direction) as usize)?;
+                Some((direction, *score))
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(direction, _)| direction)
+    }
+}
@@ -0,0 +1,405 @@
+use crate::config::{OAuthApp, OAuthConfig};
+use crate::identity;
+use crate::server::AppState;
+use crate::share;
+use actix_web::cookie::time::Duration;
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::{error, http::header, web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{self, SystemTime};
+use uuid::Uuid;
+
+/// Cookie binding a login to the browser that started it
+const STATE_COOKIE: &str = "snake_oauth_state";
+/// Time a player has to finish a login at the provider
+const LOGIN_TTL: time::Duration = time::Duration::from_secs(10 * 60);
+/// User agent sent to the providers' APIs, which GitHub requires
+const USER_AGENT: &str = "rust-snake-game";
+
+/// External identity provider
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Provider {
+    Github,
+    Google,
+}
+
+impl Provider {
+    /// Every supported provider
+    pub const ALL: [Provider; 2] = [Provider::Github, Provider::Google];
+
+    /// Name of the provider in URLs and account keys
+    pub fn name(self) -> &'static str {
+        match self {
+            Provider::Github => "github",
+            Provider::Google => "google",
+        }
+    }
+
+    /// Parses the provider name of a URL
+    pub fn parse(name: &str) -> Option<Self> {
+        Provider::ALL
+            .into_iter()
+            .find(|provider| provider.name() == name)
+    }
+
+    /// Page where the player grants access
+    fn authorize_url(self) -> &'static str {
+        match self {
+            Provider::Github => "https://github.com/login/oauth/authorize",
+            Provider::Google => "https://accounts.google.com/o/oauth2/v2/auth",
+        }
+    }
+
+    /// Endpoint exchanging an authorization code for an access token
+    fn token_url(self) -> &'static str {
+        match self {
+            Provider::Github => "https://github.com/login/oauth/access_token",
+            Provider::Google => "https://oauth2.googleapis.com/token",
+        }
+    }
+
+    /// Endpoint describing the signed-in user
+    fn user_url(self) -> &'static str {
+        match self {
+            Provider::Github => "https://api.github.com/user",
+            Provider::Google => "https://openidconnect.googleapis.com/v1/userinfo",
+        }
+    }
+
+    /// Access requested from the player: only their public profile
+    fn scope(self) -> &'static str {
+        match self {
+            Provider::Github => "read:user",
+            Provider::Google => "openid profile",
+        }
+    }
+
+    /// Stable user ID and display name in the provider's user description
+    fn identity(self, user: &Value) -> Option<(String, String)> {
+        let (id, name) = match self {
+            Provider::Github => (user.get("id")?.as_u64()?.to_string(), user.get("login")),
+            Provider::Google => (user.get("sub")?.as_str()?.to_string(), user.get("name")),
+        };
+        let name = name.and_then(Value::as_str).unwrap_or_default().to_string();
+        Some((id, name))
+    }
+
+    /// Application registered with the provider, if configured
+    fn app(self, config: &OAuthConfig) -> Option<&OAuthApp> {
+        match self {
+            Provider::Github => config.github.as_ref(),
+            Provider::Google => config.google.as_ref(),
+        }
+    }
+}
+
+/// External identity mapped to a local client
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Account {
+    /// Client whose games, scores and levels the identity signs in to
+    pub client_id: String,
+    pub provider: Provider,
+    /// Display name at the provider when the account was linked
+    pub name: String,
+}
+
+/// Login started at a provider and not yet finished
+pub struct PendingLogin {
+    provider: Provider,
+    expires_at: SystemTime,
+}
+
+/// Logins in progress and linked accounts
+pub struct OAuthState {
+    // Map of login states to the logins waiting for the provider's callback
+    pending: Mutex<HashMap<String, PendingLogin>>,
+    // Map of "provider:user ID" keys to linked accounts
+    accounts: Mutex<HashMap<String, Account>>,
+    // File where linked accounts are saved, if configured
+    account_file: Option<PathBuf>,
+}
+
+impl OAuthState {
+    /// Loads the accounts saved in the configured account file
+    /// A missing file starts with no accounts; an unreadable one is logged and ignored
+    pub fn open(config: &OAuthConfig) -> Self {
+        let account_file = config.account_file.as_ref().map(PathBuf::from);
+        let accounts = match account_file.as_deref().map(fs::read) {
+            Some(Ok(contents)) => serde_json::from_slice(&contents).unwrap_or_else(|e| {
+                tracing::error!("ignoring unreadable account file: {}", e);
+                HashMap::new()
+            }),
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => {
+                tracing::error!("cannot read account file: {}", e);
+                HashMap::new()
+            }
+            _ => HashMap::new(),
+        };
+        OAuthState {
+            pending: Mutex::new(HashMap::new()),
+            accounts: Mutex::new(accounts),
+            account_file,
+        }
+    }
+
+    /// Number of logins waiting for the provider's callback, expired ones included
+    pub fn pending_logins(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// Writes all accounts to the account file, if configured
+    fn save(&self, accounts: &HashMap<String, Account>) -> io::Result<()> {
+        let Some(path) = &self.account_file else {
+            return Ok(());
+        };
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, serde_json::to_vec(accounts)?)?;
+        fs::rename(&temp, path)
+    }
+
+    /// Linked accounts by "provider:user ID", for state archives
+    pub(crate) fn accounts(&self) -> HashMap<String, Account> {
+        self.accounts.lock().unwrap().clone()
+    }
+
+    /// Links the accounts of a state archive, replacing those with the same key, and saves
+    /// all accounts
+    pub(crate) fn import_accounts(&self, imported: HashMap<String, Account>) -> io::Result<()> {
+        let mut accounts = self.accounts.lock().unwrap();
+        accounts.extend(imported);
+        self.save(&accounts)
+    }
+
+    /// Client an external identity signs in to
+    /// Links unknown identities to the current client, or to a new one without client cookie
+    fn sign_in(
+        &self,
+        provider: Provider,
+        user_id: &str,
+        name: String,
+        current: Option<String>,
+    ) -> String {
+        let key = format!("{}:{}", provider.name(), user_id);
+        let mut accounts = self.accounts.lock().unwrap();
+        if let Some(account) = accounts.get(&key) {
+            return account.client_id.clone();
+        }
+        let client_id = current.unwrap_or_else(|| Uuid::new_v4().to_string());
+        accounts.insert(
+            key,
+            Account {
+                client_id: client_id.clone(),
+                provider,
+                name,
+            },
+        );
+        if let Err(e) = self.save(&accounts) {
+            tracing::error!("cannot save accounts: {}", e);
+        }
+        client_id
+    }
+}
+
+/// Provider of the request path with its configured application
+/// Responds with 404 Not Found for unknown or unconfigured providers
+fn configured(name: &str, data: &AppState) -> actix_web::Result<(Provider, OAuthApp)> {
+    Provider::parse(name)
+        .and_then(|provider| Some((provider, provider.app(&data.config().oauth)?.clone())))
+        .ok_or_else(|| error::ErrorNotFound("unknown login provider"))
+}
+
+/// URL the provider sends the player back to
+fn callback_url(req: &HttpRequest, data: &AppState, provider: Provider) -> String {
+    format!(
+        "{}/auth/{}/callback",
+        share::base_url(req, data),
+        provider.name()
+    )
+}
+
+/// Starts a login by redirecting to the provider
+/// provider: `github` or `google`
+pub async fn login(
+    req: HttpRequest,
+    provider: web::Path<String>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    let (provider, app) = configured(&provider, &data)?;
+    let state = Uuid::new_v4().simple().to_string();
+    let now = SystemTime::now();
+    {
+        let mut pending = data.oauth.pending.lock().unwrap();
+        pending.retain(|_, login| login.expires_at > now);
+        pending.insert(
+            state.clone(),
+            PendingLogin {
+                provider,
+                expires_at: now + LOGIN_TTL,
+            },
+        );
+    }
+    let query = serde_urlencoded::to_string([
+        ("client_id", app.client_id.as_str()),
+        ("redirect_uri", &callback_url(&req, &data, provider)),
+        ("response_type", "code"),
+        ("scope", provider.scope()),
+        ("state", &state),
+    ])
+    .map_err(error::ErrorInternalServerError)?;
+    let cookie = Cookie::build(STATE_COOKIE, state)
+        .path("/auth")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(Duration::seconds(LOGIN_TTL.as_secs() as i64))
+        .finish();
+    Ok(HttpResponse::Found()
+        .cookie(cookie)
+        .insert_header((
+            header::LOCATION,
+            format!("{}?{}", provider.authorize_url(), query),
+        ))
+        .finish())
+}
+
+/// Parameters the provider sends back to the callback
+#[derive(Deserialize)]
+pub struct Callback {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// Access token in the provider's token response
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// Exchanges an authorization code and returns the user ID and name at the provider
+async fn fetch_identity(
+    provider: Provider,
+    app: &OAuthApp,
+    code: &str,
+    redirect_uri: &str,
+) -> Result<(String, String), String> {
+    let client = awc::Client::default();
+    let token: TokenResponse = client
+        .post(provider.token_url())
+        .insert_header((header::ACCEPT, "application/json"))
+        .insert_header((header::USER_AGENT, USER_AGENT))
+        .send_form(&[
+            ("client_id", app.client_id.as_str()),
+            ("client_secret", &app.client_secret),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("grant_type", "authorization_code"),
+        ])
+        .await
+        .map_err(|e| format!("token request failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("invalid token response: {}", e))?;
+    let user: Value = client
+        .get(provider.user_url())
+        .bearer_auth(&token.access_token)
+        .insert_header((header::ACCEPT, "application/json"))
+        .insert_header((header::USER_AGENT, USER_AGENT))
+        .send()
+        .await
+        .map_err(|e| format!("user request failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("invalid user response: {}", e))?;
+    provider
+        .identity(&user)
+        .ok_or_else(|| "user response without ID".to_string())
+}
+
+/// Finishes a login: signs the browser in as the client linked to the external identity
+/// Identities seen for the first time are linked to the current client
+/// Responds with 400 Bad Request for denied, expired or forged logins and
+/// 502 Bad Gateway when the provider cannot be reached
+/// provider: `github` or `google`
+pub async fn callback(
+    req: HttpRequest,
+    provider: web::Path<String>,
+    query: web::Query<Callback>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    let (provider, app) = configured(&provider, &data)?;
+    if let Some(denied) = &query.error {
+        return Err(error::ErrorBadRequest(format!("login denied: {}", denied)));
+    }
+    let (Some(code), Some(state)) = (&query.code, &query.state) else {
+        return Err(error::ErrorBadRequest("missing code or state"));
+    };
+    if req
+        .cookie(STATE_COOKIE)
+        .map(|c| c.value().to_string())
+        .as_ref()
+        != Some(state)
+    {
+        return Err(error::ErrorBadRequest(
+            "login was started in another browser",
+        ));
+    }
+    let pending = data.oauth.pending.lock().unwrap().remove(state);
+    match pending {
+        Some(login) if login.provider == provider && login.expires_at > SystemTime::now() => {}
+        _ => return Err(error::ErrorBadRequest("unknown or expired login")),
+    }
+
+    let redirect_uri = callback_url(&req, &data, provider);
+    let (user_id, name) = fetch_identity(provider, &app, code, &redirect_uri)
+        .await
+        .map_err(|e| {
+            tracing::warn!("{} login failed: {}", provider.name(), e);
+            error::ErrorBadGateway("login provider unavailable")
+        })?;
+    let client_id = data
+        .oauth
+        .sign_in(provider, &user_id, name, identity::client_id(&req));
+
+    let mut expired = Cookie::build(STATE_COOKIE, "").path("/auth").finish();
+    expired.make_removal();
+    Ok(HttpResponse::Found()
+        .cookie(identity::cookie(&data, &client_id))
+        .cookie(expired)
+        .insert_header((header::LOCATION, "/"))
+        .finish())
+}
+
+/// Lists the configured providers and the accounts linked to the requesting client
+pub async fn me(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    let providers: Vec<Provider> = Provider::ALL
+        .into_iter()
+        .filter(|provider| provider.app(&data.config().oauth).is_some())
+        .collect();
+    let accounts: Vec<Account> = match identity::client_id(&req) {
+        Some(client_id) => data
+            .oauth
+            .accounts
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|account| account.client_id == client_id)
+            .cloned()
+            .collect(),
+        None => Vec::new(),
+    };
+    HttpResponse::Ok().json(json!({ "providers": providers, "accounts": accounts }))
+}
+
+/// Registers the login routes
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/auth/me", web::get().to(me))
+        .route("/auth/{provider}/login", web::get().to(login))
+        .route("/auth/{provider}/callback", web::get().to(callback));
+}
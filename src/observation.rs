@@ -0,0 +1,151 @@
+use crate::game::{Game, Point};
+use crate::topology::Topology;
+use serde::{Deserialize, Serialize};
+
+/// Cell codes of the grid format, in increasing precedence
+pub const EMPTY: u8 = 0;
+pub const WALL: u8 = 1;
+pub const LADDER: u8 = 2;
+pub const PORTAL: u8 = 3;
+pub const FOOD: u8 = 4;
+pub const BODY: u8 = 5;
+pub const HEAD: u8 = 6;
+
+/// Names of the channels of the channels and flat formats; channel `i` marks the cells
+/// with grid code `i + 1`, so empty cells are zero in every channel
+pub const CHANNELS: [&str; 6] = ["wall", "ladder", "portal", "food", "body", "head"];
+/// Grid codes of the channels
+const CODES: [u8; 6] = [WALL, LADDER, PORTAL, FOOD, BODY, HEAD];
+
+/// Shape of an exported board observation
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObservationFormat {
+    /// 2D grid of cell codes, `[height][width]`
+    #[default]
+    Grid,
+    /// One-hot tensor, `[channel][height][width]`
+    Channels,
+    /// The one-hot tensor flattened in channel, row, column order
+    Flat,
+}
+
+/// Board observation in one of the export formats
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum ObservationData {
+    Grid(Vec<Vec<u8>>),
+    Channels(Vec<Vec<Vec<u8>>>),
+    Flat(Vec<u8>),
+}
+
+impl ObservationData {
+    /// Values in row-major order of the observation's shape
+    pub fn into_flat(self) -> Vec<u8> {
+        match self {
+            ObservationData::Grid(rows) => rows.concat(),
+            ObservationData::Channels(planes) => planes.into_iter().flatten().flatten().collect(),
+            ObservationData::Flat(values) => values,
+        }
+    }
+}
+
+/// Board of a game as seen by a learning agent
+#[derive(Serialize)]
+pub struct Observation {
+    /// Game tick the observation was taken at
+    pub tick: u64,
+    /// Layer of the board shown: the one the snake's head is on
+    pub layer: i32,
+    /// Dimensions of `data`, outermost first
+    pub shape: Vec<usize>,
+    /// Names of the channels for the channels and flat formats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<&'static [&'static str]>,
+    pub data: ObservationData,
+    /// Score and whether the game ended, the usual reward and done signals
+    pub score: u32,
+    pub game_over: bool,
+}
+
+/// Cell codes of one layer of the board, row by row
+/// Every cell is classified once, then food, body and head are stamped on top, so the
+/// cost is one pass over the board plus the snake's length
+fn cells(game: &Game, layer: i32) -> Vec<u8> {
+    let (width, height) = (game.width.max(0) as usize, game.height.max(0) as usize);
+    let mut cells = vec![EMPTY; width * height];
+    let index = |point: Point| {
+        (point.z == layer
+            && (0..game.width).contains(&point.x)
+            && (0..game.height).contains(&point.y))
+        .then(|| point.y as usize * width + point.x as usize)
+    };
+    for y in 0..game.height {
+        for x in 0..game.width {
+            let point = Point { x, y, z: layer };
+            if game.topology.is_wall(point) {
+                cells[y as usize * width + x as usize] = WALL;
+            } else if game.topology.is_passage(point) {
+                cells[y as usize * width + x as usize] = LADDER;
+            }
+        }
+    }
+    for portal in &game.portals {
+        for cell in [portal.a, portal.b].into_iter().filter_map(index) {
+            cells[cell] = PORTAL;
+        }
+    }
+    if let Some(cell) = index(game.food) {
+        cells[cell] = FOOD;
+    }
+    for cell in game.snake.iter().skip(1).copied().filter_map(index) {
+        cells[cell] = BODY;
+    }
+    if let Some(cell) = game.snake.front().copied().and_then(index) {
+        cells[cell] = HEAD;
+    }
+    cells
+}
+
+/// Exports the board of a game in the requested format
+pub fn observe(game: &Game, format: ObservationFormat) -> Observation {
+    let layer = game.snake.front().map_or(0, |head| head.z);
+    let (width, height) = (game.width.max(0) as usize, game.height.max(0) as usize);
+    let cells = cells(game, layer);
+    let one_hot = |code: u8| cells.iter().map(move |cell| u8::from(*cell == code));
+    let (shape, channels, data) = match format {
+        ObservationFormat::Grid => (
+            vec![height, width],
+            None,
+            ObservationData::Grid(cells.chunks(width.max(1)).map(<[u8]>::to_vec).collect()),
+        ),
+        ObservationFormat::Channels => {
+            let tensor = CODES
+                .iter()
+                .map(|code| {
+                    let plane: Vec<u8> = one_hot(*code).collect();
+                    plane.chunks(width.max(1)).map(<[u8]>::to_vec).collect()
+                })
+                .collect();
+            (
+                vec![CHANNELS.len(), height, width],
+                Some(&CHANNELS[..]),
+                ObservationData::Channels(tensor),
+            )
+        }
+        ObservationFormat::Flat => (
+            vec![CHANNELS.len() * height * width],
+            Some(&CHANNELS[..]),
+            ObservationData::Flat(CODES.iter().flat_map(|code| one_hot(*code)).collect()),
+        ),
+    };
+    Observation {
+        tick: game.tick,
+        layer,
+        shape,
+        channels,
+        data,
+        score: game.score,
+        game_over: game.game_over,
+    }
+}
@@ -0,0 +1,197 @@
+use crate::server::AppState;
+use crate::signer::{unix_now, Signer};
+use actix_web::{web, HttpRequest, HttpResponse};
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Header carrying a solved challenge as `<challenge>:<solution>`
+pub const PROOF_OF_WORK_HEADER: &str = "X-Proof-Of-Work";
+/// Highest difficulty applied, in leading zero bits; higher configured ones are capped
+pub const MAX_DIFFICULTY: u32 = 32;
+/// Length of the random nonce of a challenge, in bytes
+const NONCE_LEN: usize = 16;
+
+/// Number of zero bits a hash starts with
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        if *byte != 0 {
+            return bits + byte.leading_zeros();
+        }
+        bits += 8;
+    }
+    bits
+}
+
+/// Proof of work asked for by the endpoints creating games and submitting scores,
+/// configured in the `[proof_of_work]` table
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ProofOfWorkConfig {
+    /// Leading zero bits the SHA-256 of a solved challenge must have; 0 turns the check off
+    pub difficulty: u32,
+    /// Seconds a challenge may be solved and used in
+    pub ttl_secs: u64,
+}
+
+impl Default for ProofOfWorkConfig {
+    fn default() -> Self {
+        ProofOfWorkConfig {
+            difficulty: 0,
+            ttl_secs: 300,
+        }
+    }
+}
+
+impl ProofOfWorkConfig {
+    /// Difficulty asked for, capped at `MAX_DIFFICULTY`
+    pub fn difficulty(&self) -> u32 {
+        self.difficulty.min(MAX_DIFFICULTY)
+    }
+}
+
+/// Issues and redeems proof-of-work challenges of the form
+/// `<expiry as Unix seconds>.<difficulty>.<hex nonce>.<hex HMAC-SHA256 of the first three parts>`
+/// A challenge is solved by a string whose SHA-256 together with it, as
+/// `<challenge>:<solution>`, starts with `difficulty` zero bits; each one is redeemed once
+#[derive(Default)]
+pub struct ProofOfWork {
+    // Random key, so challenges are only valid until the server restarts
+    signer: Signer,
+    // Map of redeemed challenges to their expiry, until they expire
+    spent: Mutex<HashMap<String, u64>>,
+}
+
+impl ProofOfWork {
+    /// Issues a challenge of the given difficulty
+    /// Returns the challenge and its expiry
+    pub fn issue(&self, difficulty: u32, ttl_secs: u64) -> (String, u64) {
+        let expires_at = unix_now() + ttl_secs;
+        let mut nonce = [0; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let claim = format!("{}.{}.{}", expires_at, difficulty, hex::encode(nonce));
+        (self.signer.sign(&claim), expires_at)
+    }
+
+    /// Checks a solved challenge against the required difficulty and spends it
+    /// False for forged, expired, unsolved or already redeemed challenges and those issued
+    /// with a lower difficulty
+    pub fn redeem(&self, proof: &str, difficulty: u32) -> bool {
+        let Some((challenge, _)) = proof.rsplit_once(':') else {
+            return false;
+        };
+        let Some(claim) = self.signer.verify(challenge) else {
+            return false;
+        };
+        let mut parts = claim.splitn(3, '.');
+        let expires_at = parts.next().and_then(|part| part.parse::<u64>().ok());
+        let issued = parts.next().and_then(|part| part.parse::<u32>().ok());
+        let (Some(expires_at), Some(issued)) = (expires_at, issued) else {
+            return false;
+        };
+        let now = unix_now();
+        if expires_at <= now
+            || issued < difficulty
+            || leading_zero_bits(&Sha256::digest(proof.as_bytes())) < issued
+        {
+            return false;
+        }
+        let mut spent = self.spent.lock().unwrap();
+        spent.retain(|_, expiry| *expiry > now);
+        spent.insert(challenge.to_string(), expires_at).is_none()
+    }
+}
+
+/// Rejects a request without a solved challenge in the `X-Proof-Of-Work` header while
+/// proof of work is configured: 403 Forbidden with the difficulty to solve
+/// Returns None when the request may go on
+pub(crate) fn check(req: &HttpRequest, data: &AppState) -> Option<HttpResponse> {
+    let proof = req
+        .headers()
+        .get(PROOF_OF_WORK_HEADER)
+        .and_then(|value| value.to_str().ok());
+    let difficulty = verify(proof, data).err()?;
+    Some(HttpResponse::Forbidden().json(json!({
+        "error": "missing or invalid proof of work",
+        "difficulty": difficulty,
+    })))
+}
+
+/// Redeems the solved challenge sent with a request while proof of work is configured
+/// Fails with the difficulty to solve when it is missing or invalid
+pub(crate) fn verify(proof: Option<&str>, data: &AppState) -> Result<(), u32> {
+    let difficulty = data.config().proof_of_work.difficulty();
+    if difficulty == 0 || proof.is_some_and(|proof| data.proof_of_work.redeem(proof, difficulty)) {
+        Ok(())
+    } else {
+        Err(difficulty)
+    }
+}
+
+/// Issues a challenge for the next request creating a game or submitting a score
+/// Responds with a `difficulty` of 0 and no challenge while proof of work is off
+pub async fn challenge(data: web::Data<AppState>) -> HttpResponse {
+    let config = data.config();
+    let difficulty = config.proof_of_work.difficulty();
+    if difficulty == 0 {
+        return HttpResponse::Ok().json(json!({ "difficulty": 0, "challenge": null }));
+    }
+    let (challenge, expires_at) = data
+        .proof_of_work
+        .issue(difficulty, config.proof_of_work.ttl_secs);
+    HttpResponse::Ok().json(json!({
+        "difficulty": difficulty,
+        "challenge": challenge,
+        "expires_at": expires_at,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// First solution of a challenge whose hash meets the difficulty or, with `solved`
+    /// false, misses it
+    fn solve(challenge: &str, difficulty: u32, solved: bool) -> String {
+        (0..)
+            .map(|nonce| format!("{}:{}", challenge, nonce))
+            .find(|proof| {
+                (leading_zero_bits(&Sha256::digest(proof.as_bytes())) >= difficulty) == solved
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn solved_challenge_is_redeemed_once() {
+        let proof_of_work = ProofOfWork::default();
+        let (challenge, _) = proof_of_work.issue(8, 60);
+        let proof = solve(&challenge, 8, true);
+        assert!(proof_of_work.redeem(&proof, 8));
+        assert!(!proof_of_work.redeem(&proof, 8));
+    }
+
+    #[test]
+    fn wrong_solution_is_rejected() {
+        let proof_of_work = ProofOfWork::default();
+        let (challenge, _) = proof_of_work.issue(8, 60);
+        assert!(!proof_of_work.redeem(&solve(&challenge, 8, false), 8));
+        assert!(!proof_of_work.redeem(&challenge, 8));
+    }
+
+    #[test]
+    fn stale_forged_and_easier_challenges_are_rejected() {
+        let proof_of_work = ProofOfWork::default();
+        let (stale, _) = proof_of_work.issue(4, 0);
+        assert!(!proof_of_work.redeem(&solve(&stale, 4, true), 4));
+
+        let (forged, _) = ProofOfWork::default().issue(4, 60);
+        assert!(!proof_of_work.redeem(&solve(&forged, 4, true), 4));
+
+        let (easier, _) = proof_of_work.issue(4, 60);
+        assert!(!proof_of_work.redeem(&solve(&easier, 8, true), 8));
+    }
+}
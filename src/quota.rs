@@ -0,0 +1,121 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+#[cfg(feature = "ai")]
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Token bucket limiting how fast one game advances, whoever drives it
+/// The bucket starts full; every tick takes a token and tokens come back at the rate
+#[derive(Clone, Copy)]
+pub struct TickQuota {
+    // Tokens taken and not yet refilled as of `updated`
+    used: f64,
+    updated: Instant,
+}
+
+impl Default for TickQuota {
+    fn default() -> Self {
+        TickQuota {
+            used: 0.0,
+            updated: Instant::now(),
+        }
+    }
+}
+
+impl TickQuota {
+    /// Takes a token per tick, or returns how long until that many are back and takes none
+    /// per_sec: Tokens refilled per second, zero for no limit
+    /// burst: Most tokens the bucket holds
+    pub fn take(&mut self, ticks: u32, per_sec: u32, burst: u32) -> Result<(), Duration> {
+        if per_sec == 0 {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let refilled = now.duration_since(self.updated).as_secs_f64() * f64::from(per_sec);
+        self.used = (self.used - refilled).max(0.0);
+        self.updated = now;
+        let excess = self.used + f64::from(ticks) - f64::from(burst.max(ticks));
+        if excess > 0.0 {
+            return Err(Duration::from_secs_f64(excess / f64::from(per_sec)));
+        }
+        self.used += f64::from(ticks);
+        Ok(())
+    }
+}
+
+/// Counts of games kept waiting since the server started
+#[derive(Clone, Copy, Default, Serialize)]
+pub struct Starvation {
+    /// Tick requests refused because their game was over its tick quota
+    pub throttled_ticks: u64,
+    /// AI decisions given a slot
+    pub ai_decisions: u64,
+    /// Total and longest time AI decisions waited for a slot, in milliseconds
+    pub ai_wait_ms: u64,
+    pub ai_max_wait_ms: u64,
+    /// Ticks of games ticked by the server, and those that ran more than a whole tick
+    /// interval late
+    pub server_ticks: u64,
+    pub late_ticks: u64,
+    /// Longest delay of a server tick past its due time, in milliseconds
+    pub max_lateness_ms: u64,
+}
+
+/// Shares the server's CPU fairly between games
+/// AI decisions of all games queue first come, first served for a fixed number of slots,
+/// and a game waits for its own decision before asking for the next one, so thousands of
+/// games take turns instead of crowding the blocking thread pool
+pub struct Fairness {
+    #[cfg(feature = "ai")]
+    ai_slots: Semaphore,
+    starvation: Mutex<Starvation>,
+}
+
+impl Fairness {
+    /// Fairness with up to `ai_slots` AI decisions running at once
+    #[cfg_attr(not(feature = "ai"), allow(unused_variables))]
+    pub fn new(ai_slots: usize) -> Self {
+        Fairness {
+            #[cfg(feature = "ai")]
+            ai_slots: Semaphore::new(ai_slots.max(1)),
+            starvation: Mutex::new(Starvation::default()),
+        }
+    }
+
+    /// Waits for a slot to run an AI decision in, which is freed when the permit drops
+    #[cfg(feature = "ai")]
+    pub async fn ai_slot(&self) -> SemaphorePermit<'_> {
+        let queued_at = Instant::now();
+        let permit = self
+            .ai_slots
+            .acquire()
+            .await
+            .expect("the AI slots are never closed");
+        let waited = queued_at.elapsed().as_millis() as u64;
+        let mut starvation = self.starvation.lock().unwrap();
+        starvation.ai_decisions += 1;
+        starvation.ai_wait_ms += waited;
+        starvation.ai_max_wait_ms = starvation.ai_max_wait_ms.max(waited);
+        permit
+    }
+
+    /// Counts a tick request refused by a tick quota
+    pub fn throttled(&self) {
+        self.starvation.lock().unwrap().throttled_ticks += 1;
+    }
+
+    /// Counts a tick of a game ticked by the server that ran `lateness` after it was due
+    pub fn server_tick(&self, lateness: Duration, interval: Duration) {
+        let mut starvation = self.starvation.lock().unwrap();
+        starvation.server_ticks += 1;
+        if lateness > interval {
+            starvation.late_ticks += 1;
+        }
+        starvation.max_lateness_ms = starvation.max_lateness_ms.max(lateness.as_millis() as u64);
+    }
+
+    /// Counts so far
+    pub fn starvation(&self) -> Starvation {
+        *self.starvation.lock().unwrap()
+    }
+}
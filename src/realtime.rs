@@ -0,0 +1,855 @@
+use crate::config::Config;
+use crate::game::Direction;
+use crate::identity;
+use crate::replay::Visibility;
+use crate::room::RoomPass;
+use crate::server::{AppState, DirectionInput};
+use crate::session::{Emote, RoomChange, SequencedEvent, StreamMessage, Viewer};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::{AggregatedMessage, CloseCode, CloseReason, Session as WsSession};
+use futures_util::stream::{self, AbortHandle, LocalBoxStream, SelectAll};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+use tokio::sync::broadcast::Receiver;
+use tokio::time::Interval;
+use uuid::Uuid;
+
+/// Most sessions a multiplexed connection streams at once
+pub const MAX_CHANNELS: usize = 8;
+
+/// Query parameters of the realtime endpoint
+#[derive(Deserialize)]
+pub struct ConnectQuery {
+    /// Session token; connections without it are read-only spectators
+    token: Option<String>,
+    /// Sequence number of the last event the client received
+    #[serde(default)]
+    since: u64,
+    /// Password of a private room
+    password: Option<String>,
+    /// Invite to a private room, see `room::invite`
+    invite: Option<String>,
+}
+
+/// Counts the reactions of a spectator connection in windows of a minute, so it sends at
+/// most `reactions_per_minute`
+struct ReactionLimit {
+    per_minute: u32,
+    window_start: Instant,
+    sent: u32,
+}
+
+impl ReactionLimit {
+    fn new(config: &Config) -> ReactionLimit {
+        ReactionLimit {
+            per_minute: config.reactions_per_minute,
+            window_start: Instant::now(),
+            sent: 0,
+        }
+    }
+
+    /// Whether another reaction may be sent now, counting it if so
+    fn allow(&mut self) -> bool {
+        if self.window_start.elapsed() >= Duration::from_secs(60) {
+            self.window_start = Instant::now();
+            self.sent = 0;
+        }
+        if self.sent >= self.per_minute {
+            return false;
+        }
+        self.sent += 1;
+        true
+    }
+}
+
+/// Broadcasts a spectator's reaction to everyone watching a session, within its limit
+fn react(data: &AppState, session_id: &str, emote: Emote, limit: &mut ReactionLimit) {
+    if !limit.allow() {
+        return;
+    }
+    if let Some(session) = data.sessions.lock().unwrap().get(session_id) {
+        session.react(emote);
+    }
+}
+
+/// What a realtime connection does about a message of its session's stream
+enum RoomAction {
+    /// Nothing; the message does not concern the connection
+    Stay,
+    /// The host kicked or banned the connection
+    Leave,
+    /// The connection hosts the room from now on, with this session token
+    Host(String),
+    /// The connection hosted the room and only spectates from now on
+    Spectate,
+}
+
+/// Applies a moderation change of a room to one of its connections, moving it between
+/// the host's connections and the spectators' when the host changed
+fn room_action(
+    data: &AppState,
+    session_id: &str,
+    connection: &str,
+    owner: bool,
+    message: &StreamMessage,
+) -> RoomAction {
+    let StreamMessage::Moderation { change } = message else {
+        return RoomAction::Stay;
+    };
+    match change {
+        RoomChange::Kicked { connection: target } | RoomChange::Banned { connection: target }
+            if target == connection =>
+        {
+            RoomAction::Leave
+        }
+        RoomChange::HostTransferred { connection: target } => {
+            let mut sessions = data.sessions.lock().unwrap();
+            let Some(session) = sessions.get_mut(session_id) else {
+                return RoomAction::Stay;
+            };
+            if target == connection {
+                session.set_host(connection, true);
+                RoomAction::Host(session.token.clone())
+            } else if owner {
+                session.set_host(connection, false);
+                RoomAction::Spectate
+            } else {
+                RoomAction::Stay
+            }
+        }
+        _ => RoomAction::Stay,
+    }
+}
+
+/// Close frame telling a client the host closed its connection
+fn kicked() -> CloseReason {
+    CloseReason {
+        code: CloseCode::Policy,
+        description: Some("removed by the host".to_string()),
+    }
+}
+
+/// Pings a realtime connection every `ws_ping_secs` and notices when its client went quiet
+struct Heartbeat {
+    pings: Interval,
+    /// When the client last sent a frame of any kind, pongs included
+    last_heard: Instant,
+    timeout: Duration,
+}
+
+impl Heartbeat {
+    fn new(config: &Config) -> Heartbeat {
+        let period = Duration::from_secs(config.ws_ping_secs.max(1));
+        Heartbeat {
+            pings: tokio::time::interval_at(tokio::time::Instant::now() + period, period),
+            last_heard: Instant::now(),
+            timeout: Duration::from_secs(config.ws_idle_timeout_secs),
+        }
+    }
+
+    /// Records a frame from the client
+    fn heard(&mut self) {
+        self.last_heard = Instant::now();
+    }
+
+    /// Waits until the next ping is due
+    /// Returns false when the client sent nothing for `ws_idle_timeout_secs`, and the
+    /// connection should be closed instead
+    async fn beat(&mut self) -> bool {
+        self.pings.tick().await;
+        self.last_heard.elapsed() < self.timeout
+    }
+}
+
+/// Receives the next update of a session together with the updates already queued behind
+/// it, so a subscriber that fell behind skips the frames older than the latest keyframe
+/// The events of the skipped updates are all kept, merged ahead of the reactions,
+/// moderation changes and frames; of their feedback hints only the latest tick's are
+/// Fails with `RecvError::Lagged` when updates were lost, and the subscriber needs the
+/// current keyframe
+async fn next_updates(
+    receiver: &mut Receiver<StreamMessage>,
+) -> Result<Vec<StreamMessage>, RecvError> {
+    let mut events = Vec::new();
+    let mut notices = Vec::new();
+    let mut feedback = None;
+    let mut frames = Vec::new();
+    let mut update = receiver.recv().await?;
+    loop {
+        match update {
+            StreamMessage::Events { events: more } => events.extend(more),
+            StreamMessage::Reaction { .. } | StreamMessage::Moderation { .. } => {
+                notices.push(update)
+            }
+            // Effects of skipped ticks are stale; only the latest ones are played
+            StreamMessage::Feedback { .. } => feedback = Some(update),
+            StreamMessage::State { .. } => frames = vec![update],
+            StreamMessage::Delta { .. } => frames.push(update),
+        }
+        update = match receiver.try_recv() {
+            Ok(message) => message,
+            Err(TryRecvError::Lagged(skipped)) => return Err(RecvError::Lagged(skipped)),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Closed) => break,
+        };
+    }
+    let mut messages = Vec::new();
+    if !events.is_empty() {
+        messages.push(StreamMessage::Events { events });
+    }
+    messages.extend(notices);
+    messages.extend(feedback);
+    messages.extend(frames);
+    Ok(messages)
+}
+
+/// Request of the client of a single-session connection, besides direction changes
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamRequest {
+    /// Asks for the full current state, e.g. after a gap in the frame numbers
+    Keyframe,
+    /// Reaction of a spectator, broadcast to everyone watching the session
+    Reaction { emote: Emote },
+}
+
+/// Sends a stream message as a JSON text frame
+async fn send(ws: &mut WsSession, message: &StreamMessage) -> Result<(), actix_ws::Closed> {
+    let text = serde_json::to_string(message).expect("stream messages serialize");
+    ws.text(text).await
+}
+
+/// Opens a WebSocket streaming a session's events and state
+/// Missed events since `since` are replayed first, followed by a keyframe of the current
+/// state; any client may request another one with `{"type": "keyframe"}`
+/// Token holders may send direction changes as text frames; once the session is resumed
+/// elsewhere and its token rotated, the connection only spectates
+/// Spectators may send `{"type": "reaction", "emote": "fire"}`, up to `reactions_per_minute`
+/// Connections the host kicks are closed with code 1008 (policy violation); one the host
+/// hands the room to gets the new token in a `host` message and may steer from then on
+/// Responds with 503 Service Unavailable when the game has `max_spectators` spectators, and
+/// 403 Forbidden to spectators of locked rooms, to banned browsers, to browsers without
+/// client cookie in rooms with bans and to spectators of private rooms giving neither the
+/// `password` nor an `invite`
+/// A server shutting down closes the connection with code 1012 (service restart)
+/// session_id: Unique identifier for the game instance
+pub async fn connect(
+    req: HttpRequest,
+    body: web::Payload,
+    session_id: web::Path<String>,
+    query: web::Query<ConnectQuery>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    let session_id = session_id.into_inner();
+    let ConnectQuery {
+        mut token,
+        since,
+        password,
+        invite,
+    } = query.into_inner();
+    let client_id = identity::client_id(&req);
+    let pass = RoomPass {
+        password: password.as_deref(),
+        invite: invite.as_deref(),
+    };
+    let joined = join(
+        &data,
+        &session_id,
+        token.as_deref(),
+        since,
+        client_id,
+        &pass,
+    );
+    let Joined {
+        connection,
+        mut owner,
+        backlog,
+        keyframe,
+        mut updates,
+    } = match joined {
+        Ok(joined) => joined,
+        Err(JoinError::UnknownSession) => return Ok(HttpResponse::NotFound().finish()),
+        Err(e @ JoinError::SpectatorsFull) => {
+            return Ok(HttpResponse::ServiceUnavailable().json(json!({ "error": e.message() })))
+        }
+        Err(e) => return Ok(HttpResponse::Forbidden().json(json!({ "error": e.message() }))),
+    };
+
+    let (response, mut ws, stream) = match actix_ws::handle(&req, body) {
+        Ok(handshake) => handshake,
+        Err(err) => {
+            disconnect(&data, &session_id, &connection, owner);
+            return Err(err);
+        }
+    };
+    let mut incoming = stream.aggregate_continuations();
+    let mut heartbeat = Heartbeat::new(&data.config());
+    let mut reactions = ReactionLimit::new(&data.config());
+    let mut draining = data.draining.subscribe();
+
+    actix_web::rt::spawn(async move {
+        let mut closing = None;
+        let mut open = (backlog.is_empty()
+            || send(&mut ws, &StreamMessage::Events { events: backlog })
+                .await
+                .is_ok())
+            && send(&mut ws, &keyframe).await.is_ok();
+        while open {
+            tokio::select! {
+                update = next_updates(&mut updates) => {
+                    let messages = match update {
+                        Ok(messages) => messages,
+                        // Too slow to keep up: skip to the current state
+                        Err(RecvError::Lagged(_)) => {
+                            let sessions = data.sessions.lock().unwrap();
+                            let Some(session) = sessions.get(&session_id) else { break };
+                            vec![session.keyframe()]
+                        }
+                        Err(RecvError::Closed) => break,
+                    };
+                    for message in &messages {
+                        open = open && send(&mut ws, message).await.is_ok();
+                        match room_action(&data, &session_id, &connection, owner, message) {
+                            RoomAction::Stay => {}
+                            RoomAction::Leave => {
+                                closing = Some(kicked());
+                                open = false;
+                            }
+                            RoomAction::Host(new_token) => {
+                                owner = true;
+                                let host = json!({ "type": "host", "token": new_token });
+                                open = open && ws.text(host.to_string()).await.is_ok();
+                                token = Some(new_token);
+                            }
+                            RoomAction::Spectate => owner = false,
+                        }
+                    }
+                }
+                alive = heartbeat.beat() => {
+                    open = alive && ws.ping(b"").await.is_ok();
+                }
+                _ = draining.changed() => {
+                    closing = Some(restarting());
+                    break;
+                }
+                message = incoming.next() => match message.inspect(|_| heartbeat.heard()) {
+                    Some(Ok(AggregatedMessage::Text(text))) => match serde_json::from_str(&text) {
+                        Ok(StreamRequest::Keyframe) => {
+                            let keyframe = data
+                                .sessions
+                                .lock()
+                                .unwrap()
+                                .get(&session_id)
+                                .map(|session| session.keyframe());
+                            if let Some(keyframe) = keyframe {
+                                open = send(&mut ws, &keyframe).await.is_ok();
+                            }
+                        }
+                        // Players steer; only spectators react
+                        Ok(StreamRequest::Reaction { emote }) if !owner => {
+                            react(&data, &session_id, emote, &mut reactions);
+                        }
+                        Ok(StreamRequest::Reaction { .. }) => {}
+                        Err(_) if owner => {
+                            if let Ok(input) = serde_json::from_str::<DirectionInput>(&text) {
+                                let mut sessions = data.sessions.lock().unwrap();
+                                if let Some(session) = sessions
+                                    .get_mut(&session_id)
+                                    .filter(|session| session.authorizes(token.as_deref()))
+                                {
+                                    session.touch();
+                                    input.apply(&mut session.game, 0);
+                                }
+                            }
+                        }
+                        Err(_) => {}
+                    },
+                    Some(Ok(AggregatedMessage::Ping(bytes))) => {
+                        open = ws.pong(&bytes).await.is_ok();
+                    }
+                    Some(Ok(AggregatedMessage::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+        disconnect(&data, &session_id, &connection, owner);
+        let _ = ws.close(closing).await;
+    });
+
+    Ok(response)
+}
+
+/// Close frame telling a client the server is restarting, so it reconnects to the server
+/// taking over
+fn restarting() -> CloseReason {
+    CloseReason {
+        code: CloseCode::Restart,
+        description: Some("server restarting".to_string()),
+    }
+}
+
+/// Releases a realtime connection; the owner's grace period starts now
+/// When the owner's last connection closes, the session's spectators are told the player
+/// disconnected unless they are back within `disconnect_grace_secs`
+fn disconnect(data: &web::Data<AppState>, session_id: &str, connection: &str, owner: bool) {
+    let mut sessions = data.sessions.lock().unwrap();
+    let Some(session) = sessions.get_mut(session_id) else {
+        return;
+    };
+    session
+        .viewers
+        .retain(|viewer| viewer.connection != connection);
+    session.detach(owner);
+    if !owner || session.owner_connections > 0 {
+        return;
+    }
+    let data = data.clone();
+    let session_id = session_id.to_string();
+    let grace = Duration::from_secs(data.config().disconnect_grace_secs);
+    actix_web::rt::spawn(async move {
+        actix_web::rt::time::sleep(grace).await;
+        let mut sessions = data.sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(&session_id) {
+            if session.report_disconnect(grace) {
+                tracing::info!(session_id = %session_id, "player disconnected");
+            }
+        }
+    });
+}
+
+/// Control message sent by the client of a multiplexed connection
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    /// Streams a session on a channel named by the client, replacing the session the
+    /// channel streamed before
+    Subscribe {
+        channel: String,
+        session_id: String,
+        /// Session token; channels without it are read-only spectators
+        #[serde(default)]
+        token: Option<String>,
+        /// Sequence number of the last event the client received
+        #[serde(default)]
+        since: u64,
+        /// Password of a private room
+        #[serde(default)]
+        password: Option<String>,
+        /// Invite to a private room
+        #[serde(default)]
+        invite: Option<String>,
+    },
+    /// Stops streaming a channel
+    Unsubscribe { channel: String },
+    /// Asks for the full current state of a channel's session, e.g. after a gap in the
+    /// frame numbers
+    Keyframe { channel: String },
+    /// Reaction of a spectator to the session of a channel subscribed without its token
+    Reaction { channel: String, emote: Emote },
+    /// Direction change for the session of a channel subscribed with its token
+    Direction {
+        channel: String,
+        direction: Direction,
+        /// Tick of the state the player was looking at
+        tick: Option<u64>,
+        /// Snake to steer: 0 for the first, 1 for the partner of a co-op game
+        #[serde(default)]
+        snake: u8,
+    },
+}
+
+/// Stream message tagged with the channel it belongs to
+#[derive(Serialize)]
+struct ChannelMessage<'a> {
+    channel: &'a str,
+    #[serde(flatten)]
+    message: &'a StreamMessage,
+}
+
+/// Sends a stream message of a channel as a JSON text frame
+async fn send_on(
+    ws: &mut WsSession,
+    channel: &str,
+    message: &StreamMessage,
+) -> Result<(), actix_ws::Closed> {
+    let text = serde_json::to_string(&ChannelMessage { channel, message })
+        .expect("stream messages serialize");
+    ws.text(text).await
+}
+
+/// Sends a notice about a channel, e.g. that it failed or closed
+async fn notify(
+    ws: &mut WsSession,
+    channel: &str,
+    kind: &str,
+    error: Option<&str>,
+) -> Result<(), actix_ws::Closed> {
+    let notice = match error {
+        Some(error) => json!({ "channel": channel, "type": kind, "error": error }),
+        None => json!({ "channel": channel, "type": kind }),
+    };
+    ws.text(notice.to_string()).await
+}
+
+/// Session streamed on a channel of a multiplexed connection
+struct Channel {
+    session_id: String,
+    /// Identifier of the channel's connection to the session, see `Viewer`
+    connection: String,
+    token: Option<String>,
+    owner: bool,
+    /// Ends the channel's stream of updates
+    abort: AbortHandle,
+}
+
+/// Stream of a channel's updates, tagged with the channel's name
+type ChannelUpdates = LocalBoxStream<'static, (String, Result<Vec<StreamMessage>, RecvError>)>;
+
+/// Updates of a session tagged with the channel streaming them, coalesced like those of
+/// `next_updates`; a closed session yields `RecvError::Closed` once and ends the stream
+fn channel_updates(
+    channel: String,
+    receiver: Receiver<StreamMessage>,
+) -> (ChannelUpdates, AbortHandle) {
+    let updates = stream::unfold(Some(receiver), |receiver| async move {
+        let mut receiver = receiver?;
+        let update = next_updates(&mut receiver).await;
+        let next = (!matches!(update, Err(RecvError::Closed))).then_some(receiver);
+        Some((update, next))
+    })
+    .map(move |update| (channel.clone(), update));
+    let (updates, abort) = stream::abortable(updates);
+    (updates.boxed_local(), abort)
+}
+
+/// Realtime connection registered with a session
+struct Joined {
+    /// Identifier of the connection, listed to the room's host
+    connection: String,
+    /// Whether the connection holds the session token
+    owner: bool,
+    /// Events missed since the client's last one
+    backlog: Vec<SequencedEvent>,
+    keyframe: StreamMessage,
+    updates: Receiver<StreamMessage>,
+}
+
+/// Reason a realtime connection cannot join a session
+enum JoinError {
+    UnknownSession,
+    /// The session has `max_spectators` connections without its token
+    SpectatorsFull,
+    /// The room is private and the spectator gave neither its password nor an invite
+    Private,
+    /// The host locked the room to spectators
+    Locked,
+    /// The host banned the connecting browser
+    Banned,
+    /// The room has bans and the connecting browser has no client cookie to check
+    Anonymous,
+}
+
+impl JoinError {
+    fn message(&self) -> &'static str {
+        match self {
+            JoinError::UnknownSession => "unknown session",
+            JoinError::SpectatorsFull => "too many spectators",
+            JoinError::Private => "a password or invite is required",
+            JoinError::Locked => "the room is locked",
+            JoinError::Banned => "banned from this room",
+            JoinError::Anonymous => "a client cookie is required to join this room",
+        }
+    }
+}
+
+/// Counts a new connection to a session and subscribes it to the session's updates
+/// client_id: Browser of the connection, checked against the room's bans
+/// pass: Password or invite of a spectator joining a private room
+fn join(
+    data: &AppState,
+    session_id: &str,
+    token: Option<&str>,
+    since: u64,
+    client_id: Option<String>,
+    pass: &RoomPass,
+) -> Result<Joined, JoinError> {
+    let mut sessions = data.sessions.lock().unwrap();
+    let session = sessions
+        .get_mut(session_id)
+        .ok_or(JoinError::UnknownSession)?;
+    let owner = session.authorizes(token);
+    let admitted = session.watchable_by(token, client_id.as_deref(), pass);
+    // Private games do not reveal themselves to spectators without an invite
+    if !admitted && session.visibility == Visibility::Private {
+        return Err(JoinError::UnknownSession);
+    }
+    if !admitted {
+        return Err(JoinError::Private);
+    }
+    let banned = |client_id: &String| session.banned.contains(client_id);
+    if !owner && client_id.as_ref().is_some_and(banned) {
+        return Err(JoinError::Banned);
+    }
+    if !owner && session.has_bans && client_id.is_none() {
+        return Err(JoinError::Anonymous);
+    }
+    if !owner && session.locked {
+        return Err(JoinError::Locked);
+    }
+    if !owner && session.spectators() >= data.config().max_spectators {
+        return Err(JoinError::SpectatorsFull);
+    }
+    session.attach(owner);
+    let connection = Uuid::new_v4().to_string();
+    session.viewers.push(Viewer {
+        connection: connection.clone(),
+        host: owner,
+        client_id,
+    });
+    Ok(Joined {
+        connection,
+        owner,
+        backlog: session.events_since(since),
+        keyframe: session.keyframe(),
+        updates: session.subscribe(),
+    })
+}
+
+/// Opens a WebSocket streaming any number of sessions, up to `MAX_CHANNELS`, e.g. the
+/// player's game next to a spectated one
+/// The client subscribes sessions to channels it names and every message carries its
+/// `channel`; each channel behaves like a connection of `connect`
+/// Reactions of all spectator channels count toward one `reactions_per_minute` limit
+pub async fn connect_multiplexed(
+    req: HttpRequest,
+    body: web::Payload,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    let client_id = identity::client_id(&req);
+    let (response, mut ws, stream) = actix_ws::handle(&req, body)?;
+    let mut incoming = stream.aggregate_continuations();
+    let mut heartbeat = Heartbeat::new(&data.config());
+    let mut reactions = ReactionLimit::new(&data.config());
+    let mut draining = data.draining.subscribe();
+
+    actix_web::rt::spawn(async move {
+        let mut closing = None;
+        let mut channels: HashMap<String, Channel> = HashMap::new();
+        let mut updates: SelectAll<ChannelUpdates> = SelectAll::new();
+        let mut open = true;
+        while open {
+            tokio::select! {
+                Some((name, update)) = updates.next(), if !updates.is_empty() => {
+                    let messages = match update {
+                        Ok(messages) => messages,
+                        // Too slow to keep up: skip to the current state
+                        Err(RecvError::Lagged(_)) => {
+                            let Some(channel) = channels.get(&name) else { continue };
+                            let sessions = data.sessions.lock().unwrap();
+                            let Some(session) = sessions.get(&channel.session_id) else {
+                                continue;
+                            };
+                            vec![session.keyframe()]
+                        }
+                        Err(RecvError::Closed) => {
+                            channels.remove(&name);
+                            open = notify(&mut ws, &name, "closed", None).await.is_ok();
+                            continue;
+                        }
+                    };
+                    for message in &messages {
+                        open = open && send_on(&mut ws, &name, message).await.is_ok();
+                        let Some(channel) = channels.get_mut(&name) else { break };
+                        let action = room_action(
+                            &data,
+                            &channel.session_id,
+                            &channel.connection,
+                            channel.owner,
+                            message,
+                        );
+                        match action {
+                            RoomAction::Stay => {}
+                            RoomAction::Leave => {
+                                if let Some(removed) = channels.remove(&name) {
+                                    removed.abort.abort();
+                                    disconnect(
+                                        &data,
+                                        &removed.session_id,
+                                        &removed.connection,
+                                        removed.owner,
+                                    );
+                                }
+                                open = open && notify(&mut ws, &name, "kicked", None).await.is_ok();
+                                break;
+                            }
+                            RoomAction::Host(token) => {
+                                channel.owner = true;
+                                channel.token = Some(token.clone());
+                                let host =
+                                    json!({ "channel": name, "type": "host", "token": token });
+                                open = open && ws.text(host.to_string()).await.is_ok();
+                            }
+                            RoomAction::Spectate => channel.owner = false,
+                        }
+                    }
+                }
+                alive = heartbeat.beat() => {
+                    open = alive && ws.ping(b"").await.is_ok();
+                }
+                _ = draining.changed() => {
+                    closing = Some(restarting());
+                    break;
+                }
+                message = incoming.next() => match message.inspect(|_| heartbeat.heard()) {
+                    Some(Ok(AggregatedMessage::Text(text))) => {
+                        let message = match serde_json::from_str::<ClientMessage>(&text) {
+                            Ok(message) => message,
+                            Err(e) => {
+                                open = notify(&mut ws, "", "error", Some(&e.to_string()))
+                                    .await
+                                    .is_ok();
+                                continue;
+                            }
+                        };
+                        match message {
+                            ClientMessage::Subscribe {
+                                channel,
+                                session_id,
+                                token,
+                                since,
+                                password,
+                                invite,
+                            } => {
+                                let joined = if !channels.contains_key(&channel)
+                                    && channels.len() >= MAX_CHANNELS
+                                {
+                                    Err("too many channels")
+                                } else {
+                                    let client_id = client_id.clone();
+                                    let pass = RoomPass {
+                                        password: password.as_deref(),
+                                        invite: invite.as_deref(),
+                                    };
+                                    let token = token.as_deref();
+                                    join(&data, &session_id, token, since, client_id, &pass)
+                                        .map_err(|e| e.message())
+                                };
+                                let joined = match joined {
+                                    Ok(joined) => joined,
+                                    Err(error) => {
+                                        open = notify(&mut ws, &channel, "error", Some(error))
+                                            .await
+                                            .is_ok();
+                                        continue;
+                                    }
+                                };
+                                if let Some(replaced) = channels.remove(&channel) {
+                                    replaced.abort.abort();
+                                    disconnect(
+                                        &data,
+                                        &replaced.session_id,
+                                        &replaced.connection,
+                                        replaced.owner,
+                                    );
+                                }
+                                let (stream, abort) =
+                                    channel_updates(channel.clone(), joined.updates);
+                                updates.push(stream);
+                                channels.insert(
+                                    channel.clone(),
+                                    Channel {
+                                        session_id,
+                                        connection: joined.connection,
+                                        token,
+                                        owner: joined.owner,
+                                        abort,
+                                    },
+                                );
+                                let mut messages = Vec::new();
+                                if !joined.backlog.is_empty() {
+                                    messages.push(StreamMessage::Events {
+                                        events: joined.backlog,
+                                    });
+                                }
+                                messages.push(joined.keyframe);
+                                for message in &messages {
+                                    open =
+                                        open && send_on(&mut ws, &channel, message).await.is_ok();
+                                }
+                            }
+                            ClientMessage::Unsubscribe { channel } => {
+                                if let Some(removed) = channels.remove(&channel) {
+                                    removed.abort.abort();
+                                    disconnect(
+                                        &data,
+                                        &removed.session_id,
+                                        &removed.connection,
+                                        removed.owner,
+                                    );
+                                }
+                            }
+                            ClientMessage::Keyframe { channel: name } => {
+                                let Some(channel) = channels.get(&name) else { continue };
+                                let keyframe = data
+                                    .sessions
+                                    .lock()
+                                    .unwrap()
+                                    .get(&channel.session_id)
+                                    .map(|session| session.keyframe());
+                                if let Some(keyframe) = keyframe {
+                                    open = send_on(&mut ws, &name, &keyframe).await.is_ok();
+                                }
+                            }
+                            ClientMessage::Reaction { channel, emote } => {
+                                let Some(channel) =
+                                    channels.get(&channel).filter(|channel| !channel.owner)
+                                else {
+                                    continue;
+                                };
+                                react(&data, &channel.session_id, emote, &mut reactions);
+                            }
+                            ClientMessage::Direction { channel, direction, tick, snake } => {
+                                let Some(channel) =
+                                    channels.get(&channel).filter(|channel| channel.owner)
+                                else {
+                                    continue;
+                                };
+                                let input = match tick {
+                                    Some(tick) => DirectionInput::Timed { direction, tick },
+                                    None => DirectionInput::Plain(direction),
+                                };
+                                let mut sessions = data.sessions.lock().unwrap();
+                                if let Some(session) = sessions
+                                    .get_mut(&channel.session_id)
+                                    .filter(|session| session.authorizes(channel.token.as_deref()))
+                                {
+                                    session.touch();
+                                    input.apply(&mut session.game, snake);
+                                }
+                            }
+                        }
+                    }
+                    Some(Ok(AggregatedMessage::Ping(bytes))) => {
+                        open = ws.pong(&bytes).await.is_ok();
+                    }
+                    Some(Ok(AggregatedMessage::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+        for channel in channels.into_values() {
+            disconnect(
+                &data,
+                &channel.session_id,
+                &channel.connection,
+                channel.owner,
+            );
+        }
+        let _ = ws.close(closing).await;
+    });
+
+    Ok(response)
+}
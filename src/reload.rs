@@ -0,0 +1,69 @@
+use crate::config::Config;
+use crate::request_id;
+use crate::server::AppState;
+use actix_web::web;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Loads the configuration file again and applies it to the running server
+/// Settings read only at startup keep their running values, with a warning when the file
+/// changes them; games and connections already running keep the settings they started with
+/// Fails with a message, leaving the running configuration in place, for an invalid file
+pub fn reload(data: &AppState) -> Result<(), String> {
+    let mut config = Config::load()?;
+    let restart = config.keep_startup_settings(&data.config());
+    if !restart.is_empty() {
+        tracing::warn!(
+            "configuration changes need a restart to apply: {}",
+            restart.join(", ")
+        );
+    }
+    request_id::set_log_filter(config.log_level.as_deref())?;
+    *data.config.write().unwrap() = Arc::new(config);
+    tracing::info!("configuration reloaded");
+    Ok(())
+}
+
+/// Reloads the configuration whenever the contents of its file change
+/// The file's directory is watched rather than the file, since editors often save by
+/// replacing the file; watching stops when the returned watcher is dropped
+pub fn watch(data: web::Data<AppState>) -> notify::Result<RecommendedWatcher> {
+    let path = PathBuf::from(Config::path());
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    // An editor saving a file emits several events; only changed contents are reloaded
+    let mut contents = fs::read_to_string(&path).ok();
+    let watched = path.clone();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                tracing::warn!("configuration watcher failed: {}", e);
+                return;
+            }
+        };
+        if !(event.kind.is_create() || event.kind.is_modify())
+            || !event
+                .paths
+                .iter()
+                .any(|changed| changed.file_name() == watched.file_name())
+        {
+            return;
+        }
+        let current = fs::read_to_string(&watched).ok();
+        if current.is_none() || current == contents {
+            return;
+        }
+        contents = current;
+        if let Err(e) = reload(&data) {
+            tracing::error!("configuration not reloaded: {}", e);
+        }
+    })?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+    tracing::info!("watching {} for configuration changes", path.display());
+    Ok(watcher)
+}
@@ -0,0 +1,591 @@
+use crate::game::{Direction, Game, Point, Terrain};
+use crate::topology::{Board, Topology};
+#[cfg(feature = "rendering")]
+use image::{ImageFormat, Rgba, RgbaImage};
+#[cfg(feature = "rendering")]
+use std::io::Cursor;
+
+/// Smallest and largest allowed cell size of raster renders, in pixels
+pub const MIN_CELL_SIZE: u32 = 2;
+pub const MAX_CELL_SIZE: u32 = 64;
+/// Largest allowed edge of a raster render, in pixels
+pub const MAX_IMAGE_EDGE: u32 = 2048;
+
+const ANSI_RESET: &str = "\x1b[0m";
+const ANSI_WALL: &str = "\x1b[90m";
+const ANSI_HEAD: &str = "\x1b[1;92m";
+const ANSI_BODY: &str = "\x1b[32m";
+const ANSI_FOOD: &str = "\x1b[1;31m";
+const ANSI_PORTAL: &str = "\x1b[35m";
+const ANSI_HAZARD: &str = "\x1b[1;33m";
+const ANSI_ICE: &str = "\x1b[36m";
+const ANSI_SPEED: &str = "\x1b[33m";
+const ANSI_GATE: &str = "\x1b[1;34m";
+const ANSI_OBJECTIVE: &str = "\x1b[1;93m";
+
+/// Glyph of the objective item on a cell: a key or treasure not picked up yet, a locked
+/// door or the exit
+fn objective_glyph(game: &Game, point: Point) -> Option<char> {
+    let objective = game.objective.as_ref()?;
+    if game.collected.contains(&point) {
+        None
+    } else if objective.keys.iter().any(|key| key.cell == point) {
+        Some('k')
+    } else if game.door_locked(point) {
+        Some('D')
+    } else if objective
+        .treasures
+        .iter()
+        .any(|treasure| treasure.cell == point)
+    {
+        Some('$')
+    } else if objective.exit == Some(point) {
+        Some('E')
+    } else {
+        None
+    }
+}
+
+/// Renders the board as a text grid framed by walls
+/// `#` wall (including obstacles), `@` snake head, `o` snake body, `&` partner head,
+/// `x` partner body, `!` moving hazard, `*` food, `H` ladder, `%` portal, `~` ice,
+/// `=` speed strip, `^` `v` `<` `>` `/` `\` one-way gate pointing that way, `k` key, `D` locked
+/// door, `$` treasure, `E` exit, `.` empty cell
+/// Hex boards are drawn as staggered rows without a frame, layered boards one layer after another
+/// With `color` set, cells are wrapped in ANSI escape codes for terminals
+pub fn ascii(game: &Game, color: bool) -> String {
+    let paint = |glyph: char, code: &str| {
+        if color {
+            format!("{}{}{}", code, glyph, ANSI_RESET)
+        } else {
+            glyph.to_string()
+        }
+    };
+    let head = game.snake.front().copied();
+    let partner_head = game.partner.as_ref().map(|partner| partner.snake[0]);
+    let hazards: Vec<Point> = game.hazard_cells(game.tick).collect();
+    let cell = |point: Point| {
+        if game.topology.inner_walls().contains(&point) {
+            paint('#', ANSI_WALL)
+        } else if head == Some(point) {
+            paint('@', ANSI_HEAD)
+        } else if game.snake.contains(&point) {
+            paint('o', ANSI_BODY)
+        } else if partner_head == Some(point) {
+            paint('&', ANSI_HEAD)
+        } else if game.on_partner(point) {
+            paint('x', ANSI_BODY)
+        } else if hazards.contains(&point) {
+            paint('!', ANSI_HAZARD)
+        } else if game.food == point {
+            paint('*', ANSI_FOOD)
+        } else if game.topology.is_passage(point) {
+            paint('H', ANSI_WALL)
+        } else if game
+            .portals
+            .iter()
+            .any(|portal| portal.a == point || portal.b == point)
+        {
+            paint('%', ANSI_PORTAL)
+        } else if let Some(glyph) = objective_glyph(game, point) {
+            paint(glyph, ANSI_OBJECTIVE)
+        } else if let Some(terrain) = game.terrain(point) {
+            match terrain {
+                Terrain::Ice => paint('~', ANSI_ICE),
+                Terrain::Speed => paint('=', ANSI_SPEED),
+            }
+        } else if let Some(gate) = game.gates.iter().find(|gate| gate.cell == point) {
+            let glyph = match gate.direction {
+                Direction::Up => '^',
+                Direction::Down => 'v',
+                Direction::Left => '<',
+                Direction::Right => '>',
+                Direction::UpRight | Direction::DownLeft => '/',
+                Direction::UpLeft | Direction::DownRight => '\\',
+            };
+            paint(glyph, ANSI_GATE)
+        } else {
+            ".".to_string()
+        }
+    };
+
+    let mut out = String::new();
+    if let Board::Hex(hex) = &game.topology {
+        for y in 0..game.height {
+            out.push_str(&" ".repeat((y - hex.radius).unsigned_abs() as usize));
+            for x in 0..game.width {
+                let point = Point { x, y, z: 0 };
+                if !game.topology.is_wall(point) {
+                    out.push_str(&cell(point));
+                    out.push(' ');
+                }
+            }
+            out.push('\n');
+        }
+    } else {
+        let wall_row = paint('#', ANSI_WALL).repeat(game.width as usize + 2);
+        let layers = game.topology.layers();
+        for z in 0..layers {
+            if layers > 1 {
+                out.push_str(&format!("Layer {}\n", z));
+            }
+            out.push_str(&wall_row);
+            out.push('\n');
+            for y in 0..game.height {
+                out.push_str(&paint('#', ANSI_WALL));
+                for x in 0..game.width {
+                    out.push_str(&cell(Point { x, y, z }));
+                }
+                out.push_str(&paint('#', ANSI_WALL));
+                out.push('\n');
+            }
+            out.push_str(&wall_row);
+            out.push('\n');
+        }
+    }
+    out.push_str(&format!("Score: {}", game.score));
+    if game.game_over {
+        out.push_str("  GAME OVER");
+    }
+    out.push('\n');
+    out
+}
+
+/// Name of a direction as read out, e.g. `up-left`
+fn direction_word(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Up => "up",
+        Direction::Down => "down",
+        Direction::Left => "left",
+        Direction::Right => "right",
+        Direction::UpLeft => "up-left",
+        Direction::UpRight => "up-right",
+        Direction::DownLeft => "down-left",
+        Direction::DownRight => "down-right",
+    }
+}
+
+/// Shortest offset along an axis of `size` cells, going across the edge on wrapping boards
+fn axis_offset(from: i32, to: i32, size: i32, wraps: bool) -> i32 {
+    let offset = to - from;
+    if !wraps || size <= 0 {
+        return offset;
+    }
+    let offset = offset.rem_euclid(size);
+    if offset > size / 2 {
+        offset - size
+    } else {
+        offset
+    }
+}
+
+/// Where `to` lies from `from` in words, e.g. `3 left, 2 up`, or `here`
+fn offset_words(game: &Game, from: Point, to: Point) -> String {
+    let wraps = matches!(game.topology, Board::Wrap(_));
+    let dx = axis_offset(from.x, to.x, game.width, wraps);
+    let dy = axis_offset(from.y, to.y, game.height, wraps);
+    let dz = to.z - from.z;
+    let parts: Vec<String> = [
+        (dx, "left", "right"),
+        (dy, "up", "down"),
+        (dz, "layer down", "layer up"),
+    ]
+    .into_iter()
+    .filter(|(offset, _, _)| *offset != 0)
+    .map(|(offset, negative, positive)| {
+        let word = if offset < 0 { negative } else { positive };
+        format!("{} {}", offset.unsigned_abs(), word)
+    })
+    .collect();
+    if parts.is_empty() {
+        "here".to_string()
+    } else {
+        parts.join(", ")
+    }
+}
+
+/// What the snake runs into going straight from its head, e.g. `wall 5`, within the
+/// number of cells of the board
+fn obstacle_words(game: &Game, direction: Direction) -> String {
+    let Some(mut point) = game.snake.front().copied() else {
+        return "clear".to_string();
+    };
+    let cells = game.width * game.height * game.topology.layers();
+    for steps in 1..=cells.max(1) {
+        point = game.step(point, direction);
+        if game.topology.is_wall(point) {
+            return format!("wall {}", steps);
+        }
+        if game.on_snake(point) {
+            return format!("body {}", steps);
+        }
+    }
+    "clear".to_string()
+}
+
+/// Directions to the left and right of a heading, on square boards
+fn sides(direction: Direction) -> Option<(Direction, Direction)> {
+    match direction {
+        Direction::Up => Some((Direction::Left, Direction::Right)),
+        Direction::Down => Some((Direction::Right, Direction::Left)),
+        Direction::Left => Some((Direction::Down, Direction::Up)),
+        Direction::Right => Some((Direction::Up, Direction::Down)),
+        _ => None,
+    }
+}
+
+/// Describes the situation of the snake in a few short sentences, for screen readers and
+/// voice control, e.g. `Heading right. Food 3 left, 2 up. Wall 5 ahead, body 1 to the
+/// left, clear to the right. Tail 4 left. Score 3, length 5.`
+/// Offsets are in board directions (`up` is towards the top row) and take the shortest
+/// way across the edges of wrapping boards; on hex boards only the way ahead is checked
+pub fn describe(game: &Game) -> String {
+    let Some(head) = game.snake.front().copied() else {
+        return String::new();
+    };
+    let mut sentences = Vec::new();
+    if game.game_over {
+        sentences.push("Game over.".to_string());
+    } else {
+        let ticks_left = game.countdown_ticks_left();
+        if ticks_left > 0 {
+            sentences.push(format!("Get ready, starting in {} ticks.", ticks_left));
+        }
+        sentences.push(format!("Heading {}.", direction_word(game.direction)));
+        let mut food = format!("Food {}", offset_words(game, head, game.food));
+        if let Some(ticks_left) = game.food_ticks_left {
+            food.push_str(&format!(", expires in {} ticks", ticks_left));
+        }
+        sentences.push(format!("{}.", food));
+        let ahead = match obstacle_words(game, game.direction).as_str() {
+            "clear" => "clear ahead".to_string(),
+            obstacle => format!("{} ahead", obstacle),
+        };
+        let mut surroundings = vec![ahead];
+        if let Some((left, right)) = sides(game.direction) {
+            for (side, name) in [(left, "left"), (right, "right")] {
+                surroundings.push(match obstacle_words(game, side).as_str() {
+                    "clear" => format!("clear to the {}", name),
+                    obstacle => format!("{} to the {}", obstacle, name),
+                });
+            }
+        }
+        let surroundings = surroundings.join(", ");
+        sentences.push(format!(
+            "{}{}.",
+            surroundings[..1].to_uppercase(),
+            &surroundings[1..]
+        ));
+        if let Some(tail) = game.snake.back().copied().filter(|_| game.snake.len() > 1) {
+            sentences.push(format!("Tail {}.", offset_words(game, head, tail)));
+        }
+        if let Some(ticks_left) = game.combo_ticks_left {
+            sentences.push(format!(
+                "Combo x{}, {} ticks left.",
+                game.combo_multiplier, ticks_left
+            ));
+        }
+    }
+    sentences.push(format!(
+        "Score {}, length {}.",
+        game.score,
+        game.snake.len()
+    ));
+    sentences.join(" ")
+}
+
+/// Colors used by raster renders
+#[cfg(feature = "rendering")]
+pub struct Theme {
+    pub background: Rgba<u8>,
+    pub grid: Rgba<u8>,
+    pub head: Rgba<u8>,
+    pub body: Rgba<u8>,
+    pub food: Rgba<u8>,
+    pub portal: Rgba<u8>,
+    pub hazard: Rgba<u8>,
+    pub ice: Rgba<u8>,
+    pub speed: Rgba<u8>,
+    pub gate: Rgba<u8>,
+    pub key: Rgba<u8>,
+    pub door: Rgba<u8>,
+    pub exit: Rgba<u8>,
+}
+
+#[cfg(feature = "rendering")]
+impl Theme {
+    /// Looks up a built-in theme by name
+    /// `classic` matches the bundled web frontend, `dark` suits embeds on dark backgrounds
+    pub fn by_name(name: &str) -> Option<Theme> {
+        match name {
+            "classic" => Some(Theme {
+                background: Rgba([255, 255, 255, 255]),
+                grid: Rgba([0, 0, 0, 255]),
+                head: Rgba([0, 100, 0, 255]),
+                body: Rgba([0, 128, 0, 255]),
+                food: Rgba([255, 0, 0, 255]),
+                portal: Rgba([128, 0, 128, 255]),
+                hazard: Rgba([255, 140, 0, 255]),
+                ice: Rgba([173, 216, 230, 255]),
+                speed: Rgba([255, 255, 0, 255]),
+                gate: Rgba([0, 0, 139, 255]),
+                key: Rgba([218, 165, 32, 255]),
+                door: Rgba([139, 69, 19, 255]),
+                exit: Rgba([0, 191, 255, 255]),
+            }),
+            "dark" => Some(Theme {
+                background: Rgba([24, 24, 27, 255]),
+                grid: Rgba([63, 63, 70, 255]),
+                head: Rgba([163, 230, 53, 255]),
+                body: Rgba([101, 163, 13, 255]),
+                food: Rgba([244, 63, 94, 255]),
+                portal: Rgba([167, 139, 250, 255]),
+                hazard: Rgba([251, 191, 36, 255]),
+                ice: Rgba([125, 211, 252, 255]),
+                speed: Rgba([250, 204, 21, 255]),
+                gate: Rgba([96, 165, 250, 255]),
+                key: Rgba([253, 224, 71, 255]),
+                door: Rgba([180, 83, 9, 255]),
+                exit: Rgba([34, 211, 238, 255]),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Size of a raster render in cells
+/// Layers of layered boards are placed side by side, one empty column apart
+#[cfg(feature = "rendering")]
+pub fn canvas_cells(game: &Game) -> (u32, u32) {
+    let layers = game.topology.layers().max(1) as u32;
+    let width = game.width.max(1) as u32;
+    (layers * (width + 1) - 1, game.height.max(1) as u32)
+}
+
+/// Clamps a requested cell size so the rendered image stays within bounds
+#[cfg(feature = "rendering")]
+pub fn clamp_cell_size(game: &Game, cell_size: u32) -> u32 {
+    let (columns, rows) = canvas_cells(game);
+    let longest_edge = columns.max(rows);
+    cell_size
+        .clamp(MIN_CELL_SIZE, MAX_CELL_SIZE)
+        .min((MAX_IMAGE_EDGE / longest_edge).max(1))
+}
+
+/// Top-left pixel of a board cell
+/// Hex rows are shifted by half a cell per row away from the center row, so the
+/// hexagon fills the same bounding box as a square board
+#[cfg(feature = "rendering")]
+fn cell_origin(board: &Board, point: Point, cell_size: u32) -> Option<(u32, u32)> {
+    let column = match board {
+        Board::Hex(hex) => {
+            let half_steps = 2 * point.x + point.y - hex.radius;
+            (half_steps >= 0).then(|| half_steps as u32 * cell_size / 2)
+        }
+        _ => {
+            let column = point.x + point.z * (board.size().0 + 1);
+            (point.x >= 0 && column >= 0).then(|| column as u32 * cell_size)
+        }
+    }?;
+    (point.y >= 0).then(|| (column, point.y as u32 * cell_size))
+}
+
+/// Fills one board cell, leaving a one pixel gap like the web frontend
+#[cfg(feature = "rendering")]
+fn fill_cell(img: &mut RgbaImage, board: &Board, point: Point, cell_size: u32, color: Rgba<u8>) {
+    let Some((origin_x, origin_y)) = cell_origin(board, point, cell_size) else {
+        return;
+    };
+    let inner = cell_size.saturating_sub(1).max(1);
+    for dy in 0..inner {
+        for dx in 0..inner {
+            let x = origin_x + dx;
+            let y = origin_y + dy;
+            if x < img.width() && y < img.height() {
+                img.put_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+/// Rasterizes the board with the given cell size and theme
+/// The cell size is expected to be clamped with `clamp_cell_size`
+#[cfg(feature = "rendering")]
+pub fn frame(game: &Game, cell_size: u32, theme: &Theme) -> RgbaImage {
+    let (columns, rows) = canvas_cells(game);
+    let width = columns * cell_size;
+    let height = rows * cell_size;
+    let board = &game.topology;
+    let mut img;
+    if board.is_hex() {
+        // Outline the hexagon by drawing its cells on a grid-colored canvas
+        img = RgbaImage::from_pixel(width, height, theme.grid);
+        for y in 0..game.height {
+            for x in 0..game.width {
+                let point = Point { x, y, z: 0 };
+                if !board.is_wall(point) {
+                    fill_cell(&mut img, board, point, cell_size, theme.background);
+                }
+            }
+        }
+    } else {
+        img = RgbaImage::from_pixel(width, height, theme.background);
+
+        // Draw the outer border of every layer
+        let layer_width = game.width.max(1) as u32 * cell_size;
+        for z in 0..board.layers() as u32 {
+            let left = z * (layer_width + cell_size);
+            let right = left + layer_width - 1;
+            for x in left..=right {
+                img.put_pixel(x, 0, theme.grid);
+                img.put_pixel(x, height - 1, theme.grid);
+            }
+            for y in 0..height {
+                img.put_pixel(left, y, theme.grid);
+                img.put_pixel(right, y, theme.grid);
+            }
+        }
+    }
+
+    if let Board::Layered(layered) = board {
+        for ladder in &layered.ladders {
+            let upper = Point {
+                z: ladder.z + 1,
+                ..*ladder
+            };
+            fill_cell(&mut img, board, *ladder, cell_size, theme.grid);
+            fill_cell(&mut img, board, upper, cell_size, theme.grid);
+        }
+    }
+
+    for wall in board.inner_walls() {
+        fill_cell(&mut img, board, *wall, cell_size, theme.grid);
+    }
+    for tile in &game.tiles {
+        let color = match tile.terrain {
+            Terrain::Ice => theme.ice,
+            Terrain::Speed => theme.speed,
+        };
+        fill_cell(&mut img, board, tile.cell, cell_size, color);
+    }
+    for gate in &game.gates {
+        fill_cell(&mut img, board, gate.cell, cell_size, theme.gate);
+    }
+    if let Some(objective) = &game.objective {
+        let keys = objective.keys.iter().map(|key| (key.cell, theme.key));
+        let treasures = objective
+            .treasures
+            .iter()
+            .map(|treasure| (treasure.cell, theme.food));
+        for (cell, color) in keys.chain(treasures) {
+            if !game.collected.contains(&cell) {
+                fill_cell(&mut img, board, cell, cell_size, color);
+            }
+        }
+        for door in objective
+            .doors
+            .iter()
+            .filter(|door| game.door_locked(door.cell))
+        {
+            fill_cell(&mut img, board, door.cell, cell_size, theme.door);
+        }
+        if let Some(exit) = objective.exit {
+            fill_cell(&mut img, board, exit, cell_size, theme.exit);
+        }
+    }
+    for portal in &game.portals {
+        fill_cell(&mut img, board, portal.a, cell_size, theme.portal);
+        fill_cell(&mut img, board, portal.b, cell_size, theme.portal);
+    }
+    for hazard in game.hazard_cells(game.tick) {
+        fill_cell(&mut img, board, hazard, cell_size, theme.hazard);
+    }
+    let partner = game.partner.iter().map(|partner| &partner.snake);
+    for snake in std::iter::once(&game.snake).chain(partner) {
+        for (index, point) in snake.iter().enumerate() {
+            let color = if index == 0 { theme.head } else { theme.body };
+            fill_cell(&mut img, board, *point, cell_size, color);
+        }
+    }
+    fill_cell(&mut img, board, game.food, cell_size, theme.food);
+    img
+}
+
+/// Encodes a rendered frame as PNG
+#[cfg(feature = "rendering")]
+pub fn png(img: &RgbaImage) -> Result<Vec<u8>, image::ImageError> {
+    let mut bytes = Cursor::new(Vec::new());
+    img.write_to(&mut bytes, ImageFormat::Png)?;
+    Ok(bytes.into_inner())
+}
+
+/// 3x5 pixel glyphs for the characters used on share cards, one row per entry
+#[cfg(feature = "rendering")]
+fn glyph(c: char) -> Option<[u8; 5]> {
+    Some(match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'C' => [0b111, 0b100, 0b100, 0b100, 0b111],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'G' => [0b111, 0b100, 0b101, 0b101, 0b111],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'N' => [0b111, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        'R' => [0b111, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        ' ' => [0; 5],
+        _ => return None,
+    })
+}
+
+/// Draws text with the built-in bitmap font, each font pixel `scale` pixels wide
+#[cfg(feature = "rendering")]
+fn draw_text(img: &mut RgbaImage, text: &str, x: u32, y: u32, scale: u32, color: Rgba<u8>) {
+    for (index, c) in text.chars().enumerate() {
+        let Some(rows) = glyph(c.to_ascii_uppercase()) else {
+            continue;
+        };
+        let origin_x = x + index as u32 * 4 * scale;
+        for (row, bits) in rows.iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = origin_x + col * scale + dx;
+                        let py = y + row as u32 * scale + dy;
+                        if px < img.width() && py < img.height() {
+                            img.put_pixel(px, py, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Renders a share card: the board with a banner showing score and snake length
+#[cfg(feature = "rendering")]
+pub fn share_card(game: &Game, cell_size: u32, theme: &Theme) -> RgbaImage {
+    let board = frame(game, cell_size, theme);
+    let text = format!("SCORE {}  LENGTH {}", game.score, game.snake.len());
+    let text_width = text.len() as u32 * 4 - 1;
+    let scale = ((board.width() - 8) / text_width).clamp(1, 4);
+    let banner = 5 * scale + 8;
+
+    let mut card = RgbaImage::from_pixel(board.width(), board.height() + banner, theme.background);
+    image::imageops::replace(&mut card, &board, 0, 0);
+    draw_text(&mut card, &text, 4, board.height() + 4, scale, theme.head);
+    card
+}
@@ -0,0 +1,341 @@
+use crate::game::{Game, GameConfig, Input, Split};
+use crate::migration::{self, SCHEMA_VERSION};
+#[cfg(feature = "rendering")]
+use crate::render::{self, Theme};
+use crate::state_hash;
+#[cfg(feature = "rendering")]
+use image::codecs::gif::{GifEncoder, Repeat};
+#[cfg(feature = "rendering")]
+use image::{Delay, Frame};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Most frames written to an animation; longer games are sampled evenly
+pub const MAX_ANIMATION_FRAMES: u64 = 600;
+/// Largest allowed edge of an animation, in pixels
+pub const MAX_ANIMATION_EDGE: u32 = 640;
+/// Most replays kept in memory, see `ReplayStore`
+pub const MAX_REPLAYS: usize = 10_000;
+/// Display time of a single animation frame
+#[cfg(feature = "rendering")]
+const FRAME_DELAY_MS: u32 = 100;
+
+/// Who may watch a game and its replay, chosen by the player
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    /// Only the player: others can neither spectate the game nor see its replay
+    Private,
+    /// Anyone with the link, but left out of replay listings
+    Unlisted,
+    /// Anyone, and listed
+    #[default]
+    Public,
+}
+
+/// Recording of a finished game: its config and input log, from which every state is derived
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Replay {
+    /// Schema version of the serialized replay, see `migration`
+    #[serde(default)]
+    pub schema_version: u32,
+    /// Replay identifier (the session ID of the recorded game)
+    pub id: String,
+    pub width: i32,
+    pub height: i32,
+    /// Board, rules and seed of the recorded game
+    #[serde(flatten)]
+    pub config: GameConfig,
+    /// Direction changes in tick order
+    pub inputs: Vec<Input>,
+    /// Number of simulated steps
+    pub ticks: u64,
+    /// Final score of the recorded game
+    pub score: u32,
+    /// Speed-run splits of the recorded game
+    #[serde(default)]
+    pub splits: Vec<Split>,
+    /// Wall-clock milliseconds from the creation of the recorded game to its end
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// Rolling state hash of the recorded game at its last tick, see `Game::rolling_hash`
+    #[serde(default)]
+    pub state_hash: Option<String>,
+    /// Who may watch the replay
+    #[serde(default)]
+    pub visibility: Visibility,
+    /// Client of the player, who may watch the replay when it is private and delete it
+    /// Never serialized, since client IDs sign players in
+    #[serde(skip)]
+    pub owner: Option<String>,
+}
+
+impl Replay {
+    /// Captures the replay of a game
+    pub fn from_game(id: &str, game: &Game) -> Self {
+        Replay {
+            schema_version: SCHEMA_VERSION,
+            id: id.to_string(),
+            width: game.width,
+            height: game.height,
+            config: game.config(),
+            inputs: game.inputs.clone(),
+            ticks: game.tick,
+            score: game.score,
+            splits: game.splits.clone(),
+            duration_ms: None,
+            state_hash: Some(state_hash::to_hex(game.rolling_hash)),
+            visibility: Visibility::default(),
+            owner: None,
+        }
+    }
+
+    /// Whether a client may watch the replay: anyone unless it is private, and its player
+    pub fn viewable_by(&self, client_id: Option<&str>) -> bool {
+        self.visibility != Visibility::Private
+            || (client_id.is_some() && self.owner.as_deref() == client_id)
+    }
+
+    /// Re-simulates the game, returning the initial state followed by the state after every tick
+    /// Yields nothing for recordings whose config does not build, which the server never records
+    pub fn states(&self) -> impl Iterator<Item = Game> + '_ {
+        self.config
+            .fold(&self.inputs, self.ticks)
+            .into_iter()
+            .flatten()
+    }
+
+    /// State of the recorded game before its first tick
+    fn initial_state(&self) -> Game {
+        self.config
+            .start()
+            .unwrap_or_else(|_| Game::with_topology(self.config.topology.clone(), self.config.seed))
+    }
+
+    /// Whether re-simulating the recorded inputs ends the game with the recorded score
+    pub fn verify(&self) -> bool {
+        let game = self.simulate();
+        game.game_over && game.tick == self.ticks && game.score == self.score
+    }
+
+    /// Checks that re-simulating the recording reproduces its rolling state hash, which
+    /// covers the state after every tick
+    /// Fails with both hashes when the states diverged, or when the recording has no hash
+    pub fn check(&self) -> Result<(), String> {
+        let Some(recorded) = &self.state_hash else {
+            return Err("no state hash recorded".to_string());
+        };
+        let simulated = state_hash::to_hex(self.simulate().rolling_hash);
+        if &simulated == recorded {
+            Ok(())
+        } else {
+            Err(format!("recorded {}, re-simulated {}", recorded, simulated))
+        }
+    }
+
+    /// Re-simulates the recording, checking the properties replay-based score verification
+    /// relies on: every state keeps the engine's invariants, and the last tick reproduces
+    /// the recorded tick count, score and rolling hash (when one was recorded)
+    /// Fails with the first property broken
+    pub fn check_properties(&self) -> Result<(), String> {
+        let mut last = None;
+        for game in self.states() {
+            game.check_invariants()
+                .map_err(|e| format!("tick {}: {}", game.tick, e))?;
+            last = Some(game);
+        }
+        let game = last.ok_or("the recorded config does not build")?;
+        if game.tick != self.ticks || game.score != self.score {
+            return Err(format!(
+                "recorded score {} at tick {}, re-simulated {} at tick {}",
+                self.score, self.ticks, game.score, game.tick
+            ));
+        }
+        let simulated = state_hash::to_hex(game.rolling_hash);
+        match &self.state_hash {
+            Some(recorded) if *recorded != simulated => Err(format!(
+                "recorded hash {}, re-simulated {}",
+                recorded, simulated
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Re-simulates the game and returns its final state
+    pub fn simulate(&self) -> Game {
+        self.states().last().unwrap_or_else(|| self.initial_state())
+    }
+
+    /// Renders the re-simulated game as an animated GIF
+    /// Resolution and frame count are bounded; long games are sampled evenly
+    #[cfg(feature = "rendering")]
+    pub fn animation_gif(
+        &self,
+        cell_size: u32,
+        theme: &Theme,
+    ) -> Result<Vec<u8>, image::ImageError> {
+        let (columns, rows) = render::canvas_cells(&self.initial_state());
+        let longest_edge = columns.max(rows);
+        let cell_size = cell_size
+            .clamp(render::MIN_CELL_SIZE, render::MAX_CELL_SIZE)
+            .min((MAX_ANIMATION_EDGE / longest_edge).max(1));
+        let stride = self.ticks.div_ceil(MAX_ANIMATION_FRAMES).max(1);
+        let last = self.ticks;
+
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut bytes);
+            encoder.set_repeat(Repeat::Infinite)?;
+            let frames = self
+                .states()
+                .enumerate()
+                .filter(|(index, _)| {
+                    (*index as u64).is_multiple_of(stride) || *index as u64 == last
+                })
+                .map(|(_, game)| {
+                    Frame::from_parts(
+                        render::frame(&game, cell_size, theme),
+                        0,
+                        0,
+                        Delay::from_numer_denom_ms(FRAME_DELAY_MS, 1),
+                    )
+                });
+            encoder.encode_frames(frames)?;
+        }
+        Ok(bytes)
+    }
+}
+
+/// Recordings of finished games by replay ID, keeping the `MAX_REPLAYS` recorded last
+/// Recording a replay beyond it drops the one recorded longest ago
+#[derive(Default)]
+pub struct ReplayStore {
+    replays: HashMap<String, Replay>,
+    // Replay IDs, recorded longest ago first
+    order: VecDeque<String>,
+}
+
+impl ReplayStore {
+    pub fn get(&self, replay_id: &str) -> Option<&Replay> {
+        self.replays.get(replay_id)
+    }
+
+    pub fn get_mut(&mut self, replay_id: &str) -> Option<&mut Replay> {
+        self.replays.get_mut(replay_id)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Replay> {
+        self.replays.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.replays.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.replays.is_empty()
+    }
+
+    /// Records a replay, replacing an earlier one with the same ID
+    pub fn insert(&mut self, replay_id: String, replay: Replay) {
+        if self.replays.insert(replay_id.clone(), replay).is_some() {
+            self.order.retain(|id| *id != replay_id);
+        }
+        self.order.push_back(replay_id);
+        while self.order.len() > MAX_REPLAYS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.replays.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, replay_id: &str) -> Option<Replay> {
+        let replay = self.replays.remove(replay_id)?;
+        self.order.retain(|id| id != replay_id);
+        Some(replay)
+    }
+}
+
+impl Extend<(String, Replay)> for ReplayStore {
+    fn extend<I: IntoIterator<Item = (String, Replay)>>(&mut self, replays: I) {
+        for (replay_id, replay) in replays {
+            self.insert(replay_id, replay);
+        }
+    }
+}
+
+/// Reads the recording of a replay file, or of the replay inside a checkpoint file
+fn read(path: &Path) -> Result<Replay, String> {
+    let contents = fs::read(path).map_err(|e| e.to_string())?;
+    let mut value: Value = serde_json::from_slice(&contents).map_err(|e| e.to_string())?;
+    if let Some(replay) = value.get_mut("replay") {
+        value = replay.take();
+    }
+    serde_json::from_value(migration::upgrade(value)?).map_err(|e| e.to_string())
+}
+
+/// Replay and checkpoint files named by the arguments; directories are searched for
+/// `.json` files
+fn files(args: &[String]) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for arg in args {
+        let path = PathBuf::from(arg);
+        match fs::read_dir(&path) {
+            Ok(entries) => paths.extend(
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("json")),
+            ),
+            Err(_) => paths.push(path),
+        }
+    }
+    paths.sort();
+    paths
+}
+
+/// Entry point of the `check-replays` subcommand
+/// Usage: check-replays PATH...
+/// Re-simulates replay and checkpoint files (directories are searched for `.json` files)
+/// and compares their state hashes; returns whether every file matched
+pub fn check_cli(args: &[String]) -> bool {
+    let paths = files(args);
+    let mut failures = 0;
+    for path in &paths {
+        match read(path).and_then(|replay| replay.check()) {
+            Ok(()) => println!("ok        {}", path.display()),
+            Err(e) => {
+                failures += 1;
+                println!("MISMATCH  {}: {}", path.display(), e);
+            }
+        }
+    }
+    println!("{} checked, {} mismatched", paths.len(), failures);
+    failures == 0
+}
+
+/// Entry point of the `verify-replays` subcommand
+/// Usage: verify-replays [PATH...]
+/// Re-simulates stored replays and checks their properties, see `Replay::check_properties`;
+/// without paths the checkpoints of the configured `checkpoint_dir` are verified
+/// Returns whether every replay passed
+pub fn verify_cli(args: &[String], checkpoint_dir: Option<&str>) -> bool {
+    let paths = match (args.is_empty(), checkpoint_dir) {
+        (true, Some(dir)) => files(&[dir.to_string()]),
+        _ => files(args),
+    };
+    let mut failures = 0;
+    for path in &paths {
+        match read(path).and_then(|replay| replay.check_properties()) {
+            Ok(()) => println!("ok      {}", path.display()),
+            Err(e) => {
+                failures += 1;
+                println!("FAILED  {}: {}", path.display(), e);
+            }
+        }
+    }
+    println!("{} verified, {} failed", paths.len(), failures);
+    failures == 0
+}
@@ -0,0 +1,113 @@
+use crate::server::AppState;
+use crate::signer::unix_now;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+/// Most reports waiting for a moderator; new ones are refused beyond it
+pub const MAX_OPEN_REPORTS: usize = 1000;
+/// Longest reason accepted with a report, in characters
+pub const MAX_REASON_LEN: usize = 500;
+
+/// What a report flags
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReportTarget {
+    /// The name of a leaderboard entry
+    Name { entry_id: String },
+    /// A community level
+    Level { level_id: String },
+}
+
+impl ReportTarget {
+    /// Identifier of the reported entry or level
+    pub fn id(&self) -> &str {
+        match self {
+            ReportTarget::Name { entry_id } => entry_id,
+            ReportTarget::Level { level_id } => level_id,
+        }
+    }
+}
+
+/// Report waiting for a moderator
+#[derive(Clone, Serialize)]
+pub struct Report {
+    pub id: String,
+    #[serde(flatten)]
+    pub target: ReportTarget,
+    /// Name of the entry or level when it was reported
+    pub reported: String,
+    /// Why the reporter flagged it, possibly empty
+    pub reason: String,
+    /// Unix timestamp (seconds) of the report
+    pub reported_at: u64,
+    /// Anonymous ID of the reporter
+    pub reporter: String,
+}
+
+/// Report sent by a player
+#[derive(Deserialize)]
+pub struct ReportRequest {
+    #[serde(flatten)]
+    target: ReportTarget,
+    #[serde(default)]
+    reason: String,
+}
+
+/// Name of a ranked entry or a public level, None when there is no such target
+fn reported_name(data: &AppState, target: &ReportTarget) -> Option<String> {
+    match target {
+        ReportTarget::Name { entry_id } => data.ranked_score(entry_id).map(|score| score.name),
+        ReportTarget::Level { level_id } => {
+            let levels = data.levels.lock().unwrap();
+            let level = levels.get(level_id).filter(|level| level.is_public())?;
+            Some(level.spec.name.clone())
+        }
+    }
+}
+
+/// Flags an offensive leaderboard name (`{"kind": "name", "entry_id": ..}`) or community
+/// level (`{"kind": "level", "level_id": ..}`) for the moderators, with an optional reason
+/// Responds with 202 Accepted and the report ID, 404 Not Found for entries no leaderboard
+/// holds and levels that are not public, 409 Conflict when the player already reported it
+/// and 429 Too Many Requests while the report queue is full
+pub async fn submit(
+    req: HttpRequest,
+    body: web::Json<ReportRequest>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let ReportRequest { target, reason } = body.into_inner();
+    let reason = reason.trim().to_string();
+    if reason.chars().count() > MAX_REASON_LEN {
+        return HttpResponse::BadRequest().json(json!({ "error": "reason is too long" }));
+    }
+    let Some(reported) = reported_name(&data, &target) else {
+        return HttpResponse::NotFound().json(json!({ "error": "nothing to report" }));
+    };
+    let reporter = data
+        .anonymous_ids
+        .of(&req, data.config().anonymous_id_rotation_hours);
+    let mut reports = data.reports.lock().unwrap();
+    if reports
+        .iter()
+        .any(|report| report.target == target && report.reporter == reporter)
+    {
+        return HttpResponse::Conflict().json(json!({ "error": "already reported" }));
+    }
+    if reports.len() >= MAX_OPEN_REPORTS {
+        return HttpResponse::TooManyRequests().json(json!({ "error": "too many open reports" }));
+    }
+    let report = Report {
+        id: Uuid::new_v4().to_string(),
+        target,
+        reported,
+        reason,
+        reported_at: unix_now(),
+        reporter,
+    };
+    let report_id = report.id.clone();
+    tracing::info!(report_id = %report_id, reported_id = report.target.id(), "report received");
+    reports.push(report);
+    HttpResponse::Accepted().json(json!({ "report_id": report_id }))
+}
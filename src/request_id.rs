@@ -0,0 +1,116 @@
+use crate::server::AppState;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage};
+use std::sync::OnceLock;
+use std::time::Instant;
+use tracing::Instrument;
+use tracing_subscriber::fmt::Formatter;
+use tracing_subscriber::{reload, EnvFilter};
+use uuid::Uuid;
+
+/// Header used to propagate request IDs between clients and the server
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest client-supplied request ID that is honored
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+/// Handle replacing the filter of the global tracing subscriber once it is installed
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Formatter>> = OnceLock::new();
+
+/// Request ID attached to the request extensions for use by handlers
+#[derive(Clone)]
+pub struct RequestId(pub String);
+
+/// Accepts client IDs made of printable, header-safe characters only
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Middleware that honors or generates an `X-Request-Id`, runs the request inside a
+/// tracing span carrying that ID, echoes it on the response and writes one access log line
+/// Response statuses are counted in the request metrics of the app state
+pub async fn middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    let state = req.app_data::<web::Data<AppState>>().cloned();
+    let span = tracing::info_span!("request", request_id = %request_id, %method, %path);
+    let start = Instant::now();
+    let result = next.call(req).instrument(span.clone()).await;
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    let _entered = span.enter();
+    match result {
+        Ok(mut res) => {
+            if let Some(state) = &state {
+                state.request_metrics.record(res.status());
+            }
+            let status = res.status().as_u16();
+            if let Some(error) = res.response().error() {
+                tracing::warn!(status, error = %error, "request failed");
+            }
+            tracing::info!(status, latency_ms, "access");
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                res.headers_mut()
+                    .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            }
+            Ok(res)
+        }
+        Err(error) => {
+            if let Some(state) = &state {
+                let status = error.as_response_error().status_code();
+                state.request_metrics.record(status);
+            }
+            tracing::warn!(error = %error, latency_ms, "request failed");
+            Err(error)
+        }
+    }
+}
+
+/// Log filter from `RUST_LOG`, else from the configured directives, else `info`
+fn log_filter(directives: Option<&str>) -> Result<EnvFilter, String> {
+    if let Ok(filter) = EnvFilter::try_from_default_env() {
+        return Ok(filter);
+    }
+    EnvFilter::try_new(directives.unwrap_or("info")).map_err(|e| e.to_string())
+}
+
+/// Installs the global tracing subscriber, filtered by `RUST_LOG` or the configured
+/// directives (default `info`)
+pub fn init_tracing(directives: Option<&str>) {
+    let filter = log_filter(directives).unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_filter_reloading();
+    let handle = builder.reload_handle();
+    if builder.try_init().is_ok() {
+        let _ = LOG_FILTER.set(handle);
+    }
+}
+
+/// Replaces the filter of the global tracing subscriber; `RUST_LOG` still takes precedence
+pub fn set_log_filter(directives: Option<&str>) -> Result<(), String> {
+    let filter = log_filter(directives)?;
+    match LOG_FILTER.get() {
+        Some(handle) => handle.reload(filter).map_err(|e| e.to_string()),
+        None => Ok(()),
+    }
+}
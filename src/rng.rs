@@ -0,0 +1,36 @@
+#![deny(clippy::disallowed_types)]
+
+/// Small deterministic pseudo-random generator (SplitMix64)
+/// Produces the same sequence for the same seed on every platform, which makes
+/// games reproducible from their seed and input log
+#[derive(Clone, PartialEq)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    /// Creates a generator from a seed
+    pub fn new(seed: u64) -> Self {
+        SeededRng { state: seed }
+    }
+
+    /// Current position in the sequence, for state hashes
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// Returns the next 64 random bits
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a value in `low..high`; `high` must be greater than `low`
+    pub fn gen_range(&mut self, low: i32, high: i32) -> i32 {
+        let span = (high as i64 - low as i64) as u64;
+        (low as i64 + (self.next_u64() % span) as i64) as i32
+    }
+}
@@ -0,0 +1,357 @@
+use crate::identity;
+use crate::replay::Visibility;
+use crate::server::session_token;
+#[cfg(feature = "multiplayer")]
+use crate::server::AppState;
+#[cfg(feature = "multiplayer")]
+use crate::session::RoomChange;
+use crate::session::Session;
+#[cfg(feature = "multiplayer")]
+use crate::share;
+use crate::signer::{unix_now, Signer};
+#[cfg(feature = "multiplayer")]
+use actix_web::web;
+use actix_web::{HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::Duration;
+
+/// Lifetime of an invite token
+pub const INVITE_TTL: Duration = Duration::from_secs(15 * 60);
+/// Most rooms listed by `list`
+#[cfg(feature = "multiplayer")]
+const MAX_LISTED_ROOMS: usize = 50;
+
+/// Who may spectate a room besides its host: rooms with a password admit spectators giving
+/// it, and every room admits holders of an invite the host issued
+/// The password is kept as an HMAC under the room's key, which also signs the invites
+/// The default access has a random key and no password
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct RoomAccess {
+    key: Signer,
+    password: Option<Vec<u8>>,
+}
+
+impl RoomAccess {
+    /// Sets or, with None or an empty password, removes the room's password
+    pub fn set_password(&mut self, password: Option<&str>) {
+        self.password = password
+            .filter(|password| !password.is_empty())
+            .map(|password| self.key.mac(&purposed("password", password)));
+    }
+
+    pub fn has_password(&self) -> bool {
+        self.password.is_some()
+    }
+
+    /// Issues an invite of the form `<expiry as Unix seconds>.<hex HMAC-SHA256 of it>`
+    /// Returns the invite and its expiry
+    pub fn invite(&self) -> (String, u64) {
+        let expires_at = unix_now() + INVITE_TTL.as_secs();
+        let signature = self.key.mac(&purposed("invite", &expires_at.to_string()));
+        let invite = format!("{}.{}", expires_at, hex::encode(signature));
+        (invite, expires_at)
+    }
+
+    /// Checks the credentials of a spectator: an invite that has not expired, or the
+    /// password of a room that has one
+    pub fn admits(&self, pass: &RoomPass) -> bool {
+        let invited = pass.invite.is_some_and(|invite| self.verify_invite(invite));
+        let password = self.password.as_deref().zip(pass.password);
+        invited
+            || password.is_some_and(|(expected, password)| {
+                self.key.verifies(&purposed("password", password), expected)
+            })
+    }
+
+    /// Checks the signature and expiry of an invite
+    fn verify_invite(&self, invite: &str) -> bool {
+        let Some((expires_at, signature)) = invite.split_once('.') else {
+            return false;
+        };
+        let fresh = expires_at
+            .parse()
+            .is_ok_and(|expires_at: u64| expires_at > unix_now());
+        let signature = hex::decode(signature).unwrap_or_default();
+        fresh
+            && self
+                .key
+                .verifies(&purposed("invite", expires_at), &signature)
+    }
+}
+
+/// Message signed for a value, separated by purpose so no HMAC is valid for another one
+fn purposed(purpose: &str, value: &str) -> Vec<u8> {
+    format!("{}.{}", purpose, value).into_bytes()
+}
+
+/// Credentials of a spectator joining a private room
+#[derive(Default)]
+pub struct RoomPass<'a> {
+    pub password: Option<&'a str>,
+    pub invite: Option<&'a str>,
+}
+
+/// Credentials of a spectator in the query string of the HTTP endpoints reading a live game
+#[derive(Default, Deserialize)]
+pub struct PassQuery {
+    pub password: Option<String>,
+    pub invite: Option<String>,
+}
+
+impl PassQuery {
+    pub fn pass(&self) -> RoomPass<'_> {
+        RoomPass {
+            password: self.password.as_deref(),
+            invite: self.invite.as_deref(),
+        }
+    }
+}
+
+/// Checks that a request may watch a live game, see `Session::watchable_by`
+/// The player sends the session token in the `X-Session-Token` header or comes with the
+/// client cookie; spectators of private rooms give the `password` or an `invite` in the
+/// query
+/// Returns the rejection of a request that may not: 404 Not Found for private games, which
+/// do not reveal themselves, and 403 Forbidden for rooms with a password
+pub fn check_watcher(
+    req: &HttpRequest,
+    session: &Session,
+    query: &PassQuery,
+) -> Option<HttpResponse> {
+    let client_id = identity::client_id(req);
+    if session.watchable_by(session_token(req), client_id.as_deref(), &query.pass()) {
+        None
+    } else if session.visibility == Visibility::Private {
+        Some(HttpResponse::NotFound().finish())
+    } else {
+        Some(HttpResponse::Forbidden().json(json!({ "error": "a password or invite is required" })))
+    }
+}
+
+/// Moderation action of a room's host, referring to connections by their ID
+#[cfg(feature = "multiplayer")]
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Moderation {
+    /// Closes a spectator's connection
+    Kick {
+        connection: String,
+    },
+    /// Closes a spectator's connection and keeps its browser out of the room
+    Ban {
+        connection: String,
+    },
+    /// Keeps further spectators out; those connected stay
+    Lock,
+    Unlock,
+    /// Hands the room, its session token and the game to a spectator
+    TransferHost {
+        connection: String,
+    },
+}
+
+/// Room of a game as shown to its host: whether it is locked and its connections
+#[cfg(feature = "multiplayer")]
+fn room_json(session: &Session) -> serde_json::Value {
+    json!({ "locked": session.locked, "viewers": session.viewers })
+}
+
+/// Lists the rooms of running public games that spectators may join, most watched first
+/// Private rooms, those of private or unlisted games and those with a password, are left out
+#[cfg(feature = "multiplayer")]
+pub async fn list(data: web::Data<AppState>) -> HttpResponse {
+    let sessions = data.sessions.lock().unwrap();
+    let mut rooms: Vec<_> = sessions
+        .iter()
+        .filter(|(_, session)| session.visibility == Visibility::Public)
+        .filter(|(_, session)| !session.game.game_over && !session.access.has_password())
+        .collect();
+    rooms.sort_by_key(|(_, session)| std::cmp::Reverse(session.spectators()));
+    let rooms: Vec<_> = rooms
+        .into_iter()
+        .take(MAX_LISTED_ROOMS)
+        .map(|(session_id, session)| {
+            json!({
+                "session_id": session_id,
+                "score": session.game.score,
+                "spectators": session.spectators(),
+                "locked": session.locked,
+            })
+        })
+        .collect();
+    HttpResponse::Ok().json(rooms)
+}
+
+/// Lists the realtime connections of a game's room for its host, who holds the session
+/// token, to moderate
+/// session_id: Unique identifier for the game instance
+#[cfg(feature = "multiplayer")]
+pub async fn room(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let sessions = data.sessions.lock().unwrap();
+    let Some(session) = sessions.get(session_id.as_str()) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if !session.authorizes(session_token(&req)) {
+        return HttpResponse::Unauthorized().json(json!({ "error": "invalid session token" }));
+    }
+    HttpResponse::Ok().json(room_json(session))
+}
+
+/// Moderates a game's room: kicks or bans a spectator, locks or unlocks the room, or
+/// hands it to a spectator; every change is broadcast to the room's connections
+/// Only the host, who holds the session token, may moderate; the host cannot be kicked,
+/// and handing the room over rotates the token, so the previous host only spectates
+/// Bans apply to the browser of the connection, by its client cookie; once the room has
+/// bans, also of connections without one, spectators without client cookie are refused
+/// session_id: Unique identifier for the game instance
+#[cfg(feature = "multiplayer")]
+pub async fn moderate(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    moderation: web::Json<Moderation>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let mut sessions = data.sessions.lock().unwrap();
+    let Some(session) = sessions.get_mut(session_id.as_str()) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if !session.authorizes(session_token(&req)) {
+        return HttpResponse::Unauthorized().json(json!({ "error": "invalid session token" }));
+    }
+    session.touch();
+    let target = match &*moderation {
+        Moderation::Kick { connection }
+        | Moderation::Ban { connection }
+        | Moderation::TransferHost { connection } => {
+            let viewer = session
+                .viewers
+                .iter()
+                .find(|viewer| viewer.connection == *connection);
+            match viewer {
+                Some(viewer) if viewer.host => {
+                    return HttpResponse::BadRequest()
+                        .json(json!({ "error": "the connection already hosts the room" }))
+                }
+                Some(viewer) => Some(viewer.clone()),
+                None => {
+                    return HttpResponse::NotFound().json(json!({ "error": "unknown connection" }))
+                }
+            }
+        }
+        Moderation::Lock | Moderation::Unlock => None,
+    };
+    let change = match (moderation.into_inner(), target) {
+        (Moderation::Kick { connection }, _) => RoomChange::Kicked { connection },
+        (Moderation::Ban { connection }, target) => {
+            let client_id = target.and_then(|viewer| viewer.client_id);
+            if let Some(client_id) = client_id.filter(|id| !session.banned.contains(id)) {
+                session.banned.push(client_id);
+            }
+            session.has_bans = true;
+            RoomChange::Banned { connection }
+        }
+        (Moderation::Lock, _) => {
+            session.locked = true;
+            RoomChange::Locked
+        }
+        (Moderation::Unlock, _) => {
+            session.locked = false;
+            RoomChange::Unlocked
+        }
+        (Moderation::TransferHost { connection }, target) => {
+            // The new host's browser may resume the game, the previous one's no longer
+            session.rotate_token();
+            session.client_id = target.and_then(|viewer| viewer.client_id);
+            RoomChange::HostTransferred { connection }
+        }
+    };
+    session.moderate(change);
+    HttpResponse::Ok().json(room_json(session))
+}
+
+/// Issues an invite to a game's room for its host, who holds the session token, to share
+/// The invite admits spectators to private rooms until it expires after `INVITE_TTL`; the
+/// link connects to the room's realtime stream with it
+/// session_id: Unique identifier for the game instance
+#[cfg(feature = "multiplayer")]
+pub async fn invite(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let (invite, expires_at) = {
+        let mut sessions = data.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(session_id.as_str()) else {
+            return HttpResponse::NotFound().finish();
+        };
+        if !session.authorizes(session_token(&req)) {
+            return HttpResponse::Unauthorized().json(json!({ "error": "invalid session token" }));
+        }
+        session.touch();
+        session.access.invite()
+    };
+    // http:// becomes ws:// and https:// wss://
+    let base = share::base_url(&req, &data).replacen("http", "ws", 1);
+    HttpResponse::Ok().json(json!({
+        "invite": invite,
+        "invite_url": format!("{}/ws/{}?invite={}", base, session_id, invite),
+        "expires_at": expires_at,
+        "expires_in_secs": INVITE_TTL.as_secs(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invited(invite: &str) -> RoomPass<'_> {
+        RoomPass {
+            invite: Some(invite),
+            ..RoomPass::default()
+        }
+    }
+
+    #[test]
+    fn invite_admits_until_it_expires() {
+        let access = RoomAccess::default();
+        let (invite, expires_at) = access.invite();
+        assert!(expires_at > unix_now());
+        assert!(access.admits(&invited(&invite)));
+
+        let expired = (unix_now() - 1).to_string();
+        let signature = access.key.mac(&purposed("invite", &expired));
+        let invite = format!("{}.{}", expired, hex::encode(signature));
+        assert!(!access.admits(&invited(&invite)));
+    }
+
+    #[test]
+    fn forged_invite_is_rejected() {
+        let access = RoomAccess::default();
+        let (invite, expires_at) = RoomAccess::default().invite();
+        assert!(!access.admits(&invited(&invite)));
+
+        let (invite, _) = access.invite();
+        let extended =
+            invite.replacen(&expires_at.to_string(), &(expires_at + 3600).to_string(), 1);
+        assert!(!access.admits(&invited(&extended)));
+        assert!(!access.admits(&invited("garbage")));
+    }
+
+    #[test]
+    fn password_admits_only_when_right() {
+        let mut access = RoomAccess::default();
+        let pass = |password| RoomPass {
+            password: Some(password),
+            ..RoomPass::default()
+        };
+        assert!(!access.admits(&pass("secret")));
+        access.set_password(Some("secret"));
+        assert!(access.admits(&pass("secret")));
+        assert!(!access.admits(&pass("wrong")));
+    }
+}
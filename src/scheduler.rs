@@ -0,0 +1,166 @@
+use crate::config::Config;
+use crate::leaderboard::HOUR;
+use crate::level;
+use crate::server::{self, AppState};
+use actix_web::web;
+use futures_util::future::{FutureExt, LocalBoxFuture};
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How often the scheduler checks for due jobs
+const SCHEDULER_TICK: Duration = Duration::from_secs(1);
+
+/// When a job runs
+#[derive(Clone, Copy)]
+pub enum Schedule {
+    /// When the scheduler starts, then at a fixed interval
+    Every(Duration),
+    /// Whenever the wall clock reaches a multiple of the period since the Unix epoch
+    Aligned(Duration),
+}
+
+impl Schedule {
+    /// First run of a job scheduled at `now`
+    fn first_run(&self, now: SystemTime) -> SystemTime {
+        match self {
+            Schedule::Every(_) => now,
+            Schedule::Aligned(_) => self.next_run(now),
+        }
+    }
+
+    /// Run following one that started at `now`
+    fn next_run(&self, now: SystemTime) -> SystemTime {
+        match *self {
+            Schedule::Every(interval) => now + interval,
+            Schedule::Aligned(period) => {
+                let elapsed = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                let period = period.as_secs().max(1);
+                UNIX_EPOCH + Duration::from_secs((elapsed / period + 1) * period)
+            }
+        }
+    }
+
+    /// Description of the schedule for the admin API
+    fn describe(&self) -> String {
+        match self {
+            Schedule::Every(interval) => format!("every {}s", interval.as_secs()),
+            Schedule::Aligned(period) => format!("every {}s of UTC time", period.as_secs()),
+        }
+    }
+}
+
+/// Body of a job: runs once and reports an error message on failure
+type JobFn = Box<dyn Fn(web::Data<AppState>) -> LocalBoxFuture<'static, Result<(), String>>>;
+
+/// Background job run by the scheduler
+struct Job {
+    name: &'static str,
+    schedule: Schedule,
+    run: JobFn,
+}
+
+/// Runs background jobs on their schedules from a single task
+/// Jobs run one at a time, so a slow job delays the others instead of overlapping them
+#[derive(Default)]
+pub struct Scheduler {
+    jobs: Vec<Job>,
+}
+
+impl Scheduler {
+    /// Creates a scheduler without jobs
+    pub fn new() -> Self {
+        Scheduler::default()
+    }
+
+    /// Scheduler with the server's jobs: session GC, checkpointing, taking over games
+    /// handed over by a server shutting down, level rotation and closing of hourly, daily
+    /// and weekly leaderboards
+    #[cfg_attr(not(feature = "persistence"), allow(unused_mut, unused_variables))]
+    pub fn standard(config: &Config) -> Self {
+        let mut scheduler = Scheduler::new()
+            .job(
+                "session_gc",
+                Schedule::Every(Duration::from_secs(30)),
+                server::collect_sessions,
+            )
+            .job(
+                "level_rotation",
+                Schedule::Every(Duration::from_secs(60)),
+                |data| async move {
+                    level::rotate_featured(&data, SystemTime::now());
+                    Ok(())
+                },
+            )
+            .job(
+                "leaderboard_windows",
+                Schedule::Aligned(HOUR),
+                |data| async move {
+                    data.close_windows(SystemTime::now());
+                    Ok(())
+                },
+            );
+        #[cfg(feature = "persistence")]
+        if config.checkpoint_dir.is_some() {
+            scheduler = scheduler
+                .job(
+                    "checkpoint",
+                    Schedule::Every(Duration::from_secs(1)),
+                    server::checkpoint,
+                )
+                .job(
+                    "handoff",
+                    Schedule::Every(Duration::from_secs(1)),
+                    server::adopt_handoff,
+                );
+        }
+        scheduler
+    }
+
+    /// Adds a job
+    /// name: Name of the job in the admin API
+    pub fn job<F, Fut>(mut self, name: &'static str, schedule: Schedule, run: F) -> Self
+    where
+        F: Fn(web::Data<AppState>) -> Fut + 'static,
+        Fut: Future<Output = Result<(), String>> + 'static,
+    {
+        self.jobs.push(Job {
+            name,
+            schedule,
+            run: Box::new(move |data| run(data).boxed_local()),
+        });
+        self
+    }
+
+    /// Starts running the jobs in the background
+    /// Every run is reported in the admin API with the time of the next one
+    pub fn spawn(self, data: web::Data<AppState>) {
+        let now = SystemTime::now();
+        let mut due: Vec<SystemTime> = self
+            .jobs
+            .iter()
+            .map(|job| job.schedule.first_run(now))
+            .collect();
+        for (job, next_run) in self.jobs.iter().zip(&due) {
+            data.schedule_task(job.name, job.schedule.describe(), *next_run);
+        }
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(SCHEDULER_TICK);
+            loop {
+                interval.tick().await;
+                for (job, next_run) in self.jobs.iter().zip(due.iter_mut()) {
+                    let now = SystemTime::now();
+                    if now < *next_run {
+                        continue;
+                    }
+                    let result = (job.run)(data.clone()).await;
+                    if let Err(e) = &result {
+                        tracing::error!("job {} failed: {}", job.name, e);
+                    }
+                    data.report_task(job.name, result);
+                    *next_run = job.schedule.next_run(now);
+                    data.schedule_task(job.name, job.schedule.describe(), *next_run);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,127 @@
+use crate::game::{Game, Input};
+use crate::signer::{unix_now, Signer};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+/// Lifetime of a score token; its score has to be submitted before
+pub const TOKEN_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Checksum of a move log: hex-encoded SHA-256 of the inputs as JSON
+pub fn moves_checksum(inputs: &[Input]) -> String {
+    let log = serde_json::to_vec(inputs).expect("inputs serialize");
+    hex::encode(Sha256::digest(log))
+}
+
+/// Final result of a game as certified by a score token
+pub struct ScoreClaim {
+    /// Session the game was played in
+    pub session_id: String,
+    /// Final score
+    pub score: u32,
+    /// Checksum of the move log, see `moves_checksum`
+    pub checksum: String,
+    /// Unix timestamp (seconds) the token expires at
+    pub expires_at: u64,
+}
+
+/// Signs and verifies score tokens of the form
+/// `<session_id>.<score>.<checksum>.<expiry as Unix seconds>.<hex HMAC-SHA256 of the first
+/// four parts>`
+pub struct ScoreSigner {
+    signer: Signer,
+}
+
+impl ScoreSigner {
+    /// Signer with a fixed key, so tokens stay valid across restarts
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        ScoreSigner {
+            signer: Signer::new(key),
+        }
+    }
+
+    /// Signer with a random key; its tokens are only valid until the server restarts
+    pub fn random() -> Self {
+        ScoreSigner {
+            signer: Signer::default(),
+        }
+    }
+
+    /// Issues a token certifying the current score and move log of a game, valid for
+    /// `TOKEN_TTL`
+    pub fn sign(&self, session_id: &str, game: &Game) -> String {
+        let claim = format!(
+            "{}.{}.{}.{}",
+            session_id,
+            game.score,
+            moves_checksum(&game.inputs),
+            unix_now() + TOKEN_TTL.as_secs()
+        );
+        self.signer.sign(&claim)
+    }
+
+    /// Checks the signature and expiry of a token and returns what it certifies
+    /// Returns None for malformed, forged or expired tokens
+    pub fn verify(&self, token: &str) -> Option<ScoreClaim> {
+        let claim = self.signer.verify(token)?;
+        let mut parts = claim.splitn(4, '.');
+        let session_id = parts.next()?.to_string();
+        let score = parts.next()?.parse().ok()?;
+        let checksum = parts.next()?.to_string();
+        let expires_at = parts.next()?.parse().ok()?;
+        if expires_at <= unix_now() {
+            return None;
+        }
+        Some(ScoreClaim {
+            session_id,
+            score,
+            checksum,
+            expires_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Direction;
+
+    fn game() -> Game {
+        let mut game = Game::with_seed(20, 20, 1);
+        game.score = 12;
+        game.inputs.push(Input {
+            tick: 3,
+            direction: Direction::Up,
+            snake: 0,
+        });
+        game
+    }
+
+    #[test]
+    fn signed_token_verifies() {
+        let signer = ScoreSigner::new("key");
+        let game = game();
+        let claim = signer.verify(&signer.sign("session", &game)).unwrap();
+        assert_eq!(claim.session_id, "session");
+        assert_eq!(claim.score, 12);
+        assert_eq!(claim.checksum, moves_checksum(&game.inputs));
+        assert!(claim.expires_at > unix_now());
+    }
+
+    #[test]
+    fn tampered_token_is_rejected() {
+        let signer = ScoreSigner::new("key");
+        let token = signer.sign("session", &game());
+        assert!(signer
+            .verify(&token.replacen("session.12.", "session.99.", 1))
+            .is_none());
+        assert!(ScoreSigner::new("other key").verify(&token).is_none());
+        assert!(signer.verify("session.12").is_none());
+    }
+
+    #[test]
+    fn expired_token_is_rejected() {
+        let claim = format!("session.12.{}.{}", moves_checksum(&[]), unix_now() - 1);
+        let token = Signer::new("key").sign(&claim);
+        assert!(ScoreSigner::new("key").verify(&token).is_none());
+    }
+}
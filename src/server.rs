@@ -0,0 +1,2389 @@
+use crate::audit::{AuditAction, AuditEntry, AuditLog};
+#[cfg(feature = "ai")]
+use crate::bot::{self, BotCallback};
+use crate::campaign::{self, Campaigns};
+use crate::challenge;
+#[cfg(feature = "persistence")]
+use crate::checkpoint::{Checkpoint, CheckpointStore};
+use crate::config::Config;
+use crate::feed::{self, TopEntry, MAX_TOP_ENTRIES};
+use crate::game::GameConfig;
+use crate::heatmap::{self, Heatmaps};
+#[cfg(feature = "multiplayer")]
+use crate::history::{self, MatchHistory};
+use crate::i18n::Catalogs;
+use crate::leaderboard::{ClosedWindow, OpenWindow, Window, MAX_CLOSED_WINDOWS};
+use crate::level::{Featured, Level, LevelSpec, LevelStore};
+use crate::metrics::RequestMetrics;
+use crate::moderation::{
+    HiddenScore, PendingScore, Placement, Played, SubmissionLog, Suspicion, MAX_HIDDEN_SCORES,
+    MAX_PENDING_SCORES,
+};
+use crate::names::NameError;
+use crate::observation::{self, ObservationFormat};
+use crate::proof_of_work::{self, ProofOfWork};
+use crate::quota::Fairness;
+use crate::replay::{Replay, ReplayStore, Visibility};
+use crate::report::{self, Report};
+use crate::room::PassQuery;
+use crate::score_token::{moves_checksum, ScoreClaim, ScoreSigner};
+use crate::session::Session;
+use crate::signer::{unix_now, Signer};
+#[cfg(feature = "multiplayer")]
+use crate::team::{self, TeamMatches};
+use crate::view::{self, GameView, PlayerView};
+use crate::topology::TopologySpec;
+use crate::{admin, ai, capabilities, demo, game, identity, level, render, room, summary, theme};
+use actix_files as fs;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use futures_util::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+#[cfg(feature = "persistence")]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+use uuid::Uuid;
+
+/// Size of the board of new games
+pub(crate) const BOARD_WIDTH: i32 = 20;
+pub(crate) const BOARD_HEIGHT: i32 = 20;
+
+/// Interval at which streaming APIs check a game for changes
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Longest time a long-polling request waits for a game to change
+const LONG_POLL_TIMEOUT: Duration = Duration::from_secs(25);
+
+/// Represents a player's score entry for the leaderboard
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Score {
+    // Identifier of the entry, assigned by the server when the score is submitted
+    #[serde(default)]
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) score: u32,
+    // Modifiers of the scoring game, taken from its session so they cannot be claimed
+    #[serde(default)]
+    pub(crate) modifiers: game::Modifiers,
+    // Custom level of the scoring game, taken from its session
+    #[serde(default)]
+    pub(crate) level: Option<String>,
+    // Whether the score was shared by the two players of a co-op game, taken from its session
+    #[serde(default)]
+    pub(crate) coop: bool,
+    // How fast the scoring game completed its level's objective, taken from its session
+    #[serde(default)]
+    pub(crate) completion: Option<game::Completion>,
+    // Start of the hour whose challenge seed the scoring game played, taken from its session
+    #[serde(default)]
+    pub(crate) challenge: Option<u64>,
+    // Session the score was achieved in, taken from the score token
+    #[serde(default, skip_serializing)]
+    pub(crate) session_id: Option<String>,
+    // Score token issued with the final state of the game
+    #[serde(default, skip_serializing)]
+    pub(crate) token: Option<String>,
+    // Who may watch the game's replay from now on, unchanged when absent
+    #[serde(default, skip_serializing)]
+    pub(crate) visibility: Option<Visibility>,
+    // Whether the game's replay re-simulated to this score; set by the server
+    #[serde(default)]
+    pub(crate) verified: bool,
+}
+
+/// Number of food items timed by the speed-run leaderboard
+pub const SPEEDRUN_APPLES: u32 = 50;
+
+/// Entry of the speed-run leaderboard: the time a player took to eat `SPEEDRUN_APPLES`
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct SpeedRun {
+    // Identifier of the leaderboard entry of the timed game
+    pub(crate) id: String,
+    pub(crate) name: String,
+    // Wall-clock milliseconds from creating the game to the speed-run split
+    pub(crate) elapsed_ms: u64,
+    // Modifiers of the timed game
+    pub(crate) modifiers: game::Modifiers,
+}
+
+/// Header carrying the session token issued by `/new-game` and `/session/{id}/resume`
+pub const SESSION_TOKEN_HEADER: &str = "X-Session-Token";
+
+/// Header carrying the version of the state returned by `/game/{session_id}`
+pub const GAME_VERSION_HEADER: &str = "X-Game-Version";
+
+/// Application state that is shared between all routes
+/// Uses Mutex for thread-safe access to sessions and leaderboard
+pub struct AppState {
+    // Server configuration, replaced when the configuration file is reloaded
+    pub(crate) config: RwLock<Arc<Config>>,
+    // Map of session IDs to game sessions
+    pub(crate) sessions: Mutex<HashMap<String, Session>>,
+    // Vector of top scores of all time
+    pub(crate) leaderboard: Mutex<Vec<Score>>,
+    // Map of hourly, daily and weekly windows to the top scores of the current window
+    pub(crate) windows: Mutex<HashMap<Window, OpenWindow>>,
+    // Map of hourly, daily and weekly windows to the leaderboards of past windows, newest first
+    pub(crate) closed_windows: Mutex<HashMap<Window, Vec<ClosedWindow>>>,
+    // Map of level IDs to the top scores of games on that custom level
+    pub(crate) level_leaderboards: Mutex<HashMap<String, Vec<Score>>>,
+    // Vector of fastest times to `SPEEDRUN_APPLES` food items
+    pub(crate) speedruns: Mutex<Vec<SpeedRun>>,
+    // Recordings of finished games, the most recent `replay::MAX_REPLAYS`
+    pub(crate) replays: Mutex<ReplayStore>,
+    // Health of long-running background tasks
+    pub(crate) tasks: Mutex<HashMap<String, admin::TaskHealth>>,
+    // Storage for checkpoints of active games, if enabled
+    #[cfg(feature = "persistence")]
+    pub(crate) checkpoints: Option<CheckpointStore>,
+    // Whether this server owns the checkpoint store, see `take_over_checkpoints`
+    #[cfg(feature = "persistence")]
+    pub(crate) owns_checkpoints: AtomicBool,
+    // Map of level IDs to custom levels
+    pub(crate) levels: Mutex<HashMap<String, Level>>,
+    // Storage for custom levels, if enabled
+    pub(crate) level_store: Option<LevelStore>,
+    // Signer of the score tokens of finished games
+    pub(crate) score_signer: ScoreSigner,
+    // Signs the client cookies, see `identity::client_id`
+    pub(crate) cookie_signer: Signer,
+    // Map of session IDs whose score was submitted to the expiry of their token, so each
+    // game is ranked once
+    pub(crate) submitted_scores: Mutex<HashMap<String, u64>>,
+    // Map of normalized leaderboard names to the client that first used them
+    pub(crate) name_owners: Mutex<HashMap<String, String>>,
+    // Recent score submissions per anonymous player ID and client
+    pub(crate) submissions: Mutex<SubmissionLog>,
+    // Suspicious scores waiting for an admin's review, oldest first
+    pub(crate) review_queue: Mutex<Vec<PendingScore>>,
+    // Leaderboard entries hidden by moderators or rejected in review, oldest first
+    pub(crate) hidden_scores: Mutex<Vec<HiddenScore>>,
+    // Reports of offensive names and levels waiting for a moderator, oldest first
+    pub(crate) reports: Mutex<Vec<Report>>,
+    // Entries that entered the all-time top 10, oldest first, for the leaderboard feed
+    pub(crate) top_entries: Mutex<Vec<TopEntry>>,
+    // Map of device link codes to the client they sign in
+    pub(crate) link_codes: Mutex<HashMap<String, identity::LinkCode>>,
+    // Anonymous player IDs and the records kept under them
+    pub(crate) anonymous_ids: identity::AnonymousIds,
+    // Where snakes died and ate in finished ranked games, by board size
+    pub(crate) heatmaps: Heatmaps,
+    // Translations of error messages by language
+    pub(crate) catalogs: Catalogs,
+    // 2v2 team matches by ID
+    #[cfg(feature = "multiplayer")]
+    pub(crate) team_matches: TeamMatches,
+    // Finished team matches
+    #[cfg(feature = "multiplayer")]
+    pub(crate) match_history: MatchHistory,
+    // Campaign progress of the players
+    pub(crate) campaigns: Campaigns,
+    // Signing key and redeemed challenges of the proof-of-work check
+    pub(crate) proof_of_work: ProofOfWork,
+    // Level of the week, if any level is published
+    pub(crate) featured: Mutex<Option<Featured>>,
+    // Earlier featured levels with their final leaderboards, newest first
+    pub(crate) featured_archive: Mutex<Vec<Featured>>,
+    // Logins in progress and accounts linked to external identities
+    #[cfg(feature = "oauth")]
+    pub(crate) oauth: crate::oauth::OAuthState,
+    // Request and error counts of the last few minutes
+    pub(crate) request_metrics: RequestMetrics,
+    // AI slots shared by all games and counts of games kept waiting
+    pub(crate) fairness: Fairness,
+    // Whether the server is shutting down, handing its games over to its successor
+    pub(crate) draining: watch::Sender<bool>,
+    // Administrative and destructive actions
+    pub(crate) audit: AuditLog,
+}
+
+impl AppState {
+    /// Server configuration as of now; later reloads do not change the returned copy
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Creates a new game session and returns its session ID and token
+    /// Fails with a message when the requested portals, hazards, tiles, gates, objective or
+    /// start snake do not fit on the board, or the requested level does not exist or is not
+    /// playable by the client
+    /// client_id: Browser creating the game, if known
+    /// options: Level or board variant, start snake, multiplier zones, portals, hazards, tiles,
+    /// gates and objective, modifiers, food, scoring and growth settings, and whether it is a practice game
+    pub(crate) fn create_game(
+        &self,
+        client_id: Option<String>,
+        options: NewGameOptions,
+    ) -> Result<(String, String), String> {
+        let (session_id, session) = self.new_session(client_id, options)?;
+        let token = session.token.clone();
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session_id.clone(), session);
+        Ok((session_id, token))
+    }
+
+    /// Builds the session of a new game without storing it, see `create_game`
+    /// Returns its session ID and the session
+    pub(crate) fn new_session(
+        &self,
+        client_id: Option<String>,
+        options: NewGameOptions,
+    ) -> Result<(String, Session), String> {
+        if let Some(personality) = &options.ai {
+            personality.validate()?;
+        }
+        #[cfg(feature = "ai")]
+        if let Some(bot) = &options.bot {
+            bot.validate(&self.config())?;
+        }
+        // Generate a unique session ID
+        let session_id = Uuid::new_v4().to_string();
+        // A custom level replaces the board options of the request
+        let spec = match &options.level {
+            Some(level_id) => {
+                let mut levels = self.levels.lock().unwrap();
+                let level = levels
+                    .get_mut(level_id)
+                    .filter(|level| level.playable_by(client_id.as_deref()))
+                    .ok_or_else(|| "unknown level".to_string())?;
+                level.plays += 1;
+                level.spec.clone()
+            }
+            None => LevelSpec {
+                name: String::new(),
+                topology: options.topology,
+                start: options.start,
+                zones: options.zones,
+                portals: options.portals,
+                hazards: options.hazards,
+                tiles: options.tiles,
+                gates: options.gates,
+                objective: options.objective,
+            },
+        };
+        // Create new game and store it in the HashMap
+        let seed = options.seed.unwrap_or_else(rand::random);
+        let config = GameConfig {
+            modifiers: options.modifiers,
+            food_behavior: options.food_behavior,
+            food_expiry: options.food_expiry,
+            combo: options.combo,
+            growth: options.growth,
+            countdown: options.countdown.unwrap_or(self.config().countdown_ticks),
+            coop: options.coop,
+            practice: options.practice,
+            level: options.level,
+            ..spec.config(BOARD_WIDTH, BOARD_HEIGHT, seed)
+        };
+        let mut game = config.start()?;
+        game.history_len = game.snapshot_len();
+        let mut session = Session::new(game, client_id);
+        session.ai_personality = options.ai;
+        session.visibility = options.visibility;
+        session.access.set_password(options.password.as_deref());
+        #[cfg(feature = "ai")]
+        {
+            session.bot = options.bot;
+        }
+        Ok((session_id, session))
+    }
+
+    /// Removes sessions whose owner has been gone for longer than the grace period
+    /// Returns the IDs of the removed sessions; each removal is recorded in the audit log
+    pub(crate) fn collect_abandoned_sessions(&self) -> Vec<String> {
+        let grace = Duration::from_secs(self.config().session_grace_secs);
+        let removed: Vec<(String, Session)> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .extract_if(|_, session| session.is_abandoned(grace))
+            .collect();
+        removed
+            .into_iter()
+            .map(|(session_id, session)| {
+                let snapshot = json!({
+                    "client_id": session.client_id,
+                    "tick": session.game.tick,
+                    "score": session.game.score,
+                    "game_over": session.game.game_over,
+                });
+                self.audit.record(
+                    AuditEntry::new("system:session_gc", AuditAction::GameDeleted, &session_id)
+                        .before(snapshot),
+                );
+                session_id
+            })
+            .collect()
+    }
+
+    /// Collects checkpoints of games that advanced far enough since their last one
+    /// Returns checkpoints to write and IDs of finished games whose checkpoint is obsolete;
+    /// none while another server owns the store
+    #[cfg(feature = "persistence")]
+    fn due_checkpoints(&self) -> (Vec<Checkpoint>, Vec<String>) {
+        if !self.owns_checkpoints.load(Ordering::Relaxed) {
+            return (Vec::new(), Vec::new());
+        }
+        let every = self.config().checkpoint_every_ticks.max(1);
+        let mut sessions = self.sessions.lock().unwrap();
+        let mut due = Vec::new();
+        let mut obsolete = Vec::new();
+        for (session_id, session) in sessions.iter_mut() {
+            if session.game.game_over {
+                if session.checkpoint_tick.take().is_some() {
+                    obsolete.push(session_id.clone());
+                }
+            } else if !session.demo
+                && session.game.tick >= session.checkpoint_tick.unwrap_or(0) + every
+            {
+                session.checkpoint_tick = Some(session.game.tick);
+                due.push(checkpoint_of(session_id, session));
+            }
+        }
+        (due, obsolete)
+    }
+
+    /// Counts the checkpoints the next checkpoint run would write and delete, without
+    /// claiming them
+    #[cfg(feature = "persistence")]
+    pub(crate) fn checkpoint_backlog(&self) -> (usize, usize) {
+        if self.checkpoints.is_none() || !self.owns_checkpoints.load(Ordering::Relaxed) {
+            return (0, 0);
+        }
+        let every = self.config().checkpoint_every_ticks.max(1);
+        let sessions = self.sessions.lock().unwrap();
+        sessions.values().fold((0, 0), |(due, obsolete), session| {
+            if session.game.game_over {
+                (due, obsolete + usize::from(session.checkpoint_tick.is_some()))
+            } else {
+                let next = session.checkpoint_tick.unwrap_or(0) + every;
+                let is_due = !session.demo && session.game.tick >= next;
+                (due + usize::from(is_due), obsolete)
+            }
+        })
+    }
+
+    /// Restores the games of all stored checkpoints as sessions once this server owns the
+    /// store, which one running server does at a time
+    /// A server starting next to another one, e.g. during a deploy sharing the listening
+    /// port, neither restores nor checkpoints games until that one hands its games over or
+    /// stops running, so no game is played from a stale checkpoint while its server runs it
+    /// The owner still takes over the games handed over by a server that never owned the store
+    /// A restored game replaces the session in memory unless that one is further along;
+    /// restored sessions get a fresh token and their owners resume them with the client cookie
+    /// Returns the IDs of the restored games that were not in memory
+    #[cfg(feature = "persistence")]
+    fn take_over_checkpoints(&self) -> std::io::Result<Vec<String>> {
+        let Some(store) = &self.checkpoints else {
+            return Ok(Vec::new());
+        };
+        if self.is_draining() {
+            return Ok(Vec::new());
+        }
+        let owner = self.owns_checkpoints.load(Ordering::Relaxed);
+        if owner {
+            if !store.take_handoff()? {
+                return Ok(Vec::new());
+            }
+        } else if store.claim()? {
+            store.take_handoff()?;
+        } else {
+            return Ok(Vec::new());
+        }
+        let checkpoints: Vec<(String, Session)> = store
+            .load_all()?
+            .into_iter()
+            .filter(|checkpoint| !owner || checkpoint.handoff)
+            .map(restored)
+            .collect();
+        let mut restored = Vec::new();
+        let mut sessions = self.sessions.lock().unwrap();
+        for (session_id, session) in checkpoints {
+            match sessions.get(&session_id) {
+                Some(current) if current.game.tick >= session.game.tick => continue,
+                Some(_) => {}
+                None => restored.push(session_id.clone()),
+            }
+            sessions.insert(session_id, session);
+        }
+        self.owns_checkpoints.store(true, Ordering::Relaxed);
+        tracing::info!(restored = restored.len(), "took over checkpointed games");
+        Ok(restored)
+    }
+
+    /// Checkpoints every active game for the server taking over, marks the store and gives
+    /// up its ownership
+    /// Returns the number of games handed over
+    #[cfg(feature = "persistence")]
+    pub(crate) fn hand_over(&self) -> std::io::Result<usize> {
+        let Some(store) = &self.checkpoints else {
+            return Ok(0);
+        };
+        let checkpoints: Vec<Checkpoint> = {
+            let mut sessions = self.sessions.lock().unwrap();
+            sessions
+                .iter_mut()
+                .filter(|(_, session)| !session.game.game_over && !session.demo)
+                .map(|(session_id, session)| {
+                    session.checkpoint_tick = Some(session.game.tick);
+                    Checkpoint {
+                        handoff: true,
+                        ..checkpoint_of(session_id, session)
+                    }
+                })
+                .collect()
+        };
+        checkpoints
+            .iter()
+            .try_for_each(|checkpoint| store.save(checkpoint))?;
+        store.mark_handoff()?;
+        self.owns_checkpoints.store(false, Ordering::Relaxed);
+        store.release()?;
+        Ok(checkpoints.len())
+    }
+
+    /// Whether the server is shutting down
+    pub(crate) fn is_draining(&self) -> bool {
+        *self.draining.borrow()
+    }
+
+    /// Starts shutting down: new requests are turned away, realtime connections are told
+    /// to reconnect and bot games stop
+    pub fn start_draining(&self) {
+        self.draining.send_replace(true);
+    }
+
+    /// Loads all stored custom levels
+    fn load_levels(&self) {
+        let Some(store) = &self.level_store else {
+            return;
+        };
+        match store.load_all() {
+            Ok(loaded) => {
+                let mut levels = self.levels.lock().unwrap();
+                levels.extend(loaded.into_iter().map(|level| (level.id.clone(), level)));
+                tracing::info!("loaded {} levels", levels.len());
+            }
+            Err(e) => tracing::error!("cannot load levels: {}", e),
+        }
+    }
+
+    /// Records a run of a background task for the admin API
+    pub(crate) fn report_task(&self, name: &str, result: std::result::Result<(), String>) {
+        let mut tasks = self.tasks.lock().unwrap();
+        let health = tasks.entry(name.to_string()).or_default();
+        health.runs += 1;
+        health.last_run = Some(unix_now());
+        health.last_error = result.err();
+    }
+
+    /// Records the schedule and next run of a background task for the admin API
+    pub(crate) fn schedule_task(&self, name: &str, schedule: String, next_run: SystemTime) {
+        let mut tasks = self.tasks.lock().unwrap();
+        let health = tasks.entry(name.to_string()).or_default();
+        health.schedule = Some(schedule);
+        health.next_run = next_run
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|elapsed| elapsed.as_secs());
+    }
+
+    /// Reserves a leaderboard name for the client that first submits a score with it
+    /// Names are compared in normalized form; scores of games without a known client
+    /// may use any name that is not reserved, without reserving it
+    pub(crate) fn claim_name(
+        &self,
+        name: &str,
+        client_id: Option<String>,
+    ) -> std::result::Result<(), NameError> {
+        let normalized = self.config().name_filter.normalize(name);
+        let mut owners = self.name_owners.lock().unwrap();
+        match (owners.get(&normalized), client_id) {
+            (Some(owner), client_id) if client_id.as_ref() != Some(owner) => Err(NameError::Taken),
+            (None, Some(client_id)) => {
+                owners.insert(normalized, client_id);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the recorded replay of a game re-simulates to the score and move log its
+    /// token certifies; false when the replay is missing
+    pub(crate) fn verify_score(&self, claim: &ScoreClaim) -> bool {
+        let replay = self.replays.lock().unwrap().get(&claim.session_id).cloned();
+        replay.is_some_and(|replay| {
+            replay.score == claim.score
+                && moves_checksum(&replay.inputs) == claim.checksum
+                && replay.verify()
+        })
+    }
+
+    /// Reasons to hold a score for review; empty when it can be ranked right away
+    /// Every call counts as a submission from the IP address and the client
+    /// network: Anonymous ID of the IP address, see `AnonymousIds::network_of`
+    /// played: Duration and bounds of the game, unknown once its session is removed
+    pub(crate) fn suspicions(
+        &self,
+        score: &Score,
+        network: &str,
+        client_id: Option<&str>,
+        played: Option<Played>,
+    ) -> Vec<Suspicion> {
+        let config = self.config();
+        let rules = &config.anti_spam;
+        let mut reasons = played.map_or_else(Vec::new, |played| rules.check(score.score, &played));
+        let now = SystemTime::now();
+        let mut log = self.submissions.lock().unwrap();
+        let sources = [("network", Some(network)), ("client", client_id)];
+        for (source, id) in sources {
+            let Some(id) = id else { continue };
+            let submissions = log.record(&format!("{}:{}", source, id), now);
+            if submissions > rules.max_submissions_per_hour {
+                reasons.push(Suspicion::TooFrequent {
+                    source: source.to_string(),
+                    submissions,
+                });
+            }
+        }
+        reasons
+    }
+
+    /// Ranks a score on the leaderboards it belongs to and returns the one shown to
+    /// its player: the level's leaderboard for games on custom levels, otherwise the
+    /// all-time leaderboard
+    /// speedrun: Time to the speed-run split, if the game reached it
+    pub(crate) fn publish_score(&self, score: Score, speedrun: Option<u64>) -> Vec<Score> {
+        if let Some(level_id) = score.level.clone() {
+            // Games on the level of the week also enter the rotation's leaderboard
+            let mut featured = self.featured.lock().unwrap();
+            if let Some(featured) = featured.as_mut().filter(|f| f.level_id == level_id) {
+                rank(&mut featured.leaderboard, score.clone());
+            }
+            // Games on custom levels are ranked on the level's own leaderboard
+            let mut leaderboards = self.level_leaderboards.lock().unwrap();
+            let leaderboard = leaderboards.entry(level_id).or_default();
+            rank(leaderboard, score);
+            return leaderboard.clone();
+        }
+        if let Some(elapsed_ms) = speedrun {
+            let mut speedruns = self.speedruns.lock().unwrap();
+            speedruns.push(SpeedRun {
+                id: score.id.clone(),
+                name: score.name.clone(),
+                elapsed_ms,
+                modifiers: score.modifiers,
+            });
+            speedruns.sort_by_key(|entry| entry.elapsed_ms);
+            speedruns.truncate(10);
+        }
+        {
+            let mut windows = self.windows.lock().unwrap();
+            for (window, open) in windows.iter_mut() {
+                // The hourly leaderboard only ranks games of the hour's challenge seed
+                if *window != Window::Hourly || score.challenge == Some(open.start) {
+                    rank(&mut open.scores, score.clone());
+                }
+            }
+        }
+        let entry_id = score.id.clone();
+        let mut leaderboard = self.leaderboard.lock().unwrap();
+        rank(&mut leaderboard, score);
+        if let Some(entered) = leaderboard.iter().find(|score| score.id == entry_id) {
+            let mut top_entries = self.top_entries.lock().unwrap();
+            top_entries.push(TopEntry {
+                score: entered.clone(),
+                ranked_at: unix_now(),
+            });
+            let excess = top_entries.len().saturating_sub(MAX_TOP_ENTRIES);
+            top_entries.drain(..excess);
+        }
+        leaderboard.clone()
+    }
+
+    /// Entries of the current hourly, daily or weekly window, or of all time
+    pub(crate) fn leaderboard_of(&self, window: Window) -> Vec<Score> {
+        if window == Window::AllTime {
+            return self.leaderboard.lock().unwrap().clone();
+        }
+        let windows = self.windows.lock().unwrap();
+        windows
+            .get(&window)
+            .map(|open| open.scores.clone())
+            .unwrap_or_default()
+    }
+
+    /// Entry ranked on any leaderboard, past windows and rotations included
+    pub(crate) fn ranked_score(&self, entry_id: &str) -> Option<Score> {
+        let find = |scores: &[Score]| scores.iter().find(|score| score.id == entry_id).cloned();
+        find(&self.leaderboard.lock().unwrap())
+            .or_else(|| {
+                let windows = self.windows.lock().unwrap();
+                windows.values().find_map(|open| find(&open.scores))
+            })
+            .or_else(|| {
+                let closed = self.closed_windows.lock().unwrap();
+                closed.values().flatten().find_map(|past| find(&past.scores))
+            })
+            .or_else(|| {
+                let leaderboards = self.level_leaderboards.lock().unwrap();
+                leaderboards.values().find_map(|scores| find(scores))
+            })
+            .or_else(|| {
+                let featured = self.featured.lock().unwrap();
+                let archive = self.featured_archive.lock().unwrap();
+                let mut rotations = featured.iter().chain(archive.iter());
+                rotations.find_map(|rotation| find(&rotation.leaderboard))
+            })
+    }
+
+    /// Takes an entry off every leaderboard it is ranked on, past windows and rotations
+    /// included, and off the speed-run leaderboard
+    /// Returns the entry with the leaderboards it was on and its speed-run time, or None
+    /// when no leaderboard holds it
+    pub(crate) fn unrank_score(
+        &self,
+        entry_id: &str,
+    ) -> Option<(Score, Vec<Placement>, Option<u64>)> {
+        let mut found = None;
+        let mut placements = Vec::new();
+        let mut take = |scores: &mut Vec<Score>, placement: Placement| {
+            if let Some(index) = scores.iter().position(|score| score.id == entry_id) {
+                found = Some(scores.remove(index));
+                placements.push(placement);
+            }
+        };
+        take(
+            &mut self.leaderboard.lock().unwrap(),
+            Placement::Window { window: Window::AllTime, start: 0 },
+        );
+        for (window, open) in self.windows.lock().unwrap().iter_mut() {
+            let placement = Placement::Window { window: *window, start: open.start };
+            take(&mut open.scores, placement);
+        }
+        for (window, closed) in self.closed_windows.lock().unwrap().iter_mut() {
+            for past in closed.iter_mut() {
+                let placement = Placement::Window { window: *window, start: past.start };
+                take(&mut past.scores, placement);
+            }
+        }
+        for (level_id, scores) in self.level_leaderboards.lock().unwrap().iter_mut() {
+            take(scores, Placement::Level { level_id: level_id.clone() });
+        }
+        let mut featured = self.featured.lock().unwrap();
+        let mut archive = self.featured_archive.lock().unwrap();
+        for rotation in featured.iter_mut().chain(archive.iter_mut()) {
+            let placement = Placement::Featured {
+                level_id: rotation.level_id.clone(),
+                week: rotation.week,
+            };
+            take(&mut rotation.leaderboard, placement);
+        }
+        self.top_entries
+            .lock()
+            .unwrap()
+            .retain(|entry| entry.score.id != entry_id);
+        let mut speedruns = self.speedruns.lock().unwrap();
+        let speedrun = speedruns
+            .iter()
+            .position(|run| run.id == entry_id)
+            .map(|index| speedruns.remove(index).elapsed_ms);
+        found.map(|score| (score, placements, speedrun))
+    }
+
+    /// Ranks a restored entry again on the leaderboards it was taken off, as far as they
+    /// are still kept, and its time on the speed-run leaderboard
+    pub(crate) fn rerank_score(
+        &self,
+        score: &Score,
+        placements: &[Placement],
+        speedrun: Option<u64>,
+    ) {
+        for placement in placements {
+            match placement {
+                Placement::Window { window: Window::AllTime, .. } => {
+                    rank(&mut self.leaderboard.lock().unwrap(), score.clone());
+                }
+                Placement::Window { window, start } => {
+                    let mut windows = self.windows.lock().unwrap();
+                    let open = windows.get_mut(window).filter(|open| open.start == *start);
+                    if let Some(open) = open {
+                        rank(&mut open.scores, score.clone());
+                        continue;
+                    }
+                    let mut closed = self.closed_windows.lock().unwrap();
+                    let past = closed.get_mut(window).into_iter().flatten();
+                    if let Some(past) = past.into_iter().find(|past| past.start == *start) {
+                        rank(&mut past.scores, score.clone());
+                    }
+                }
+                Placement::Level { level_id } => {
+                    let mut leaderboards = self.level_leaderboards.lock().unwrap();
+                    rank(leaderboards.entry(level_id.clone()).or_default(), score.clone());
+                }
+                Placement::Featured { level_id, week } => {
+                    let mut featured = self.featured.lock().unwrap();
+                    let mut archive = self.featured_archive.lock().unwrap();
+                    let rotation = featured
+                        .iter_mut()
+                        .chain(archive.iter_mut())
+                        .find(|rotation| rotation.level_id == *level_id && rotation.week == *week);
+                    if let Some(rotation) = rotation {
+                        rank(&mut rotation.leaderboard, score.clone());
+                    }
+                }
+            }
+        }
+        if let Some(elapsed_ms) = speedrun {
+            let mut speedruns = self.speedruns.lock().unwrap();
+            speedruns.push(SpeedRun {
+                id: score.id.clone(),
+                name: score.name.clone(),
+                elapsed_ms,
+                modifiers: score.modifiers,
+            });
+            speedruns.sort_by_key(|entry| entry.elapsed_ms);
+            speedruns.truncate(10);
+        }
+    }
+
+    /// Keeps a hidden entry for restoring, dropping the oldest beyond `MAX_HIDDEN_SCORES`
+    pub(crate) fn keep_hidden(&self, hidden: HiddenScore) {
+        let mut hidden_scores = self.hidden_scores.lock().unwrap();
+        hidden_scores.push(hidden);
+        let excess = hidden_scores.len().saturating_sub(MAX_HIDDEN_SCORES);
+        hidden_scores.drain(..excess);
+    }
+
+    /// Archives the hourly, daily and weekly leaderboards whose window ended before `now`
+    /// and starts their next window; windows without scores are not archived
+    pub(crate) fn close_windows(&self, now: SystemTime) {
+        let mut windows = self.windows.lock().unwrap();
+        let mut closed = self.closed_windows.lock().unwrap();
+        for window in Window::CLOSING {
+            let open = windows
+                .entry(window)
+                .or_insert_with(|| OpenWindow::starting(window, now));
+            let start = window.start_of(now);
+            if open.start == start {
+                continue;
+            }
+            let ended = std::mem::replace(open, OpenWindow::starting(window, now));
+            if ended.scores.is_empty() {
+                continue;
+            }
+            let period = window.period().expect("closing windows have a period");
+            let past = closed.entry(window).or_default();
+            past.insert(
+                0,
+                ClosedWindow {
+                    start: ended.start,
+                    end: ended.start + period.as_secs(),
+                    scores: ended.scores,
+                },
+            );
+            past.truncate(MAX_CLOSED_WINDOWS);
+        }
+    }
+
+    /// Stores the replay of a game once it is over and issues its score token
+    /// Replays are keyed by session ID; a game revived by latency compensation
+    /// overwrites its earlier recording and token when it ends again
+    /// Replays take the visibility of their session; a replay the player deleted is not
+    /// recorded again
+    /// Practice games get no score token since they are not ranked; the token is not
+    /// published, so only the player receives it with `view::PlayerView`
+    pub(crate) fn record_if_finished(&self, session_id: &str, session: &mut Session) {
+        let game = &mut session.game;
+        if game.game_over && !session.replay_deleted {
+            let mut replays = self.replays.lock().unwrap();
+            if replays
+                .get(session_id)
+                .is_none_or(|replay| replay.ticks != game.tick)
+            {
+                let replay = Replay {
+                    duration_ms: session
+                        .created_at
+                        .elapsed()
+                        .ok()
+                        .map(|elapsed| elapsed.as_millis() as u64),
+                    visibility: session.visibility,
+                    owner: session.client_id.clone(),
+                    ..Replay::from_game(session_id, game)
+                };
+                replays.insert(session_id.to_string(), replay);
+                if !game.practice && !session.demo {
+                    // Practice games are left out, since rewinds repeat their moves
+                    self.heatmaps.record(game, &session.eaten_at);
+                    // Campaign stages are ranked by the campaign leaderboard alone
+                    if !session.campaign {
+                        // Only handed to the player, see `view::PlayerView`
+                        game.score_token = Some(self.score_signer.sign(session_id, game));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Changes who may watch a game and its replay, as the player may when submitting its score
+    fn set_visibility(&self, session_id: &str, visibility: Visibility) {
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(session_id) {
+            session.visibility = visibility;
+        }
+        if let Some(replay) = self.replays.lock().unwrap().get_mut(session_id) {
+            replay.visibility = visibility;
+        }
+    }
+
+    /// Replay a client may watch; private replays are only found for their player's client
+    pub(crate) fn viewable_replay(
+        &self,
+        replay_id: &str,
+        client_id: Option<&str>,
+    ) -> Option<Replay> {
+        self.replays
+            .lock()
+            .unwrap()
+            .get(replay_id)
+            .filter(|replay| replay.viewable_by(client_id))
+            .cloned()
+    }
+
+    /// Takes `ticks` ticks from the tick quota of a game
+    /// Fails with the time until the game may advance that far when it is over its quota
+    pub(crate) fn take_ticks(
+        &self,
+        session: &mut Session,
+        ticks: u32,
+    ) -> std::result::Result<(), Duration> {
+        let config = self.config();
+        session
+            .quota
+            .take(ticks, config.max_ticks_per_sec, config.tick_burst)
+            .inspect_err(|_| self.fairness.throttled())
+    }
+}
+
+/// Responds with 429 Too Many Requests to a game over its tick quota, saying when to retry
+fn over_quota(retry_after: Duration) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header(("Retry-After", retry_after.as_secs_f64().ceil() as u64))
+        .json(json!({
+            "error": "the game is advancing faster than its tick quota",
+            "retry_after_ms": retry_after.as_millis() as u64,
+        }))
+}
+
+/// Streams a copy of the game whenever it changes
+/// Ends after the game-over state has been emitted or when the session disappears
+pub fn watch_game(
+    data: web::Data<AppState>,
+    session_id: String,
+) -> impl Stream<Item = game::Game> {
+    stream::unfold(None, move |last: Option<game::Game>| {
+        let data = data.clone();
+        let session_id = session_id.clone();
+        async move {
+            if last.as_ref().is_some_and(|game| game.game_over) {
+                return None;
+            }
+            loop {
+                let current = data
+                    .sessions
+                    .lock()
+                    .unwrap()
+                    .get(&session_id)
+                    .map(|session| session.game.clone())?;
+                if last.as_ref() != Some(&current) {
+                    return Some((current.clone(), Some(current)));
+                }
+                actix_web::rt::time::sleep(WATCH_POLL_INTERVAL).await;
+            }
+        }
+    })
+}
+
+/// Serves the main HTML page
+async fn index() -> Result<fs::NamedFile> {
+    Ok(fs::NamedFile::open("static/index.html")?)
+}
+
+/// Whether an `If-None-Match` header names an entity tag, weak or strong, or is `*`
+fn none_match(req: &HttpRequest, etag: &str) -> bool {
+    req.headers()
+        .get("If-None-Match")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|tags| {
+            tags.split(',')
+                .map(|tag| tag.trim())
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+        })
+}
+
+/// Returns the current state of a specific game
+/// The `ETag` and `X-Game-Version` headers carry the version of the state; a client polling
+/// with `If-None-Match` gets 304 Not Modified without a body while the state is unchanged
+/// session_id: Unique identifier for the game instance
+async fn get_game_state(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    pass: web::Query<PassQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let sessions = data.sessions.lock().unwrap();
+    let Some(session) = sessions.get(&session_id.into_inner()) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if let Some(rejection) = room::check_watcher(&req, session, &pass) {
+        return rejection;
+    }
+    let version = view::version(&session.game);
+    let etag = format!("\"{}\"", version);
+    let unchanged = none_match(&req, &etag);
+    let mut response = if unchanged {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header(("ETag", etag.as_str()))
+        .insert_header((GAME_VERSION_HEADER, version.as_str()))
+        // Caches may keep the state but must ask whether it is still current
+        .insert_header(("Cache-Control", "no-cache"));
+    if unchanged {
+        return response.finish();
+    }
+    response.json(GameView::from(&session.game))
+}
+
+/// Query parameters of the long-polling endpoint
+#[derive(Deserialize)]
+struct WaitQuery {
+    // Tick of the state the client has
+    since_tick: u64,
+}
+
+/// Long-polling fallback of the realtime endpoints for clients that cannot open a WebSocket
+/// Returns the state of a game right away when its tick is not `since_tick`, and otherwise
+/// as soon as a new state is published; responds with 204 No Content when none was within
+/// 25 seconds, and the client polls again
+/// session_id: Unique identifier for the game instance
+async fn wait_for_game(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    query: web::Query<WaitQuery>,
+    pass: web::Query<PassQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let session_id = session_id.into_inner();
+    let mut updates = {
+        let sessions = data.sessions.lock().unwrap();
+        let Some(session) = sessions.get(&session_id) else {
+            return HttpResponse::NotFound().finish();
+        };
+        if let Some(rejection) = room::check_watcher(&req, session, &pass) {
+            return rejection;
+        }
+        if session.game.tick != query.since_tick {
+            return HttpResponse::Ok().json(GameView::from(&session.game));
+        }
+        session.subscribe()
+    };
+    let published = actix_web::rt::time::timeout(LONG_POLL_TIMEOUT, updates.recv())
+        .await
+        .is_ok();
+    let sessions = data.sessions.lock().unwrap();
+    match sessions.get(&session_id) {
+        Some(session) if published => HttpResponse::Ok().json(GameView::from(&session.game)),
+        Some(_) => HttpResponse::NoContent().finish(),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Query parameters of the ASCII render endpoint
+#[derive(Deserialize)]
+struct AsciiQuery {
+    // Wrap cells in ANSI color codes
+    #[serde(default)]
+    color: bool,
+}
+
+/// Renders a specific game as a text grid, e.g. for `curl` or `watch`
+/// session_id: Unique identifier for the game instance
+async fn get_game_ascii(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    query: web::Query<AsciiQuery>,
+    pass: web::Query<PassQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let sessions = data.sessions.lock().unwrap();
+    let Some(session) = sessions.get(&session_id.into_inner()) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if let Some(rejection) = room::check_watcher(&req, session, &pass) {
+        return rejection;
+    }
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(render::ascii(&session.game, query.color))
+}
+
+/// Describes the situation of a specific game in short sentences, for screen readers and
+/// voice-controlled clients, e.g. `Heading right. Food 3 left, 2 up. Wall 5 ahead, ...`
+/// session_id: Unique identifier for the game instance
+async fn describe_game(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    pass: web::Query<PassQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let sessions = data.sessions.lock().unwrap();
+    let Some(session) = sessions.get(&session_id.into_inner()) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if let Some(rejection) = room::check_watcher(&req, session, &pass) {
+        return rejection;
+    }
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(render::describe(&session.game))
+}
+
+/// Query parameters of the observation endpoint
+#[derive(Deserialize)]
+struct ObservationQuery {
+    // Grid of cell codes, one-hot channels, or flattened channels
+    #[serde(default)]
+    format: ObservationFormat,
+}
+
+/// Exports the board of a specific game as a grid, one-hot tensor or flat vector
+/// for reinforcement learning agents
+/// session_id: Unique identifier for the game instance
+async fn get_game_observation(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    query: web::Query<ObservationQuery>,
+    pass: web::Query<PassQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let sessions = data.sessions.lock().unwrap();
+    let Some(session) = sessions.get(&session_id.into_inner()) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if let Some(rejection) = room::check_watcher(&req, session, &pass) {
+        return rejection;
+    }
+    HttpResponse::Ok().json(observation::observe(&session.game, query.format))
+}
+
+/// Query parameters of the PNG frame endpoint
+#[cfg(feature = "rendering")]
+#[derive(Deserialize)]
+struct FrameQuery {
+    // Edge length of one board cell in pixels
+    #[serde(default = "default_cell_size")]
+    cell_size: u32,
+    // Name of a built-in color theme
+    #[serde(default = "default_theme")]
+    theme: String,
+}
+
+#[cfg(feature = "rendering")]
+fn default_cell_size() -> u32 {
+    20
+}
+
+#[cfg(feature = "rendering")]
+fn default_theme() -> String {
+    "classic".to_string()
+}
+
+/// Renders a specific game as a PNG image
+/// session_id: Unique identifier for the game instance
+#[cfg(feature = "rendering")]
+async fn get_game_frame(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    query: web::Query<FrameQuery>,
+    pass: web::Query<PassQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let Some(theme) = render::Theme::by_name(&query.theme) else {
+        return HttpResponse::BadRequest().json(json!({ "error": "unknown theme" }));
+    };
+    // Copy the game so rendering happens outside the lock
+    let game = {
+        let sessions = data.sessions.lock().unwrap();
+        let Some(session) = sessions.get(&session_id.into_inner()) else {
+            return HttpResponse::NotFound().finish();
+        };
+        if let Some(rejection) = room::check_watcher(&req, session, &pass) {
+            return rejection;
+        }
+        session.game.clone()
+    };
+    let cell_size = render::clamp_cell_size(&game, query.cell_size);
+    match render::png(&render::frame(&game, cell_size, &theme)) {
+        Ok(bytes) => HttpResponse::Ok()
+            .content_type("image/png")
+            .insert_header(("Cache-Control", "no-store"))
+            .body(bytes),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Longest prediction a client may request, in ticks
+pub(crate) const MAX_PREDICT_TICKS: u32 = 10;
+
+/// Query parameters of the prediction endpoint
+#[derive(Deserialize)]
+struct PredictQuery {
+    // Number of ticks to simulate ahead
+    #[serde(default = "default_predict_ticks")]
+    ticks: u32,
+}
+
+fn default_predict_ticks() -> u32 {
+    3
+}
+
+/// Returns the states of the next few ticks under the current direction without committing them
+/// Lets laggy clients extrapolate rendering between authoritative updates
+/// session_id: Unique identifier for the game instance
+async fn predict_game(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    query: web::Query<PredictQuery>,
+    pass: web::Query<PassQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let sessions = data.sessions.lock().unwrap();
+    let Some(session) = sessions.get(&session_id.into_inner()) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if let Some(rejection) = room::check_watcher(&req, session, &pass) {
+        return rejection;
+    }
+    let states = session.game.predict(query.ticks.min(MAX_PREDICT_TICKS));
+    let states: Vec<GameView> = states.iter().map(GameView::from).collect();
+    HttpResponse::Ok().json(json!({ "tick": session.game.tick, "states": states }))
+}
+
+/// Body of a direction change: either a bare direction, or a direction
+/// together with the tick of the state the client was looking at
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum DirectionInput {
+    Plain(game::Direction),
+    Timed { direction: game::Direction, tick: u64 },
+}
+
+impl DirectionInput {
+    /// Applies the direction change to a snake of a game: 0 for the first snake, 1 for the
+    /// partner of a co-op game, whose inputs are never timed
+    pub(crate) fn apply(self, game: &mut game::Game, snake: u8) -> game::InputTiming {
+        match self {
+            DirectionInput::Plain(direction) | DirectionInput::Timed { direction, .. }
+                if snake != 0 =>
+            {
+                game.queue_partner_direction(direction)
+            }
+            // An untimed input applies to the upcoming tick
+            DirectionInput::Plain(direction) => game.queue_direction_at(direction, game.tick),
+            DirectionInput::Timed { direction, tick } => game.queue_direction_at(direction, tick),
+        }
+    }
+}
+
+/// Responds to a direction input with how it was applied and the state of the input queue
+/// of the snake it was for
+/// An input dropped because the queue is full gets 429 Too Many Requests: a tick takes one
+/// input, so a client sending more than the queue holds is sending faster than the game runs
+fn input_response(timing: game::InputTiming, game: &game::Game, snake: u8) -> HttpResponse {
+    let length = match &game.partner {
+        Some(partner) if snake != 0 => partner.input_queue.len(),
+        _ => game.input_queue.len(),
+    };
+    let body = json!({
+        "timing": timing,
+        "tick": game.tick,
+        "queue": { "length": length, "capacity": game::MAX_QUEUED_INPUTS },
+    });
+    if timing == game::InputTiming::QueueFull {
+        HttpResponse::TooManyRequests().json(body)
+    } else {
+        HttpResponse::Ok().json(body)
+    }
+}
+
+/// Query parameters of the direction endpoint
+#[derive(Deserialize)]
+struct DirectionQuery {
+    // Snake to steer: 0 for the first, 1 for the partner of a co-op game
+    #[serde(default)]
+    snake: u8,
+}
+
+/// Updates the direction of the snake for a specific game
+/// Timestamped inputs that arrive slightly late are applied at the tick the client saw
+/// In co-op games `?snake=1` steers the partner; its inputs are rejected in other games
+/// Only the player may steer, see `check_player`
+/// session_id: Unique identifier for the game instance
+/// direction: New direction for the snake
+async fn update_direction(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    query: web::Query<DirectionQuery>,
+    direction: web::Json<DirectionInput>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let session_id = session_id.into_inner();
+    let mut sessions = data.sessions.lock().unwrap();
+    if let Some(session) = sessions.get_mut(&session_id) {
+        if let Some(rejection) = check_player(&req, session) {
+            return rejection;
+        }
+        session.touch();
+        let timing = direction.into_inner().apply(&mut session.game, query.snake);
+        data.record_if_finished(&session_id, session);
+        input_response(timing, &session.game, query.snake)
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+/// Body of a relative turn: either a bare turn, or a turn together with the tick of the
+/// state the client was looking at
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TurnInput {
+    Plain(game::Turn),
+    Timed { turn: game::Turn, tick: u64 },
+}
+
+/// Turns the snake of a specific game left or right of its heading, or keeps it straight
+/// An alternative to absolute directions for voice commands, single-switch devices and bots;
+/// timestamped turns are relative to the heading at the tick the client saw
+/// Only the player may turn, see `check_player`
+/// session_id: Unique identifier for the game instance
+/// turn: `Left`, `Right` or `Straight`
+async fn update_turn(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    turn: web::Json<TurnInput>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let session_id = session_id.into_inner();
+    let mut sessions = data.sessions.lock().unwrap();
+    if let Some(session) = sessions.get_mut(&session_id) {
+        if let Some(rejection) = check_player(&req, session) {
+            return rejection;
+        }
+        session.touch();
+        let game = &mut session.game;
+        let timing = match turn.into_inner() {
+            // An untimed turn applies to the upcoming tick
+            TurnInput::Plain(turn) => game.queue_turn_at(turn, game.tick),
+            TurnInput::Timed { turn, tick } => game.queue_turn_at(turn, tick),
+        };
+        data.record_if_finished(&session_id, session);
+        input_response(timing, &session.game, 0)
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+/// Most ticks a single debug update may step
+pub(crate) const MAX_UPDATE_STEPS: u32 = 100;
+
+/// Query parameters of the update endpoint
+#[derive(Deserialize)]
+struct UpdateQuery {
+    // Number of ticks to step, reporting every intermediate state
+    steps: Option<u32>,
+    // Compute the steps on a copy without committing them
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// State and events of one tick of a debug update
+#[derive(Serialize)]
+struct UpdateStep {
+    // Events that happened during the tick
+    events: Vec<game::GameEvent>,
+    // State after the tick
+    #[serde(serialize_with = "view::serialize_player_game")]
+    state: game::Game,
+}
+
+/// Updates the game state (moves snake, checks collisions, etc.)
+/// With `steps` or `dry_run` set, responds with the state and events of every tick instead
+/// of the final state; a dry run leaves the game unchanged
+/// Responds with 429 Too Many Requests when the game would advance faster than its tick quota
+/// Only the player may advance the game, see `check_player`
+/// session_id: Unique identifier for the game instance
+async fn update_game(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    query: web::Query<UpdateQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let session_id = session_id.into_inner();
+    let mut sessions = data.sessions.lock().unwrap();
+    let Some(session) = sessions.get_mut(&session_id) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if let Some(rejection) = check_player(&req, session) {
+        return rejection;
+    }
+    session.touch();
+    if !query.dry_run {
+        let ticks = query.steps.map_or(1, |steps| steps.clamp(1, MAX_UPDATE_STEPS));
+        if let Err(retry_after) = data.take_ticks(session, ticks) {
+            return over_quota(retry_after);
+        }
+    }
+    if query.steps.is_none() && !query.dry_run {
+        session.tick();
+        data.record_if_finished(&session_id, session);
+        return HttpResponse::Ok().json(PlayerView::from(&session.game));
+    }
+
+    let count = query.steps.unwrap_or(1).clamp(1, MAX_UPDATE_STEPS);
+    let tick = session.game.tick;
+    let mut steps = Vec::new();
+    if query.dry_run {
+        let mut game = session.game.clone();
+        game.history.clear();
+        game.history_len = 0;
+        while steps.len() < count as usize && !game.game_over {
+            let events = game.update();
+            steps.push(UpdateStep {
+                events,
+                state: game.clone(),
+            });
+        }
+    } else {
+        while steps.len() < count as usize && !session.game.game_over {
+            let events = session.tick();
+            data.record_if_finished(&session_id, session);
+            steps.push(UpdateStep {
+                events,
+                state: session.game.clone(),
+            });
+        }
+    }
+    HttpResponse::Ok().json(json!({ "tick": tick, "dry_run": query.dry_run, "steps": steps }))
+}
+
+/// Query parameters of the rewind endpoint
+#[derive(Deserialize)]
+struct RewindQuery {
+    // Number of ticks to step back
+    #[serde(default = "default_rewind_ticks")]
+    ticks: u64,
+}
+
+fn default_rewind_ticks() -> u64 {
+    1
+}
+
+/// Steps a practice game back in time, as far as its snapshot history allows
+/// Responds with the rewound state, or 403 Forbidden for games not in practice mode and
+/// requests not from the player, see `check_player`
+/// session_id: Unique identifier for the game instance
+async fn rewind_game(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    query: web::Query<RewindQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let mut sessions = data.sessions.lock().unwrap();
+    let Some(session) = sessions.get_mut(&session_id.into_inner()) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if let Some(rejection) = check_player(&req, session) {
+        return rejection;
+    }
+    if !session.game.practice {
+        return HttpResponse::Forbidden()
+            .json(json!({ "error": "only practice games can be rewound" }));
+    }
+    session.touch();
+    let ticks = session.game.rewind(query.ticks);
+    session.publish(vec![game::GameEvent::Rewound { ticks }]);
+    HttpResponse::Ok().json(GameView::from(&session.game))
+}
+
+/// Query parameters of the AI move endpoint
+#[cfg(feature = "ai")]
+#[derive(Deserialize)]
+struct AiMoveQuery {
+    // Strategy choosing the move; the lookahead for games created with an AI
+    // personality, otherwise greedy
+    strategy: Option<ai::Strategy>,
+    // Whether to answer with a ticket at once and leave the move to the next tick
+    #[serde(default)]
+    defer: bool,
+}
+
+/// Decides an AI move on the blocking thread pool once an AI slot is free, without holding
+/// the session lock, and stores it on the session's ticket
+/// Returns false when the session is gone or the ticket was replaced meanwhile
+#[cfg(feature = "ai")]
+async fn run_ai_ticket(
+    data: web::Data<AppState>,
+    session_id: String,
+    ticket: String,
+    game: game::Game,
+    strategy: ai::Strategy,
+    personality: ai::Personality,
+) -> std::result::Result<bool, actix_web::error::BlockingError> {
+    let config = data.config().ai.clone();
+    let _slot = data.fairness.ai_slot().await;
+    let decision =
+        web::block(move || ai::decide(&game, strategy, personality, &config)).await?;
+    if decision.exhausted {
+        tracing::debug!(
+            ?strategy,
+            nodes = decision.nodes,
+            elapsed_ms = decision.elapsed.as_millis() as u64,
+            "AI decision cut short by its budget"
+        );
+    }
+    let mut sessions = data.sessions.lock().unwrap();
+    Ok(sessions
+        .get_mut(&session_id)
+        .is_some_and(|session| session.fulfill_ai_ticket(&ticket, decision.direction)))
+}
+
+/// Makes an AI move for a specific game
+/// The strategy decides within its configured time and node budget on the blocking
+/// thread pool, so AI games hold neither the session lock nor an HTTP worker meanwhile
+/// Responds with 409 Conflict when the game advanced or another AI move was requested
+/// before the decision was done, and with 429 Too Many Requests when the game is over its
+/// tick quota
+/// With `defer=true` responds at once with 202 Accepted and the ticket of the decision;
+/// the next tick of the game plays the move if it is done by then and the game has not
+/// advanced since
+/// Only the player may ask for AI moves, see `check_player`
+/// session_id: Unique identifier for the game instance
+#[cfg(feature = "ai")]
+async fn ai_move(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    query: web::Query<AiMoveQuery>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    let session_id = session_id.into_inner();
+    let (ticket, game, personality) = {
+        let mut sessions = data.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&session_id) else {
+            return Ok(HttpResponse::NotFound().finish());
+        };
+        if let Some(rejection) = check_player(&req, session) {
+            return Ok(rejection);
+        }
+        session.touch();
+        if let Err(retry_after) = data.take_ticks(session, 1) {
+            return Ok(over_quota(retry_after));
+        }
+        let (ticket, game) = session.issue_ai_ticket();
+        (ticket, game, session.ai_personality)
+    };
+    let tick = game.tick;
+    let strategy = query.strategy.unwrap_or(match personality {
+        Some(_) => ai::Strategy::Lookahead,
+        None => ai::Strategy::Greedy,
+    });
+    #[cfg(feature = "onnx")]
+    if strategy == ai::Strategy::Neural && data.config().ai.model.policy.is_none() {
+        return Ok(HttpResponse::BadRequest()
+            .json(json!({ "error": "no policy model configured" })));
+    }
+    let personality = personality.unwrap_or_default();
+    let run = run_ai_ticket(
+        data.clone(),
+        session_id.clone(),
+        ticket.clone(),
+        game,
+        strategy,
+        personality,
+    );
+    if query.defer {
+        actix_web::rt::spawn(run);
+        return Ok(HttpResponse::Accepted().json(json!({ "ticket": ticket, "tick": tick })));
+    }
+    let fulfilled = run.await?;
+    let mut sessions = data.sessions.lock().unwrap();
+    let Some(session) = sessions.get_mut(&session_id) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    if !fulfilled || session.game.tick != tick {
+        return Ok(HttpResponse::Conflict()
+            .json(json!({ "error": "the game advanced while the AI was deciding" })));
+    }
+    session.tick();
+    data.record_if_finished(&session_id, session);
+    Ok(HttpResponse::Ok().json(PlayerView::from(&session.game)))
+}
+
+/// Returns the recording of a finished game
+/// Private replays are only returned to their player's client, and not found for others
+/// replay_id: Identifier of the replay (the session ID of the recorded game)
+async fn get_replay(
+    req: HttpRequest,
+    replay_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let client_id = identity::client_id(&req);
+    if let Some(replay) = data.viewable_replay(&replay_id, client_id.as_deref()) {
+        HttpResponse::Ok().json(replay)
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+/// Deletes the recording of a finished game at the request of its player
+/// Responds with 401 without client cookie and 404 when the replay does not exist or was
+/// recorded for another client; the game is not recorded again
+/// replay_id: Identifier of the replay (the session ID of the recorded game)
+async fn delete_replay(
+    req: HttpRequest,
+    replay_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let Some(client_id) = identity::client_id(&req) else {
+        return HttpResponse::Unauthorized().json(json!({ "error": "missing client cookie" }));
+    };
+    let replay_id = replay_id.into_inner();
+    // Lock sessions before replays, as `record_if_finished` does
+    let mut sessions = data.sessions.lock().unwrap();
+    let mut replays = data.replays.lock().unwrap();
+    if replays
+        .get(&replay_id)
+        .is_none_or(|replay| replay.owner.as_deref() != Some(client_id.as_str()))
+    {
+        return HttpResponse::NotFound().finish();
+    }
+    replays.remove(&replay_id);
+    if let Some(session) = sessions.get_mut(&replay_id) {
+        session.replay_deleted = true;
+    }
+    HttpResponse::NoContent().finish()
+}
+
+/// Re-simulates a replay and renders it as an animated GIF
+/// replay_id: Identifier of the replay (the session ID of the recorded game)
+#[cfg(feature = "rendering")]
+async fn get_replay_animation(
+    req: HttpRequest,
+    replay_id: web::Path<String>,
+    query: web::Query<FrameQuery>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    let Some(theme) = render::Theme::by_name(&query.theme) else {
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": "unknown theme" })));
+    };
+    let client_id = identity::client_id(&req);
+    let Some(replay) = data.viewable_replay(&replay_id, client_id.as_deref()) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    // Simulation and encoding are CPU-bound, keep them off the async workers
+    let cell_size = query.cell_size;
+    let bytes = web::block(move || replay.animation_gif(cell_size, &theme))
+        .await?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type("image/gif").body(bytes))
+}
+
+/// Optional body of the new game endpoint
+#[derive(Default, Deserialize)]
+pub(crate) struct NewGameOptions {
+    // Board variant, a classic walled board by default
+    #[serde(default)]
+    pub(crate) topology: TopologySpec,
+    // Challenge modifiers, none by default
+    #[serde(default)]
+    pub(crate) modifiers: game::Modifiers,
+    // Food behavior, static food by default
+    #[serde(default)]
+    pub(crate) food_behavior: game::FoodBehavior,
+    // Lifetime of uneaten food, unlimited by default
+    #[serde(default)]
+    pub(crate) food_expiry: Option<game::FoodExpiry>,
+    // Combo scoring rules, no combos by default
+    #[serde(default)]
+    pub(crate) combo: Option<game::ComboScoring>,
+    // Growth per food item, one segment per pickup by default
+    #[serde(default)]
+    pub(crate) growth: game::Growth,
+    // Length, position and direction of the initial snake, one segment at the center
+    // heading right by default
+    #[serde(default)]
+    pub(crate) start: game::Start,
+    // Ticks of the countdown before the snake starts moving, `countdown_ticks` of the
+    // config by default
+    #[serde(default)]
+    pub(crate) countdown: Option<u32>,
+    // Co-op game: a second snake steered by a second player, sharing the score
+    #[serde(default)]
+    pub(crate) coop: bool,
+    // Practice game that can be rewound but is not ranked
+    #[serde(default)]
+    pub(crate) practice: bool,
+    // Score multiplier zones of the level, none by default
+    #[serde(default)]
+    pub(crate) zones: Vec<game::Zone>,
+    // Teleport portals of the level, none by default
+    #[serde(default)]
+    pub(crate) portals: Vec<game::Portal>,
+    // Moving hazards of the level, none by default
+    #[serde(default)]
+    pub(crate) hazards: Vec<game::Hazard>,
+    // Ice and speed tiles of the level, none by default
+    #[serde(default)]
+    pub(crate) tiles: Vec<game::Tile>,
+    // One-way gates of the level, none by default
+    #[serde(default)]
+    pub(crate) gates: Vec<game::Gate>,
+    // Keys and doors objective of the level, none by default
+    #[serde(default)]
+    pub(crate) objective: Option<game::Objective>,
+    // Custom level to play, replacing the board, start, zones, portals, hazards, tiles, gates
+    // and objective options
+    #[serde(default)]
+    pub(crate) level: Option<String>,
+    // Personality of the lookahead AI steering the game, none by default
+    #[serde(default)]
+    pub(crate) ai: Option<ai::Personality>,
+    // External bot the server asks for a move every tick, none by default
+    #[cfg(feature = "ai")]
+    #[serde(default)]
+    pub(crate) bot: Option<BotCallback>,
+    // Who may spectate the game and watch its replay, anyone by default
+    #[serde(default)]
+    pub(crate) visibility: Visibility,
+    // Password spectators give to join the game's room, none by default
+    #[serde(default)]
+    pub(crate) password: Option<String>,
+    // Seed of the game's random generator, random by default; only the server sets it
+    #[serde(skip)]
+    pub(crate) seed: Option<u64>,
+}
+
+/// Creates a new game instance and returns its session ID and token
+/// Issues a client cookie on the first game of a browser
+/// Asks for a solved challenge while proof of work is configured, see `proof_of_work::check`
+async fn new_game(
+    req: HttpRequest,
+    options: Option<web::Json<NewGameOptions>>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Some(rejection) = proof_of_work::check(&req, &data) {
+        return Ok(rejection);
+    }
+    let options = options.map(web::Json::into_inner).unwrap_or_default();
+    let (client_id, is_new) = identity::client_id_or_new(&req);
+    let level_id = options.level.clone();
+    #[cfg(feature = "ai")]
+    let has_bot = options.bot.is_some();
+    #[cfg(feature = "ai")]
+    if let Some(bot) = &options.bot {
+        let config = data.config();
+        if let Err(e) = bot.resolve(&config).await {
+            return Ok(HttpResponse::BadRequest().json(json!({ "error": e })));
+        }
+        let playing = data
+            .sessions
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|session| {
+                session.is_bot_game()
+                    && !session.game.game_over
+                    && session.belongs_to(Some(&client_id))
+            })
+            .count();
+        if playing >= config.max_bot_games_per_client {
+            return Ok(HttpResponse::TooManyRequests()
+                .json(json!({ "error": "too many bot games at once" })));
+        }
+    }
+    let (session_id, token) = match data.create_game(Some(client_id.clone()), options) {
+        Ok(created) => created,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(json!({ "error": e }))),
+    };
+    #[cfg(feature = "ai")]
+    if has_bot {
+        actix_web::rt::spawn(bot::play(data.clone(), session_id.clone()));
+    }
+    if let Some(level_id) = level_id {
+        // Keep the play count of the level
+        level::persist(&data, &level_id).await?;
+    }
+    let mut response = HttpResponse::Ok();
+    if is_new {
+        response.cookie(identity::cookie(&data, &client_id));
+    }
+    Ok(response.json(json!({ "session_id": session_id, "token": token })))
+}
+
+/// Lists the sessions created by the calling browser, most recently played first
+async fn my_games(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    let client_id = identity::client_id(&req);
+    let sessions = data.sessions.lock().unwrap();
+    let mut games: Vec<_> = sessions
+        .iter()
+        .filter(|(_, session)| session.belongs_to(client_id.as_deref()))
+        .collect();
+    games.sort_by_key(|(_, session)| std::cmp::Reverse(session.last_seen));
+    let games: Vec<_> = games
+        .into_iter()
+        .map(|(session_id, session)| {
+            json!({
+                "session_id": session_id,
+                "score": session.game.score,
+                "status": if session.game.game_over { "finished" } else { "active" },
+                "age_secs": session.created_at.elapsed().map_or(0, |age| age.as_secs()),
+                "idle_secs": session.last_seen.elapsed().as_secs(),
+            })
+        })
+        .collect();
+    HttpResponse::Ok().json(games)
+}
+
+/// Query parameters of the resume endpoint
+#[derive(Deserialize)]
+struct ResumeQuery {
+    // Sequence number of the last event the client received
+    #[serde(default)]
+    since: u64,
+}
+
+/// Reads the session token from the request header
+pub(crate) fn session_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(SESSION_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Checks that a request comes from the player of a game: with the session token in the
+/// `X-Session-Token` header or from the browser that created the game
+/// Returns 403 Forbidden for anyone else, e.g. spectators or a host who handed the room over
+pub(crate) fn check_player(req: &HttpRequest, session: &Session) -> Option<HttpResponse> {
+    if session.authorizes(session_token(req))
+        || session.belongs_to(identity::client_id(req).as_deref())
+    {
+        None
+    } else {
+        Some(
+            HttpResponse::Forbidden()
+                .json(json!({ "error": "only the player may change the game" })),
+        )
+    }
+}
+
+/// Resumes a session after a disconnect
+/// Requires the current session token or the cookie of the browser that created
+/// the session, issues a new token and returns the full state together with the
+/// events missed since `since`
+/// session_id: Unique identifier for the game instance
+async fn resume_session(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    query: web::Query<ResumeQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let session_id = session_id.into_inner();
+    let mut sessions = data.sessions.lock().unwrap();
+    let Some(session) = sessions.get_mut(&session_id) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if !session.authorizes(session_token(&req))
+        && !session.belongs_to(identity::client_id(&req).as_deref())
+    {
+        return HttpResponse::Unauthorized().json(json!({ "error": "invalid session token" }));
+    }
+    session.touch();
+    let token = session.rotate_token();
+    HttpResponse::Ok().json(json!({
+        "session_id": session_id,
+        "token": token,
+        "state": PlayerView::from(&session.game),
+        "events": session.events_since(query.since),
+        "last_seq": session.last_seq(),
+    }))
+}
+
+/// Facts about the game a score was submitted for, taken from its session
+struct ScoredGame {
+    modifiers: game::Modifiers,
+    practice: bool,
+    campaign: bool,
+    challenge: Option<u64>,
+    coop: bool,
+    speedrun: Option<u64>,
+    level: Option<String>,
+    completion: Option<game::Completion>,
+    client_id: Option<String>,
+    played: Option<Played>,
+    bot: bool,
+}
+
+/// Submits a new score to the all-time, daily and weekly leaderboards, and games of the
+/// current hour's challenge to the hourly one
+/// Keeps only top 10 scores; responds with the all-time leaderboard
+/// The score must come with the `token` issued at the end of its game: 403 Forbidden for
+/// missing, forged or mismatching tokens and 409 Conflict when it was already submitted
+/// Names are cleaned and checked by the configured name filter: 400 Bad Request for
+/// invalid names and 409 Conflict for names claimed by another player
+/// Scores submitted too often from one IP address or client, earned implausibly fast or
+/// higher than the board allows are held for review: 202 Accepted with the reasons, or
+/// 429 Too Many Requests while `MAX_PENDING_SCORES` are waiting, after which the score may be
+/// submitted again
+/// Modifiers and co-op play are recorded from the token's session; tokens whose session
+/// has been removed are refused with 403 Forbidden, since what was played is unknown
+/// Games that reached the `SPEEDRUN_APPLES` split also enter the speed-run leaderboard
+/// Stages of campaigns are not ranked here, see `campaign::leaderboard`, and games played
+/// by external bots not at all
+/// Games on custom levels are ranked on the level's own leaderboard instead, those that
+/// completed the level's objective by how fast they did
+/// Asks for a solved challenge while proof of work is configured, see `proof_of_work::check`
+async fn submit_score(
+    req: HttpRequest,
+    score: web::Json<Score>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    if let Some(rejection) = proof_of_work::check(&req, &data) {
+        return rejection;
+    }
+    let mut score = score.into_inner();
+    let Some(claim) = score
+        .token
+        .as_deref()
+        .and_then(|token| data.score_signer.verify(token))
+    else {
+        return HttpResponse::Forbidden().json(json!({ "error": "missing or invalid score token" }));
+    };
+    if claim.score != score.score {
+        return HttpResponse::Forbidden()
+            .json(json!({ "error": "score does not match its token" }));
+    }
+    let game = {
+        let sessions = data.sessions.lock().unwrap();
+        sessions.get(&claim.session_id).map(|session| ScoredGame {
+            modifiers: session.game.modifiers,
+            practice: session.game.practice,
+            campaign: session.campaign,
+            challenge: session.challenge,
+            coop: session.game.partner.is_some(),
+            speedrun: session.game.split_time(SPEEDRUN_APPLES),
+            level: session.game.level.clone(),
+            completion: session.game.completion,
+            client_id: session.client_id.clone(),
+            bot: session.is_bot_game(),
+            played: Some(Played {
+                elapsed: session.created_at.elapsed().unwrap_or_default(),
+                max_score: session.game.max_score(),
+            }),
+        })
+    };
+    let Some(game) = game else {
+        return HttpResponse::Forbidden()
+            .json(json!({ "error": "the game of this score token is no longer available" }));
+    };
+    if game.practice {
+        return HttpResponse::Forbidden().json(json!({ "error": "practice games are not ranked" }));
+    }
+    if game.campaign {
+        return HttpResponse::Forbidden()
+            .json(json!({ "error": "campaign games are ranked by the stage they reach" }));
+    }
+    if game.bot {
+        return HttpResponse::Forbidden().json(json!({ "error": "bot games are not ranked" }));
+    }
+    score.name = match data.config().name_filter.clean(&score.name) {
+        Ok(name) => name,
+        Err(e) => return HttpResponse::BadRequest().json(e.to_json()),
+    };
+    if let Err(e) = data.claim_name(&score.name, game.client_id.clone()) {
+        return HttpResponse::Conflict().json(e.to_json());
+    }
+    {
+        // Tokens that expired cannot be submitted again, so their sessions are forgotten
+        let mut submitted = data.submitted_scores.lock().unwrap();
+        let now = unix_now();
+        submitted.retain(|_, expires_at| *expires_at > now);
+        if submitted
+            .insert(claim.session_id.clone(), claim.expires_at)
+            .is_some()
+        {
+            return HttpResponse::Conflict().json(json!({ "error": "score already submitted" }));
+        }
+    }
+    if let Some(visibility) = score.visibility {
+        data.set_visibility(&claim.session_id, visibility);
+    }
+    score.id = Uuid::new_v4().to_string();
+    score.verified = data.verify_score(&claim);
+    score.session_id = Some(claim.session_id);
+    score.modifiers = game.modifiers;
+    score.level = game.level;
+    score.coop = game.coop;
+    score.completion = game.completion;
+    score.challenge = game.challenge;
+
+    // Hold suspicious scores for review instead of ranking them
+    let rotation_hours = data.config().anonymous_id_rotation_hours;
+    let player = data.anonymous_ids.of(&req, rotation_hours);
+    let network = data.anonymous_ids.network_of(&req, rotation_hours);
+    let reasons = data.suspicions(&score, &network, game.client_id.as_deref(), game.played);
+    if !reasons.is_empty() {
+        let mut queue = data.review_queue.lock().unwrap();
+        if queue.len() >= MAX_PENDING_SCORES {
+            drop(queue);
+            // The score may be submitted again once the queue has room
+            if let Some(session_id) = &score.session_id {
+                data.submitted_scores.lock().unwrap().remove(session_id);
+            }
+            return HttpResponse::TooManyRequests()
+                .json(json!({ "error": "too many scores waiting for review" }));
+        }
+        tracing::warn!(name = %score.name, score = score.score, "score held for review");
+        let pending = PendingScore {
+            id: Uuid::new_v4().to_string(),
+            score,
+            speedrun: game.speedrun,
+            reasons: reasons.clone(),
+            submitted_at: unix_now(),
+            player,
+            client_id: game.client_id,
+        };
+        queue.push(pending);
+        return HttpResponse::Accepted().json(json!({ "status": "in_review", "reasons": reasons }));
+    }
+    data.anonymous_ids
+        .record_score(&player, score.score, rotation_hours);
+    HttpResponse::Ok().json(data.publish_score(score, game.speedrun))
+}
+
+/// Adds a score to a leaderboard, keeping only the top 10 scores
+/// Games that completed their level's objective rank above the others, fastest first and
+/// then with the fewest turns
+fn rank(leaderboard: &mut Vec<Score>, score: Score) {
+    leaderboard.push(score);
+    // Sort completed games by time and turns, then the others by score in descending order
+    leaderboard.sort_by_key(|entry| {
+        let completion = entry
+            .completion
+            .map(|completion| (completion.ticks, completion.turns));
+        let score = std::cmp::Reverse(entry.score);
+        (completion.is_none(), completion, score)
+    });
+    // Keep only top 10 scores
+    if leaderboard.len() > 10 {
+        leaderboard.truncate(10);
+    }
+}
+
+/// Gets the fastest times to `SPEEDRUN_APPLES` food items, fastest first
+async fn get_speedruns(data: web::Data<AppState>) -> HttpResponse {
+    let speedruns = data.speedruns.lock().unwrap();
+    HttpResponse::Ok().json(&*speedruns)
+}
+
+/// Query parameters selecting a leaderboard window
+#[derive(Deserialize)]
+pub struct WindowQuery {
+    // Hourly, daily, weekly or all-time leaderboard
+    #[serde(default)]
+    pub(crate) window: Window,
+    // Whether to leave out entries without a verified replay
+    #[serde(default)]
+    pub(crate) verified: bool,
+}
+
+/// Entries of a leaderboard, only those with a verified replay when `verified_only` is set
+pub(crate) fn filter_verified(scores: &[Score], verified_only: bool) -> Vec<&Score> {
+    scores
+        .iter()
+        .filter(|score| score.verified || !verified_only)
+        .collect()
+}
+
+/// Returns the leaderboard of the current hourly, daily or weekly window, or of all time
+/// With `verified=true` only entries whose replay was verified are listed
+async fn get_leaderboard(
+    query: web::Query<WindowQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let scores = data.leaderboard_of(query.window);
+    HttpResponse::Ok().json(filter_verified(&scores, query.verified))
+}
+
+/// Lists the winners and final leaderboards of past hourly, daily or weekly windows, newest
+/// first
+async fn get_winners(query: web::Query<WindowQuery>, data: web::Data<AppState>) -> HttpResponse {
+    if query.window == Window::AllTime {
+        return HttpResponse::BadRequest()
+            .json(json!({ "error": "the all-time leaderboard never closes" }));
+    }
+    let closed = data.closed_windows.lock().unwrap();
+    let past: Vec<_> = closed
+        .get(&query.window)
+        .into_iter()
+        .flatten()
+        .map(|window| {
+            json!({
+                "start": window.start,
+                "end": window.end,
+                "winner": window.scores.first(),
+                "leaderboard": window.scores,
+            })
+        })
+        .collect();
+    HttpResponse::Ok().json(past)
+}
+
+/// Removes sessions abandoned for longer than the grace period, with their checkpoints,
+/// and team matches idle for as long
+pub(crate) async fn collect_sessions(data: web::Data<AppState>) -> std::result::Result<(), String> {
+    let removed = data.collect_abandoned_sessions();
+    if !removed.is_empty() {
+        tracing::info!(removed = removed.len(), "collected abandoned sessions");
+    }
+    #[cfg(feature = "multiplayer")]
+    {
+        let grace = Duration::from_secs(data.config().session_grace_secs);
+        let idle = data.team_matches.collect_idle(grace);
+        if idle > 0 {
+            tracing::info!(removed = idle, "collected idle team matches");
+        }
+    }
+    #[cfg(feature = "persistence")]
+    if let Some(store) = &data.checkpoints {
+        return removed
+            .iter()
+            .try_for_each(|session_id| store.remove(session_id))
+            .map_err(|e| e.to_string());
+    }
+    Ok(())
+}
+
+/// Checkpoints active games that advanced far enough since their last checkpoint
+/// Checkpoints of finished games are deleted; their replay is kept in memory instead
+#[cfg(feature = "persistence")]
+pub(crate) async fn checkpoint(data: web::Data<AppState>) -> std::result::Result<(), String> {
+    if data.checkpoints.is_none() {
+        return Ok(());
+    }
+    let (due, obsolete) = data.due_checkpoints();
+    if due.is_empty() && obsolete.is_empty() {
+        return Ok(());
+    }
+    let store_data = data.clone();
+    let result = web::block(move || {
+        let store = store_data.checkpoints.as_ref().expect("checked above");
+        due.iter().try_for_each(|checkpoint| store.save(checkpoint))?;
+        obsolete
+            .iter()
+            .try_for_each(|session_id| store.remove(session_id))
+    })
+    .await;
+    match result {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Checkpoint of the current progress of a session
+#[cfg(feature = "persistence")]
+fn checkpoint_of(session_id: &str, session: &Session) -> Checkpoint {
+    Checkpoint {
+        ai_personality: session.ai_personality,
+        #[cfg(feature = "ai")]
+        bot: session.bot.clone(),
+        visibility: session.visibility,
+        access: session.access.clone(),
+        campaign: session.campaign,
+        challenge: session.challenge,
+        ..Checkpoint::new(
+            session_id,
+            session.client_id.clone(),
+            session.created_at,
+            &session.game,
+        )
+    }
+}
+
+/// Session of a restored checkpoint, with a fresh token; its owner resumes it with the
+/// client cookie
+#[cfg(feature = "persistence")]
+fn restored(checkpoint: Checkpoint) -> (String, Session) {
+    let mut session = Session::new(checkpoint.restore(), checkpoint.client_id);
+    session.created_at = checkpoint.created_at;
+    session.checkpoint_tick = Some(session.game.tick);
+    session.ai_personality = checkpoint.ai_personality;
+    session.visibility = checkpoint.visibility;
+    session.access = checkpoint.access;
+    session.campaign = checkpoint.campaign;
+    session.challenge = checkpoint.challenge;
+    #[cfg(feature = "ai")]
+    {
+        session.bot = checkpoint.bot;
+    }
+    (checkpoint.session_id, session)
+}
+
+/// Takes over the checkpointed games once the server owning them handed them over or
+/// stopped, e.g. during a deploy sharing the listening port, and resumes their bots
+/// See `AppState::take_over_checkpoints`
+#[cfg(feature = "persistence")]
+#[cfg_attr(not(feature = "ai"), allow(unused_variables))]
+pub(crate) async fn adopt_handoff(data: web::Data<AppState>) -> std::result::Result<(), String> {
+    let adopt_data = data.clone();
+    let adopted = web::block(move || adopt_data.take_over_checkpoints())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+    #[cfg(feature = "ai")]
+    for session_id in adopted {
+        actix_web::rt::spawn(bot::play(data.clone(), session_id));
+    }
+    Ok(())
+}
+
+/// Creates shared application state
+/// Games saved in the configured checkpoint directory are restored as sessions
+/// Score tokens are signed with the configured secret, or a random key without one
+pub fn new_state(config: Config) -> web::Data<AppState> {
+    #[cfg(not(feature = "persistence"))]
+    if config.checkpoint_dir.is_some() {
+        tracing::warn!("checkpointing disabled, built without the persistence feature");
+    }
+    #[cfg(not(feature = "multiplayer"))]
+    if config.match_log.is_some() {
+        tracing::warn!("match_log ignored, built without the multiplayer feature");
+    }
+    #[cfg(feature = "persistence")]
+    let checkpoints = config.checkpoint_dir.as_deref().and_then(|dir| {
+        CheckpointStore::open(dir)
+            .map_err(|e| tracing::error!("checkpointing disabled, cannot open {}: {}", dir, e))
+            .ok()
+    });
+    let level_store = config.level_dir.as_deref().and_then(|dir| {
+        LevelStore::open(dir)
+            .map_err(|e| tracing::error!("levels are not saved, cannot open {}: {}", dir, e))
+            .ok()
+    });
+    let catalogs = config
+        .locale_dir
+        .as_deref()
+        .map_or_else(Catalogs::default, |dir| {
+            Catalogs::load(dir).unwrap_or_else(|e| {
+                tracing::error!("error messages are not translated: {}", e);
+                Catalogs::default()
+            })
+        });
+    let score_signer = match &config.score_secret {
+        Some(secret) => ScoreSigner::new(secret.as_bytes()),
+        None => ScoreSigner::random(),
+    };
+    let cookie_signer = config
+        .cookie_secret
+        .as_ref()
+        .map_or_else(Signer::default, |secret| Signer::new(secret.as_bytes()));
+    #[cfg(feature = "oauth")]
+    let oauth = crate::oauth::OAuthState::open(&config.oauth);
+    let audit = AuditLog::open(config.audit_log.as_deref());
+    #[cfg(feature = "multiplayer")]
+    let match_history = MatchHistory::open(config.match_log.as_deref());
+    let campaigns = Campaigns::open(config.campaign_file.as_deref());
+    let ai_slots = config
+        .ai_slots
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, usize::from));
+    let state = AppState {
+        config: RwLock::new(Arc::new(config)),
+        sessions: Mutex::new(HashMap::new()),
+        leaderboard: Mutex::new(Vec::new()),
+        windows: Mutex::new(
+            Window::CLOSING
+                .into_iter()
+                .map(|window| (window, OpenWindow::starting(window, SystemTime::now())))
+                .collect(),
+        ),
+        closed_windows: Mutex::new(HashMap::new()),
+        level_leaderboards: Mutex::new(HashMap::new()),
+        speedruns: Mutex::new(Vec::new()),
+        replays: Mutex::new(ReplayStore::default()),
+        tasks: Mutex::new(HashMap::new()),
+        #[cfg(feature = "persistence")]
+        checkpoints,
+        #[cfg(feature = "persistence")]
+        owns_checkpoints: AtomicBool::new(false),
+        levels: Mutex::new(HashMap::new()),
+        level_store,
+        score_signer,
+        cookie_signer,
+        submitted_scores: Mutex::new(HashMap::new()),
+        name_owners: Mutex::new(HashMap::new()),
+        submissions: Mutex::new(SubmissionLog::default()),
+        review_queue: Mutex::new(Vec::new()),
+        hidden_scores: Mutex::new(Vec::new()),
+        reports: Mutex::new(Vec::new()),
+        top_entries: Mutex::new(Vec::new()),
+        link_codes: Mutex::new(HashMap::new()),
+        anonymous_ids: identity::AnonymousIds::default(),
+        heatmaps: Heatmaps::default(),
+        catalogs,
+        #[cfg(feature = "multiplayer")]
+        team_matches: TeamMatches::default(),
+        #[cfg(feature = "multiplayer")]
+        match_history,
+        campaigns,
+        proof_of_work: ProofOfWork::default(),
+        featured: Mutex::new(None),
+        featured_archive: Mutex::new(Vec::new()),
+        #[cfg(feature = "oauth")]
+        oauth,
+        request_metrics: RequestMetrics::default(),
+        fairness: Fairness::new(ai_slots),
+        draining: watch::channel(false).0,
+        audit,
+    };
+    #[cfg(feature = "persistence")]
+    if let Err(e) = state.take_over_checkpoints() {
+        tracing::error!("cannot restore checkpoints: {}", e);
+    }
+    state.load_levels();
+    web::Data::new(state)
+}
+
+/// Registers static files and all API routes
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg
+        // Serve static files from the 'static' directory
+        .service(fs::Files::new("/static", "static").show_files_listing())
+        // Define routes
+        .route("/", web::get().to(index))
+        .route("/api/capabilities", web::get().to(capabilities::capabilities))
+        .route("/api/theme", web::get().to(theme::theme))
+        .route("/game/{session_id}", web::get().to(get_game_state))
+        .route("/game/{session_id}/ascii", web::get().to(get_game_ascii))
+        .route("/game/{session_id}/describe", web::get().to(describe_game))
+        .route("/game/{session_id}/summary", web::get().to(summary::summary))
+        .route("/game/{session_id}/wait", web::get().to(wait_for_game))
+        .route("/game/{session_id}/observation", web::get().to(get_game_observation))
+        .route("/game/{session_id}/predict", web::get().to(predict_game))
+        .route("/direction/{session_id}", web::post().to(update_direction))
+        .route("/turn/{session_id}", web::post().to(update_turn))
+        .route("/update/{session_id}", web::post().to(update_game))
+        .route("/rewind/{session_id}", web::post().to(rewind_game))
+        .route("/replays/{replay_id}", web::get().to(get_replay))
+        .route("/replays/{replay_id}", web::delete().to(delete_replay))
+        .route("/new-game", web::post().to(new_game))
+        .route("/proof-of-work", web::get().to(proof_of_work::challenge))
+        .route("/demo", web::post().to(demo::start))
+        .route("/demo/current", web::get().to(demo::current))
+        .route("/challenge/hourly", web::get().to(challenge::current))
+        .route("/challenge/hourly", web::post().to(challenge::start))
+        .route("/campaign", web::get().to(campaign::progress))
+        .route("/campaign", web::post().to(campaign::start))
+        .route("/campaign/advance", web::post().to(campaign::advance))
+        .route(
+            "/campaign/leaderboard",
+            web::get().to(campaign::leaderboard),
+        )
+        .route("/my-games", web::get().to(my_games))
+        .route("/my-best", web::get().to(identity::my_best))
+        .route("/stats/heatmap", web::get().to(heatmap::heatmap))
+        .route("/link-code", web::post().to(identity::create_link_code))
+        .route("/link", web::post().to(identity::link_device))
+        .route("/session/{session_id}/resume", web::get().to(resume_session))
+        .route("/submit-score", web::post().to(submit_score))
+        .route("/leaderboard", web::get().to(get_leaderboard))
+        .route("/leaderboard.csv", web::get().to(feed::leaderboard_csv))
+        .route(
+            "/leaderboard/feed.rss",
+            web::get().to(feed::leaderboard_rss),
+        )
+        .route("/leaderboard/speedrun", web::get().to(get_speedruns))
+        .route("/leaderboard/winners", web::get().to(get_winners))
+        .route("/levels", web::get().to(level::list))
+        .route("/levels", web::post().to(level::create))
+        .route("/levels/validate", web::post().to(level::validate))
+        .route("/levels/featured", web::get().to(level::featured))
+        .route("/levels/featured/archive", web::get().to(level::featured_archive))
+        .route("/levels/{level_id}", web::get().to(level::get))
+        .route("/levels/{level_id}", web::put().to(level::update))
+        .route("/levels/{level_id}/publish", web::post().to(level::publish))
+        .route("/levels/{level_id}/rate", web::post().to(level::rate))
+        .route("/levels/{level_id}/leaderboard", web::get().to(level::leaderboard))
+        .route("/report", web::post().to(report::submit))
+        .route("/admin/audit", web::get().to(admin::audit_log))
+        .route("/admin/dashboard", web::get().to(admin::dashboard))
+        .route("/admin/debug", web::get().to(admin::debug))
+        .route("/admin/featured", web::post().to(admin::feature_level))
+        .route("/admin/jobs", web::get().to(admin::jobs))
+        .route("/admin/replays/verify", web::post().to(admin::verify_replays))
+        .route("/admin/review", web::get().to(admin::review_queue))
+        .route("/admin/review/{review_id}/approve", web::post().to(admin::approve_score))
+        .route("/admin/review/{review_id}/reject", web::post().to(admin::reject_score))
+        .route("/admin/reports", web::get().to(admin::reports))
+        .route(
+            "/admin/reports/{report_id}/hide",
+            web::post().to(admin::hide_reported),
+        )
+        .route(
+            "/admin/reports/{report_id}/dismiss",
+            web::post().to(admin::dismiss_report),
+        )
+        .route(
+            "/admin/levels/{level_id}/restore",
+            web::post().to(admin::restore_level),
+        )
+        .route("/admin/scores/hidden", web::get().to(admin::hidden_scores))
+        .route("/admin/state", web::get().to(admin::export_state))
+        .route("/admin/state", web::post().to(admin::import_state))
+        .route("/admin/scores/{entry_id}/hide", web::post().to(admin::hide_score))
+        .route("/admin/scores/{entry_id}/restore", web::post().to(admin::restore_score));
+    #[cfg(feature = "ai")]
+    cfg.route("/ai-move/{session_id}", web::post().to(ai_move))
+        .route("/admin/training-data", web::get().to(admin::training_data))
+        .configure(crate::battlesnake::configure);
+    #[cfg(feature = "multiplayer")]
+    cfg.route("/team-match", web::post().to(team::create))
+        .route("/team-match/{match_id}", web::get().to(team::state))
+        .route("/team-match/{match_id}/direction/{snake}", web::post().to(team::direction))
+        .route("/team-match/{match_id}/update", web::post().to(team::update))
+        .route("/matches", web::get().to(history::matches))
+        .route("/matches/head-to-head", web::get().to(history::head_to_head))
+        .route("/matches/{match_id}", web::get().to(history::get))
+        .route("/matches/{match_id}/replay", web::get().to(history::replay))
+        .route("/rooms", web::get().to(room::list))
+        .route("/rooms/{session_id}", web::get().to(room::room))
+        .route("/rooms/{session_id}/invite", web::post().to(room::invite))
+        .route("/rooms/{session_id}/moderate", web::post().to(room::moderate))
+        .route("/ws", web::get().to(crate::realtime::connect_multiplexed))
+        .route("/ws/{session_id}", web::get().to(crate::realtime::connect));
+    #[cfg(feature = "rendering")]
+    cfg.route("/game/{session_id}/frame.png", web::get().to(get_game_frame))
+        .route(
+            "/replays/{replay_id}/animation.gif",
+            web::get().to(get_replay_animation),
+        )
+        .route("/replays/{replay_id}/card.png", web::get().to(crate::share::replay_card))
+        .route("/share/{replay_id}", web::get().to(crate::share::share_page));
+    #[cfg(feature = "graphql")]
+    cfg.configure(crate::graphql::configure);
+    #[cfg(feature = "oauth")]
+    cfg.configure(crate::oauth::configure);
+}
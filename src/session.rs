@@ -0,0 +1,527 @@
+use crate::ai::Personality;
+#[cfg(feature = "ai")]
+use crate::bot::BotCallback;
+use crate::feedback::{self, FeedbackHint};
+use crate::game::{Direction, Game, GameEvent, Point, Split, SPLIT_EVERY_APPLES};
+use crate::quota::TickQuota;
+use crate::replay::Visibility;
+use crate::room::{RoomAccess, RoomPass};
+use crate::view::{self, GameDelta, GameView};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Number of recent events kept per session for clients that reconnect
+pub const EVENT_BACKLOG: usize = 256;
+/// Period over which the tick rate of a session is measured
+const TICK_RATE_WINDOW: Duration = Duration::from_secs(5);
+/// Number of stream messages buffered per realtime subscriber
+const STREAM_CAPACITY: usize = 64;
+/// Frames pushed to realtime subscribers from one full state to the next; the frames in
+/// between are deltas
+pub const KEYFRAME_INTERVAL: u64 = 20;
+
+/// Game event tagged with its position in the session's event sequence
+#[derive(Clone, Serialize)]
+pub struct SequencedEvent {
+    /// Sequence number, strictly increasing within a session
+    pub seq: u64,
+    /// Tick during which the event happened
+    pub tick: u64,
+    #[serde(flatten)]
+    pub event: GameEvent,
+}
+
+/// Emote a spectator reacts to a game with
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Emote {
+    Clap,
+    Fire,
+    Laugh,
+    Wow,
+    Heart,
+    Sad,
+}
+
+/// Moderation change of a session's room, broadcast to everyone connected to it
+#[derive(Clone, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum RoomChange {
+    /// The connection was closed by the host
+    Kicked { connection: String },
+    /// The connection was closed by the host, and its browser may not connect again
+    Banned { connection: String },
+    /// No more spectators may connect
+    Locked,
+    /// Spectators may connect again
+    Unlocked,
+    /// The connection holds the new session token and hosts the room from now on
+    HostTransferred { connection: String },
+}
+
+/// Realtime connection to a session, as listed to the room's host
+#[derive(Clone, Serialize)]
+pub struct Viewer {
+    /// Identifier of the connection, which moderation actions refer to
+    pub connection: String,
+    /// Whether the connection holds the session token
+    pub host: bool,
+    // Browser of the connection, from the client cookie
+    #[serde(skip)]
+    pub client_id: Option<String>,
+}
+
+/// Message pushed to realtime subscribers of a session
+#[derive(Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamMessage {
+    /// Events that happened since the previous message
+    Events { events: Vec<SequencedEvent> },
+    /// Cues for client feedback effects of the tick numbered `tick`; they are not kept in
+    /// the backlog, since effects of past ticks are not worth playing
+    Feedback { tick: u64, hints: Vec<FeedbackHint> },
+    /// Full game state, a keyframe; later deltas apply to it
+    State {
+        /// Number of the frame, strictly increasing within a session
+        frame: u64,
+        #[serde(serialize_with = "view::serialize_game")]
+        state: Box<Game>,
+    },
+    /// Changes of the state since the frame numbered `frame - 1`
+    /// A client that missed that frame requests a keyframe
+    Delta { frame: u64, delta: Box<GameDelta> },
+    /// A spectator reacted, e.g. for clients to show as an overlay; reactions are
+    /// anonymous and not kept in the backlog
+    Reaction { emote: Emote },
+    /// The host moderated the room; not kept in the backlog
+    Moderation {
+        #[serde(flatten)]
+        change: RoomChange,
+    },
+}
+
+/// AI decision requested for a game and computed off the request workers
+/// The next tick of the game plays it, provided the game has not advanced since
+pub struct AiTicket {
+    pub id: String,
+    /// Tick of the position the decision is computed for
+    pub tick: u64,
+    /// Chosen move once the decision is done; `Some(None)` when every move is fatal
+    pub decision: Option<Option<Direction>>,
+}
+
+/// A game together with the server-side bookkeeping of the player owning it
+pub struct Session {
+    pub game: Game,
+    /// Secret that authorizes the owning player, rotated on every resume
+    pub token: String,
+    /// Browser that created the session, from the client cookie
+    pub client_id: Option<String>,
+    /// Wall-clock creation time
+    pub created_at: SystemTime,
+    /// Last time the owning player interacted with the session
+    pub last_seen: Instant,
+    /// Number of open realtime connections
+    pub connections: usize,
+    /// Number of open realtime connections holding the session token
+    pub owner_connections: usize,
+    /// When the owner's last realtime connection closed, while none is open
+    pub owner_left_at: Option<Instant>,
+    /// Whether spectators were told that the owner is gone
+    pub player_disconnected: bool,
+    /// Number of the most recent frame pushed to realtime subscribers
+    pub frame: u64,
+    /// View of that frame, kept while there are subscribers to compute the next delta
+    last_view: Option<serde_json::Value>,
+    /// Most recent events, oldest first
+    pub events: VecDeque<SequencedEvent>,
+    /// Tick of the most recent checkpoint written to storage
+    pub checkpoint_tick: Option<u64>,
+    /// Last time the game advanced, or the creation of the session before its first tick
+    pub last_tick_at: Instant,
+    /// Ticks per second over the last completed `TICK_RATE_WINDOW`
+    pub tick_rate: f64,
+    // Start and tick count of the rate window in progress
+    rate_window: (Instant, u64),
+    /// Ticks the game may still advance before it has to slow down
+    pub quota: TickQuota,
+    /// AI decision waiting to be played, at most one per session
+    pub ai_ticket: Option<AiTicket>,
+    /// Personality of the lookahead AI steering the game, if chosen at creation
+    pub ai_personality: Option<Personality>,
+    /// External bot playing the game, if chosen at creation
+    #[cfg(feature = "ai")]
+    pub bot: Option<BotCallback>,
+    /// Who may spectate the game and watch its replay
+    pub visibility: Visibility,
+    /// Whether the player deleted the game's replay, which is then not recorded again
+    pub replay_deleted: bool,
+    /// Whether the server plays the game as a demo, see `demo::start`
+    pub demo: bool,
+    /// Whether the game is a stage of a campaign, see `campaign::start`
+    pub campaign: bool,
+    /// Start of the hour whose challenge seed the game plays, see `challenge::start`
+    pub challenge: Option<u64>,
+    /// Cells where the snake ate since the session was created or restored, for the heatmaps
+    pub eaten_at: Vec<Point>,
+    /// Open realtime connections, the room moderated by the token holder
+    pub viewers: Vec<Viewer>,
+    /// Whether the host locked the room, so no more spectators may connect
+    pub locked: bool,
+    /// Browsers the host banned from the room, by client ID
+    pub banned: Vec<String>,
+    /// Whether the host banned anyone, also a connection without client cookie; spectators
+    /// without one are then refused, since bans cannot tell them apart
+    pub has_bans: bool,
+    /// Password and invite key of the room
+    pub access: RoomAccess,
+    next_seq: u64,
+    sender: broadcast::Sender<StreamMessage>,
+}
+
+impl Session {
+    /// Wraps a new game in a session with a fresh token
+    pub fn new(game: Game, client_id: Option<String>) -> Self {
+        let (sender, _) = broadcast::channel(STREAM_CAPACITY);
+        Session {
+            game,
+            token: Uuid::new_v4().to_string(),
+            client_id,
+            created_at: SystemTime::now(),
+            last_seen: Instant::now(),
+            connections: 0,
+            owner_connections: 0,
+            owner_left_at: None,
+            player_disconnected: false,
+            frame: 0,
+            last_view: None,
+            events: VecDeque::new(),
+            checkpoint_tick: None,
+            last_tick_at: Instant::now(),
+            tick_rate: 0.0,
+            rate_window: (Instant::now(), 0),
+            quota: TickQuota::default(),
+            ai_ticket: None,
+            ai_personality: None,
+            #[cfg(feature = "ai")]
+            bot: None,
+            visibility: Visibility::default(),
+            replay_deleted: false,
+            demo: false,
+            campaign: false,
+            challenge: None,
+            eaten_at: Vec::new(),
+            viewers: Vec::new(),
+            locked: false,
+            banned: Vec::new(),
+            has_bans: false,
+            access: RoomAccess::default(),
+            next_seq: 1,
+            sender,
+        }
+    }
+
+    /// Records owner activity, keeping the session alive
+    pub fn touch(&mut self) {
+        self.last_seen = Instant::now();
+    }
+
+    /// Checks a token presented by a client
+    pub fn authorizes(&self, token: Option<&str>) -> bool {
+        token == Some(self.token.as_str())
+    }
+
+    /// Checks whether a client ID belongs to the browser that created the session
+    pub fn belongs_to(&self, client_id: Option<&str>) -> bool {
+        client_id.is_some() && self.client_id.as_deref() == client_id
+    }
+
+    /// Whether an external bot plays the game
+    pub fn is_bot_game(&self) -> bool {
+        #[cfg(feature = "ai")]
+        return self.bot.is_some();
+        #[cfg(not(feature = "ai"))]
+        false
+    }
+
+    /// Whether spectators need a password or an invite to join the session's room
+    pub fn is_private(&self) -> bool {
+        self.visibility == Visibility::Private || self.access.has_password()
+    }
+
+    /// Whether the live game may be watched: by its player, holding the token or using the
+    /// browser that created the game, and by spectators unless the room is private, in which
+    /// case they need its password or an invite
+    pub fn watchable_by(
+        &self,
+        token: Option<&str>,
+        client_id: Option<&str>,
+        pass: &RoomPass,
+    ) -> bool {
+        self.authorizes(token)
+            || self.belongs_to(client_id)
+            || !self.is_private()
+            || self.access.admits(pass)
+    }
+
+    /// Replaces the session token, invalidating the previous one
+    pub fn rotate_token(&mut self) -> String {
+        self.token = Uuid::new_v4().to_string();
+        self.token.clone()
+    }
+
+    /// Counts a new realtime connection
+    /// The owner coming back after being reported gone is announced to the other
+    /// connections
+    pub fn attach(&mut self, owner: bool) {
+        self.connections += 1;
+        if !owner {
+            return;
+        }
+        self.touch();
+        self.owner_connections += 1;
+        self.owner_left_at = None;
+        if self.player_disconnected {
+            self.player_disconnected = false;
+            self.publish(vec![GameEvent::PlayerReconnected]);
+        }
+    }
+
+    /// Moves a realtime connection between the host's connections and the spectators'
+    pub fn set_host(&mut self, connection: &str, host: bool) {
+        self.detach(!host);
+        self.attach(host);
+        if let Some(viewer) = self.viewers.iter_mut().find(|v| v.connection == connection) {
+            viewer.host = host;
+        }
+    }
+
+    /// Number of open realtime connections without the session token
+    pub fn spectators(&self) -> usize {
+        self.connections.saturating_sub(self.owner_connections)
+    }
+
+    /// Releases a realtime connection; the owner's grace period starts with their last one
+    pub fn detach(&mut self, owner: bool) {
+        self.connections = self.connections.saturating_sub(1);
+        if !owner {
+            return;
+        }
+        self.touch();
+        self.owner_connections = self.owner_connections.saturating_sub(1);
+        if self.owner_connections == 0 {
+            self.owner_left_at = Some(Instant::now());
+        }
+    }
+
+    /// Announces that the owner is gone once they have had no realtime connection for
+    /// `grace`; returns whether they were announced
+    pub fn report_disconnect(&mut self, grace: Duration) -> bool {
+        let gone = self
+            .owner_left_at
+            .is_some_and(|left_at| left_at.elapsed() >= grace);
+        if !gone || self.player_disconnected {
+            return false;
+        }
+        self.player_disconnected = true;
+        self.publish(vec![GameEvent::PlayerDisconnected]);
+        true
+    }
+
+    /// Whether the owner has been away longer than the grace period with no open connection
+    pub fn is_abandoned(&self, grace: Duration) -> bool {
+        self.connections == 0 && self.last_seen.elapsed() > grace
+    }
+
+    /// Requests an AI decision for the current position, replacing any pending one
+    /// Returns the ticket ID and a copy of the game, without its snapshot history, to
+    /// decide on without holding the session
+    pub fn issue_ai_ticket(&mut self) -> (String, Game) {
+        let id = Uuid::new_v4().to_string();
+        self.ai_ticket = Some(AiTicket {
+            id: id.clone(),
+            tick: self.game.tick,
+            decision: None,
+        });
+        (id, self.game.snapshot())
+    }
+
+    /// Stores the outcome of an AI decision
+    /// Returns false when the ticket was replaced or already played
+    pub fn fulfill_ai_ticket(&mut self, id: &str, direction: Option<Direction>) -> bool {
+        match self.ai_ticket.as_mut().filter(|ticket| ticket.id == id) {
+            Some(ticket) => {
+                ticket.decision = Some(direction);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Plays a finished AI decision computed for the current tick
+    /// Decisions for earlier ticks are stale and dropped; unfinished ones keep waiting
+    fn play_ai_ticket(&mut self) {
+        let Some(ticket) = self.ai_ticket.take() else {
+            return;
+        };
+        match ticket.decision {
+            Some(direction) if ticket.tick == self.game.tick => self.game.steer(direction),
+            None if ticket.tick == self.game.tick => self.ai_ticket = Some(ticket),
+            _ => {}
+        }
+    }
+
+    /// Advances the game by one step and publishes what happened
+    /// A finished AI decision for the current position is played first
+    /// Returns the events of the step
+    pub fn tick(&mut self) -> Vec<GameEvent> {
+        self.play_ai_ticket();
+        self.measure_tick();
+        let mut events = self.game.update();
+        self.eaten_at
+            .extend(events.iter().filter_map(|event| match event {
+                GameEvent::AteFood { position, .. } => Some(*position),
+                _ => None,
+            }));
+        events.extend(self.take_splits());
+        let hints = feedback::hints(&self.game, &events);
+        self.publish_with_hints(events.clone(), hints);
+        events
+    }
+
+    /// Counts a tick towards the tick rate
+    fn measure_tick(&mut self) {
+        let now = Instant::now();
+        self.last_tick_at = now;
+        let (start, ticks) = &mut self.rate_window;
+        *ticks += 1;
+        let elapsed = now.duration_since(*start);
+        if elapsed >= TICK_RATE_WINDOW {
+            self.tick_rate = *ticks as f64 / elapsed.as_secs_f64();
+            self.rate_window = (now, 0);
+        }
+    }
+
+    /// Ticks per second, or zero when the game has not advanced for a whole rate window
+    pub fn current_tick_rate(&self) -> f64 {
+        if self.last_tick_at.elapsed() > TICK_RATE_WINDOW {
+            0.0
+        } else {
+            self.tick_rate
+        }
+    }
+
+    /// Takes the speed-run splits the game reached since the last one, timed from the
+    /// creation of the session
+    fn take_splits(&mut self) -> Vec<GameEvent> {
+        let elapsed_ms = self
+            .created_at
+            .elapsed()
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let mut events = Vec::new();
+        let mut apples = (self.game.splits.len() as u32 + 1) * SPLIT_EVERY_APPLES;
+        while apples <= self.game.food_eaten {
+            self.game.splits.push(Split {
+                apples,
+                tick: self.game.tick,
+                elapsed_ms,
+            });
+            events.push(GameEvent::Split { apples, elapsed_ms });
+            apples += SPLIT_EVERY_APPLES;
+        }
+        events
+    }
+
+    /// Pushes a moderation change of the room to every subscriber
+    pub fn moderate(&self, change: RoomChange) {
+        let _ = self.sender.send(StreamMessage::Moderation { change });
+    }
+
+    /// Pushes a spectator's reaction to every subscriber, the player included
+    pub fn react(&self, emote: Emote) {
+        let _ = self.sender.send(StreamMessage::Reaction { emote });
+    }
+
+    /// Appends events to the backlog and pushes them, followed by the new frame, to subscribers
+    /// Every `KEYFRAME_INTERVAL`-th frame, and the first one after a time without
+    /// subscribers, is the full state; the others are deltas
+    pub fn publish(&mut self, events: Vec<GameEvent>) {
+        self.publish_with_hints(events, Vec::new());
+    }
+
+    /// Publishes like `publish`, with the feedback hints of a tick pushed between the
+    /// events and the frame
+    fn publish_with_hints(&mut self, events: Vec<GameEvent>, hints: Vec<FeedbackHint>) {
+        let tick = self.game.tick;
+        let sequenced: Vec<SequencedEvent> = events
+            .into_iter()
+            .map(|event| {
+                let seq = self.next_seq;
+                self.next_seq += 1;
+                SequencedEvent { seq, tick, event }
+            })
+            .collect();
+        for event in &sequenced {
+            self.events.push_back(event.clone());
+            if self.events.len() > EVENT_BACKLOG {
+                self.events.pop_front();
+            }
+        }
+        // Sending only fails when nobody is subscribed
+        if !sequenced.is_empty() {
+            let _ = self
+                .sender
+                .send(StreamMessage::Events { events: sequenced });
+        }
+        if !hints.is_empty() {
+            let _ = self.sender.send(StreamMessage::Feedback { tick, hints });
+        }
+        self.frame += 1;
+        if self.sender.receiver_count() == 0 {
+            self.last_view = None;
+            return;
+        }
+        let view = serde_json::to_value(GameView::from(&self.game)).expect("game views serialize");
+        let message = match self.last_view.take() {
+            Some(previous) if !self.frame.is_multiple_of(KEYFRAME_INTERVAL) => {
+                StreamMessage::Delta {
+                    frame: self.frame,
+                    delta: Box::new(GameDelta::between(&previous, &view)),
+                }
+            }
+            _ => self.keyframe(),
+        };
+        self.last_view = Some(view);
+        let _ = self.sender.send(message);
+    }
+
+    /// Full state of the most recent frame, for new subscribers and those that fell behind
+    pub fn keyframe(&self) -> StreamMessage {
+        StreamMessage::State {
+            frame: self.frame,
+            state: Box::new(self.game.clone()),
+        }
+    }
+
+    /// Sequence number of the most recent event, zero before the first one
+    pub fn last_seq(&self) -> u64 {
+        self.next_seq - 1
+    }
+
+    /// Events with a sequence number greater than `since` that are still in the backlog
+    pub fn events_since(&self, since: u64) -> Vec<SequencedEvent> {
+        self.events
+            .iter()
+            .filter(|event| event.seq > since)
+            .cloned()
+            .collect()
+    }
+
+    /// Subscribes to stream messages published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<StreamMessage> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,135 @@
+#[cfg(feature = "rendering")]
+use crate::identity;
+#[cfg(feature = "rendering")]
+use crate::render::{self, Theme};
+#[cfg(feature = "rendering")]
+use crate::replay::Visibility;
+use crate::server::AppState;
+use actix_web::HttpRequest;
+#[cfg(feature = "rendering")]
+use actix_web::{web, HttpResponse};
+
+/// Cell size of share card images, sized for chat app previews
+#[cfg(feature = "rendering")]
+const CARD_CELL_SIZE: u32 = 24;
+
+/// Escapes text for safe inclusion in HTML content and attribute values
+pub fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Absolute base URL used in share links
+/// Uses `public_url` from the configuration, falling back to the request's host
+pub fn base_url(req: &HttpRequest, data: &AppState) -> String {
+    match data.config().public_url.as_deref() {
+        Some(url) => url.trim_end_matches('/').to_string(),
+        None => {
+            let info = req.connection_info();
+            format!("{}://{}", info.scheme(), info.host())
+        }
+    }
+}
+
+/// Renders the final board of a replay with a score and length banner as PNG
+/// replay_id: Identifier of the replay
+#[cfg(feature = "rendering")]
+pub async fn replay_card(
+    req: HttpRequest,
+    replay_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    let client_id = identity::client_id(&req);
+    let Some(replay) = data.viewable_replay(&replay_id, client_id.as_deref()) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    // Cards of private replays are for their player only, so shared caches must not keep them
+    let cache_control = if replay.visibility == Visibility::Private {
+        "private, max-age=86400"
+    } else {
+        "public, max-age=86400"
+    };
+    let bytes = web::block(move || {
+        let game = replay.simulate();
+        let theme = Theme::by_name("dark").expect("built-in theme");
+        let cell_size = render::clamp_cell_size(&game, CARD_CELL_SIZE);
+        render::png(&render::share_card(&game, cell_size, &theme))
+    })
+    .await?
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok()
+        .content_type("image/png")
+        .insert_header(("Cache-Control", cache_control))
+        .body(bytes))
+}
+
+/// Serves a shareable page with Open Graph and Twitter meta tags for a finished game
+/// The page shows the share card and animation, so it is served with rendering only
+/// Pages of unlisted and private replays ask search engines not to index them
+/// replay_id: Identifier of the replay
+#[cfg(feature = "rendering")]
+pub async fn share_page(
+    req: HttpRequest,
+    replay_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let client_id = identity::client_id(&req);
+    let Some(replay) = data.viewable_replay(&replay_id, client_id.as_deref()) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let robots = if replay.visibility == Visibility::Public {
+        ""
+    } else {
+        "\n    <meta name=\"robots\" content=\"noindex\">"
+    };
+    let base = base_url(&req, &data);
+    let id = html_escape(&replay.id);
+    let title = format!("Snake: {} points", replay.score);
+    let description = format!(
+        "Scored {} on a {}x{} board in {} moves. Can you beat it?",
+        replay.score, replay.width, replay.height, replay.ticks
+    );
+    let card_url = format!("{}/replays/{}/card.png", base, id);
+    let animation_url = format!("{}/replays/{}/animation.gif", base, id);
+    let page_url = format!("{}/share/{}", base, id);
+
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>{title}</title>{robots}
+    <meta property="og:type" content="website">
+    <meta property="og:title" content="{title}">
+    <meta property="og:description" content="{description}">
+    <meta property="og:url" content="{page_url}">
+    <meta property="og:image" content="{card_url}">
+    <meta name="twitter:card" content="summary_large_image">
+    <meta name="twitter:title" content="{title}">
+    <meta name="twitter:description" content="{description}">
+    <meta name="twitter:image" content="{card_url}">
+</head>
+<body>
+    <h1>{title}</h1>
+    <p>{description}</p>
+    <img src="{animation_url}" alt="Replay of the game">
+    <p><a href="/">Play Snake</a></p>
+</body>
+</html>"#,
+        title = html_escape(&title),
+        description = html_escape(&description),
+    );
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(html)
+}
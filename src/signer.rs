@@ -0,0 +1,71 @@
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::time::SystemTime;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Length of a generated signing key, in bytes
+const KEY_LEN: usize = 32;
+
+/// Current Unix timestamp (seconds)
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// HMAC-SHA256 key shared by the tokens the server issues: score tokens, room invites,
+/// proof-of-work challenges, client cookies and anonymous IDs
+/// Signed tokens have the form `<claim>.<hex HMAC-SHA256 of the claim>`
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Signer {
+    key: Vec<u8>,
+}
+
+impl Default for Signer {
+    /// Signer with a random key; its tokens are only valid until the server restarts
+    fn default() -> Self {
+        let mut key = vec![0; KEY_LEN];
+        rand::thread_rng().fill_bytes(&mut key);
+        Signer { key }
+    }
+}
+
+impl Signer {
+    /// Signer with a fixed key, so tokens stay valid across restarts
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Signer { key: key.into() }
+    }
+
+    /// HMAC of a message under the signer's key
+    pub fn mac(&self, message: &[u8]) -> Vec<u8> {
+        self.hmac(message).finalize().into_bytes().to_vec()
+    }
+
+    /// Whether `mac` is the HMAC of a message, compared in constant time
+    pub fn verifies(&self, message: &[u8], mac: &[u8]) -> bool {
+        self.hmac(message).verify_slice(mac).is_ok()
+    }
+
+    /// Appends the hex HMAC of a claim to it
+    pub fn sign(&self, claim: &str) -> String {
+        format!("{}.{}", claim, hex::encode(self.mac(claim.as_bytes())))
+    }
+
+    /// Checks the signature of a token made by `sign` and returns its claim
+    /// Returns None for malformed or forged tokens
+    pub fn verify<'a>(&self, token: &'a str) -> Option<&'a str> {
+        let (claim, signature) = token.rsplit_once('.')?;
+        let signature = hex::decode(signature).ok()?;
+        self.verifies(claim.as_bytes(), &signature).then_some(claim)
+    }
+
+    fn hmac(&self, message: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC takes keys of any size");
+        mac.update(message);
+        mac
+    }
+}
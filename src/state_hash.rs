@@ -0,0 +1,70 @@
+#![deny(clippy::disallowed_types)]
+
+use crate::game::Point;
+
+/// FNV-1a offset basis and prime for 64-bit hashes
+const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// 64-bit FNV-1a hasher over explicitly little-endian encoded values
+/// Unlike `std::hash`, its output is specified and the same on every platform and Rust
+/// release, so state hashes can be stored with replays and compared across builds
+pub struct StateHasher(u64);
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        StateHasher(OFFSET_BASIS)
+    }
+}
+
+impl StateHasher {
+    /// Continues from an earlier hash
+    pub fn resume(hash: u64) -> Self {
+        StateHasher(hash)
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(PRIME);
+        }
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_point(&mut self, point: Point) {
+        self.write_i32(point.x);
+        self.write_i32(point.y);
+        self.write_i32(point.z);
+    }
+
+    /// Optional values are prefixed by whether they are set
+    pub fn write_option_u32(&mut self, value: Option<u32>) {
+        match value {
+            Some(value) => {
+                self.write_bytes(&[1]);
+                self.write_u32(value);
+            }
+            None => self.write_bytes(&[0]),
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Formats a state hash as 16 hex digits, the form it takes in JSON
+/// A string, because JavaScript numbers cannot hold all 64 bits
+pub fn to_hex(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
@@ -0,0 +1,177 @@
+use crate::game::{Completion, DeathCause, Game, Point};
+use crate::replay::Replay;
+use crate::server::AppState;
+use crate::topology::Topology;
+use crate::{identity, share};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+/// Cell of a heatmap with the number of ticks the snake's head was on it
+#[derive(Serialize)]
+pub struct HeatCell {
+    #[serde(flatten)]
+    pub cell: Point,
+    pub visits: u32,
+}
+
+/// Results of a finished game, the data of a results screen
+#[derive(Serialize)]
+pub struct GameSummary {
+    pub score: u32,
+    /// Final length of the snake
+    pub length: usize,
+    pub food_eaten: u32,
+    /// Number of simulated steps, the countdown included
+    pub ticks: u64,
+    /// Wall-clock milliseconds from the creation of the game to its end, when recorded
+    pub duration_ms: Option<u64>,
+    /// Food items eaten per minute of that duration
+    pub apples_per_minute: Option<f64>,
+    /// Most ticks in a row the snake survived without eating
+    pub longest_streak_ticks: u64,
+    /// Cells the head moved through, ordered by layer, row and column
+    pub heatmap: Vec<HeatCell>,
+    /// Why the game ended; None when the recording stops before the end or the level was
+    /// completed
+    pub death_cause: Option<DeathCause>,
+    /// How fast the level's objective was completed, if it was
+    pub completion: Option<Completion>,
+}
+
+/// Why a game that is over ended: the fatal move, which the engine does not carry out,
+/// leads a head into a wall, a one-way gate, a locked door, its own body, a moving hazard
+/// or, in co-op games, the other snake; None for games that completed their level
+fn death_cause(game: &Game) -> Option<DeathCause> {
+    let head = *game.snake.front()?;
+    let next = game.step(head, game.direction);
+    let partner_next = game
+        .partner
+        .as_ref()
+        .map(|partner| game.step(partner.snake[0], partner.direction));
+    if !game.game_over || game.completion.is_some() {
+        None
+    } else if game.topology.is_wall(next) {
+        Some(DeathCause::Wall)
+    } else if game.gate_blocks(next, game.direction) {
+        Some(DeathCause::Gate)
+    } else if game.door_locked(next) {
+        Some(DeathCause::Door)
+    } else if game.snake.contains(&next) {
+        Some(DeathCause::SelfCollision)
+    } else if partner_next == Some(next) || game.on_partner(next) {
+        Some(DeathCause::PartnerCollision)
+    } else if game.hits_hazard(next, &game.snake) {
+        Some(DeathCause::Hazard)
+    } else {
+        let (partner, next) = game.partner.as_ref().zip(partner_next)?;
+        if game.topology.is_wall(next) {
+            Some(DeathCause::Wall)
+        } else if game.gate_blocks(next, partner.direction) {
+            Some(DeathCause::Gate)
+        } else if game.door_locked(next) {
+            Some(DeathCause::Door)
+        } else if partner.snake.contains(&next) {
+            Some(DeathCause::SelfCollision)
+        } else if game.hits_hazard(next, &partner.snake) {
+            Some(DeathCause::Hazard)
+        } else {
+            Some(DeathCause::PartnerCollision)
+        }
+    }
+}
+
+/// Summarizes a recorded game by re-simulating it
+pub fn summarize(replay: &Replay) -> GameSummary {
+    let mut visits: BTreeMap<(i32, i32, i32), u32> = BTreeMap::new();
+    let mut last = None;
+    let mut food_eaten = 0;
+    let mut last_meal = 0;
+    let mut longest_streak_ticks = 0;
+    for game in replay.states() {
+        let start = u64::from(game.countdown);
+        // Neither the countdown nor the fatal move, which is not carried out, moves the head
+        if game.tick >= start && !game.game_over {
+            if let Some(head) = game.snake.front() {
+                *visits.entry((head.z, head.y, head.x)).or_default() += 1;
+            }
+        }
+        if game.food_eaten > food_eaten {
+            longest_streak_ticks = longest_streak_ticks.max(game.tick - last_meal.max(start));
+            last_meal = game.tick;
+            food_eaten = game.food_eaten;
+        }
+        last = Some(game);
+    }
+    let game = last.unwrap_or_else(|| replay.simulate());
+    let start = u64::from(game.countdown);
+    longest_streak_ticks = longest_streak_ticks.max(game.tick.saturating_sub(last_meal.max(start)));
+
+    let apples_per_minute = replay
+        .duration_ms
+        .filter(|ms| *ms > 0)
+        .map(|ms| f64::from(game.food_eaten) * 60_000.0 / ms as f64);
+    GameSummary {
+        score: game.score,
+        length: game.snake.len(),
+        food_eaten: game.food_eaten,
+        ticks: game.tick,
+        duration_ms: replay.duration_ms,
+        apples_per_minute,
+        longest_streak_ticks,
+        heatmap: visits
+            .into_iter()
+            .map(|((z, y, x), visits)| HeatCell {
+                cell: Point { x, y, z },
+                visits,
+            })
+            .collect(),
+        death_cause: death_cause(&game),
+        completion: game.completion,
+    }
+}
+
+/// Summary of a game with links to its replay
+#[derive(Serialize)]
+struct SummaryResponse {
+    #[serde(flatten)]
+    summary: GameSummary,
+    replay_url: String,
+    /// Share page of the replay, served with the `rendering` feature only
+    share_url: Option<String>,
+}
+
+/// Summarizes a finished game for its results screen: score, length, duration, pace,
+/// longest stretch without food, heatmap of the head's cells, death cause and replay links
+/// The summary is re-simulated from the game's replay, so games whose replay was deleted
+/// or is private to another client are not found; running games get 409 Conflict
+/// session_id: Unique identifier for the game instance
+pub async fn summary(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    let session_id = session_id.into_inner();
+    let running = data
+        .sessions
+        .lock()
+        .unwrap()
+        .get(&session_id)
+        .is_some_and(|session| !session.game.game_over);
+    if running {
+        return Ok(HttpResponse::Conflict().json(json!({ "error": "the game is not over" })));
+    }
+    let client_id = identity::client_id(&req);
+    let Some(replay) = data.viewable_replay(&session_id, client_id.as_deref()) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    // Re-simulation is CPU-bound, keep it off the async workers
+    let summary = web::block(move || summarize(&replay)).await?;
+    let base = share::base_url(&req, &data);
+    Ok(HttpResponse::Ok().json(SummaryResponse {
+        summary,
+        replay_url: format!("{}/replays/{}", base, session_id),
+        share_url: cfg!(feature = "rendering").then(|| format!("{}/share/{}", base, session_id)),
+    }))
+}
@@ -0,0 +1,770 @@
+#![deny(clippy::disallowed_types)]
+
+use crate::game::{Direction, InputTiming, Point, MAX_QUEUED_INPUTS};
+use crate::history::MatchRecord;
+use crate::rng::SeededRng;
+use crate::server::{session_token, AppState, BOARD_HEIGHT, BOARD_WIDTH};
+use crate::topology::{Solid, Topology};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Number of teams in a match
+pub const TEAMS: usize = 2;
+/// Snakes per team
+pub const TEAM_SIZE: usize = 2;
+/// Team score that wins a match, unless the match sets another
+pub const DEFAULT_SCORE_CAP: u32 = 20;
+/// Most team matches kept at once
+pub const MAX_TEAM_MATCHES: usize = 64;
+/// Most rounds a match may be played over
+pub const MAX_ROUNDS: u32 = 9;
+/// Length of every snake at the start of a match
+const START_LENGTH: i32 = 3;
+/// Random draws for a free food cell before falling back to a scan of the board
+const FOOD_SPAWN_ATTEMPTS: u32 = 100;
+
+/// Snake of a team match
+#[derive(Clone, Serialize)]
+pub struct TeamSnake {
+    /// Team the snake plays for, 0 or 1
+    pub team: usize,
+    /// Body of the snake, head first; empty once it is eliminated
+    pub body: VecDeque<Point>,
+    pub direction: Direction,
+    pub alive: bool,
+    /// Name of the snake's player, if given
+    pub player: Option<String>,
+    /// Whether the AI steers the snake while its player is gone
+    pub bot: bool,
+    /// Whether the AI steered the snake at some point of the match
+    pub taken_over: bool,
+    /// Whether the snake was eliminated because its player was gone
+    pub forfeited: bool,
+    // Direction changes waiting to be applied, one per tick
+    #[serde(skip)]
+    input_queue: VecDeque<Direction>,
+}
+
+/// Action of a team match's input log
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum TeamAction {
+    /// Direction change queued by the snake's player
+    Turn { direction: Direction },
+    /// The snake's player was gone, see `TeamMatch::abandon`
+    Abandon,
+    /// The snake's player came back, see `TeamMatch::reclaim`
+    Reclaim,
+}
+
+pub use input_log::TeamInput;
+
+// Deserializing the flattened action buffers the fields in serde's own value type, which
+// can hold floats; the log itself has none
+#[allow(clippy::disallowed_types)]
+mod input_log {
+    use super::TeamAction;
+    use serde::{Deserialize, Serialize};
+
+    /// Entry of a team match's input log
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct TeamInput {
+        /// Tick the action was taken at, before the match advanced past it
+        pub tick: u64,
+        pub snake: usize,
+        #[serde(flatten)]
+        pub action: TeamAction,
+    }
+}
+
+/// Recording of a team match: its settings, seed and input log, from which every state is
+/// derived like a single game's from its `Replay`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TeamReplay {
+    pub width: i32,
+    pub height: i32,
+    pub score_cap: u32,
+    pub rounds: u32,
+    pub bot_takeover: bool,
+    pub seed: u64,
+    /// Actions in tick order
+    pub inputs: Vec<TeamInput>,
+    /// Number of simulated steps
+    pub ticks: u64,
+}
+
+impl TeamReplay {
+    /// Re-simulates the match up to its last recorded tick
+    pub fn simulate(&self) -> Result<TeamMatch, String> {
+        let mut game = TeamMatch::new(self.width, self.height, self.score_cap, self.seed)?;
+        game.rounds = self.rounds;
+        game.bot_takeover = self.bot_takeover;
+        let mut inputs = self.inputs.iter().peekable();
+        loop {
+            while let Some(input) = inputs.next_if(|input| input.tick <= game.tick) {
+                game.apply(input);
+            }
+            if game.over || game.tick >= self.ticks {
+                return Ok(game);
+            }
+            game.update();
+        }
+    }
+}
+
+/// Result of a finished round of a team match
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RoundResult {
+    /// Number of the round, starting at 1
+    pub round: u32,
+    /// Team that won the round; None when both teams were eliminated at once
+    pub winner: Option<usize>,
+    /// Score of each team in the round
+    pub scores: [u32; TEAMS],
+    /// Ticks the round lasted
+    pub ticks: u64,
+}
+
+/// Match of two teams of two snakes on a walled board, played over one or more rounds
+/// Teammates pass through each other harmlessly, while a snake running into a wall, its own
+/// body or any snake of the other team is eliminated; food counts toward the team score
+/// A round ends when a team is eliminated or reaches the score cap, and the board is reset
+/// for the next one; the match ends when a team won more than half of its rounds or all
+/// were played
+#[derive(Clone, Serialize)]
+pub struct TeamMatch {
+    /// Number of steps simulated so far
+    pub tick: u64,
+    pub width: i32,
+    pub height: i32,
+    /// Snakes of team 0 come first, then those of team 1
+    pub snakes: Vec<TeamSnake>,
+    pub food: Point,
+    /// Score of each team in the current round
+    pub scores: [u32; TEAMS],
+    pub score_cap: u32,
+    /// Number of rounds the match is played over at most, best of `rounds`
+    pub rounds: u32,
+    /// Number of the current round, starting at 1
+    pub round: u32,
+    /// Rounds won by each team
+    pub round_wins: [u32; TEAMS],
+    /// Results of the finished rounds, in order
+    pub history: Vec<RoundResult>,
+    pub over: bool,
+    /// Team that won the match; None while it runs and when it ends in a draw
+    pub winner: Option<usize>,
+    /// Whether the AI takes over the snakes of players who are gone, rather than them
+    /// forfeiting
+    pub bot_takeover: bool,
+    // Tick the current round started at
+    #[serde(skip)]
+    round_start: u64,
+    // Seed of the match's generator, and the actions taken so far, see `TeamReplay`
+    #[serde(skip)]
+    seed: u64,
+    #[serde(skip)]
+    inputs: Vec<TeamInput>,
+    #[serde(skip)]
+    board: Solid,
+    #[serde(skip)]
+    rng: SeededRng,
+}
+
+impl TeamMatch {
+    /// Starts a single-round match on a board of the given size, see `lineup`
+    /// Fails when the board is too small to keep the snakes apart
+    pub fn new(width: i32, height: i32, score_cap: u32, seed: u64) -> Result<Self, String> {
+        if width < 4 * START_LENGTH || height < 2 * TEAM_SIZE as i32 + 1 {
+            return Err(format!(
+                "team matches need a board of at least {}x{}",
+                4 * START_LENGTH,
+                2 * TEAM_SIZE as i32 + 1
+            ));
+        }
+        let mut game = TeamMatch {
+            tick: 0,
+            width,
+            height,
+            snakes: Self::lineup(width, height),
+            food: Point { x: 0, y: 0, z: 0 },
+            scores: [0; TEAMS],
+            score_cap: score_cap.max(1),
+            rounds: 1,
+            round: 1,
+            round_wins: [0; TEAMS],
+            history: Vec::new(),
+            over: false,
+            winner: None,
+            bot_takeover: false,
+            round_start: 0,
+            seed,
+            inputs: Vec::new(),
+            board: Solid { width, height },
+            rng: SeededRng::new(seed),
+        };
+        game.spawn_food();
+        Ok(game)
+    }
+
+    /// Snakes at the start of a round: team 0 on the left heading right, team 1 mirrored on
+    /// the right, each snake `START_LENGTH` segments long
+    fn lineup(width: i32, height: i32) -> Vec<TeamSnake> {
+        let mut snakes = Vec::with_capacity(TEAMS * TEAM_SIZE);
+        for team in 0..TEAMS {
+            for member in 0..TEAM_SIZE {
+                // Rows split the board evenly between the members of a team
+                let y = (member as i32 + 1) * height / (TEAM_SIZE as i32 + 1);
+                let (direction, head_x, behind) = match team {
+                    0 => (Direction::Right, START_LENGTH, -1),
+                    _ => (Direction::Left, width - 1 - START_LENGTH, 1),
+                };
+                snakes.push(TeamSnake {
+                    team,
+                    body: (0..START_LENGTH)
+                        .map(|i| Point {
+                            x: head_x + i * behind,
+                            y,
+                            z: 0,
+                        })
+                        .collect(),
+                    direction,
+                    alive: true,
+                    player: None,
+                    bot: false,
+                    taken_over: false,
+                    forfeited: false,
+                    input_queue: VecDeque::new(),
+                });
+            }
+        }
+        snakes
+    }
+
+    /// Buffers a direction change of a snake for an upcoming tick, like `Game::queue_direction`
+    /// Rejected for unknown or eliminated snakes and once the match is over
+    pub fn queue_direction(&mut self, index: usize, direction: Direction) -> InputTiming {
+        let valid = !self.over && self.board.directions().contains(&direction);
+        let snake = self.snakes.get_mut(index).filter(|snake| snake.alive);
+        let Some(snake) = snake.filter(|_| valid) else {
+            return InputTiming::Rejected;
+        };
+        if snake.input_queue.back().copied().unwrap_or(snake.direction) == direction {
+            return InputTiming::Coalesced;
+        }
+        if snake.input_queue.len() >= MAX_QUEUED_INPUTS {
+            return InputTiming::QueueFull;
+        }
+        snake.input_queue.push_back(direction);
+        self.log(index, TeamAction::Turn { direction });
+        InputTiming::Queued
+    }
+
+    /// Adds an action to the input log
+    fn log(&mut self, snake: usize, action: TeamAction) {
+        self.inputs.push(TeamInput {
+            tick: self.tick,
+            snake,
+            action,
+        });
+    }
+
+    /// Takes a logged action again, when re-simulating a match
+    fn apply(&mut self, input: &TeamInput) {
+        match input.action {
+            TeamAction::Turn { direction } => {
+                self.queue_direction(input.snake, direction);
+            }
+            TeamAction::Abandon => self.abandon(input.snake),
+            TeamAction::Reclaim => self.reclaim(input.snake),
+        }
+    }
+
+    /// Recording of the match so far
+    pub fn replay(&self) -> TeamReplay {
+        TeamReplay {
+            width: self.width,
+            height: self.height,
+            score_cap: self.score_cap,
+            rounds: self.rounds,
+            bot_takeover: self.bot_takeover,
+            seed: self.seed,
+            inputs: self.inputs.clone(),
+            ticks: self.tick,
+        }
+    }
+
+    /// Handles a snake whose player is gone: the AI takes it over when the match allows it,
+    /// otherwise it forfeits and is eliminated
+    pub fn abandon(&mut self, index: usize) {
+        if self.over {
+            return;
+        }
+        let takeover = self.bot_takeover;
+        let snake = self.snakes.get_mut(index).filter(|snake| snake.alive);
+        let Some(snake) = snake.filter(|snake| !snake.bot) else {
+            return;
+        };
+        if takeover {
+            snake.bot = true;
+            snake.taken_over = true;
+            self.log(index, TeamAction::Abandon);
+        } else {
+            snake.alive = false;
+            snake.forfeited = true;
+            snake.body.clear();
+            snake.input_queue.clear();
+            self.log(index, TeamAction::Abandon);
+            self.settle();
+        }
+    }
+
+    /// Hands a snake the AI took over back to its returning player
+    pub fn reclaim(&mut self, index: usize) {
+        if self.over {
+            return;
+        }
+        let Some(snake) = self.snakes.get_mut(index).filter(|snake| snake.bot) else {
+            return;
+        };
+        snake.bot = false;
+        self.log(index, TeamAction::Reclaim);
+    }
+
+    /// Whether a snake moving onto a cell is eliminated: the cell is a wall, on the snake's
+    /// own body or on a snake of the other team
+    fn is_fatal(&self, snake: usize, point: Point) -> bool {
+        let team = self.snakes[snake].team;
+        self.board.is_wall(point)
+            || self
+                .snakes
+                .iter()
+                .enumerate()
+                .filter(|(i, other)| *i == snake || other.team != team)
+                .any(|(_, other)| other.body.contains(&point))
+    }
+
+    /// Direction the AI steers a snake in: the move closest to the food that is not fatal,
+    /// or straight on when every move is
+    fn bot_direction(&self, snake: usize) -> Direction {
+        let current = self.snakes[snake].direction;
+        let head = self.snakes[snake].body[0];
+        let food = self.food;
+        self.board
+            .directions()
+            .iter()
+            .copied()
+            .filter(|direction| *direction != current.opposite())
+            .map(|direction| (direction, self.board.neighbor(head, direction)))
+            .filter(|(_, next)| !self.is_fatal(snake, *next))
+            .min_by_key(|(_, next)| (next.x - food.x).abs() + (next.y - food.y).abs())
+            .map_or(current, |(direction, _)| direction)
+    }
+
+    /// Whether a cell is taken by a snake that is still alive
+    fn is_taken(&self, point: Point) -> bool {
+        self.snakes.iter().any(|snake| snake.body.contains(&point))
+    }
+
+    /// Places food on a random free cell drawn from the match's seeded generator, falling
+    /// back to a scan of the board; the food stays put when no cell is free
+    fn spawn_food(&mut self) {
+        for _ in 0..FOOD_SPAWN_ATTEMPTS {
+            let point = Point {
+                x: self.rng.gen_range(0, self.width),
+                y: self.rng.gen_range(0, self.height),
+                z: 0,
+            };
+            if !self.is_taken(point) {
+                self.food = point;
+                return;
+            }
+        }
+        let free = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| Point { x, y, z: 0 }))
+            .find(|point| !self.is_taken(*point));
+        if let Some(point) = free {
+            self.food = point;
+        }
+    }
+
+    /// Advances the match by one tick
+    /// All snakes move at once, their tails first unless they reach the food; then every snake
+    /// whose head is on a wall, on its own body or on a snake of the other team is
+    /// eliminated, so enemies meeting head-on are both out. Of the snakes reaching the food,
+    /// the first that survives eats it
+    /// Snakes the AI took over ignore their queued inputs and follow `bot_direction`
+    pub fn update(&mut self) {
+        if self.over {
+            return;
+        }
+        self.tick += 1;
+        let steered: Vec<(usize, Direction)> = (0..self.snakes.len())
+            .filter(|i| self.snakes[*i].alive && self.snakes[*i].bot)
+            .map(|i| (i, self.bot_direction(i)))
+            .collect();
+        for (i, direction) in steered {
+            self.snakes[i].input_queue.clear();
+            self.snakes[i].direction = direction;
+        }
+        for snake in self.snakes.iter_mut().filter(|snake| snake.alive) {
+            if let Some(direction) = snake.input_queue.pop_front() {
+                snake.direction = direction;
+            }
+            let head = self.board.neighbor(snake.body[0], snake.direction);
+            snake.body.push_front(head);
+            // Snakes reaching the food keep their tail until it is known who ate
+            if head != self.food {
+                snake.body.pop_back();
+            }
+        }
+
+        let eliminated: Vec<bool> = self
+            .snakes
+            .iter()
+            .map(|snake| {
+                let Some(head) = snake.body.front().copied() else {
+                    return false;
+                };
+                self.board.is_wall(head)
+                    || snake.body.iter().skip(1).any(|segment| *segment == head)
+                    || self
+                        .snakes
+                        .iter()
+                        .filter(|other| other.team != snake.team)
+                        .any(|other| other.body.contains(&head))
+            })
+            .collect();
+        for (snake, eliminated) in self.snakes.iter_mut().zip(eliminated) {
+            if eliminated {
+                snake.alive = false;
+                snake.body.clear();
+                snake.input_queue.clear();
+            }
+        }
+
+        let food = self.food;
+        let mut eater = None;
+        for snake in self.snakes.iter_mut().filter(|snake| snake.alive) {
+            if snake.body.front() == Some(&food) {
+                if eater.is_none() {
+                    eater = Some(snake.team);
+                } else {
+                    snake.body.pop_back();
+                }
+            }
+        }
+        if let Some(team) = eater {
+            self.scores[team] += 1;
+            self.spawn_food();
+        }
+        self.settle();
+    }
+
+    /// Ends the round once a team is eliminated or reaches the score cap
+    fn settle(&mut self) {
+        let capped = (0..TEAMS).find(|team| self.scores[*team] >= self.score_cap);
+        let winner = match (self.is_alive(0), self.is_alive(1), capped) {
+            (true, true, None) => return,
+            (true, true, Some(team)) => Some(team),
+            (true, false, _) => Some(0),
+            (false, true, _) => Some(1),
+            (false, false, _) => None,
+        };
+        self.end_round(winner);
+    }
+
+    /// Records the result of the current round, then ends the match once a team won more
+    /// than half of its rounds or all were played, or starts the next round
+    fn end_round(&mut self, winner: Option<usize>) {
+        self.history.push(RoundResult {
+            round: self.round,
+            winner,
+            scores: self.scores,
+            ticks: self.tick - self.round_start,
+        });
+        if let Some(team) = winner {
+            self.round_wins[team] += 1;
+        }
+        let decided = (0..TEAMS).find(|team| self.round_wins[*team] > self.rounds / 2);
+        if decided.is_some() || self.round >= self.rounds {
+            self.finish(decided.or_else(|| self.leader()));
+        } else {
+            self.next_round();
+        }
+    }
+
+    /// Team with the most round wins, None when teams are level
+    fn leader(&self) -> Option<usize> {
+        let most = self.round_wins.iter().max().copied()?;
+        let mut leaders = (0..TEAMS).filter(|team| self.round_wins[*team] == most);
+        let leader = leaders.next();
+        leaders.next().map_or(leader, |_| None)
+    }
+
+    /// Resets the board for the next round; snakes the AI took over stay with it until
+    /// their players are back
+    fn next_round(&mut self) {
+        let previous = std::mem::replace(&mut self.snakes, Self::lineup(self.width, self.height));
+        for (snake, previous) in self.snakes.iter_mut().zip(previous) {
+            snake.player = previous.player;
+            snake.bot = previous.bot;
+            snake.taken_over = previous.taken_over;
+        }
+        self.scores = [0; TEAMS];
+        self.round += 1;
+        self.round_start = self.tick;
+        self.spawn_food();
+    }
+
+    /// Whether a team has a snake left
+    fn is_alive(&self, team: usize) -> bool {
+        self.snakes
+            .iter()
+            .any(|snake| snake.team == team && snake.alive)
+    }
+
+    /// Ends the match
+    fn finish(&mut self, winner: Option<usize>) {
+        self.over = true;
+        self.winner = winner;
+    }
+}
+
+/// Team match together with the time it was last played, for collecting idle matches, the
+/// times its players were last seen and the tokens of its host and players
+struct TeamSession {
+    game: TeamMatch,
+    last_seen: Instant,
+    // Last request of each snake's player: a direction change or a poll of the state
+    players_seen: [Instant; TEAMS * TEAM_SIZE],
+    // Whether the finished match was added to the match history
+    recorded: bool,
+    // Token of the match's creator, who advances the match
+    host_token: String,
+    // Token of each snake's player, handed out by the creator
+    snake_tokens: [String; TEAMS * TEAM_SIZE],
+}
+
+impl TeamSession {
+    /// Checks a token against the one of the match's creator
+    fn authorizes_host(&self, token: Option<&str>) -> bool {
+        token == Some(self.host_token.as_str())
+    }
+
+    /// Checks a token against the one of a snake's player
+    fn authorizes_snake(&self, snake: usize, token: Option<&str>) -> bool {
+        token.is_some() && self.snake_tokens.get(snake).map(String::as_str) == token
+    }
+
+    /// Records a request of a snake's player, who takes the snake back from the AI
+    fn seen(&mut self, snake: usize) {
+        if let Some(seen) = self.players_seen.get_mut(snake) {
+            *seen = Instant::now();
+            self.game.reclaim(snake);
+        }
+    }
+
+    /// Hands the snakes of players gone for longer than the grace period to the AI, or has
+    /// them forfeit
+    fn abandon_absent(&mut self, grace: Duration) {
+        for (snake, seen) in self.players_seen.iter().enumerate() {
+            if seen.elapsed() > grace {
+                self.game.abandon(snake);
+            }
+        }
+    }
+}
+
+/// Running and finished team matches by ID, kept in memory only
+#[derive(Default)]
+pub struct TeamMatches {
+    matches: Mutex<BTreeMap<String, TeamSession>>,
+}
+
+impl TeamMatches {
+    /// Removes matches nobody played for longer than the grace period
+    /// Returns the number of matches removed
+    pub fn collect_idle(&self, grace: Duration) -> usize {
+        let mut matches = self.matches.lock().unwrap();
+        let before = matches.len();
+        matches.retain(|_, session| session.last_seen.elapsed() <= grace);
+        before - matches.len()
+    }
+}
+
+/// Optional body of the new team match endpoint
+#[derive(Default, Deserialize)]
+pub struct NewTeamMatchOptions {
+    // Team score that wins a round, `DEFAULT_SCORE_CAP` by default
+    #[serde(default)]
+    score_cap: Option<u32>,
+    // Number of rounds the match is played over at most, best of one by default
+    #[serde(default)]
+    rounds: Option<u32>,
+    // Whether the AI takes over the snakes of players who are gone, who forfeit by default
+    #[serde(default)]
+    bot_takeover: bool,
+    // Names of the snakes' players in snake order, under which the match is recorded in
+    // the match history; anonymous by default
+    #[serde(default)]
+    players: Vec<Option<String>>,
+}
+
+/// Starts a 2v2 team match on the standard board, played over best of `rounds`
+/// Player names follow the rules of leaderboard names
+/// Responds with the match ID, its state, the `host_token` advancing the match and the
+/// `snake_tokens` the creator hands to the players, 400 Bad Request for more than `MAX_ROUNDS`
+/// rounds, more than four players or invalid names, or 429 Too Many Requests while
+/// `MAX_TEAM_MATCHES` matches are kept
+pub async fn create(
+    options: Option<web::Json<NewTeamMatchOptions>>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let options = options.map(web::Json::into_inner).unwrap_or_default();
+    let score_cap = options.score_cap.unwrap_or(DEFAULT_SCORE_CAP);
+    let rounds = options.rounds.unwrap_or(1);
+    if !(1..=MAX_ROUNDS).contains(&rounds) {
+        return HttpResponse::BadRequest()
+            .json(json!({ "error": format!("matches have 1 to {} rounds", MAX_ROUNDS) }));
+    }
+    let mut game = match TeamMatch::new(BOARD_WIDTH, BOARD_HEIGHT, score_cap, rand::random()) {
+        Ok(game) => game,
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e })),
+    };
+    game.bot_takeover = options.bot_takeover;
+    game.rounds = rounds;
+    if options.players.len() > game.snakes.len() {
+        return HttpResponse::BadRequest()
+            .json(json!({ "error": "a match has one player per snake" }));
+    }
+    let config = data.config();
+    for (snake, name) in game.snakes.iter_mut().zip(&options.players) {
+        snake.player = match name.as_deref().map(|name| config.name_filter.clean(name)) {
+            Some(Ok(name)) => Some(name),
+            Some(Err(e)) => return HttpResponse::BadRequest().json(e.to_json()),
+            None => None,
+        };
+    }
+    let mut matches = data.team_matches.matches.lock().unwrap();
+    if matches.len() >= MAX_TEAM_MATCHES {
+        return HttpResponse::TooManyRequests()
+            .json(json!({ "error": "too many team matches are running" }));
+    }
+    let match_id = Uuid::new_v4().to_string();
+    let host_token = Uuid::new_v4().to_string();
+    let snake_tokens: [String; TEAMS * TEAM_SIZE] =
+        std::array::from_fn(|_| Uuid::new_v4().to_string());
+    let response = json!({
+        "match_id": match_id,
+        "state": game,
+        "host_token": host_token,
+        "snake_tokens": snake_tokens,
+    });
+    let session = TeamSession {
+        game,
+        last_seen: Instant::now(),
+        players_seen: [Instant::now(); TEAMS * TEAM_SIZE],
+        recorded: false,
+        host_token,
+        snake_tokens,
+    };
+    matches.insert(match_id, session);
+    HttpResponse::Ok().json(response)
+}
+
+/// Query parameters of the team match state endpoint
+#[derive(Deserialize)]
+pub struct StateQuery {
+    // Snake of the polling player, who is then counted as present
+    snake: Option<usize>,
+}
+
+/// Returns the current state of a team match
+/// Players poll it with their `snake` and its token in the `X-Session-Token` header to stay
+/// present, see `update`; 401 Unauthorized when the token is not the snake's
+/// match_id: Unique identifier for the team match
+pub async fn state(
+    req: HttpRequest,
+    match_id: web::Path<String>,
+    query: web::Query<StateQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let mut matches = data.team_matches.matches.lock().unwrap();
+    let Some(session) = matches.get_mut(match_id.as_str()) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if let Some(snake) = query.snake {
+        if !session.authorizes_snake(snake, session_token(&req)) {
+            return HttpResponse::Unauthorized().json(json!({ "error": "invalid snake token" }));
+        }
+        session.seen(snake);
+    }
+    HttpResponse::Ok().json(&session.game)
+}
+
+/// Updates the direction of one snake of a team match
+/// Requires the snake's token in the `X-Session-Token` header: 401 Unauthorized otherwise
+/// The response gives the `timing` of the input, as for single games
+/// match_id: Unique identifier for the team match
+/// snake: Index of the snake, 0 and 1 for team 0, 2 and 3 for team 1
+pub async fn direction(
+    req: HttpRequest,
+    path: web::Path<(String, usize)>,
+    direction: web::Json<Direction>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let (match_id, snake) = path.into_inner();
+    let mut matches = data.team_matches.matches.lock().unwrap();
+    let Some(session) = matches.get_mut(&match_id) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if !session.authorizes_snake(snake, session_token(&req)) {
+        return HttpResponse::Unauthorized().json(json!({ "error": "invalid snake token" }));
+    }
+    session.last_seen = Instant::now();
+    session.seen(snake);
+    let timing = session.game.queue_direction(snake, direction.into_inner());
+    let body = json!({ "timing": timing, "tick": session.game.tick });
+    if timing == InputTiming::QueueFull {
+        HttpResponse::TooManyRequests().json(body)
+    } else {
+        HttpResponse::Ok().json(body)
+    }
+}
+
+/// Advances a team match by one tick and returns its new state
+/// Players who neither changed direction nor polled the state with their snake for
+/// `disconnect_grace_secs` are gone: the AI takes over their snake when the match allows
+/// it, otherwise the snake forfeits; a player coming back takes over again
+/// Only the match's creator advances it, with the `host_token` in the `X-Session-Token`
+/// header: 401 Unauthorized otherwise
+/// match_id: Unique identifier for the team match
+pub async fn update(
+    req: HttpRequest,
+    match_id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let grace = Duration::from_secs(data.config().disconnect_grace_secs);
+    let mut matches = data.team_matches.matches.lock().unwrap();
+    let Some(session) = matches.get_mut(match_id.as_str()) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if !session.authorizes_host(session_token(&req)) {
+        return HttpResponse::Unauthorized().json(json!({ "error": "invalid host token" }));
+    }
+    session.last_seen = Instant::now();
+    session.abandon_absent(grace);
+    session.game.update();
+    if session.game.over && !session.recorded {
+        session.recorded = true;
+        data.match_history
+            .record(MatchRecord::new(match_id.as_str(), &session.game));
+    }
+    HttpResponse::Ok().json(&session.game)
+}
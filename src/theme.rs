@@ -0,0 +1,247 @@
+use crate::server::AppState;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Longest host name a tenant theme is looked up for
+const MAX_HOST_LEN: usize = 253;
+
+/// Look of the bundled frontend, served at `GET /api/theme`
+/// Configured in the `[theme]` table; the defaults are the frontend's built-in look
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeManifest {
+    /// Name shown to players, e.g. in the page title
+    pub name: String,
+    pub colors: ThemeColors,
+    /// Images drawn instead of colored cells, e.g. `/static/head.png`
+    pub sprites: Sprites,
+    /// Image drawn behind the board instead of the background color
+    pub background_image: Option<String>,
+    pub sounds: Sounds,
+}
+
+/// Colors of the board, as CSS hex colors (`#rgb` or `#rrggbb`)
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeColors {
+    pub background: String,
+    /// Cells of hex boards
+    pub board: String,
+    pub head: String,
+    pub body: String,
+    pub food: String,
+    pub wall: String,
+    pub portal: String,
+    /// Moving hazards
+    pub hazard: String,
+    pub ladder: String,
+    pub zone: String,
+    /// Ice tiles
+    pub ice: String,
+    /// Speed strips
+    pub speed: String,
+    /// One-way gates
+    pub gate: String,
+    /// Keys, locked doors and the exit of a level's objective
+    pub key: String,
+    pub door: String,
+    pub exit: String,
+}
+
+/// Images of the snake and food; cells without one are drawn in their color
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sprites {
+    pub head: Option<String>,
+    pub body: Option<String>,
+    pub food: Option<String>,
+}
+
+/// Sound effects the frontend plays
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sounds {
+    /// A blip when the snake eats
+    pub eat: bool,
+    /// A low tone when the game ends
+    pub game_over: bool,
+}
+
+impl Default for ThemeManifest {
+    fn default() -> Self {
+        ThemeManifest {
+            name: "Snake Game".to_string(),
+            colors: ThemeColors::default(),
+            sprites: Sprites::default(),
+            background_image: None,
+            sounds: Sounds::default(),
+        }
+    }
+}
+
+impl Default for ThemeColors {
+    fn default() -> Self {
+        ThemeColors {
+            background: "#ffffff".to_string(),
+            board: "#eeeeee".to_string(),
+            head: "#008000".to_string(),
+            body: "#008000".to_string(),
+            food: "#ff0000".to_string(),
+            wall: "#000000".to_string(),
+            portal: "#800080".to_string(),
+            hazard: "#ff8c00".to_string(),
+            ladder: "#0000ff".to_string(),
+            zone: "#fff3b0".to_string(),
+            ice: "#add8e6".to_string(),
+            speed: "#ffff00".to_string(),
+            gate: "#00008b".to_string(),
+            key: "#daa520".to_string(),
+            door: "#8b4513".to_string(),
+            exit: "#00bfff".to_string(),
+        }
+    }
+}
+
+/// Whether a color is a CSS hex color, `#rgb` or `#rrggbb`
+fn is_hex_color(color: &str) -> bool {
+    color
+        .strip_prefix('#')
+        .is_some_and(|hex| matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Whether an image URL is a path on this server or an HTTP(S) URL
+fn is_image_url(url: &str) -> bool {
+    (url.starts_with('/') && !url.starts_with("//"))
+        || url.starts_with("https://")
+        || url.starts_with("http://")
+}
+
+impl ThemeManifest {
+    /// Fails with a message naming the first color that is not a hex color or image that
+    /// is not a path or HTTP(S) URL, so a theme cannot inject styles or scripts
+    pub fn validate(&self) -> Result<(), String> {
+        let colors = &self.colors;
+        let colors = [
+            ("background", &colors.background),
+            ("board", &colors.board),
+            ("head", &colors.head),
+            ("body", &colors.body),
+            ("food", &colors.food),
+            ("wall", &colors.wall),
+            ("portal", &colors.portal),
+            ("hazard", &colors.hazard),
+            ("ladder", &colors.ladder),
+            ("zone", &colors.zone),
+            ("ice", &colors.ice),
+            ("speed", &colors.speed),
+            ("gate", &colors.gate),
+            ("key", &colors.key),
+            ("door", &colors.door),
+            ("exit", &colors.exit),
+        ];
+        if let Some((name, color)) = colors.iter().find(|(_, color)| !is_hex_color(color)) {
+            return Err(format!(
+                "theme color {} is not a hex color: {}",
+                name, color
+            ));
+        }
+        let images = [
+            ("sprites.head", &self.sprites.head),
+            ("sprites.body", &self.sprites.body),
+            ("sprites.food", &self.sprites.food),
+            ("background_image", &self.background_image),
+        ];
+        for (name, image) in images {
+            if let Some(url) = image.as_deref().filter(|url| !is_image_url(url)) {
+                return Err(format!(
+                    "theme image {} is not a path or URL: {}",
+                    name, url
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Overlays the settings of `overlay` on `base`, table by table
+fn merge(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Theme of a tenant: the file `<host>.toml` in `theme_dir`, whose settings override those
+/// of the configured theme
+/// Fails with a message for an unreadable or invalid file; None when the host has none
+fn tenant_theme(
+    dir: &str,
+    host: &str,
+    base: &ThemeManifest,
+) -> Result<Option<ThemeManifest>, String> {
+    // Host names only, so a crafted Host header cannot name a file outside the directory
+    let host = host
+        .split(':')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let valid = !host.is_empty()
+        && host.len() <= MAX_HOST_LEN
+        && !host.starts_with('.')
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    if !valid {
+        return Ok(None);
+    }
+    let path = Path::new(dir).join(format!("{}.toml", host));
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("cannot read {}: {}", path.display(), e)),
+    };
+    let overlay: toml::Value = toml::from_str(&contents)
+        .map_err(|e| format!("invalid theme {}: {}", path.display(), e))?;
+    let mut theme = toml::Value::try_from(base).map_err(|e| e.to_string())?;
+    merge(&mut theme, overlay);
+    let theme: ThemeManifest = theme
+        .try_into()
+        .map_err(|e| format!("invalid theme {}: {}", path.display(), e))?;
+    theme
+        .validate()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(Some(theme))
+}
+
+/// Serves the theme manifest of the bundled frontend
+/// With `theme_dir` configured, the request's host picks a tenant theme from it; hosts
+/// without one, or with an invalid one, get the configured theme
+pub async fn theme(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    let config = data.config();
+    let host = req.connection_info().host().to_string();
+    let tenant = config
+        .theme_dir
+        .as_deref()
+        .map(|dir| tenant_theme(dir, &host, &config.theme))
+        .transpose()
+        .unwrap_or_else(|e| {
+            tracing::warn!(host = %host, "tenant theme ignored: {}", e);
+            None
+        })
+        .flatten();
+    HttpResponse::Ok()
+        // Themes change with the configuration, so clients check back on every page load
+        .insert_header(("Cache-Control", "no-cache"))
+        .json(tenant.as_ref().unwrap_or(&config.theme))
+}
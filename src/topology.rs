@@ -0,0 +1,475 @@
+#![deny(clippy::disallowed_types)]
+
+use crate::fixed::Fixed;
+use crate::game::{Direction, Point};
+use serde::{Deserialize, Serialize};
+
+/// Shape of a game board: where a step leads and which cells cannot be entered
+/// Game rules and the AI only move through this trait, so board variants need no
+/// bounds checks of their own elsewhere
+pub trait Topology {
+    /// Cell reached by moving one step from `point` in `direction`
+    fn neighbor(&self, point: Point, direction: Direction) -> Point;
+
+    /// Whether the snake dies when entering `point`
+    fn is_wall(&self, point: Point) -> bool;
+
+    /// Directions the snake can move in
+    fn directions(&self) -> &'static [Direction] {
+        &SQUARE_DIRECTIONS
+    }
+
+    /// Number of stacked layers; cells of flat boards all lie on layer zero
+    fn layers(&self) -> i32 {
+        1
+    }
+
+    /// Whether `point` is only passed through, like a ladder, so food never goes there
+    fn is_passage(&self, _point: Point) -> bool {
+        false
+    }
+
+    /// Distance between two cells as estimated by the AI and fleeing food
+    fn distance(&self, a: Point, b: Point) -> Fixed {
+        Fixed::hypot(a.x - b.x, a.y - b.y)
+    }
+}
+
+/// Movement directions of square boards
+const SQUARE_DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+/// Movement directions of hex boards
+const HEX_DIRECTIONS: [Direction; 6] = [
+    Direction::Left,
+    Direction::Right,
+    Direction::UpLeft,
+    Direction::UpRight,
+    Direction::DownLeft,
+    Direction::DownRight,
+];
+
+/// Moves one cell in a direction without any boundary handling
+fn step(point: Point, direction: Direction) -> Point {
+    match direction {
+        Direction::Up => Point {
+            x: point.x,
+            y: point.y - 1,
+            z: point.z,
+        },
+        Direction::Down => Point {
+            x: point.x,
+            y: point.y + 1,
+            z: point.z,
+        },
+        Direction::Left => Point {
+            x: point.x - 1,
+            y: point.y,
+            z: point.z,
+        },
+        Direction::Right => Point {
+            x: point.x + 1,
+            y: point.y,
+            z: point.z,
+        },
+        Direction::UpLeft => Point {
+            x: point.x - 1,
+            y: point.y - 1,
+            z: point.z,
+        },
+        Direction::UpRight => Point {
+            x: point.x + 1,
+            y: point.y - 1,
+            z: point.z,
+        },
+        Direction::DownLeft => Point {
+            x: point.x - 1,
+            y: point.y + 1,
+            z: point.z,
+        },
+        Direction::DownRight => Point {
+            x: point.x + 1,
+            y: point.y + 1,
+            z: point.z,
+        },
+    }
+}
+
+/// Whether a point lies outside a board of the given size
+fn out_of_bounds(point: Point, width: i32, height: i32) -> bool {
+    point.x < 0 || point.x >= width || point.y < 0 || point.y >= height
+}
+
+/// Classic board surrounded by walls
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Solid {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Topology for Solid {
+    fn neighbor(&self, point: Point, direction: Direction) -> Point {
+        step(point, direction)
+    }
+
+    fn is_wall(&self, point: Point) -> bool {
+        out_of_bounds(point, self.width, self.height)
+    }
+}
+
+/// Board without walls whose edges wrap around to the opposite side
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Wrap {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Topology for Wrap {
+    fn neighbor(&self, point: Point, direction: Direction) -> Point {
+        let next = step(point, direction);
+        Point {
+            x: next.x.rem_euclid(self.width.max(1)),
+            y: next.y.rem_euclid(self.height.max(1)),
+            z: next.z,
+        }
+    }
+
+    fn is_wall(&self, _point: Point) -> bool {
+        false
+    }
+
+    /// Shortest distance, going across edges where that is closer
+    fn distance(&self, a: Point, b: Point) -> Fixed {
+        let dx = (a.x - b.x).abs();
+        let dy = (a.y - b.y).abs();
+        let dx = dx.min(self.width - dx);
+        let dy = dy.min(self.height - dy);
+        Fixed::hypot(dx, dy)
+    }
+}
+
+/// Walled board with additional wall cells inside, e.g. a maze
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObstacleGrid {
+    pub width: i32,
+    pub height: i32,
+    /// Wall cells inside the board
+    pub walls: Vec<Point>,
+}
+
+impl ObstacleGrid {
+    /// Creates the grid, dropping walls outside the board and on the starting cell
+    pub fn new(width: i32, height: i32, walls: Vec<Point>) -> Self {
+        let start = start_point(width, height);
+        let mut walls: Vec<Point> = walls
+            .into_iter()
+            .filter(|wall| !out_of_bounds(*wall, width, height) && *wall != start)
+            .collect();
+        walls.sort_by_key(|wall| (wall.y, wall.x));
+        walls.dedup();
+        ObstacleGrid {
+            width,
+            height,
+            walls,
+        }
+    }
+}
+
+impl Topology for ObstacleGrid {
+    fn neighbor(&self, point: Point, direction: Direction) -> Point {
+        step(point, direction)
+    }
+
+    fn is_wall(&self, point: Point) -> bool {
+        out_of_bounds(point, self.width, self.height)
+            || self
+                .walls
+                .binary_search_by_key(&(point.y, point.x), |wall| (wall.y, wall.x))
+                .is_ok()
+    }
+}
+
+/// Hexagonal board of pointy-top cells in axial coordinates
+/// `x` is the axial column `q` and `y` the row `r`; the board is the hexagon of
+/// `radius` around the cell (`radius`, `radius`), so both coordinates lie in `0..=2 * radius`
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hex {
+    pub radius: i32,
+}
+
+impl Hex {
+    /// Axial offset of a point from the center cell
+    fn offset(&self, point: Point) -> (i32, i32) {
+        (point.x - self.radius, point.y - self.radius)
+    }
+
+    /// Width and height of the bounding box of the board
+    pub fn size(&self) -> i32 {
+        2 * self.radius + 1
+    }
+}
+
+impl Topology for Hex {
+    fn neighbor(&self, point: Point, direction: Direction) -> Point {
+        let (dq, dr) = match direction {
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+            Direction::UpLeft => (0, -1),
+            Direction::UpRight => (1, -1),
+            Direction::DownLeft => (-1, 1),
+            Direction::DownRight => (0, 1),
+            // Not a hex direction; rejected before it reaches the board
+            Direction::Up | Direction::Down => (0, 0),
+        };
+        Point {
+            x: point.x + dq,
+            y: point.y + dr,
+            z: point.z,
+        }
+    }
+
+    fn is_wall(&self, point: Point) -> bool {
+        let (q, r) = self.offset(point);
+        q.abs().max(r.abs()).max((q + r).abs()) > self.radius
+    }
+
+    fn directions(&self) -> &'static [Direction] {
+        &HEX_DIRECTIONS
+    }
+
+    /// Number of hex steps between the cells
+    fn distance(&self, a: Point, b: Point) -> Fixed {
+        let dq = a.x - b.x;
+        let dr = a.y - b.y;
+        Fixed::from_int((dq.unsigned_abs() + dr.unsigned_abs() + (dq + dr).unsigned_abs()) / 2)
+    }
+}
+
+/// Most layers a layered board may have
+pub const MAX_LAYERS: i32 = 4;
+
+/// Stack of walled layers connected by ladders
+/// A ladder at (`x`, `y`, `z`) links that cell to the same cell one layer up: a
+/// snake stepping onto it climbs to layer `z + 1`, and a snake stepping onto the
+/// upper end descends to layer `z`
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Layered {
+    pub width: i32,
+    pub height: i32,
+    pub layers: i32,
+    /// Lower ends of the ladders
+    pub ladders: Vec<Point>,
+}
+
+impl Layered {
+    /// Creates the stack, dropping ladders that leave the board or start on the starting cell
+    pub fn new(width: i32, height: i32, layers: i32, ladders: Vec<Point>) -> Self {
+        let layers = layers.clamp(1, MAX_LAYERS);
+        let start = start_point(width, height);
+        let mut ladders: Vec<Point> = ladders
+            .into_iter()
+            .filter(|ladder| {
+                !out_of_bounds(*ladder, width, height)
+                    && ladder.z >= 0
+                    && ladder.z < layers - 1
+                    && (ladder.x, ladder.y) != (start.x, start.y)
+            })
+            .collect();
+        ladders.sort_by_key(|ladder| (ladder.z, ladder.y, ladder.x));
+        ladders.dedup();
+        Layered {
+            width,
+            height,
+            layers,
+            ladders,
+        }
+    }
+
+    /// Whether a ladder starts at `point`
+    fn has_ladder(&self, point: Point) -> bool {
+        self.ladders
+            .binary_search_by_key(&(point.z, point.y, point.x), |ladder| {
+                (ladder.z, ladder.y, ladder.x)
+            })
+            .is_ok()
+    }
+
+    /// Shortest distance within one layer
+    fn flat_distance(a: Point, b: Point) -> Fixed {
+        Fixed::hypot(a.x - b.x, a.y - b.y)
+    }
+}
+
+impl Topology for Layered {
+    fn neighbor(&self, point: Point, direction: Direction) -> Point {
+        let mut next = step(point, direction);
+        if self.has_ladder(next) {
+            next.z += 1;
+        } else if self.has_ladder(Point {
+            z: next.z - 1,
+            ..next
+        }) {
+            next.z -= 1;
+        }
+        next
+    }
+
+    fn is_wall(&self, point: Point) -> bool {
+        out_of_bounds(point, self.width, self.height) || point.z < 0 || point.z >= self.layers
+    }
+
+    fn layers(&self) -> i32 {
+        self.layers
+    }
+
+    fn is_passage(&self, point: Point) -> bool {
+        self.has_ladder(point)
+            || self.has_ladder(Point {
+                z: point.z - 1,
+                ..point
+            })
+    }
+
+    /// Distance within the layer, or via the best ladder leading towards the target layer
+    fn distance(&self, a: Point, b: Point) -> Fixed {
+        if a.z == b.z {
+            return Layered::flat_distance(a, b);
+        }
+        let ladder_layer = if b.z > a.z { a.z } else { a.z - 1 };
+        self.ladders
+            .iter()
+            .filter(|ladder| ladder.z == ladder_layer)
+            .map(|ladder| {
+                Layered::flat_distance(a, *ladder)
+                    + Layered::flat_distance(*ladder, b)
+                    + Fixed::from_int((b.z - a.z).unsigned_abs())
+            })
+            .min()
+            .unwrap_or(Fixed::MAX)
+    }
+}
+
+/// Cell the snake starts on: the center of the board
+pub fn start_point(width: i32, height: i32) -> Point {
+    Point {
+        x: width / 2,
+        y: height / 2,
+        z: 0,
+    }
+}
+
+/// Topology of a game, serialized with the game state so clients can draw it
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Board {
+    Solid(Solid),
+    Wrap(Wrap),
+    Obstacles(ObstacleGrid),
+    Hex(Hex),
+    Layered(Layered),
+}
+
+impl Board {
+    /// Width and height of the board's bounding box
+    pub fn size(&self) -> (i32, i32) {
+        match self {
+            Board::Solid(Solid { width, height })
+            | Board::Wrap(Wrap { width, height })
+            | Board::Obstacles(ObstacleGrid { width, height, .. })
+            | Board::Layered(Layered { width, height, .. }) => (*width, *height),
+            Board::Hex(hex) => (hex.size(), hex.size()),
+        }
+    }
+
+    /// Whether the board uses hex cells
+    pub fn is_hex(&self) -> bool {
+        matches!(self, Board::Hex(_))
+    }
+
+    /// Wall cells inside the board, for renderers
+    pub fn inner_walls(&self) -> &[Point] {
+        match self {
+            Board::Obstacles(grid) => &grid.walls,
+            Board::Solid(_) | Board::Wrap(_) | Board::Hex(_) | Board::Layered(_) => &[],
+        }
+    }
+
+    /// The topology implementation behind this board
+    fn topology(&self) -> &dyn Topology {
+        match self {
+            Board::Solid(solid) => solid,
+            Board::Wrap(wrap) => wrap,
+            Board::Obstacles(grid) => grid,
+            Board::Hex(hex) => hex,
+            Board::Layered(layered) => layered,
+        }
+    }
+}
+
+impl Topology for Board {
+    fn neighbor(&self, point: Point, direction: Direction) -> Point {
+        self.topology().neighbor(point, direction)
+    }
+
+    fn is_wall(&self, point: Point) -> bool {
+        self.topology().is_wall(point)
+    }
+
+    fn directions(&self) -> &'static [Direction] {
+        self.topology().directions()
+    }
+
+    fn layers(&self) -> i32 {
+        self.topology().layers()
+    }
+
+    fn is_passage(&self, point: Point) -> bool {
+        self.topology().is_passage(point)
+    }
+
+    fn distance(&self, a: Point, b: Point) -> Fixed {
+        self.topology().distance(a, b)
+    }
+}
+
+/// Board variant requested by a client; the server supplies the dimensions
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TopologySpec {
+    #[default]
+    Solid,
+    Wrap,
+    Obstacles {
+        walls: Vec<Point>,
+    },
+    /// Hexagon fitting into the requested dimensions
+    Hex,
+    /// Experimental stack of layers connected by ladders
+    Layered {
+        layers: i32,
+        ladders: Vec<Point>,
+    },
+}
+
+impl TopologySpec {
+    /// Builds the board for the given dimensions
+    pub fn build(self, width: i32, height: i32) -> Board {
+        match self {
+            TopologySpec::Solid => Board::Solid(Solid { width, height }),
+            TopologySpec::Wrap => Board::Wrap(Wrap { width, height }),
+            TopologySpec::Obstacles { walls } => {
+                Board::Obstacles(ObstacleGrid::new(width, height, walls))
+            }
+            TopologySpec::Hex => Board::Hex(Hex {
+                radius: (width.min(height) - 1).max(0) / 2,
+            }),
+            TopologySpec::Layered { layers, ladders } => {
+                Board::Layered(Layered::new(width, height, layers, ladders))
+            }
+        }
+    }
+}
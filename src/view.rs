@@ -0,0 +1,337 @@
+use crate::game::{
+    ComboScoring, Completion, Direction, FoodBehavior, FoodExpiry, Game, Gate, Growth, Hazard,
+    Modifiers, Objective, Partner, Phase, Point, Portal, Split, Tile, Zone,
+};
+use crate::state_hash;
+use crate::topology::Board;
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::borrow::Borrow;
+use std::collections::VecDeque;
+
+/// Public JSON schema of a game's state, kept apart from the engine's `Game`
+/// Fields are camelCase and only change deliberately; engine bookkeeping such as pending
+/// growth, snapshots, the seed or the input log stays out of it
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameView<'a> {
+    /// Number of steps simulated so far
+    pub tick: u64,
+    /// Rolling hash of the states up to `tick`, as 16 hex digits; clients, nodes and replay
+    /// players compare it at equal ticks to detect diverging simulations
+    pub state_hash: String,
+    /// Snake body, head first
+    pub snake: &'a VecDeque<Point>,
+    pub food: Point,
+    pub direction: Direction,
+    /// Second snake of a co-op game, which shares the score
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partner: Option<PartnerView<'a>>,
+    pub score: u32,
+    pub food_eaten: u32,
+    pub game_over: bool,
+    /// Stage of the game: `starting` during the countdown, `running` or `over`
+    pub phase: Phase,
+    /// Ticks until the snake starts moving, zero once it does
+    pub countdown_ticks_left: u32,
+    pub width: i32,
+    pub height: i32,
+    /// Shape of the board, tagged by `kind`
+    pub topology: &'a Board,
+    pub modifiers: ModifiersView,
+    pub food_behavior: FoodBehaviorView,
+    pub food_expiry: Option<FoodExpiryView>,
+    /// Ticks until the current food expires
+    pub food_ticks_left: Option<u32>,
+    pub combo: Option<ComboView>,
+    pub combo_multiplier: u32,
+    /// Ticks left in the combo window
+    pub combo_ticks_left: Option<u32>,
+    pub growth: GrowthView,
+    pub practice: bool,
+    pub splits: Vec<SplitView>,
+    pub zones: &'a [Zone],
+    pub portals: &'a [Portal],
+    pub hazards: &'a [Hazard],
+    /// Cells of the moving hazards on the current tick
+    pub hazard_cells: Vec<Point>,
+    pub tiles: &'a [Tile],
+    pub gates: &'a [Gate],
+    pub objective: Option<&'a Objective>,
+    /// Keys picked up, whose doors are open
+    pub keys_held: &'a [u32],
+    /// Cells of the keys and treasures picked up
+    pub collected: &'a [Point],
+    /// How fast the objective was completed, once it was
+    pub completion: Option<Completion>,
+    /// Custom level the game is played on
+    pub level: Option<&'a str>,
+}
+
+impl<'a> From<&'a Game> for GameView<'a> {
+    fn from(game: &'a Game) -> Self {
+        GameView {
+            tick: game.tick,
+            state_hash: state_hash::to_hex(game.rolling_hash),
+            snake: &game.snake,
+            food: game.food,
+            direction: game.direction,
+            partner: game.partner.as_ref().map(Into::into),
+            score: game.score,
+            food_eaten: game.food_eaten,
+            game_over: game.game_over,
+            phase: game.phase(),
+            countdown_ticks_left: game.countdown_ticks_left(),
+            width: game.width,
+            height: game.height,
+            topology: &game.topology,
+            modifiers: game.modifiers.into(),
+            food_behavior: game.food_behavior.into(),
+            food_expiry: game.food_expiry.map(Into::into),
+            food_ticks_left: game.food_ticks_left,
+            combo: game.combo.map(Into::into),
+            combo_multiplier: game.combo_multiplier,
+            combo_ticks_left: game.combo_ticks_left,
+            growth: game.growth.into(),
+            practice: game.practice,
+            splits: game.splits.iter().copied().map(Into::into).collect(),
+            zones: &game.zones,
+            portals: &game.portals,
+            hazards: &game.hazards,
+            hazard_cells: game.hazard_cells(game.tick).collect(),
+            tiles: &game.tiles,
+            gates: &game.gates,
+            objective: game.objective.as_ref(),
+            keys_held: &game.keys_held,
+            collected: &game.collected,
+            completion: game.completion,
+            level: game.level.as_deref(),
+        }
+    }
+}
+
+/// View of a game sent to its player alone: the `GameView` and, once a ranked game is
+/// over, the signed token certifying its score, which spectators must not get to submit
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerView<'a> {
+    #[serde(flatten)]
+    pub view: GameView<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score_token: Option<&'a str>,
+}
+
+impl<'a> From<&'a Game> for PlayerView<'a> {
+    fn from(game: &'a Game) -> Self {
+        PlayerView {
+            view: GameView::from(game),
+            score_token: game.score_token.as_deref(),
+        }
+    }
+}
+
+/// Version of a game's view, as 16 hex digits, which changes whenever the view does
+/// The rolling hash covers every state up to the current tick; the directions and the
+/// player's score token are added since they may change between ticks
+pub fn version(game: &Game) -> String {
+    let mut hasher = state_hash::StateHasher::resume(game.rolling_hash);
+    hasher.write_u64(game.tick);
+    hasher.write_bytes(&[game.direction as u8, game.score_token.is_some() as u8]);
+    if let Some(partner) = &game.partner {
+        hasher.write_bytes(&[partner.direction as u8]);
+    }
+    state_hash::to_hex(hasher.finish())
+}
+
+/// Serializes a game as its `GameView`, for use with `#[serde(serialize_with)]`
+pub fn serialize_game<G: Borrow<Game>, S: Serializer>(
+    game: &G,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    GameView::from(game.borrow()).serialize(serializer)
+}
+
+/// Serializes a game as its `PlayerView`, for use with `#[serde(serialize_with)]`
+pub fn serialize_player_game<G: Borrow<Game>, S: Serializer>(
+    game: &G,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    PlayerView::from(game.borrow()).serialize(serializer)
+}
+
+/// Changes of a game's view from one frame of a realtime stream to the next
+/// The snake is sent as the cells it gained at the head and the number it lost at the
+/// tail; the other fields of `GameView` only when their value changed, removed ones as null
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameDelta {
+    /// Cells added in front of the snake, head first
+    pub head: Vec<Value>,
+    /// Cells removed from the end of the snake
+    pub tail_removed: usize,
+    #[serde(flatten)]
+    pub changed: Map<String, Value>,
+}
+
+impl GameDelta {
+    /// Delta between two views serialized as JSON
+    /// A snake that did not move forward, e.g. after a rewind, is replaced as a whole
+    pub fn between(previous: &Value, current: &Value) -> GameDelta {
+        let snake = |view: &Value| {
+            view.get("snake")
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default()
+        };
+        let (old, new) = (snake(previous), snake(current));
+        // Every cell is new at worst, leaving nothing to match
+        let gained = (0..=new.len())
+            .find(|gained| old.starts_with(&new[*gained..]))
+            .unwrap_or(new.len());
+        let removed = previous
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter(|(key, _)| current.get(key.as_str()).is_none())
+            .map(|(key, _)| (key.clone(), Value::Null));
+        let changed = current
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter(|(key, value)| *key != "snake" && previous.get(key.as_str()) != Some(*value))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .chain(removed)
+            .collect();
+        GameDelta {
+            head: new[..gained].to_vec(),
+            tail_removed: old.len() - (new.len() - gained),
+            changed,
+        }
+    }
+}
+
+/// Second snake of a co-op game
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartnerView<'a> {
+    /// Snake body, head first
+    pub snake: &'a VecDeque<Point>,
+    pub direction: Direction,
+}
+
+impl<'a> From<&'a Partner> for PartnerView<'a> {
+    fn from(partner: &'a Partner) -> Self {
+        PartnerView {
+            snake: &partner.snake,
+            direction: partner.direction,
+        }
+    }
+}
+
+/// Challenge modifiers of a game
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModifiersView {
+    pub mirror_controls: bool,
+    pub random_control_scramble_every_n_apples: Option<u32>,
+}
+
+impl From<Modifiers> for ModifiersView {
+    fn from(modifiers: Modifiers) -> Self {
+        ModifiersView {
+            mirror_controls: modifiers.mirror_controls,
+            random_control_scramble_every_n_apples: modifiers
+                .random_control_scramble_every_n_apples,
+        }
+    }
+}
+
+/// Food behavior of a game, tagged by `kind`
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum FoodBehaviorView {
+    Static,
+    #[serde(rename_all = "camelCase")]
+    Fleeing {
+        every_ticks: u32,
+    },
+}
+
+impl From<FoodBehavior> for FoodBehaviorView {
+    fn from(behavior: FoodBehavior) -> Self {
+        match behavior {
+            FoodBehavior::Static => FoodBehaviorView::Static,
+            FoodBehavior::Fleeing { every_ticks } => FoodBehaviorView::Fleeing { every_ticks },
+        }
+    }
+}
+
+/// Food lifetime of a game
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FoodExpiryView {
+    pub lifetime_ticks: u32,
+    pub quick_bonus: u32,
+}
+
+impl From<FoodExpiry> for FoodExpiryView {
+    fn from(expiry: FoodExpiry) -> Self {
+        FoodExpiryView {
+            lifetime_ticks: expiry.lifetime_ticks,
+            quick_bonus: expiry.quick_bonus,
+        }
+    }
+}
+
+/// Combo scoring rules of a game
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComboView {
+    pub window_ticks: u32,
+    pub max_multiplier: Option<u32>,
+}
+
+impl From<ComboScoring> for ComboView {
+    fn from(combo: ComboScoring) -> Self {
+        ComboView {
+            window_ticks: combo.window_ticks,
+            max_multiplier: combo.max_multiplier,
+        }
+    }
+}
+
+/// Growth settings of a game
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GrowthView {
+    pub segments: u32,
+    pub every_n_apples: u32,
+}
+
+impl From<Growth> for GrowthView {
+    fn from(growth: Growth) -> Self {
+        GrowthView {
+            segments: growth.segments,
+            every_n_apples: growth.every_n_apples,
+        }
+    }
+}
+
+/// Speed-run split of a game
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SplitView {
+    pub apples: u32,
+    pub tick: u64,
+    pub elapsed_ms: u64,
+}
+
+impl From<Split> for SplitView {
+    fn from(split: Split) -> Self {
+        SplitView {
+            apples: split.apples,
+            tick: split.tick,
+            elapsed_ms: split.elapsed_ms,
+        }
+    }
+}